@@ -3,6 +3,33 @@ pub use sea_orm_migration::prelude::*;
 mod m20260124_154523_create_subscriptions_table;
 mod m20260408_131915_add_status_to_subscriptions;
 mod m20260411_062612_create_subscription_tokens_table;
+mod m20261019_080000_create_email_outbox_table;
+mod m20261019_090000_add_bounce_counts_to_subscriptions;
+mod m20261019_100000_create_suppressions_table;
+mod m20261019_110000_create_newsletter_issues_table;
+mod m20261019_110100_create_tracking_tables;
+mod m20261019_120000_add_scheduled_at_to_newsletter_issues;
+mod m20261019_130000_add_slug_to_newsletter_issues;
+mod m20261019_140000_create_lists_tables;
+mod m20261019_150000_create_preferences_tables;
+mod m20261019_160000_create_tags_and_segments_tables;
+mod m20261019_170000_add_attributes_to_subscriptions;
+mod m20261019_180000_create_ab_testing_tables;
+mod m20261019_190000_create_import_jobs_table;
+mod m20261019_200000_create_privacy_requests_table;
+mod m20261019_210000_create_consent_events_table;
+mod m20261019_220000_create_audit_log_table;
+mod m20261019_230000_create_rate_limit_buckets_table;
+mod m20261019_231000_add_canonical_email_to_subscriptions;
+mod m20261019_232000_add_publish_attempts_to_newsletter_issues;
+mod m20261019_233000_add_transactional_to_email_outbox;
+mod m20261019_234000_create_used_form_tokens_table;
+mod m20261019_235000_add_attributes_to_subscription_tokens;
+mod m20261019_235100_add_send_attempts_to_ab_tests;
+mod m20261019_235200_add_next_attempt_at_to_newsletter_issues;
+mod m20261019_235300_add_used_at_to_subscription_tokens;
+mod m20261019_235400_add_digest_due_at_to_issue_recipients;
+mod m20261019_235500_add_soft_bounced_at_to_subscriptions;
 
 pub struct Migrator;
 
@@ -13,6 +40,33 @@ impl MigratorTrait for Migrator {
             Box::new(m20260124_154523_create_subscriptions_table::Migration),
             Box::new(m20260408_131915_add_status_to_subscriptions::Migration),
             Box::new(m20260411_062612_create_subscription_tokens_table::Migration),
+            Box::new(m20261019_080000_create_email_outbox_table::Migration),
+            Box::new(m20261019_090000_add_bounce_counts_to_subscriptions::Migration),
+            Box::new(m20261019_100000_create_suppressions_table::Migration),
+            Box::new(m20261019_110000_create_newsletter_issues_table::Migration),
+            Box::new(m20261019_110100_create_tracking_tables::Migration),
+            Box::new(m20261019_120000_add_scheduled_at_to_newsletter_issues::Migration),
+            Box::new(m20261019_130000_add_slug_to_newsletter_issues::Migration),
+            Box::new(m20261019_140000_create_lists_tables::Migration),
+            Box::new(m20261019_150000_create_preferences_tables::Migration),
+            Box::new(m20261019_160000_create_tags_and_segments_tables::Migration),
+            Box::new(m20261019_170000_add_attributes_to_subscriptions::Migration),
+            Box::new(m20261019_180000_create_ab_testing_tables::Migration),
+            Box::new(m20261019_190000_create_import_jobs_table::Migration),
+            Box::new(m20261019_200000_create_privacy_requests_table::Migration),
+            Box::new(m20261019_210000_create_consent_events_table::Migration),
+            Box::new(m20261019_220000_create_audit_log_table::Migration),
+            Box::new(m20261019_230000_create_rate_limit_buckets_table::Migration),
+            Box::new(m20261019_231000_add_canonical_email_to_subscriptions::Migration),
+            Box::new(m20261019_232000_add_publish_attempts_to_newsletter_issues::Migration),
+            Box::new(m20261019_233000_add_transactional_to_email_outbox::Migration),
+            Box::new(m20261019_234000_create_used_form_tokens_table::Migration),
+            Box::new(m20261019_235000_add_attributes_to_subscription_tokens::Migration),
+            Box::new(m20261019_235100_add_send_attempts_to_ab_tests::Migration),
+            Box::new(m20261019_235200_add_next_attempt_at_to_newsletter_issues::Migration),
+            Box::new(m20261019_235300_add_used_at_to_subscription_tokens::Migration),
+            Box::new(m20261019_235400_add_digest_due_at_to_issue_recipients::Migration),
+            Box::new(m20261019_235500_add_soft_bounced_at_to_subscriptions::Migration),
         ]
     }
 }
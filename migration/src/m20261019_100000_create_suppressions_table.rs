@@ -0,0 +1,69 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Suppressions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Suppressions::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Suppressions::EmailHash)
+                            .char_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Suppressions::Reason).string().not_null())
+                    .col(ColumnDef::new(Suppressions::Source).string().not_null())
+                    .col(
+                        ColumnDef::new(Suppressions::CreatedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 把已经退订 / 退信 / 投诉的订阅者导入 suppression list。
+        // The hash must match `suppression::hash_email`: SHA-256 of the
+        // trimmed, lowercased address.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "INSERT IGNORE INTO suppressions (email_hash, reason, source, created_at) \
+                 SELECT SHA2(LOWER(TRIM(email)), 256), status, 'import', UTC_TIMESTAMP() \
+                 FROM subscriptions \
+                 WHERE status IN ('unsubscribed', 'bounced', 'complained')",
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Suppressions::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Suppressions {
+    Table,
+    Id,
+    EmailHash,
+    Reason,
+    Source,
+    CreatedAt,
+}
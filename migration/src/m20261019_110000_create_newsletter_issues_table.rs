@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(NewsletterIssues::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(NewsletterIssues::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(NewsletterIssues::Title).string().not_null())
+                    .col(
+                        ColumnDef::new(NewsletterIssues::HtmlContent)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(NewsletterIssues::TextContent).text().null())
+                    .col(ColumnDef::new(NewsletterIssues::Status).string().not_null())
+                    .col(
+                        ColumnDef::new(NewsletterIssues::CreatedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NewsletterIssues::PublishedAt)
+                            .timestamp()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(NewsletterIssues::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum NewsletterIssues {
+    Table,
+    Id,
+    Title,
+    HtmlContent,
+    TextContent,
+    Status,
+    CreatedAt,
+    PublishedAt,
+}
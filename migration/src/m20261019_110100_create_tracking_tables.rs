@@ -0,0 +1,234 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 每个收件人一行：记录谁收到了哪一期，以及用于追踪的 token
+        manager
+            .create_table(
+                Table::create()
+                    .table(IssueRecipients::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(IssueRecipients::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(IssueRecipients::NewsletterIssueId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(IssueRecipients::SubscriberId)
+                            .integer()
+                            .null(),
+                    )
+                    .col(ColumnDef::new(IssueRecipients::Email).string().not_null())
+                    .col(
+                        ColumnDef::new(IssueRecipients::TrackingToken)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(IssueRecipients::CreatedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_issue_recipients_newsletter_issue")
+                            .from(IssueRecipients::Table, IssueRecipients::NewsletterIssueId)
+                            .to(NewsletterIssues::Table, NewsletterIssues::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_issue_recipients_subscriber")
+                            .from(IssueRecipients::Table, IssueRecipients::SubscriberId)
+                            .to(Subscriptions::Table, Subscriptions::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TrackedLinks::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TrackedLinks::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TrackedLinks::NewsletterIssueId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(TrackedLinks::Url).text().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_tracked_links_newsletter_issue")
+                            .from(TrackedLinks::Table, TrackedLinks::NewsletterIssueId)
+                            .to(NewsletterIssues::Table, NewsletterIssues::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(EmailOpens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EmailOpens::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailOpens::IssueRecipientId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(EmailOpens::OpenedAt).timestamp().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_email_opens_issue_recipient")
+                            .from(EmailOpens::Table, EmailOpens::IssueRecipientId)
+                            .to(IssueRecipients::Table, IssueRecipients::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(EmailClicks::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EmailClicks::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailClicks::IssueRecipientId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailClicks::TrackedLinkId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EmailClicks::ClickedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_email_clicks_issue_recipient")
+                            .from(EmailClicks::Table, EmailClicks::IssueRecipientId)
+                            .to(IssueRecipients::Table, IssueRecipients::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_email_clicks_tracked_link")
+                            .from(EmailClicks::Table, EmailClicks::TrackedLinkId)
+                            .to(TrackedLinks::Table, TrackedLinks::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EmailClicks::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(EmailOpens::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(TrackedLinks::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(IssueRecipients::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum NewsletterIssues {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Subscriptions {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum IssueRecipients {
+    Table,
+    Id,
+    NewsletterIssueId,
+    SubscriberId,
+    Email,
+    TrackingToken,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum TrackedLinks {
+    Table,
+    Id,
+    NewsletterIssueId,
+    Url,
+}
+
+#[derive(DeriveIden)]
+enum EmailOpens {
+    Table,
+    Id,
+    IssueRecipientId,
+    OpenedAt,
+}
+
+#[derive(DeriveIden)]
+enum EmailClicks {
+    Table,
+    Id,
+    IssueRecipientId,
+    TrackedLinkId,
+    ClickedAt,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NewsletterIssues::Table)
+                    .add_column(
+                        ColumnDef::new(NewsletterIssues::ScheduledAt)
+                            .timestamp()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The scheduler looks for scheduled issues that are due.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_newsletter_issues_status_scheduled_at")
+                    .table(NewsletterIssues::Table)
+                    .col(NewsletterIssues::Status)
+                    .col(NewsletterIssues::ScheduledAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_newsletter_issues_status_scheduled_at")
+                    .table(NewsletterIssues::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NewsletterIssues::Table)
+                    .drop_column(NewsletterIssues::ScheduledAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum NewsletterIssues {
+    Table,
+    Status,
+    ScheduledAt,
+}
@@ -0,0 +1,233 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Lists::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Lists::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Lists::Slug).string().not_null().unique_key())
+                    .col(ColumnDef::new(Lists::Name).string().not_null())
+                    .col(ColumnDef::new(Lists::CreatedAt).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ListSubscriptions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ListSubscriptions::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ListSubscriptions::ListId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ListSubscriptions::SubscriberId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ListSubscriptions::Status)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ListSubscriptions::SubscribedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .index(
+                        Index::create()
+                            .name("idx_list_subscriptions_list_subscriber")
+                            .col(ListSubscriptions::ListId)
+                            .col(ListSubscriptions::SubscriberId)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_list_subscriptions_list")
+                            .from(ListSubscriptions::Table, ListSubscriptions::ListId)
+                            .to(Lists::Table, Lists::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_list_subscriptions_subscriber")
+                            .from(ListSubscriptions::Table, ListSubscriptions::SubscriberId)
+                            .to(Subscriptions::Table, Subscriptions::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Everyone so far subscribed to our one newsletter: it becomes the
+        // default list.
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "INSERT INTO lists (slug, name, created_at) \
+             VALUES ('newsletter', 'Newsletter', UTC_TIMESTAMP())",
+        )
+        .await?;
+        db.execute_unprepared(
+            "INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at) \
+             SELECT lists.id, subscriptions.id, subscriptions.status, subscriptions.subscribed_at \
+             FROM subscriptions JOIN lists ON lists.slug = 'newsletter'",
+        )
+        .await?;
+
+        for (table, column, foreign_key) in [
+            (
+                SubscriptionTokens::Table.into_iden(),
+                SubscriptionTokens::ListId.into_iden(),
+                "fk_subscription_tokens_list",
+            ),
+            (
+                NewsletterIssues::Table.into_iden(),
+                NewsletterIssues::ListId.into_iden(),
+                "fk_newsletter_issues_list",
+            ),
+        ] {
+            add_list_reference(manager, table, column, foreign_key).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (table, column, foreign_key) in [
+            (
+                NewsletterIssues::Table.into_iden(),
+                NewsletterIssues::ListId.into_iden(),
+                "fk_newsletter_issues_list",
+            ),
+            (
+                SubscriptionTokens::Table.into_iden(),
+                SubscriptionTokens::ListId.into_iden(),
+                "fk_subscription_tokens_list",
+            ),
+        ] {
+            manager
+                .drop_foreign_key(
+                    ForeignKey::drop()
+                        .name(foreign_key)
+                        .table(table.clone())
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .alter_table(Table::alter().table(table).drop_column(column).to_owned())
+                .await?;
+        }
+        manager
+            .drop_table(Table::drop().table(ListSubscriptions::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Lists::Table).to_owned())
+            .await
+    }
+}
+
+/// Add a mandatory `list_id` column to `table`, pointing existing rows at the
+/// default list.
+async fn add_list_reference(
+    manager: &SchemaManager<'_>,
+    table: DynIden,
+    column: DynIden,
+    foreign_key: &str,
+) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(table.clone())
+                .add_column(ColumnDef::new(column.clone()).integer().null())
+                .to_owned(),
+        )
+        .await?;
+    let backfill = Query::update()
+        .table(table.clone())
+        .value(
+            column.clone(),
+            Expr::cust("(SELECT id FROM lists WHERE slug = 'newsletter')"),
+        )
+        .to_owned();
+    manager.exec_stmt(backfill).await?;
+    manager
+        .alter_table(
+            Table::alter()
+                .table(table.clone())
+                .modify_column(ColumnDef::new(column.clone()).integer().not_null())
+                .to_owned(),
+        )
+        .await?;
+    manager
+        .create_foreign_key(
+            ForeignKey::create()
+                .name(foreign_key)
+                .from(table, column)
+                .to(Lists::Table, Lists::Id)
+                .on_delete(ForeignKeyAction::Cascade)
+                .on_update(ForeignKeyAction::Cascade)
+                .to_owned(),
+        )
+        .await
+}
+
+#[derive(DeriveIden)]
+enum Lists {
+    Table,
+    Id,
+    Slug,
+    Name,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ListSubscriptions {
+    Table,
+    Id,
+    ListId,
+    SubscriberId,
+    Status,
+    SubscribedAt,
+}
+
+#[derive(DeriveIden)]
+enum Subscriptions {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum SubscriptionTokens {
+    Table,
+    ListId,
+}
+
+#[derive(DeriveIden)]
+enum NewsletterIssues {
+    Table,
+    ListId,
+}
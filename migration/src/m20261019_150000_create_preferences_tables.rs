@@ -0,0 +1,140 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The token in the preference center link of every email.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .add_column(
+                        ColumnDef::new(Subscriptions::PreferencesToken)
+                            .string_len(32)
+                            .null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(Subscriptions::DigestFrequency)
+                            .string_len(16)
+                            .not_null()
+                            .default("immediate"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Existing subscribers get a random token of the same length as the
+        // ones generated by `preferences::generate_preferences_token`.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE subscriptions \
+                 SET preferences_token = LEFT(SHA2(CONCAT(UUID(), RAND(), id), 256), 32)",
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .modify_column(
+                        ColumnDef::new(Subscriptions::PreferencesToken)
+                            .string_len(32)
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_subscriptions_preferences_token")
+                    .table(Subscriptions::Table)
+                    .col(Subscriptions::PreferencesToken)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // 订阅者在 preference center 里做的每一次修改
+        manager
+            .create_table(
+                Table::create()
+                    .table(PreferenceChanges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PreferenceChanges::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PreferenceChanges::SubscriberId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PreferenceChanges::Field).string().not_null())
+                    .col(ColumnDef::new(PreferenceChanges::OldValue).text().null())
+                    .col(ColumnDef::new(PreferenceChanges::NewValue).text().null())
+                    .col(
+                        ColumnDef::new(PreferenceChanges::ChangedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_preference_changes_subscriber")
+                            .from(PreferenceChanges::Table, PreferenceChanges::SubscriberId)
+                            .to(Subscriptions::Table, Subscriptions::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PreferenceChanges::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_subscriptions_preferences_token")
+                    .table(Subscriptions::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .drop_column(Subscriptions::PreferencesToken)
+                    .drop_column(Subscriptions::DigestFrequency)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Subscriptions {
+    Table,
+    Id,
+    PreferencesToken,
+    DigestFrequency,
+}
+
+#[derive(DeriveIden)]
+enum PreferenceChanges {
+    Table,
+    Id,
+    SubscriberId,
+    Field,
+    OldValue,
+    NewValue,
+    ChangedAt,
+}
@@ -0,0 +1,201 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .add_column(ColumnDef::new(Subscriptions::Locale).string_len(16).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tags::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Tags::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Tags::Name).string().not_null().unique_key())
+                    .col(ColumnDef::new(Tags::CreatedAt).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SubscriberTags::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SubscriberTags::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SubscriberTags::SubscriberId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SubscriberTags::TagId).integer().not_null())
+                    .index(
+                        Index::create()
+                            .name("idx_subscriber_tags_subscriber_tag")
+                            .col(SubscriberTags::SubscriberId)
+                            .col(SubscriberTags::TagId)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_subscriber_tags_subscriber")
+                            .from(SubscriberTags::Table, SubscriberTags::SubscriberId)
+                            .to(Subscriptions::Table, Subscriptions::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_subscriber_tags_tag")
+                            .from(SubscriberTags::Table, SubscriberTags::TagId)
+                            .to(Tags::Table, Tags::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // A saved filter over subscribers, see `segments::SegmentFilter` for
+        // the format of `definition`.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Segments::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Segments::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Segments::Name)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Segments::Definition).text().not_null())
+                    .col(ColumnDef::new(Segments::CreatedAt).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Issues without a segment go to everyone on their list.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NewsletterIssues::Table)
+                    .add_column(ColumnDef::new(NewsletterIssues::SegmentId).integer().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_newsletter_issues_segment")
+                            .from_tbl(NewsletterIssues::Table)
+                            .from_col(NewsletterIssues::SegmentId)
+                            .to_tbl(Segments::Table)
+                            .to_col(Segments::Id)
+                            .on_delete(ForeignKeyAction::Restrict)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .name("fk_newsletter_issues_segment")
+                    .table(NewsletterIssues::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(NewsletterIssues::Table)
+                    .drop_column(NewsletterIssues::SegmentId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Segments::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(SubscriberTags::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Tags::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .drop_column(Subscriptions::Locale)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Subscriptions {
+    Table,
+    Id,
+    Locale,
+}
+
+#[derive(DeriveIden)]
+enum Tags {
+    Table,
+    Id,
+    Name,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum SubscriberTags {
+    Table,
+    Id,
+    SubscriberId,
+    TagId,
+}
+
+#[derive(DeriveIden)]
+enum Segments {
+    Table,
+    Id,
+    Name,
+    Definition,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum NewsletterIssues {
+    Table,
+    SegmentId,
+}
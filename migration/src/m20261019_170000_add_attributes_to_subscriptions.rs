@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Custom fields of the subscriber, e.g. `{"first_name": "Ursula"}`.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .add_column(ColumnDef::new(Subscriptions::Attributes).json().null())
+                    .to_owned(),
+            )
+            .await?;
+        // The fields the subscribe form of the list asks for.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Lists::Table)
+                    .add_column(ColumnDef::new(Lists::AttributeSchema).json().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Lists::Table)
+                    .drop_column(Lists::AttributeSchema)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .drop_column(Subscriptions::Attributes)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Subscriptions {
+    Table,
+    Attributes,
+}
+
+#[derive(DeriveIden)]
+enum Lists {
+    Table,
+    AttributeSchema,
+}
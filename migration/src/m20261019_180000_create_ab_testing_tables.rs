@@ -0,0 +1,200 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The versions of an issue tested against each other. A missing
+        // content falls back to the content of the issue.
+        manager
+            .create_table(
+                Table::create()
+                    .table(IssueVariants::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(IssueVariants::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(IssueVariants::NewsletterIssueId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(IssueVariants::Label)
+                            .string_len(8)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(IssueVariants::Title).string().not_null())
+                    .col(ColumnDef::new(IssueVariants::HtmlContent).text().null())
+                    .col(ColumnDef::new(IssueVariants::TextContent).text().null())
+                    .index(
+                        Index::create()
+                            .name("idx_issue_variants_issue_label")
+                            .col(IssueVariants::NewsletterIssueId)
+                            .col(IssueVariants::Label)
+                            .unique(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_issue_variants_issue")
+                            .from(IssueVariants::Table, IssueVariants::NewsletterIssueId)
+                            .to(NewsletterIssues::Table, NewsletterIssues::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 一个 issue 最多一个 A/B test：pending → testing → completed
+        manager
+            .create_table(
+                Table::create()
+                    .table(AbTests::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AbTests::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AbTests::NewsletterIssueId)
+                            .integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AbTests::SamplePercentage)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AbTests::WaitMinutes).integer().not_null())
+                    .col(ColumnDef::new(AbTests::Metric).string_len(16).not_null())
+                    .col(
+                        ColumnDef::new(AbTests::Status)
+                            .string_len(16)
+                            .not_null()
+                            .default("pending"),
+                    )
+                    .col(ColumnDef::new(AbTests::DecideAt).timestamp().null())
+                    .col(ColumnDef::new(AbTests::WinnerVariantId).integer().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ab_tests_issue")
+                            .from(AbTests::Table, AbTests::NewsletterIssueId)
+                            .to(NewsletterIssues::Table, NewsletterIssues::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ab_tests_winner_variant")
+                            .from(AbTests::Table, AbTests::WinnerVariantId)
+                            .to(IssueVariants::Table, IssueVariants::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ab_tests_status_decide_at")
+                    .table(AbTests::Table)
+                    .col(AbTests::Status)
+                    .col(AbTests::DecideAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        // The variant a recipient of the test got; `NULL` outside of tests.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IssueRecipients::Table)
+                    .add_column(ColumnDef::new(IssueRecipients::VariantId).integer().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_issue_recipients_variant")
+                            .from_tbl(IssueRecipients::Table)
+                            .from_col(IssueRecipients::VariantId)
+                            .to_tbl(IssueVariants::Table)
+                            .to_col(IssueVariants::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .name("fk_issue_recipients_variant")
+                    .table(IssueRecipients::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IssueRecipients::Table)
+                    .drop_column(IssueRecipients::VariantId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(AbTests::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(IssueVariants::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum NewsletterIssues {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum IssueVariants {
+    Table,
+    Id,
+    NewsletterIssueId,
+    Label,
+    Title,
+    HtmlContent,
+    TextContent,
+}
+
+#[derive(DeriveIden)]
+enum AbTests {
+    Table,
+    Id,
+    NewsletterIssueId,
+    SamplePercentage,
+    WaitMinutes,
+    Metric,
+    Status,
+    DecideAt,
+    WinnerVariantId,
+}
+
+#[derive(DeriveIden)]
+enum IssueRecipients {
+    Table,
+    VariantId,
+}
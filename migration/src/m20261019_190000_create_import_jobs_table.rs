@@ -0,0 +1,76 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 每次 CSV 导入一行：running → completed / failed。
+        // The counts are updated after every chunk, the report once the
+        // import is over.
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportJobs::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImportJobs::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImportJobs::CreatedBy).string().not_null())
+                    .col(
+                        ColumnDef::new(ImportJobs::Status)
+                            .string_len(16)
+                            .not_null()
+                            .default("running"),
+                    )
+                    .col(
+                        ColumnDef::new(ImportJobs::Accepted)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImportJobs::Duplicates)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ImportJobs::Rejected)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(ImportJobs::Report).json().null())
+                    .col(ColumnDef::new(ImportJobs::CreatedAt).timestamp().not_null())
+                    .col(ColumnDef::new(ImportJobs::FinishedAt).timestamp().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImportJobs::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImportJobs {
+    Table,
+    Id,
+    CreatedBy,
+    Status,
+    Accepted,
+    Duplicates,
+    Rejected,
+    Report,
+    CreatedAt,
+    FinishedAt,
+}
@@ -0,0 +1,110 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 订阅和确认时记录同意的证据：时间、IP、UA、来源、同意文本版本
+        manager
+            .create_table(
+                Table::create()
+                    .table(ConsentEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ConsentEvents::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ConsentEvents::SubscriberId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ConsentEvents::ListId).integer().not_null())
+                    .col(
+                        ColumnDef::new(ConsentEvents::Event)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConsentEvents::IpAddress)
+                            .string_len(45)
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConsentEvents::UserAgent)
+                            .string_len(512)
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConsentEvents::Source)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConsentEvents::ConsentTextVersion)
+                            .string_len(32)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConsentEvents::OccurredAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_consent_events_subscriber")
+                            .from(ConsentEvents::Table, ConsentEvents::SubscriberId)
+                            .to(Subscriptions::Table, Subscriptions::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_consent_events_list")
+                            .from(ConsentEvents::Table, ConsentEvents::ListId)
+                            .to(Lists::Table, Lists::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ConsentEvents::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Subscriptions {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Lists {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ConsentEvents {
+    Table,
+    Id,
+    SubscriberId,
+    ListId,
+    Event,
+    IpAddress,
+    UserAgent,
+    Source,
+    ConsentTextVersion,
+    OccurredAt,
+}
@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 1️⃣ 管理操作的审计日志：谁、做了什么、对象、修改前后
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLog::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Actor).string_len(255).not_null())
+                    .col(ColumnDef::new(AuditLog::Action).string_len(64).not_null())
+                    .col(
+                        ColumnDef::new(AuditLog::TargetType)
+                            .string_len(32)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditLog::TargetId).string_len(64).null())
+                    .col(ColumnDef::new(AuditLog::Before).json().null())
+                    .col(ColumnDef::new(AuditLog::After).json().null())
+                    .col(ColumnDef::new(AuditLog::RequestId).string_len(64).null())
+                    .col(ColumnDef::new(AuditLog::CreatedAt).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // 2️⃣ 按对象查询（例如某个订阅者的全部记录）
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_target")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::TargetType)
+                    .col(AuditLog::TargetId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditLog::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditLog {
+    Table,
+    Id,
+    Actor,
+    Action,
+    TargetType,
+    TargetId,
+    Before,
+    After,
+    RequestId,
+    CreatedAt,
+}
@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 1️⃣ 多实例部署时共享的限流令牌桶（每个 IP / 邮箱一行）
+        manager
+            .create_table(
+                Table::create()
+                    .table(RateLimitBuckets::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RateLimitBuckets::BucketKey)
+                            .string_len(128)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RateLimitBuckets::Tokens).double().not_null())
+                    .col(
+                        ColumnDef::new(RateLimitBuckets::UpdatedAtMs)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RateLimitBuckets::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RateLimitBuckets {
+    Table,
+    BucketKey,
+    Tokens,
+    UpdatedAtMs,
+}
@@ -0,0 +1,117 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::Statement;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Subscribers whose addresses only differ in case, but the oldest one.
+const DUPLICATES: &str = "FROM subscriptions s \
+    JOIN (SELECT MIN(id) AS kept_id, LOWER(email) AS canonical FROM subscriptions \
+          GROUP BY LOWER(email) HAVING COUNT(*) > 1) d \
+    ON LOWER(s.email) = d.canonical AND s.id <> d.kept_id";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // 1️⃣ 比较用的规范化邮箱，先允许为空（中途失败后重跑时跳过）
+        if !manager
+            .has_column("subscriptions", "canonical_email")
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Subscriptions::Table)
+                        .add_column(
+                            ColumnDef::new(Subscriptions::CanonicalEmail)
+                                .string()
+                                .null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // 2️⃣ 已有数据按默认规则（忽略大小写）回填
+        db.execute_unprepared(
+            "UPDATE subscriptions SET canonical_email = LOWER(email) \
+             WHERE canonical_email IS NULL",
+        )
+        .await?;
+
+        // 3️⃣ 大小写不同的重复地址：最早的一个保留规范形式，其余保留原地址，
+        //    仍是各自独立的订阅者，打印出来以便人工合并
+        let duplicates = db
+            .query_all_raw(Statement::from_string(
+                manager.get_database_backend(),
+                format!("SELECT s.id, s.email {}", DUPLICATES),
+            ))
+            .await?;
+        let mut ids = Vec::with_capacity(duplicates.len());
+        for duplicate in &duplicates {
+            let id: i32 = duplicate.try_get("", "id")?;
+            let email: String = duplicate.try_get("", "email")?;
+            println!(
+                "Subscriber {} ({}) differs from an older one only in case: merge them by hand.",
+                id, email
+            );
+            ids.push(id.to_string());
+        }
+        if !ids.is_empty() {
+            db.execute_unprepared(&format!(
+                "UPDATE subscriptions SET canonical_email = email WHERE id IN ({})",
+                ids.join(",")
+            ))
+            .await?;
+        }
+
+        // 4️⃣ 改为非空并加唯一索引
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .modify_column(
+                        ColumnDef::new(Subscriptions::CanonicalEmail)
+                            .string()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        if manager
+            .has_index("subscriptions", "idx_subscriptions_canonical_email")
+            .await?
+        {
+            return Ok(());
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_subscriptions_canonical_email")
+                    .table(Subscriptions::Table)
+                    .col(Subscriptions::CanonicalEmail)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Subscriptions::Table)
+                    .drop_column(Subscriptions::CanonicalEmail)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Subscriptions {
+    Table,
+    CanonicalEmail,
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 1️⃣ 已用过的表单 token（多实例共享，防止重放）
+        manager
+            .create_table(
+                Table::create()
+                    .table(UsedFormTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UsedFormTokens::Nonce)
+                            .string_len(32)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UsedFormTokens::ExpiresAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 2️⃣ 按过期时间清理
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_used_form_tokens_expires_at")
+                    .table(UsedFormTokens::Table)
+                    .col(UsedFormTokens::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UsedFormTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UsedFormTokens {
+    Table,
+    Nonce,
+    ExpiresAt,
+}
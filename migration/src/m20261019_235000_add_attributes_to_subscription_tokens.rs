@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The attributes of the subscribe form, applied to the subscriber only
+        // once the token confirms the subscription.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubscriptionTokens::Table)
+                    .add_column(ColumnDef::new(SubscriptionTokens::Attributes).json().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubscriptionTokens::Table)
+                    .drop_column(SubscriptionTokens::Attributes)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SubscriptionTokens {
+    Table,
+    Attributes,
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A token confirms once: an old link must not undo a later
+        // unsubscribe.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubscriptionTokens::Table)
+                    .add_column(
+                        ColumnDef::new(SubscriptionTokens::UsedAt)
+                            .timestamp()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SubscriptionTokens::Table)
+                    .drop_column(SubscriptionTokens::UsedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SubscriptionTokens {
+    Table,
+    UsedAt,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // When an issue waiting for the digest of a daily or weekly
+        // subscriber is sent; `NULL` once it is, or for immediate delivery.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IssueRecipients::Table)
+                    .add_column(
+                        ColumnDef::new(IssueRecipients::DigestDueAt)
+                            .timestamp()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The scheduler looks for digests that are due.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_issue_recipients_digest_due_at")
+                    .table(IssueRecipients::Table)
+                    .col(IssueRecipients::DigestDueAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_issue_recipients_digest_due_at")
+                    .table(IssueRecipients::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IssueRecipients::Table)
+                    .drop_column(IssueRecipients::DigestDueAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum IssueRecipients {
+    Table,
+    DigestDueAt,
+}
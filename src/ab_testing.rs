@@ -0,0 +1,388 @@
+//! A/B tests of newsletter issues: the variants of an issue are first sent to
+//! a sample of its audience and, once the test has run for long enough, the
+//! variant with the best open or click rate goes to everyone else.
+//!
+//! A test is `pending` until the issue is published, `testing` while the
+//! sample reads it and `completed` once the winner has been sent. A winner
+//! that cannot be sent is retried like a scheduled issue, and the test is
+//! `failed` once it has been tried too often.
+
+use crate::email_outbox::{ExecutionOutcome, next_attempt_at};
+use crate::entity::newsletter_issues;
+use crate::entity::{ab_tests, email_clicks, email_opens, issue_recipients, issue_variants};
+use crate::issue_delivery::{Sample, enqueue_delivery, publish};
+use crate::issue_scheduler::MAX_PUBLISH_ATTEMPTS;
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::{Expr, Func, LockBehavior, LockType, SimpleExpr};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr,
+    EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+    Set, TransactionTrait,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{Span, field::display};
+
+/// What the winner is picked on, the first being the default.
+pub const METRICS: [&str; 2] = ["opens", "clicks"];
+
+/// Buckets subscribers are split into: the sample of a test is the first
+/// `sample_percentage` hundredths of them.
+const BUCKETS: u64 = 10_000;
+
+/// The bucket of a subscriber for an issue. Random across issues, but the
+/// same every time for a given issue and subscriber.
+pub fn bucket(issue_id: i32, subscriber_id: i32) -> u32 {
+    let digest = Sha256::digest(format!("{}:{}", issue_id, subscriber_id).as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(bytes) % BUCKETS) as u32
+}
+
+/// Whether the subscriber in `bucket` is part of a sample of `percentage`
+/// percents of the audience, and which of the `variants` they get.
+pub fn assign(bucket: u32, percentage: i32, variants: usize) -> Option<usize> {
+    let in_sample = (bucket as u64) < (percentage.max(0) as u64) * BUCKETS / 100;
+    (in_sample && variants > 0).then(|| bucket as usize % variants)
+}
+
+/// The issue as written in `variant`. A variant without content of its own
+/// uses the content of the issue.
+pub fn apply_variant(
+    issue: &newsletter_issues::Model,
+    variant: &issue_variants::Model,
+) -> newsletter_issues::Model {
+    let mut issue = issue.clone();
+    issue.title = variant.title.clone();
+    if let Some(html_content) = &variant.html_content {
+        issue.html_content = html_content.clone();
+        issue.text_content = variant.text_content.clone();
+    } else if let Some(text_content) = &variant.text_content {
+        issue.text_content = Some(text_content.clone());
+    }
+    issue
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VariantStats {
+    pub variant_id: i32,
+    pub label: String,
+    pub title: String,
+    pub recipients: u64,
+    pub unique_opens: i64,
+    pub unique_clicks: i64,
+}
+
+impl VariantStats {
+    fn rate(&self, metric: &str) -> f64 {
+        if self.recipients == 0 {
+            return 0.0;
+        }
+        let readers = if metric == "clicks" {
+            self.unique_clicks
+        } else {
+            self.unique_opens
+        };
+        readers as f64 / self.recipients as f64
+    }
+}
+
+/// The variant with the best rate on `metric`; the first one on a tie.
+pub fn pick_winner(stats: &[VariantStats], metric: &str) -> Option<i32> {
+    let mut winner: Option<&VariantStats> = None;
+    for variant in stats {
+        if winner.is_none_or(|best| variant.rate(metric) > best.rate(metric)) {
+            winner = Some(variant);
+        }
+    }
+    winner.map(|variant| variant.variant_id)
+}
+
+pub async fn find_variants<C: ConnectionTrait>(
+    db: &C,
+    issue_id: i32,
+) -> Result<Vec<issue_variants::Model>, DbErr> {
+    issue_variants::Entity::find()
+        .filter(issue_variants::Column::NewsletterIssueId.eq(issue_id))
+        .order_by_asc(issue_variants::Column::Id)
+        .all(db)
+        .await
+}
+
+/// The test of the issue, if it has one that has not started yet.
+pub async fn find_pending_test<C: ConnectionTrait>(
+    db: &C,
+    issue_id: i32,
+) -> Result<Option<ab_tests::Model>, DbErr> {
+    ab_tests::Entity::find()
+        .filter(ab_tests::Column::NewsletterIssueId.eq(issue_id))
+        .filter(ab_tests::Column::Status.eq("pending"))
+        .one(db)
+        .await
+}
+
+/// Send the variants to the sample of `test` as part of `txn`, returning the
+/// number of recipients.
+#[tracing::instrument(
+    name = "Start the A/B test of a newsletter issue",
+    skip_all,
+    fields(issue_id = issue.id)
+)]
+pub async fn start_test(
+    txn: &DatabaseTransaction,
+    issue: newsletter_issues::Model,
+    test: ab_tests::Model,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<u64, DbErr> {
+    let variants = find_variants(txn, issue.id).await?;
+    let sample = Sample {
+        percentage: test.sample_percentage,
+        variants: &variants,
+    };
+    let recipients =
+        enqueue_delivery(txn, &issue, Some(sample), base_url, tracking_enabled).await?;
+
+    let mut issue: newsletter_issues::ActiveModel = issue.into();
+    issue.status = Set("testing".to_string());
+    issue.update(txn).await?;
+    let decide_at = Utc::now() + Duration::minutes(test.wait_minutes as i64);
+    let mut test: ab_tests::ActiveModel = test.into();
+    test.status = Set("testing".to_string());
+    test.decide_at = Set(Some(decide_at));
+    test.update(txn).await?;
+    Ok(recipients)
+}
+
+/// How each variant of the issue did with the sample.
+pub async fn variant_stats<C: ConnectionTrait>(
+    db: &C,
+    issue_id: i32,
+) -> Result<Vec<VariantStats>, DbErr> {
+    let mut stats = Vec::new();
+    for variant in find_variants(db, issue_id).await? {
+        let recipients = issue_recipients::Entity::find()
+            .filter(issue_recipients::Column::VariantId.eq(variant.id))
+            .count(db)
+            .await?;
+        let unique_opens = email_opens::Entity::find()
+            .join(
+                JoinType::InnerJoin,
+                email_opens::Relation::IssueRecipients.def(),
+            )
+            .filter(issue_recipients::Column::VariantId.eq(variant.id))
+            .select_only()
+            .column_as(
+                SimpleExpr::from(Func::count_distinct(Expr::col((
+                    email_opens::Entity,
+                    email_opens::Column::IssueRecipientId,
+                )))),
+                "unique_opens",
+            )
+            .into_tuple::<i64>()
+            .one(db)
+            .await?
+            .unwrap_or_default();
+        let unique_clicks = email_clicks::Entity::find()
+            .join(
+                JoinType::InnerJoin,
+                email_clicks::Relation::IssueRecipients.def(),
+            )
+            .filter(issue_recipients::Column::VariantId.eq(variant.id))
+            .select_only()
+            .column_as(
+                SimpleExpr::from(Func::count_distinct(Expr::col((
+                    email_clicks::Entity,
+                    email_clicks::Column::IssueRecipientId,
+                )))),
+                "unique_clicks",
+            )
+            .into_tuple::<i64>()
+            .one(db)
+            .await?
+            .unwrap_or_default();
+        stats.push(VariantStats {
+            variant_id: variant.id,
+            label: variant.label,
+            title: variant.title,
+            recipients,
+            unique_opens,
+            unique_clicks,
+        });
+    }
+    Ok(stats)
+}
+
+/// Send the winner of the oldest test that has run for long enough, if any,
+/// to the rest of the audience.
+#[tracing::instrument(skip_all, fields(issue_id = tracing::field::Empty), err)]
+pub async fn try_send_due_winner(
+    db: &DatabaseConnection,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<ExecutionOutcome, DbErr> {
+    let txn = db.begin().await?;
+    let Some(test) = ab_tests::Entity::find()
+        .filter(ab_tests::Column::Status.eq("testing"))
+        .filter(ab_tests::Column::DecideAt.lte(Utc::now()))
+        .order_by_asc(ab_tests::Column::DecideAt)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .one(&txn)
+        .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("issue_id", display(test.newsletter_issue_id));
+    let test_id = test.id;
+    match send_winner(&txn, test, base_url, tracking_enabled).await {
+        Ok(()) => txn.commit().await?,
+        Err(e) => {
+            // As for scheduled issues: only the failure is kept, and the test
+            // steps aside for the ones due after it.
+            txn.rollback().await?;
+            record_failed_attempt(db, test_id, &e).await?;
+        }
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn send_winner(
+    txn: &DatabaseTransaction,
+    test: ab_tests::Model,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<(), DbErr> {
+    let issue = newsletter_issues::Entity::find_by_id(test.newsletter_issue_id)
+        .one(txn)
+        .await?
+        .ok_or_else(|| {
+            DbErr::RecordNotFound(format!("Issue {} does not exist", test.newsletter_issue_id))
+        })?;
+    let stats = variant_stats(txn, issue.id).await?;
+    let winner_id = pick_winner(&stats, &test.metric);
+    let variants = find_variants(txn, issue.id).await?;
+    let winner = variants
+        .iter()
+        .find(|variant| Some(variant.id) == winner_id)
+        .ok_or_else(|| DbErr::RecordNotFound(format!("Issue {} has no variants", issue.id)))?;
+
+    // The issue becomes the winning variant, for the rest of the audience
+    // and the archive.
+    let issue = apply_variant(&issue, winner);
+    let mut winning_issue: newsletter_issues::ActiveModel = issue.clone().into();
+    winning_issue.title = Set(issue.title.clone());
+    winning_issue.html_content = Set(issue.html_content.clone());
+    winning_issue.text_content = Set(issue.text_content.clone());
+    let issue = winning_issue.update(txn).await?;
+    let label = winner.label.clone();
+    let mut test: ab_tests::ActiveModel = test.into();
+    test.status = Set("completed".to_string());
+    test.winner_variant_id = Set(winner_id);
+    test.update(txn).await?;
+
+    let delivery = publish(txn, issue, base_url, tracking_enabled).await?;
+    tracing::info!(
+        winner = %label,
+        recipients = delivery.recipients,
+        "Sent the winner of an A/B test"
+    );
+    Ok(())
+}
+
+/// Push the decision back, or give up on the test after
+/// [`MAX_PUBLISH_ATTEMPTS`].
+async fn record_failed_attempt(
+    db: &DatabaseConnection,
+    test_id: i32,
+    error: &DbErr,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    let Some(test) = ab_tests::Entity::find_by_id(test_id)
+        .lock(LockType::Update)
+        .one(&txn)
+        .await?
+    else {
+        return Ok(());
+    };
+    if test.status != "testing" {
+        return Ok(());
+    }
+    let attempts = test.send_attempts + 1;
+    let mut test: ab_tests::ActiveModel = test.into();
+    test.send_attempts = Set(attempts);
+    test.last_error = Set(Some(error.to_string()));
+    if attempts >= MAX_PUBLISH_ATTEMPTS {
+        tracing::error!(
+            attempts,
+            "Gave up on sending the winner of an A/B test: {}",
+            error
+        );
+        test.status = Set("failed".to_string());
+    } else {
+        tracing::warn!(
+            attempts,
+            "Failed to send the winner of an A/B test: {}",
+            error
+        );
+        test.decide_at = Set(Some(next_attempt_at(attempts)));
+    }
+    test.update(&txn).await?;
+    txn.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BUCKETS, VariantStats, assign, bucket, pick_winner};
+
+    fn stats(variant_id: i32, recipients: u64, opens: i64, clicks: i64) -> VariantStats {
+        VariantStats {
+            variant_id,
+            label: variant_id.to_string(),
+            title: String::new(),
+            recipients,
+            unique_opens: opens,
+            unique_clicks: clicks,
+        }
+    }
+
+    #[test]
+    fn buckets_are_deterministic() {
+        assert_eq!(bucket(1, 42), bucket(1, 42));
+        assert!((0..100).any(|id| bucket(1, id) != bucket(2, id)));
+        assert!((0..1000).all(|id| (bucket(1, id) as u64) < BUCKETS));
+    }
+
+    #[test]
+    fn the_sample_is_about_the_requested_size_and_evenly_split() {
+        let mut per_variant = [0; 3];
+        for subscriber_id in 0..10_000 {
+            if let Some(variant) = assign(bucket(7, subscriber_id), 30, 3) {
+                per_variant[variant] += 1;
+            }
+        }
+        let sampled: i32 = per_variant.iter().sum();
+        assert!((2_700..3_300).contains(&sampled), "{} sampled", sampled);
+        assert!(per_variant.iter().all(|n| (800..1_200).contains(n)));
+    }
+
+    #[test]
+    fn nobody_is_sampled_at_zero_percent_and_everybody_at_a_hundred() {
+        assert_eq!(assign(0, 0, 2), None);
+        assert_eq!(assign(9_999, 100, 2), Some(1));
+        assert_eq!(assign(9_999, 99, 2), None);
+    }
+
+    #[test]
+    fn the_best_rate_wins() {
+        let variants = [stats(1, 100, 30, 5), stats(2, 50, 20, 1)];
+        assert_eq!(pick_winner(&variants, "opens"), Some(2));
+        assert_eq!(pick_winner(&variants, "clicks"), Some(1));
+    }
+
+    #[test]
+    fn the_first_variant_wins_a_tie() {
+        let variants = [stats(1, 10, 0, 0), stats(2, 0, 0, 0), stats(3, 10, 0, 0)];
+        assert_eq!(pick_winner(&variants, "opens"), Some(1));
+        assert_eq!(pick_winner(&[], "opens"), None);
+    }
+}
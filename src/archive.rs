@@ -0,0 +1,296 @@
+//! The public archive of published issues: HTML pages plus Atom and RSS
+//! feeds, served with validators so that clients can revalidate cheaply.
+
+use crate::entity::newsletter_issues;
+use crate::issue_template::{escape_html, without_variables};
+use axum::http::{HeaderMap, HeaderValue, header};
+use chrono::{DateTime, Utc};
+
+/// Issues listed in the feeds.
+pub const FEED_LENGTH: u64 = 20;
+
+/// URL-friendly version of an issue title: lowercase ASCII letters and digits
+/// separated by single dashes.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    // Keep slugs well within the column size.
+    let mut end = slug.len().min(80);
+    while slug[..end].ends_with('-') {
+        end -= 1;
+    }
+    slug[..end].to_string()
+}
+
+/// What a cached copy of a page is checked against.
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// `version` must change whenever the content of the page does.
+    pub fn new(version: &str, last_modified: DateTime<Utc>) -> Self {
+        Self {
+            etag: format!("W/\"{}\"", version),
+            last_modified,
+        }
+    }
+
+    /// Whether the client's copy is still fresh, i.e. we can answer
+    /// `304 Not Modified`. `If-None-Match` takes precedence over
+    /// `If-Modified-Since`, as required by RFC 9110.
+    pub fn is_fresh(&self, request_headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+            let if_none_match = if_none_match.to_str().unwrap_or_default();
+            return if_none_match.trim() == "*"
+                || if_none_match
+                    .split(',')
+                    .any(|etag| weak_eq(etag.trim(), &self.etag));
+        }
+        request_headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .is_some_and(|since| self.last_modified.timestamp() <= since.timestamp())
+    }
+
+    pub fn headers(&self) -> [(header::HeaderName, HeaderValue); 3] {
+        [
+            (
+                header::ETAG,
+                HeaderValue::from_str(&self.etag).expect("ETags are valid header values."),
+            ),
+            (
+                header::LAST_MODIFIED,
+                HeaderValue::from_str(&http_date(self.last_modified))
+                    .expect("HTTP dates are valid header values."),
+            ),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=300"),
+            ),
+        ]
+    }
+}
+
+/// Weak comparison of two entity tags.
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+/// The IMF-fixdate format of HTTP headers.
+pub fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+pub fn issue_url(base_url: &str, issue: &newsletter_issues::Model) -> String {
+    format!(
+        "{}/archive/{}",
+        base_url,
+        issue.slug.as_deref().unwrap_or_default()
+    )
+}
+
+fn published_at(issue: &newsletter_issues::Model) -> DateTime<Utc> {
+    issue.published_at.unwrap_or(issue.created_at)
+}
+
+/// The `/archive` page.
+pub fn archive_page(issues: &[newsletter_issues::Model]) -> String {
+    let mut items = String::new();
+    for issue in issues {
+        items.push_str(&format!(
+            "<li><a href=\"/archive/{}\">{}</a> <time datetime=\"{}\">{}</time></li>\n",
+            escape_html(issue.slug.as_deref().unwrap_or_default()),
+            escape_html(&without_variables(&issue.title)),
+            published_at(issue).to_rfc3339(),
+            published_at(issue).format("%B %-d, %Y"),
+        ));
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<title>Newsletter archive</title>\n\
+<link rel=\"alternate\" type=\"application/atom+xml\" href=\"/feed.xml\">\n\
+<link rel=\"alternate\" type=\"application/rss+xml\" href=\"/rss.xml\">\n\
+</head>\n<body>\n<h1>Newsletter archive</h1>\n<ul>\n{}</ul>\n</body>\n</html>\n",
+        items
+    )
+}
+
+/// The Atom feed, `issues` being the latest ones first.
+pub fn atom_feed(base_url: &str, issues: &[newsletter_issues::Model]) -> String {
+    let updated = issues
+        .first()
+        .map(published_at)
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let mut entries = String::new();
+    for issue in issues {
+        let url = escape_html(&issue_url(base_url, issue));
+        entries.push_str(&format!(
+            "<entry>\n<title>{}</title>\n<link href=\"{}\"/>\n<id>{}</id>\n\
+<updated>{}</updated>\n<content type=\"html\">{}</content>\n</entry>\n",
+            escape_html(&without_variables(&issue.title)),
+            url,
+            url,
+            published_at(issue).to_rfc3339(),
+            escape_html(&without_variables(&issue.html_content)),
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+<feed xmlns=\"http://www.w3.org/2005/Atom\">\n<title>Newsletter</title>\n\
+<link href=\"{base}/archive\"/>\n<link rel=\"self\" href=\"{base}/feed.xml\"/>\n\
+<id>{base}/archive</id>\n<updated>{}</updated>\n<author><name>Newsletter</name></author>\n\
+{}</feed>\n",
+        updated.to_rfc3339(),
+        entries,
+        base = escape_html(base_url),
+    )
+}
+
+/// The RSS 2.0 feed, `issues` being the latest ones first.
+pub fn rss_feed(base_url: &str, issues: &[newsletter_issues::Model]) -> String {
+    let mut items = String::new();
+    for issue in issues {
+        let url = escape_html(&issue_url(base_url, issue));
+        items.push_str(&format!(
+            "<item>\n<title>{}</title>\n<link>{}</link>\n<guid>{}</guid>\n\
+<pubDate>{}</pubDate>\n<description>{}</description>\n</item>\n",
+            escape_html(&without_variables(&issue.title)),
+            url,
+            url,
+            published_at(issue).to_rfc2822(),
+            escape_html(&without_variables(&issue.html_content)),
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\">\n<channel>\n\
+<title>Newsletter</title>\n<link>{}/archive</link>\n\
+<description>Past issues of our newsletter</description>\n{}</channel>\n</rss>\n",
+        escape_html(base_url),
+        items
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Validators, atom_feed, http_date, rss_feed, slugify};
+    use crate::entity::newsletter_issues;
+    use axum::http::{HeaderMap, HeaderValue, header};
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn published_on(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, day, 9, 0, 0).unwrap()
+    }
+
+    fn issue(title: &str, slug: &str, day: u32) -> newsletter_issues::Model {
+        newsletter_issues::Model {
+            id: day as i32,
+            title: title.into(),
+            html_content: "<p>Tom & Jerry</p>".into(),
+            text_content: None,
+            status: "published".into(),
+            created_at: published_on(day),
+            published_at: Some(published_on(day)),
+            scheduled_at: None,
+            list_id: 1,
+            segment_id: None,
+            slug: Some(slug.into()),
+            publish_attempts: 0,
+            last_error: None,
+            next_attempt_at: None,
+        }
+    }
+
+    fn request_headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn slugs_only_keep_ascii_letters_and_digits() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(
+            slugify("  Rust 2026 -- what's new?  "),
+            "rust-2026-what-s-new"
+        );
+        assert_eq!(slugify("Café crème"), "caf-cr-me");
+        assert_eq!(slugify("日本語"), "");
+    }
+
+    #[test]
+    fn slugs_are_truncated() {
+        let slug = slugify(&"a-".repeat(100));
+        assert!(slug.len() <= 80);
+        assert!(!slug.ends_with('-'));
+    }
+
+    #[test]
+    fn http_dates_use_the_imf_fixdate_format() {
+        assert_eq!(http_date(published_on(19)), "Mon, 19 Oct 2026 09:00:00 GMT");
+    }
+
+    #[test]
+    fn a_matching_etag_is_fresh() {
+        let validators = Validators::new("3-42", published_on(19));
+        assert!(validators.is_fresh(&request_headers(header::IF_NONE_MATCH, "W/\"3-42\"")));
+        assert!(validators.is_fresh(&request_headers(
+            header::IF_NONE_MATCH,
+            "\"other\", \"3-42\""
+        )));
+        assert!(!validators.is_fresh(&request_headers(header::IF_NONE_MATCH, "W/\"3-41\"")));
+    }
+
+    #[test]
+    fn if_modified_since_is_compared_to_the_last_modification() {
+        let validators = Validators::new("3-42", published_on(19));
+        assert!(validators.is_fresh(&request_headers(
+            header::IF_MODIFIED_SINCE,
+            "Mon, 19 Oct 2026 09:00:00 GMT"
+        )));
+        assert!(!validators.is_fresh(&request_headers(
+            header::IF_MODIFIED_SINCE,
+            "Sun, 18 Oct 2026 09:00:00 GMT"
+        )));
+        assert!(!validators.is_fresh(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        let validators = Validators::new("3-42", published_on(19));
+        let mut headers = request_headers(header::IF_NONE_MATCH, "W/\"3-41\"");
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Mon, 19 Oct 2026 09:00:00 GMT"),
+        );
+        assert!(!validators.is_fresh(&headers));
+    }
+
+    #[test]
+    fn the_atom_feed_lists_issues_with_escaped_content() {
+        let feed = atom_feed(
+            "https://example.com",
+            &[issue("Second", "second", 19), issue("First", "first", 12)],
+        );
+        assert!(feed.contains("<updated>2026-10-19T09:00:00+00:00</updated>\n<author>"));
+        assert!(feed.contains("<link href=\"https://example.com/archive/second\"/>"));
+        assert!(feed.contains("&lt;p&gt;Tom &amp; Jerry&lt;/p&gt;"));
+        assert_eq!(feed.matches("<entry>").count(), 2);
+    }
+
+    #[test]
+    fn the_rss_feed_lists_issues_with_rfc_2822_dates() {
+        let feed = rss_feed("https://example.com", &[issue("First", "first", 12)]);
+        assert!(feed.contains("<pubDate>Mon, 12 Oct 2026 09:00:00 +0000</pubDate>"));
+        assert!(feed.contains("<link>https://example.com/archive/first</link>"));
+    }
+}
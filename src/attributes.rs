@@ -0,0 +1,268 @@
+//! Custom subscriber attributes, e.g. a first name or a plan, used to
+//! personalize issues.
+//!
+//! Every list has a schema of the attributes its subscribe form accepts. The
+//! values are stored in the JSON `attributes` column of `subscriptions`, shared
+//! by all the lists of the subscriber.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Form fields that already mean something on the subscribe form, and
+/// template variables that are not attributes.
+const RESERVED_NAMES: [&str; 7] = [
+    "email",
+    "name",
+    "list",
+    "locale",
+    "source",
+    "consent_text_version",
+    "form_token",
+];
+const MAX_FIELDS: usize = 50;
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_STRING_LENGTH: usize = 255;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributeType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// A `YYYY-MM-DD` date, stored as it is.
+    Date,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AttributeField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: AttributeType,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct AttributeSchema(pub Vec<AttributeField>);
+
+impl AttributeSchema {
+    /// The schema stored for a list, empty when it has none.
+    pub fn from_column(column: Option<&Value>) -> Result<Self, String> {
+        match column {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid attribute schema: {}", e)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// `form_fields` are the names the subscribe form uses on top of the
+    /// [`RESERVED_NAMES`], e.g. those of the bot protection.
+    pub fn validate(&self, form_fields: &[&str]) -> Result<(), String> {
+        if self.0.len() > MAX_FIELDS {
+            return Err(format!(
+                "A list cannot have more than {} attributes.",
+                MAX_FIELDS
+            ));
+        }
+        let mut names = HashSet::new();
+        for field in &self.0 {
+            let is_valid = field.name.len() <= MAX_NAME_LENGTH
+                && field.name.starts_with(|c: char| c.is_ascii_lowercase())
+                && field
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if !is_valid {
+                return Err(format!("{} is not a valid attribute name.", field.name));
+            }
+            if RESERVED_NAMES.contains(&field.name.as_str())
+                || form_fields.contains(&field.name.as_str())
+            {
+                return Err(format!("{} is a reserved name.", field.name));
+            }
+            if !names.insert(field.name.as_str()) {
+                return Err(format!("{} is defined twice.", field.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// The attributes of the schema found in the fields of a form, typed.
+    /// Blank values count as missing; fields outside of the schema are
+    /// ignored.
+    pub fn parse_form(&self, form: &HashMap<String, String>) -> Result<Map<String, Value>, String> {
+        let mut attributes = Map::new();
+        for field in &self.0 {
+            let raw = form
+                .get(&field.name)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty());
+            let Some(raw) = raw else {
+                if field.required {
+                    return Err(format!("{} is required.", field.name));
+                }
+                continue;
+            };
+            let value = parse_value(field.kind, raw)
+                .ok_or_else(|| format!("{} is not a valid {:?}.", field.name, field.kind))?;
+            attributes.insert(field.name.clone(), value);
+        }
+        Ok(attributes)
+    }
+}
+
+fn parse_value(kind: AttributeType, raw: &str) -> Option<Value> {
+    match kind {
+        AttributeType::String => {
+            (raw.chars().count() <= MAX_STRING_LENGTH).then(|| Value::String(raw.to_string()))
+        }
+        AttributeType::Integer => raw.parse::<i64>().ok().map(Value::from),
+        AttributeType::Number => raw
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        // Checkboxes are sent as `on`.
+        AttributeType::Boolean => match raw.to_ascii_lowercase().as_str() {
+            "true" | "on" | "yes" | "1" => Some(Value::Bool(true)),
+            "false" | "off" | "no" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        AttributeType::Date => chrono::NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+            .ok()
+            .map(|_| Value::String(raw.to_string())),
+    }
+}
+
+/// Add the `new` attributes the subscriber does not have yet to `existing`.
+/// Values already stored are kept: anyone can fill in the subscribe form
+/// with someone else's address.
+pub fn merge_new(existing: Option<&Value>, new: Map<String, Value>) -> Option<Value> {
+    let mut attributes = match existing {
+        Some(Value::Object(existing)) => existing.clone(),
+        _ => Map::new(),
+    };
+    for (name, value) in new {
+        attributes.entry(name).or_insert(value);
+    }
+    (!attributes.is_empty()).then_some(Value::Object(attributes))
+}
+
+/// The variables an issue can use for a subscriber: their attributes, `name`
+/// and `email`.
+pub fn template_variables(
+    name: &str,
+    email: &str,
+    attributes: Option<&Value>,
+) -> Map<String, Value> {
+    let mut variables = match attributes {
+        Some(Value::Object(attributes)) => attributes.clone(),
+        _ => Map::new(),
+    };
+    variables.insert("name".into(), Value::String(name.to_string()));
+    variables.insert("email".into(), Value::String(email.to_string()));
+    variables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttributeField, AttributeSchema, AttributeType, merge_new};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn schema() -> AttributeSchema {
+        serde_json::from_value(json!([
+            { "name": "first_name", "type": "string", "required": true },
+            { "name": "age", "type": "integer" },
+            { "name": "vip", "type": "boolean" },
+            { "name": "birthday", "type": "date" },
+        ]))
+        .unwrap()
+    }
+
+    fn form(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn form_fields_are_typed() {
+        let attributes = schema()
+            .parse_form(&form(&[
+                ("first_name", " Ursula "),
+                ("age", "88"),
+                ("vip", "on"),
+                ("birthday", ""),
+                ("other", "ignored"),
+            ]))
+            .unwrap();
+        assert_eq!(
+            serde_json::Value::Object(attributes),
+            json!({ "first_name": "Ursula", "age": 88, "vip": true })
+        );
+    }
+
+    #[test]
+    fn invalid_or_missing_values_are_rejected() {
+        assert!(schema().parse_form(&form(&[("age", "88")])).is_err());
+        assert!(
+            schema()
+                .parse_form(&form(&[("first_name", "Ursula"), ("age", "old")]))
+                .is_err()
+        );
+        assert!(
+            schema()
+                .parse_form(&form(&[
+                    ("first_name", "Ursula"),
+                    ("birthday", "1929-13-21")
+                ]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn schemas_cannot_shadow_form_fields() {
+        assert!(schema().validate(&[]).is_ok());
+        for name in [
+            "email",
+            "source",
+            "consent_text_version",
+            "form_token",
+            "website",
+            "First_Name",
+            "1st",
+            "first name",
+            "",
+        ] {
+            let schema = AttributeSchema(vec![AttributeField {
+                name: name.into(),
+                kind: AttributeType::String,
+                required: false,
+            }]);
+            assert!(
+                schema.validate(&["website"]).is_err(),
+                "{} was accepted",
+                name
+            );
+        }
+        let mut twice = schema();
+        twice.0.push(twice.0[0].clone());
+        assert!(twice.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn stored_attributes_are_not_overwritten() {
+        let existing = json!({ "first_name": "Ursula" });
+        let new = json!({ "first_name": "Mallory", "age": 88 });
+        assert_eq!(
+            merge_new(Some(&existing), new.as_object().unwrap().clone()),
+            Some(json!({ "first_name": "Ursula", "age": 88 }))
+        );
+        assert_eq!(merge_new(None, Default::default()), None);
+    }
+}
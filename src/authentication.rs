@@ -0,0 +1,98 @@
+use axum::http::HeaderMap;
+use base64::Engine;
+use secrecy::{ExposeSecret, SecretString};
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// Extract the credentials of an `Authorization: Basic ...` header.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, String> {
+    let header_value = headers
+        .get("Authorization")
+        .ok_or("The 'Authorization' header was missing")?
+        .to_str()
+        .map_err(|_| "The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .ok_or("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .map_err(|_| "Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .map_err(|_| "The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or("A password must be provided in 'Basic' auth.")?;
+
+    Ok(Credentials {
+        username: username.to_string(),
+        password: SecretString::from(password),
+    })
+}
+
+/// Compare a secret we received with the one we expect without leaking, via
+/// timing, how many leading bytes matched.
+pub fn secrets_match(received: &SecretString, expected: &SecretString) -> bool {
+    let received = received.expose_secret().as_bytes();
+    let expected = expected.expose_secret().as_bytes();
+    received.len() == expected.len()
+        && received
+            .iter()
+            .zip(expected)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{basic_authentication, secrets_match};
+    use axum::http::{HeaderMap, HeaderValue};
+    use claims::{assert_err, assert_ok};
+    use secrecy::{ExposeSecret, SecretString};
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn valid_basic_credentials_are_extracted() {
+        // "postmark:s3cr3t:with-colon"
+        let credentials = assert_ok!(basic_authentication(&headers(
+            "Basic cG9zdG1hcms6czNjcjN0OndpdGgtY29sb24="
+        )));
+        assert_eq!(credentials.username, "postmark");
+        assert_eq!(credentials.password.expose_secret(), "s3cr3t:with-colon");
+    }
+
+    #[test]
+    fn a_missing_header_is_rejected() {
+        assert_err!(basic_authentication(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn other_schemes_are_rejected() {
+        assert_err!(basic_authentication(&headers("Bearer abc")));
+    }
+
+    #[test]
+    fn invalid_base64_is_rejected() {
+        assert_err!(basic_authentication(&headers("Basic not-base64!")));
+    }
+
+    #[test]
+    fn secrets_are_compared_by_value() {
+        let expected = SecretString::from("s3cr3t");
+        assert!(secrets_match(&SecretString::from("s3cr3t"), &expected));
+        assert!(!secrets_match(&SecretString::from("s3cr3T"), &expected));
+        assert!(!secrets_match(&SecretString::from("s3cr3t!"), &expected));
+    }
+}
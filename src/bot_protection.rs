@@ -0,0 +1,470 @@
+//! Challenges a subscription form has to pass before we email anyone: bots
+//! sign up random victims, who then receive confirmation emails they never
+//! asked for.
+//!
+//! Each challenge is a [`ChallengeVerifier`]; the built-in ones are a
+//! honeypot field, a minimum time between fetching a form token and posting
+//! the form, and an hCaptcha/Turnstile-style CAPTCHA.
+
+use crate::authentication::secrets_match;
+use crate::entity::used_form_tokens;
+use crate::rate_limit::ip_key;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use fake::RngExt;
+use fake::rand::distr::Alphanumeric;
+use fake::rand::rng;
+use hmac::{Hmac, Mac};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, QueryFilter, Set,
+    SqlErr,
+};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The form field carrying the token of [`form_token`].
+pub const FORM_TOKEN_FIELD: &str = "form_token";
+/// How long a form can stay open before it has to be reloaded.
+const FORM_TOKEN_LIFETIME_SECONDS: i64 = 24 * 60 * 60;
+
+/// A posted form, as the challenges see it.
+pub struct Submission<'a> {
+    /// Every field of the form.
+    pub fields: &'a HashMap<String, String>,
+    pub client_ip: IpAddr,
+}
+
+impl Submission<'_> {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .get(name)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    /// Certainly a bot: answer as if the form was accepted, so that it does
+    /// not learn what gave it away.
+    Bot(&'static str),
+    /// The challenge was not passed, e.g. a missing or wrong CAPTCHA; a
+    /// person can try again.
+    Failed(String),
+    /// The challenge could not be checked.
+    Unavailable(String),
+}
+
+#[async_trait]
+pub trait ChallengeVerifier: Send + Sync {
+    async fn verify(&self, submission: &Submission<'_>) -> Result<(), Rejection>;
+
+    /// The form fields the challenge reads.
+    fn fields(&self) -> Vec<&str>;
+
+    /// Called in the transaction saving the subscription, once the form
+    /// passed every challenge and was accepted: a form turned away, e.g.
+    /// for a typo in the address, can be fixed and posted again.
+    async fn accept(
+        &self,
+        _txn: &DatabaseTransaction,
+        _submission: &Submission<'_>,
+    ) -> Result<(), Rejection> {
+        Ok(())
+    }
+}
+
+/// The challenges of the subscription form, checked in order.
+#[derive(Clone, Default)]
+pub struct BotProtection {
+    verifiers: Vec<Arc<dyn ChallengeVerifier>>,
+}
+
+impl BotProtection {
+    pub fn with(mut self, verifier: impl ChallengeVerifier + 'static) -> Self {
+        self.verifiers.push(Arc::new(verifier));
+        self
+    }
+
+    pub async fn verify(&self, submission: &Submission<'_>) -> Result<(), Rejection> {
+        for verifier in &self.verifiers {
+            verifier.verify(submission).await?;
+        }
+        Ok(())
+    }
+
+    /// The form fields the challenges read, which nothing else may use.
+    pub fn fields(&self) -> Vec<&str> {
+        self.verifiers
+            .iter()
+            .flat_map(|verifier| verifier.fields())
+            .collect()
+    }
+
+    pub async fn accept(
+        &self,
+        txn: &DatabaseTransaction,
+        submission: &Submission<'_>,
+    ) -> Result<(), Rejection> {
+        for verifier in &self.verifiers {
+            verifier.accept(txn, submission).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A field hidden from people with CSS: only bots fill it in.
+pub struct Honeypot {
+    pub field: String,
+}
+
+#[async_trait]
+impl ChallengeVerifier for Honeypot {
+    async fn verify(&self, submission: &Submission<'_>) -> Result<(), Rejection> {
+        match submission.field(&self.field) {
+            Some(_) => Err(Rejection::Bot("honeypot")),
+            None => Ok(()),
+        }
+    }
+
+    fn fields(&self) -> Vec<&str> {
+        vec![&self.field]
+    }
+}
+
+/// People take a few seconds to fill a form in, bots post it at once. The
+/// form carries a signed token of when it was served, so the time cannot be
+/// made up. The token only works from the address it was served to, and only
+/// for one accepted form, so a bot cannot wait once and replay it. Used
+/// tokens are kept in the `used_form_tokens` table, shared by every instance.
+pub struct MinimumSubmitTime {
+    minimum: Duration,
+    secret: SecretString,
+}
+
+impl MinimumSubmitTime {
+    pub fn new(minimum: Duration, secret: SecretString) -> Self {
+        Self { minimum, secret }
+    }
+
+    fn token<'a>(&self, submission: &'a Submission<'_>) -> Result<FormToken<'a>, Rejection> {
+        let token = submission
+            .field(FORM_TOKEN_FIELD)
+            .ok_or_else(|| Rejection::Failed("The form token is missing.".into()))?;
+        verify_form_token(&self.secret, token, submission.client_ip)
+            .ok_or_else(|| Rejection::Failed("The form token is invalid.".into()))
+    }
+}
+
+#[async_trait]
+impl ChallengeVerifier for MinimumSubmitTime {
+    async fn verify(&self, submission: &Submission<'_>) -> Result<(), Rejection> {
+        let token = self.token(submission)?;
+        let age = Utc::now().timestamp() - token.issued_at;
+        if age > FORM_TOKEN_LIFETIME_SECONDS {
+            return Err(Rejection::Failed(
+                "The form has expired, reload the page.".into(),
+            ));
+        }
+        if age < self.minimum.as_secs() as i64 {
+            return Err(Rejection::Bot("submitted too fast"));
+        }
+        Ok(())
+    }
+
+    fn fields(&self) -> Vec<&str> {
+        vec![FORM_TOKEN_FIELD]
+    }
+
+    async fn accept(
+        &self,
+        txn: &DatabaseTransaction,
+        submission: &Submission<'_>,
+    ) -> Result<(), Rejection> {
+        let token = self.token(submission)?;
+        let expires_at = DateTime::from_timestamp(token.issued_at + FORM_TOKEN_LIFETIME_SECONDS, 0)
+            .ok_or_else(|| Rejection::Failed("The form token is invalid.".into()))?;
+        let used = used_form_tokens::ActiveModel {
+            nonce: Set(token.nonce.to_string()),
+            expires_at: Set(expires_at),
+        };
+        // The primary key turns a concurrent replay away too: it waits for
+        // this transaction, then fails.
+        match used_form_tokens::Entity::insert(used).exec(txn).await {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                Err(Rejection::Bot("form token reused"))
+            }
+            Err(e) => Err(Rejection::Unavailable(e.to_string())),
+        }
+    }
+}
+
+/// Forget the tokens that expired: they are turned away anyway.
+#[tracing::instrument(name = "Prune the used form tokens", skip(db))]
+pub async fn prune_used_form_tokens(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let result = used_form_tokens::Entity::delete_many()
+        .filter(used_form_tokens::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+/// What a valid form token tells.
+struct FormToken<'a> {
+    /// Seconds since the epoch.
+    issued_at: i64,
+    nonce: &'a str,
+}
+
+/// A token of a form served to `client_ip` at `issued_at` (seconds since the
+/// epoch), to be posted back in the [`FORM_TOKEN_FIELD`] field.
+pub fn form_token(secret: &SecretString, issued_at: i64, client_ip: IpAddr) -> String {
+    let mut rng = rng();
+    let nonce: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(16)
+        .collect();
+    let signature = sign(secret, issued_at, &nonce, client_ip);
+    format!("{}.{}.{}", issued_at, nonce, signature)
+}
+
+/// What `token` tells, if we signed it for `client_ip`.
+fn verify_form_token<'a>(
+    secret: &SecretString,
+    token: &'a str,
+    client_ip: IpAddr,
+) -> Option<FormToken<'a>> {
+    let mut parts = token.splitn(3, '.');
+    let issued_at: i64 = parts.next()?.parse().ok()?;
+    let nonce = parts.next()?;
+    let signature = SecretString::from(parts.next()?);
+    let expected = SecretString::from(sign(secret, issued_at, nonce, client_ip));
+    secrets_match(&signature, &expected).then_some(FormToken { issued_at, nonce })
+}
+
+/// Hex-encoded HMAC-SHA-256 of the token. IPv6 clients are bound to their
+/// /64, as their address can change within it while the form is open.
+fn sign(secret: &SecretString, issued_at: i64, nonce: &str, client_ip: IpAddr) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC takes keys of any size");
+    mac.update(format!("{}.{}.{}", issued_at, nonce, ip_key(client_ip)).as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// An hCaptcha or Cloudflare Turnstile style CAPTCHA: the widget puts a
+/// response in the form, which we check with the provider.
+pub struct Captcha {
+    pub http_client: reqwest::Client,
+    pub verify_url: url::Url,
+    pub secret: SecretString,
+    /// The form field the widget writes its response to, e.g.
+    /// `h-captcha-response` or `cf-turnstile-response`.
+    pub response_field: String,
+}
+
+#[derive(serde::Deserialize)]
+struct CaptchaVerification {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+#[async_trait]
+impl ChallengeVerifier for Captcha {
+    async fn verify(&self, submission: &Submission<'_>) -> Result<(), Rejection> {
+        let response = submission
+            .field(&self.response_field)
+            .ok_or_else(|| Rejection::Failed("The CAPTCHA was not solved.".into()))?;
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("secret", self.secret.expose_secret())
+            .append_pair("response", response)
+            .append_pair("remoteip", &submission.client_ip.to_string())
+            .finish();
+        let unavailable = |e: reqwest::Error| {
+            tracing::error!("Failed to verify the CAPTCHA: {:?}", e);
+            Rejection::Unavailable("The CAPTCHA could not be verified.".into())
+        };
+        let verification: CaptchaVerification = self
+            .http_client
+            .post(self.verify_url.clone())
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(unavailable)?
+            .json()
+            .await
+            .map_err(unavailable)?;
+        if verification.success {
+            Ok(())
+        } else {
+            tracing::info!(error_codes = ?verification.error_codes, "CAPTCHA rejected");
+            Err(Rejection::Failed("The CAPTCHA was not solved.".into()))
+        }
+    }
+
+    fn fields(&self) -> Vec<&str> {
+        vec![&self.response_field]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BotProtection, ChallengeVerifier};
+    use super::{
+        FORM_TOKEN_FIELD, Honeypot, MinimumSubmitTime, Rejection, Submission, form_token,
+        verify_form_token,
+    };
+    use chrono::Utc;
+    use secrecy::SecretString;
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    fn client_ip() -> IpAddr {
+        "203.0.113.7".parse().unwrap()
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn secret() -> SecretString {
+        SecretString::from("my-form-token-secret")
+    }
+
+    async fn verify(
+        verifier: &impl ChallengeVerifier,
+        fields: &HashMap<String, String>,
+    ) -> Result<(), Rejection> {
+        verifier
+            .verify(&Submission {
+                fields,
+                client_ip: client_ip(),
+            })
+            .await
+    }
+
+    fn minimum_time() -> MinimumSubmitTime {
+        MinimumSubmitTime::new(Duration::from_secs(3), secret())
+    }
+
+    #[test]
+    fn tokens_only_verify_with_the_secret_and_address_they_were_signed_for() {
+        let token = form_token(&secret(), 1_700_000_000, client_ip());
+        let verified = verify_form_token(&secret(), &token, client_ip()).unwrap();
+        assert_eq!(verified.issued_at, 1_700_000_000);
+        assert!(verify_form_token(&SecretString::from("another"), &token, client_ip()).is_none());
+        let forged = token.replacen("1700000000", "1600000000", 1);
+        assert!(verify_form_token(&secret(), &forged, client_ip()).is_none());
+        let elsewhere = "198.51.100.1".parse().unwrap();
+        assert!(verify_form_token(&secret(), &token, elsewhere).is_none());
+    }
+
+    #[test]
+    fn ipv6_tokens_are_bound_to_the_prefix() {
+        let token = form_token(&secret(), 1_700_000_000, "2001:db8::1".parse().unwrap());
+        let same_prefix = "2001:db8::2".parse().unwrap();
+        assert!(verify_form_token(&secret(), &token, same_prefix).is_some());
+    }
+
+    #[tokio::test]
+    async fn a_filled_in_honeypot_gives_the_bot_away() {
+        let honeypot = || Honeypot {
+            field: "website".into(),
+        };
+        assert_eq!(
+            verify(&honeypot(), &fields(&[("website", "https://spam.example")])).await,
+            Err(Rejection::Bot("honeypot"))
+        );
+        assert!(
+            verify(&honeypot(), &fields(&[("website", " ")]))
+                .await
+                .is_ok()
+        );
+        assert!(verify(&honeypot(), &fields(&[])).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn forms_posted_too_fast_are_from_bots() {
+        let token = form_token(&secret(), Utc::now().timestamp(), client_ip());
+        assert_eq!(
+            verify(&minimum_time(), &fields(&[(FORM_TOKEN_FIELD, &token)])).await,
+            Err(Rejection::Bot("submitted too fast"))
+        );
+        let token = form_token(&secret(), Utc::now().timestamp() - 10, client_ip());
+        assert!(
+            verify(&minimum_time(), &fields(&[(FORM_TOKEN_FIELD, &token)]))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn tokens_are_only_used_up_by_accepted_forms() {
+        // Until `accept`, a form turned away later on can be posted again.
+        let challenge = minimum_time();
+        let token = form_token(&secret(), Utc::now().timestamp() - 10, client_ip());
+        let fields = fields(&[(FORM_TOKEN_FIELD, &token)]);
+        assert!(verify(&challenge, &fields).await.is_ok());
+        assert!(verify(&challenge, &fields).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn missing_and_expired_tokens_fail() {
+        assert!(matches!(
+            verify(&minimum_time(), &fields(&[])).await,
+            Err(Rejection::Failed(_))
+        ));
+        let token = form_token(
+            &secret(),
+            Utc::now().timestamp() - 2 * 24 * 60 * 60,
+            client_ip(),
+        );
+        assert!(matches!(
+            verify(&minimum_time(), &fields(&[(FORM_TOKEN_FIELD, &token)])).await,
+            Err(Rejection::Failed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn challenges_stop_at_the_first_rejection() {
+        let protection = BotProtection::default()
+            .with(Honeypot {
+                field: "website".into(),
+            })
+            .with(minimum_time());
+        let fields = fields(&[("website", "spam")]);
+        let submission = Submission {
+            fields: &fields,
+            client_ip: client_ip(),
+        };
+        assert_eq!(
+            protection.verify(&submission).await,
+            Err(Rejection::Bot("honeypot"))
+        );
+    }
+
+    #[test]
+    fn the_fields_of_every_challenge_are_listed() {
+        let protection = BotProtection::default()
+            .with(Honeypot {
+                field: "website".into(),
+            })
+            .with(minimum_time());
+        assert_eq!(protection.fields(), vec!["website", FORM_TOKEN_FIELD]);
+    }
+}
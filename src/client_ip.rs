@@ -0,0 +1,155 @@
+//! The address of the client behind the reverse proxies we trust.
+
+use crate::startup::AppState;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+
+/// Addresses or CIDR ranges of the proxies whose `X-Forwarded-For` we
+/// believe, e.g. `10.0.0.1` or `10.0.0.0/8`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(try_from = "Vec<String>")]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+impl TryFrom<Vec<String>> for TrustedProxies {
+    type Error = String;
+
+    fn try_from(entries: Vec<String>) -> Result<Self, Self::Error> {
+        entries
+            .iter()
+            .map(|entry| {
+                let entry = entry.trim();
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("{} is not an IP address or a CIDR range.", entry))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// The client address of a request coming from `peer`. `X-Forwarded-For` is
+/// only read when `peer` is a trusted proxy, from the right: the first
+/// address that is not a trusted proxy is the client, anything left of it
+/// could have been made up by the client.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &TrustedProxies) -> IpAddr {
+    let mut client = peer.to_canonical();
+    if !trusted_proxies.contains(&client) {
+        return client;
+    }
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for hop in hops.into_iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip.to_canonical();
+                if !trusted_proxies.contains(&client) {
+                    break;
+                }
+            }
+            // A garbled entry: stop at the last hop we can vouch for.
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+/// Extracts the client address, see [`client_ip`].
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .copied()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(Self(client_ip(
+            peer.ip(),
+            &parts.headers,
+            &state.trusted_proxies,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrustedProxies, client_ip};
+    use axum::http::{HeaderMap, HeaderValue};
+    use std::net::IpAddr;
+
+    fn proxies(entries: &[&str]) -> TrustedProxies {
+        TrustedProxies::try_from(entries.iter().map(|e| e.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn the_header_is_ignored_from_untrusted_peers() {
+        let headers = forwarded_for("198.51.100.1");
+        assert_eq!(
+            client_ip(ip("203.0.113.7"), &headers, &proxies(&[])),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn the_rightmost_untrusted_hop_is_the_client() {
+        let headers = forwarded_for("1.1.1.1, 198.51.100.1, 10.0.0.2");
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies(&["10.0.0.0/8"])),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn garbled_hops_stop_the_walk() {
+        let headers = forwarded_for("198.51.100.1, nonsense");
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies(&["10.0.0.1"])),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_peers_match_ipv4_proxies() {
+        let headers = forwarded_for("198.51.100.1");
+        assert_eq!(
+            client_ip(ip("::ffff:10.0.0.1"), &headers, &proxies(&["10.0.0.1"])),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        assert!(TrustedProxies::try_from(vec!["10.0.0.0/33".to_string()]).is_err());
+        assert!(TrustedProxies::try_from(vec!["proxy.local".to_string()]).is_err());
+    }
+}
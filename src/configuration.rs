@@ -1,8 +1,17 @@
+use crate::bot_protection::{BotProtection, Captcha, Honeypot, MinimumSubmitTime};
+use crate::client_ip::TrustedProxies;
 use crate::domain::SubscriberEmail;
-use sea_orm::ConnectOptions;
+use crate::domain_check::{DomainCheck, DomainResolver};
+use crate::email_client::{CircuitBreaker, EmailClient, EmailProvider, RateLimiter};
+use crate::email_policy::{Canonicalization, EmailPolicy, load_disposable_domains};
+use crate::rate_limit::{BucketLimit, BucketStore, RateLimits};
+use sea_orm::{ConnectOptions, DatabaseConnection};
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
 use tracing_log::log::LevelFilter;
 use url::Url;
 
@@ -11,6 +20,16 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_outbox: EmailOutboxSettings,
+    pub issue_scheduler: IssueSchedulerSettings,
+    pub postmark_webhooks: PostmarkWebhookSettings,
+    pub admin: AdminSettings,
+    pub tracking: TrackingSettings,
+    pub newsletter: NewsletterSettings,
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub email_policy: EmailPolicySettings,
+    pub domain_check: DomainCheckSettings,
 }
 
 #[derive(Deserialize, Clone)]
@@ -19,6 +38,9 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    /// Reverse proxies whose `X-Forwarded-For` tells the client address.
+    #[serde(default)]
+    pub trusted_proxies: TrustedProxies,
 }
 
 #[derive(Deserialize, Clone)]
@@ -33,13 +55,273 @@ pub struct DatabaseSettings {
 
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    /// The primary provider: `base_url` and `authorization_token` stay at the
+    /// top level so they can keep being overridden with `APP_EMAIL_CLIENT__*`.
     pub base_url: Url,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    /// Providers to fall over to, in order, when the primary one fails.
+    #[serde(default)]
+    pub fallback_providers: Vec<EmailProviderSettings>,
+    /// Consecutive failures after which a provider is skipped for a while.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub circuit_breaker_failure_threshold: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub circuit_breaker_cooldown_seconds: u64,
+    /// Maximum number of emails sent per second, `0` means unlimited.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub messages_per_second: u32,
+    /// Maximum number of requests to the email provider in flight at once.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrent_requests: usize,
+}
+
+/// Basic auth credentials protecting the `/admin` routes.
+#[derive(Deserialize, Clone)]
+pub struct AdminSettings {
+    pub username: String,
+    pub password: SecretString,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct NewsletterSettings {
+    /// Internal addresses draft issues are test-sent to by default.
+    #[serde(default)]
+    pub test_recipients: Vec<String>,
+    /// Version of the consent wording shown on the subscription form,
+    /// recorded with every consent unless the form sends its own.
+    pub consent_text_version: String,
+}
+
+/// Token buckets limiting how often the public forms can be posted.
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    pub store: RateLimitStore,
+    /// Requests a client address can make at once, then `per_ip_per_minute`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_ip_burst: u32,
+    /// `0` disables the limit per client address.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_ip_per_minute: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_email_burst: u32,
+    /// `0` disables the limit per email address.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_email_per_minute: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStore {
+    /// Each instance counts on its own.
+    Memory,
+    /// Instances share their counts through the database.
+    Mysql,
+}
+
+impl RateLimitSettings {
+    pub fn limits(&self, db: &DatabaseConnection) -> RateLimits {
+        RateLimits {
+            enabled: self.enabled,
+            per_ip: BucketLimit {
+                burst: self.per_ip_burst,
+                per_minute: self.per_ip_per_minute,
+            },
+            per_email: BucketLimit {
+                burst: self.per_email_burst,
+                per_minute: self.per_email_per_minute,
+            },
+            store: match self.store {
+                RateLimitStore::Memory => BucketStore::memory(),
+                RateLimitStore::Mysql => BucketStore::MySql(db.clone()),
+            },
+        }
+    }
+}
+
+/// Which addresses subscribers can use, see `email_policy`.
+#[derive(Deserialize, Clone)]
+pub struct EmailPolicySettings {
+    pub canonicalization: Canonicalization,
+    pub block_disposable_domains: bool,
+    /// One domain per line, replacing the bundled list.
+    #[serde(default)]
+    pub disposable_domains_file: Option<String>,
+}
+
+impl EmailPolicySettings {
+    pub fn policy(&self) -> std::io::Result<EmailPolicy> {
+        let disposable_domains = if self.block_disposable_domains {
+            load_disposable_domains(self.disposable_domains_file.as_deref().map(Path::new))?
+        } else {
+            HashSet::new()
+        };
+        Ok(EmailPolicy::new(self.canonicalization, disposable_domains))
+    }
+}
+
+/// Whether the domains of new subscribers take email, see `domain_check`.
+#[derive(Deserialize, Clone)]
+pub struct DomainCheckSettings {
+    pub enabled: bool,
+    /// Past it the address is accepted unchecked.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_ttl_seconds: u64,
+    /// Reject addresses a typo away from a common provider, even when their
+    /// domain resolves.
+    pub reject_suspected_typos: bool,
+}
+
+impl DomainCheckSettings {
+    /// The check asking `resolver`, `None` when disabled.
+    pub fn check(&self, resolver: Arc<dyn DomainResolver>) -> Option<DomainCheck> {
+        self.enabled.then(|| {
+            DomainCheck::new(
+                resolver,
+                std::time::Duration::from_millis(self.timeout_milliseconds),
+                std::time::Duration::from_secs(self.cache_ttl_seconds),
+                self.reject_suspected_typos,
+            )
+        })
+    }
+}
+
+/// Challenges of the subscription form, see `bot_protection`.
+#[derive(Deserialize, Clone)]
+pub struct BotProtectionSettings {
+    /// A field hidden from people that bots fill in, empty to disable.
+    pub honeypot_field: String,
+    /// Seconds between fetching a form token and posting the form, `0`
+    /// disables the check and the form token with it.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_submit_seconds: u64,
+    /// Signs the form tokens.
+    pub form_token_secret: SecretString,
+    /// No CAPTCHA is asked for when missing.
+    #[serde(default)]
+    pub captcha: Option<CaptchaSettings>,
+}
+
+/// An hCaptcha or Turnstile compatible verification endpoint.
+#[derive(Deserialize, Clone)]
+pub struct CaptchaSettings {
+    /// E.g. `https://challenges.cloudflare.com/turnstile/v0/siteverify`.
+    pub verify_url: Url,
+    pub secret: SecretString,
+    /// The form field the widget writes its response to.
+    pub response_field: String,
+    pub timeout_milliseconds: u64,
+}
+
+impl BotProtectionSettings {
+    pub fn protection(&self) -> BotProtection {
+        let mut protection = BotProtection::default();
+        if !self.honeypot_field.is_empty() {
+            protection = protection.with(Honeypot {
+                field: self.honeypot_field.clone(),
+            });
+        }
+        if self.min_submit_seconds > 0 {
+            protection = protection.with(MinimumSubmitTime::new(
+                std::time::Duration::from_secs(self.min_submit_seconds),
+                self.form_token_secret.clone(),
+            ));
+        }
+        if let Some(captcha) = &self.captcha {
+            let http_client = reqwest::Client::builder()
+                .timeout(std::time::Duration::from_millis(
+                    captcha.timeout_milliseconds,
+                ))
+                .build()
+                .unwrap();
+            protection = protection.with(Captcha {
+                http_client,
+                verify_url: captcha.verify_url.clone(),
+                secret: captcha.secret.clone(),
+                response_field: captcha.response_field.clone(),
+            });
+        }
+        protection
+    }
+}
+
+/// Open and click tracking of newsletter issues.
+#[derive(Deserialize, Clone)]
+pub struct TrackingSettings {
+    /// When disabled, links are sent as they are, no pixel is added and the
+    /// tracking routes record nothing.
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PostmarkWebhookSettings {
+    /// Basic auth credentials embedded in the webhook URLs configured on Postmark.
+    pub username: String,
+    pub password: SecretString,
+    /// Accepted instead of basic auth in the `X-Webhook-Secret` header.
+    pub shared_secret: SecretString,
+    /// Bounces after which we stop mailing an address.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub hard_bounce_threshold: i32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub soft_bounce_threshold: i32,
+    /// Soft bounces only add up when they are less than this many days apart.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub soft_bounce_window_days: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EmailOutboxSettings {
+    /// Run the outbox dispatcher inside the application process.
+    pub dispatcher_enabled: bool,
+    /// How long the dispatcher waits before polling an empty outbox again.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+}
+
+impl EmailOutboxSettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct IssueSchedulerSettings {
+    /// Publish scheduled newsletter issues from inside the application process.
+    pub enabled: bool,
+    /// How often the scheduler looks for issues that are due.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+}
+
+impl IssueSchedulerSettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EmailProviderSettings {
+    pub name: String,
+    pub base_url: Url,
+    pub authorization_token: SecretString,
 }
 
 impl EmailClientSettings {
+    pub fn client(&self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        EmailClient::new(
+            self.providers(),
+            sender_email,
+            self.timeout(),
+            self.rate_limiter(),
+        )
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
@@ -47,6 +329,37 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.messages_per_second, self.max_concurrent_requests)
+    }
+
+    /// The primary provider followed by the fallback ones, each with its own
+    /// circuit breaker.
+    pub fn providers(&self) -> Vec<EmailProvider> {
+        let circuit_breaker = || {
+            CircuitBreaker::new(
+                self.circuit_breaker_failure_threshold,
+                std::time::Duration::from_secs(self.circuit_breaker_cooldown_seconds),
+            )
+        };
+        let primary = EmailProvider::new(
+            "primary".into(),
+            self.base_url.clone(),
+            self.authorization_token.clone(),
+            circuit_breaker(),
+        );
+        std::iter::once(primary)
+            .chain(self.fallback_providers.iter().map(|provider| {
+                EmailProvider::new(
+                    provider.name.clone(),
+                    provider.base_url.clone(),
+                    provider.authorization_token.clone(),
+                    circuit_breaker(),
+                )
+            }))
+            .collect()
+    }
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -74,7 +387,50 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         )
         .build()?;
 
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    if let Environment::Production = environment {
+        settings.check_secrets()?;
+    }
+    Ok(settings)
+}
+
+/// The credentials of `local.yaml`, public along with the code.
+const SAMPLE_SECRETS: [&str; 4] = [
+    "my-admin-password",
+    "my-webhook-password",
+    "my-webhook-secret",
+    "my-form-token-secret",
+];
+
+impl Settings {
+    /// Refuse to start with a secret left empty or copied from `local.yaml`.
+    fn check_secrets(&self) -> Result<(), config::ConfigError> {
+        let secrets = [
+            ("admin.password", &self.admin.password),
+            (
+                "postmark_webhooks.password",
+                &self.postmark_webhooks.password,
+            ),
+            (
+                "postmark_webhooks.shared_secret",
+                &self.postmark_webhooks.shared_secret,
+            ),
+            (
+                "bot_protection.form_token_secret",
+                &self.bot_protection.form_token_secret,
+            ),
+        ];
+        for (key, secret) in secrets {
+            let secret = secret.expose_secret();
+            if secret.is_empty() || SAMPLE_SECRETS.contains(&secret) {
+                return Err(config::ConfigError::Message(format!(
+                    "{} must be set to a secret of your own.",
+                    key
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl DatabaseSettings {
@@ -162,3 +518,42 @@ Use either `local` or `production`.",
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Settings;
+    use config::{Config, File, FileFormat};
+    use secrecy::SecretString;
+
+    /// The local settings, without the process environment.
+    fn local_settings() -> Settings {
+        Config::builder()
+            .add_source(File::from_str(
+                include_str!("../configuration/base.yaml"),
+                FileFormat::Yaml,
+            ))
+            .add_source(File::from_str(
+                include_str!("../configuration/local.yaml"),
+                FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn the_sample_secrets_are_refused() {
+        let mut settings = local_settings();
+        assert!(settings.check_secrets().is_err());
+
+        settings.admin.password = SecretString::from("s3cr3t-admin");
+        settings.postmark_webhooks.password = SecretString::from("s3cr3t-webhook");
+        settings.postmark_webhooks.shared_secret = SecretString::from("s3cr3t-shared");
+        settings.bot_protection.form_token_secret = SecretString::from("s3cr3t-form");
+        assert!(settings.check_secrets().is_ok());
+
+        settings.bot_protection.form_token_secret = SecretString::from("");
+        assert!(settings.check_secrets().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Proof of consent for double opt-in: when, from where and to which wording
+//! each person agreed, both when they subscribed and when they confirmed.
+
+use crate::entity::{consent_events, lists};
+use axum::http::{HeaderMap, header};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// Source recorded when the form does not name one.
+pub const DEFAULT_SOURCE: &str = "subscription_form";
+/// Source recorded when the link of the confirmation email is followed.
+pub const CONFIRMATION_SOURCE: &str = "confirmation_link";
+
+const MAX_SOURCE_LENGTH: usize = 64;
+const MAX_VERSION_LENGTH: usize = 32;
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// How a consent was given.
+#[derive(Debug, Clone)]
+pub struct Consent {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    /// The form or page the consent was given on.
+    pub source: String,
+    /// The version of the consent wording shown to the person.
+    pub text_version: String,
+}
+
+impl Consent {
+    /// Validate the `source` and `text_version` sent by a form, falling back
+    /// to the defaults when they are missing or blank.
+    pub fn parse(
+        ip_address: Option<IpAddr>,
+        headers: &HeaderMap,
+        source: Option<String>,
+        text_version: Option<String>,
+        default_text_version: &str,
+    ) -> Result<Self, String> {
+        let source = non_blank(source).unwrap_or_else(|| DEFAULT_SOURCE.to_string());
+        if source.chars().count() > MAX_SOURCE_LENGTH {
+            return Err(format!(
+                "The source must be at most {} characters long.",
+                MAX_SOURCE_LENGTH
+            ));
+        }
+        let text_version =
+            non_blank(text_version).unwrap_or_else(|| default_text_version.to_string());
+        if text_version.chars().count() > MAX_VERSION_LENGTH {
+            return Err(format!(
+                "The consent text version must be at most {} characters long.",
+                MAX_VERSION_LENGTH
+            ));
+        }
+        Ok(Self {
+            ip_address,
+            user_agent: user_agent(headers),
+            source,
+            text_version,
+        })
+    }
+}
+
+fn non_blank(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// The `User-Agent` header, cut to what the column holds.
+pub fn user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect())
+}
+
+/// Record that `subscriber_id` consented to receive `list_id`. `event` is
+/// `subscribe` or `confirm`.
+#[tracing::instrument(name = "Record a consent event", skip(db, consent))]
+pub async fn record_consent<C: ConnectionTrait>(
+    db: &C,
+    subscriber_id: i32,
+    list_id: i32,
+    event: &str,
+    consent: &Consent,
+) -> Result<(), DbErr> {
+    consent_events::ActiveModel {
+        subscriber_id: Set(subscriber_id),
+        list_id: Set(list_id),
+        event: Set(event.to_string()),
+        ip_address: Set(consent.ip_address.map(|ip| ip.to_string())),
+        user_agent: Set(consent.user_agent.clone()),
+        source: Set(consent.source.clone()),
+        consent_text_version: Set(consent.text_version.clone()),
+        occurred_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(())
+}
+
+/// The consent text version the subscriber last agreed to when subscribing
+/// to `list_id`: confirming applies to that wording.
+pub async fn subscribed_text_version<C: ConnectionTrait>(
+    db: &C,
+    subscriber_id: i32,
+    list_id: i32,
+) -> Result<Option<String>, DbErr> {
+    consent_events::Entity::find()
+        .filter(consent_events::Column::SubscriberId.eq(subscriber_id))
+        .filter(consent_events::Column::ListId.eq(list_id))
+        .filter(consent_events::Column::Event.eq("subscribe"))
+        .order_by_desc(consent_events::Column::Id)
+        .select_only()
+        .column(consent_events::Column::ConsentTextVersion)
+        .into_tuple::<String>()
+        .one(db)
+        .await
+}
+
+/// A recorded consent, as shown to admins and in data exports.
+#[derive(Serialize, Debug)]
+pub struct ConsentEvent {
+    pub event: String,
+    /// Slug of the list consented to.
+    pub list: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+    pub consent_text_version: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Every consent of `subscriber_id`, oldest first.
+pub async fn consent_history<C: ConnectionTrait>(
+    db: &C,
+    subscriber_id: i32,
+) -> Result<Vec<ConsentEvent>, DbErr> {
+    Ok(consent_events::Entity::find()
+        .find_also_related(lists::Entity)
+        .filter(consent_events::Column::SubscriberId.eq(subscriber_id))
+        .order_by_asc(consent_events::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|(event, list)| ConsentEvent {
+            event: event.event,
+            list: list.map(|list| list.slug).unwrap_or_default(),
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            source: event.source,
+            consent_text_version: event.consent_text_version,
+            occurred_at: event.occurred_at,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Consent, DEFAULT_SOURCE};
+    use axum::http::{HeaderMap, HeaderValue, header};
+
+    #[test]
+    fn missing_fields_fall_back_to_the_defaults() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("curl/8.0"));
+        let consent = Consent::parse(
+            Some("203.0.113.7".parse().unwrap()),
+            &headers,
+            None,
+            Some("  ".into()),
+            "v1",
+        )
+        .unwrap();
+        assert_eq!(consent.source, DEFAULT_SOURCE);
+        assert_eq!(consent.text_version, "v1");
+        assert_eq!(consent.user_agent.as_deref(), Some("curl/8.0"));
+    }
+
+    #[test]
+    fn overlong_fields_are_rejected() {
+        let headers = HeaderMap::new();
+        assert!(Consent::parse(None, &headers, Some("a".repeat(65)), None, "v1").is_err());
+        assert!(Consent::parse(None, &headers, None, Some("a".repeat(33)), "v1").is_err());
+    }
+
+    #[test]
+    fn long_user_agents_are_cut() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::from_str(&"a".repeat(600)).unwrap(),
+        );
+        let consent = Consent::parse(None, &headers, None, None, "v1").unwrap();
+        assert_eq!(consent.user_agent.unwrap().len(), 512);
+    }
+}
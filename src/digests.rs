@@ -0,0 +1,292 @@
+//! Digests: subscribers who chose a daily or weekly digest in the preference
+//! center get the issues published in the meantime in a single email.
+//!
+//! Their `issue_recipients` rows are created when an issue is published, with
+//! the `digest_due_at` of their next digest instead of an email in the
+//! outbox. The scheduler sends the digests that are due and clears it.
+
+use crate::attributes::template_variables;
+use crate::domain::SubscriberEmail;
+use crate::email_outbox::{ExecutionOutcome, enqueue_email};
+use crate::entity::{
+    issue_recipients, list_subscriptions, newsletter_issues, subscriptions, tracked_links,
+};
+use crate::issue_delivery::personalize_tracking;
+use crate::issue_template::{RenderedIssue, escape_html, personalize, render_issue};
+use crate::preferences::{add_preferences_link, preferences_url};
+use chrono::{DateTime, Datelike, Days, NaiveTime, Utc};
+use sea_orm::sea_query::{Expr, LockBehavior, LockType};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, TransactionTrait,
+};
+use std::collections::{HashMap, HashSet};
+use tracing::{Span, field::display};
+
+/// When the next digest at `frequency` goes out after `now`: the next
+/// midnight UTC for daily digests, the next Monday for weekly ones. `None`
+/// for immediate delivery.
+pub fn next_digest_at(frequency: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let days = match frequency {
+        "daily" => 1,
+        "weekly" => 7 - u64::from(now.weekday().num_days_from_monday()),
+        _ => return None,
+    };
+    let day = now.date_naive().checked_add_days(Days::new(days))?;
+    Some(day.and_time(NaiveTime::MIN).and_utc())
+}
+
+/// Send the digest of the subscriber whose oldest pending issue is due, if
+/// any, with every issue due for them.
+#[tracing::instrument(skip_all, fields(subscriber_id = tracing::field::Empty), err)]
+pub async fn try_send_due_digest(
+    db: &DatabaseConnection,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<ExecutionOutcome, DbErr> {
+    let txn = db.begin().await?;
+    let now = Utc::now();
+    let Some(next) = issue_recipients::Entity::find()
+        .filter(issue_recipients::Column::DigestDueAt.lte(now))
+        .order_by_asc(issue_recipients::Column::DigestDueAt)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .one(&txn)
+        .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let (due, subscriber) = match next.subscriber_id {
+        Some(subscriber_id) => {
+            Span::current().record("subscriber_id", display(subscriber_id));
+            let due = issue_recipients::Entity::find()
+                .filter(issue_recipients::Column::SubscriberId.eq(subscriber_id))
+                .filter(issue_recipients::Column::DigestDueAt.lte(now))
+                .order_by_asc(issue_recipients::Column::Id)
+                .lock(LockType::Update)
+                .all(&txn)
+                .await?;
+            let subscriber = subscriptions::Entity::find_by_id(subscriber_id)
+                .one(&txn)
+                .await?;
+            (due, subscriber)
+        }
+        // The subscriber was deleted since.
+        None => (vec![next], None),
+    };
+    // Nothing goes to subscribers who unsubscribed from everything since.
+    if let Some(subscriber) = subscriber.filter(|subscriber| subscriber.status == "confirmed") {
+        send_digest(&txn, &subscriber, &due, base_url, tracking_enabled).await?;
+    }
+    issue_recipients::Entity::update_many()
+        .col_expr(
+            issue_recipients::Column::DigestDueAt,
+            Expr::value(Option::<DateTime<Utc>>::None),
+        )
+        .filter(issue_recipients::Column::Id.is_in(due.iter().map(|recipient| recipient.id)))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Enqueue one email with the issues of `due`, skipping the lists the
+/// subscriber left since.
+async fn send_digest(
+    txn: &DatabaseTransaction,
+    subscriber: &subscriptions::Model,
+    due: &[issue_recipients::Model],
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<(), DbErr> {
+    let recipient = match SubscriberEmail::parse(subscriber.email.clone()) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::warn!(
+                error.message = %e,
+                "Skipping a digest. The stored contact details of the subscriber are invalid",
+            );
+            return Ok(());
+        }
+    };
+    let lists: HashSet<i32> = list_subscriptions::Entity::find()
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber.id))
+        .filter(list_subscriptions::Column::Status.eq("confirmed"))
+        .all(txn)
+        .await?
+        .into_iter()
+        .map(|subscription| subscription.list_id)
+        .collect();
+    let variables = template_variables(
+        &subscriber.name,
+        &subscriber.email,
+        subscriber.attributes.as_ref(),
+    );
+
+    let mut issues = Vec::new();
+    for recipient in due {
+        let Some(issue) = newsletter_issues::Entity::find_by_id(recipient.newsletter_issue_id)
+            .one(txn)
+            .await?
+        else {
+            continue;
+        };
+        if !lists.contains(&issue.list_id) {
+            continue;
+        }
+        let mut personalized = personalize(&render_issue(&issue), &variables);
+        if tracking_enabled {
+            let link_ids: HashMap<_, _> = tracked_links::Entity::find()
+                .filter(tracked_links::Column::NewsletterIssueId.eq(issue.id))
+                .all(txn)
+                .await?
+                .into_iter()
+                .map(|link| (link.url, link.id))
+                .collect();
+            personalized.html = personalize_tracking(
+                &personalized.html,
+                &link_ids,
+                base_url,
+                &recipient.tracking_token,
+            );
+        }
+        issues.push(personalized);
+    }
+    let Some(digest) = combine(&issues) else {
+        return Ok(());
+    };
+    let (html_body, text_body) = add_preferences_link(
+        &digest.html,
+        &digest.text,
+        &preferences_url(base_url, &subscriber.preferences_token),
+    );
+    enqueue_email(
+        txn,
+        &recipient,
+        &digest.subject,
+        &html_body,
+        Some(&text_body),
+    )
+    .await?;
+    tracing::info!(issues = issues.len(), "Enqueued a digest");
+    Ok(())
+}
+
+/// One email with every issue, one after the other. A single issue is sent
+/// as it is.
+fn combine(issues: &[RenderedIssue]) -> Option<RenderedIssue> {
+    match issues {
+        [] => None,
+        [issue] => Some(RenderedIssue {
+            subject: issue.subject.clone(),
+            html: issue.html.clone(),
+            text: issue.text.clone(),
+        }),
+        issues => {
+            let sections: Vec<String> = issues
+                .iter()
+                .map(|issue| {
+                    format!(
+                        "<section>\n<h2>{}</h2>\n{}\n</section>\n",
+                        escape_html(&issue.subject),
+                        body_of(&issue.html)
+                    )
+                })
+                .collect();
+            let texts: Vec<String> = issues
+                .iter()
+                .map(|issue| format!("{}\n\n{}", issue.subject, issue.text))
+                .collect();
+            let subject = format!("Your digest: {} new issues", issues.len());
+            let html = format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+                escape_html(&subject),
+                sections.join("<hr>\n")
+            );
+            Some(RenderedIssue {
+                subject,
+                html,
+                text: texts.join("\n\n---\n\n"),
+            })
+        }
+    }
+}
+
+/// What is between the `<body>` tags of `html`, or all of it.
+fn body_of(html: &str) -> &str {
+    // Lowercasing ASCII keeps byte offsets unchanged.
+    let lowercase = html.to_ascii_lowercase();
+    let Some(start) = lowercase
+        .find("<body")
+        .and_then(|open| lowercase[open..].find('>').map(|end| open + end + 1))
+    else {
+        return html;
+    };
+    let end = lowercase
+        .rfind("</body")
+        .filter(|end| *end >= start)
+        .unwrap_or(html.len());
+    html[start..end].trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{body_of, combine, next_digest_at};
+    use crate::issue_template::RenderedIssue;
+    use chrono::{TimeZone, Utc};
+
+    fn issue(subject: &str) -> RenderedIssue {
+        RenderedIssue {
+            subject: subject.into(),
+            html: format!("<html><body><p>{}</p></body></html>", subject),
+            text: subject.into(),
+        }
+    }
+
+    #[test]
+    fn digests_go_out_at_midnight_utc() {
+        // A Wednesday.
+        let now = Utc.with_ymd_and_hms(2026, 10, 21, 9, 30, 0).unwrap();
+        assert_eq!(
+            next_digest_at("daily", now),
+            Some(Utc.with_ymd_and_hms(2026, 10, 22, 0, 0, 0).unwrap())
+        );
+        assert_eq!(
+            next_digest_at("weekly", now),
+            Some(Utc.with_ymd_and_hms(2026, 10, 26, 0, 0, 0).unwrap())
+        );
+        assert_eq!(next_digest_at("immediate", now), None);
+    }
+
+    #[test]
+    fn weekly_digests_of_a_monday_go_out_the_next_monday() {
+        let monday = Utc.with_ymd_and_hms(2026, 10, 26, 0, 0, 0).unwrap();
+        assert_eq!(
+            next_digest_at("weekly", monday),
+            Some(Utc.with_ymd_and_hms(2026, 11, 2, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn issues_are_combined_one_after_the_other() {
+        assert!(combine(&[]).is_none());
+        assert_eq!(combine(&[issue("First")]).unwrap().subject, "First");
+
+        let digest = combine(&[issue("First"), issue("Second")]).unwrap();
+        assert_eq!(digest.subject, "Your digest: 2 new issues");
+        assert!(digest.html.contains("<h2>First</h2>\n<p>First</p>"));
+        assert!(digest.html.contains("<h2>Second</h2>\n<p>Second</p>"));
+        assert_eq!(digest.html.matches("<body>").count(), 1);
+        assert_eq!(digest.text, "First\n\nFirst\n\n---\n\nSecond\n\nSecond");
+    }
+
+    #[test]
+    fn bodies_are_extracted_from_full_documents() {
+        assert_eq!(
+            body_of("<BODY class=\"x\">\n<p>Hi</p>\n</Body>"),
+            "<p>Hi</p>"
+        );
+        assert_eq!(body_of("<p>Hi</p>"), "<p>Hi</p>");
+    }
+}
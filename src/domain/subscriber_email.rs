@@ -1,14 +1,34 @@
 use validator::ValidateEmail;
 
+/// A valid email address, trimmed, with its domain lowercased and in
+/// punycode. The local part is kept as it was typed.
 #[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
     pub fn parse(s: String) -> Result<Self, String> {
-        if !s.validate_email() {
-            return Err(format!("{s} is not a valid subscriber email."));
+        let invalid = || format!("{s} is not a valid subscriber email.");
+        let (local_part, domain) = s.trim().rsplit_once('@').ok_or_else(invalid)?;
+        let domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+        let email = format!("{}@{}", local_part, domain);
+        if !email.validate_email() {
+            return Err(invalid());
         }
-        Ok(Self(s))
+        Ok(Self(email))
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map(|(local_part, _)| local_part)
+            .unwrap_or_default()
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
     }
 }
 
@@ -44,6 +64,26 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn whitespace_is_trimmed_and_the_domain_lowercased() {
+        let email = SubscriberEmail::parse("  Ursula.Le.Guin@Example.COM \n".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula.Le.Guin@example.com");
+        assert_eq!(email.local_part(), "Ursula.Le.Guin");
+        assert_eq!(email.domain(), "example.com");
+    }
+
+    #[test]
+    fn international_domains_are_converted_to_punycode() {
+        let email = SubscriberEmail::parse("ursula@Bücher.example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn invalid_domains_are_rejected() {
+        assert_err!(SubscriberEmail::parse("ursula@exa mple.com".to_string()));
+        assert_err!(SubscriberEmail::parse("ursula@".to_string()));
+    }
+
     fn valid_email_strategy() -> impl Strategy<Value = String> {
         any::<()>().prop_map(|_| SafeEmail().fake())
     }
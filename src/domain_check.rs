@@ -0,0 +1,499 @@
+//! Whether the domain of a new subscriber can receive email at all, so that
+//! typos like `gmial.com` are caught on the form instead of bouncing.
+
+use crate::domain::SubscriberEmail;
+use async_trait::async_trait;
+use hickory_resolver::TokioResolver;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Past this many domains the cache forgets the least recently checked ones.
+const MAX_CACHED_DOMAINS: usize = 10_000;
+
+/// Providers most people have their address at, checked for near misses.
+const COMMON_DOMAINS: &[&str] = &[
+    "aol.com",
+    "att.net",
+    "comcast.net",
+    "fastmail.com",
+    "free.fr",
+    "gmail.com",
+    "gmx.com",
+    "gmx.de",
+    "gmx.net",
+    "googlemail.com",
+    "hotmail.co.uk",
+    "hotmail.com",
+    "hotmail.fr",
+    "icloud.com",
+    "live.com",
+    "mac.com",
+    "mail.com",
+    "mail.ru",
+    "me.com",
+    "msn.com",
+    "orange.fr",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "qq.com",
+    "t-online.de",
+    "verizon.net",
+    "web.de",
+    "yahoo.co.uk",
+    "yahoo.com",
+    "yahoo.fr",
+    "yandex.ru",
+    "ymail.com",
+    "zoho.com",
+];
+
+#[derive(Debug)]
+pub struct LookupError(pub String);
+
+/// The DNS queries the check needs, so that tests can answer them.
+#[async_trait]
+pub trait DomainResolver: Send + Sync {
+    /// The mail exchangers of `domain`, empty when it has none.
+    async fn mx_hosts(&self, domain: &str) -> Result<Vec<String>, LookupError>;
+    /// Whether `domain` has an A or AAAA record.
+    async fn has_address(&self, domain: &str) -> Result<bool, LookupError>;
+}
+
+/// The resolvers of `/etc/resolv.conf`.
+pub struct SystemResolver(TokioResolver);
+
+impl SystemResolver {
+    pub fn from_system_conf() -> std::io::Result<Self> {
+        TokioResolver::builder_tokio()
+            .map(|builder| Self(builder.build()))
+            .map_err(std::io::Error::other)
+    }
+}
+
+#[async_trait]
+impl DomainResolver for SystemResolver {
+    async fn mx_hosts(&self, domain: &str) -> Result<Vec<String>, LookupError> {
+        // Fully qualified, so that no search domain is appended.
+        match self.0.mx_lookup(format!("{}.", domain)).await {
+            Ok(lookup) => Ok(lookup.iter().map(|mx| mx.exchange().to_utf8()).collect()),
+            Err(e) if e.is_nx_domain() || e.is_no_records_found() => Ok(Vec::new()),
+            Err(e) => Err(LookupError(e.to_string())),
+        }
+    }
+
+    async fn has_address(&self, domain: &str) -> Result<bool, LookupError> {
+        match self.0.lookup_ip(format!("{}.", domain)).await {
+            Ok(lookup) => Ok(lookup.iter().next().is_some()),
+            Err(e) if e.is_nx_domain() || e.is_no_records_found() => Ok(false),
+            Err(e) => Err(LookupError(e.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deliverability {
+    Deliverable,
+    Undeliverable,
+    /// The lookup failed or timed out.
+    Unknown,
+}
+
+/// Looks up the MX records of domains, falling back to A/AAAA as mail
+/// servers do, and remembers the answers for a while.
+#[derive(Clone)]
+pub struct DomainCheck {
+    resolver: Arc<dyn DomainResolver>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<LruCache<String, (Deliverability, Instant)>>>,
+    /// Reject near misses of common providers even when they resolve:
+    /// typo domains are often registered on purpose.
+    reject_suspected_typos: bool,
+}
+
+impl DomainCheck {
+    pub fn new(
+        resolver: Arc<dyn DomainResolver>,
+        timeout: Duration,
+        cache_ttl: Duration,
+        reject_suspected_typos: bool,
+    ) -> Self {
+        Self::with_cache_capacity(
+            resolver,
+            timeout,
+            cache_ttl,
+            reject_suspected_typos,
+            NonZeroUsize::new(MAX_CACHED_DOMAINS).unwrap(),
+        )
+    }
+
+    fn with_cache_capacity(
+        resolver: Arc<dyn DomainResolver>,
+        timeout: Duration,
+        cache_ttl: Duration,
+        reject_suspected_typos: bool,
+        capacity: NonZeroUsize,
+    ) -> Self {
+        Self {
+            resolver,
+            timeout,
+            cache_ttl,
+            cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            reject_suspected_typos,
+        }
+    }
+
+    /// Whether we take `email`, with the reason when we do not. A domain we
+    /// could not look up is given the benefit of the doubt.
+    pub async fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        let suggestion = suggest(email);
+        if self.reject_suspected_typos
+            && let Some(suggestion) = &suggestion
+        {
+            return Err(format!("Did you mean {}?", suggestion));
+        }
+        if self.deliverability(email.domain()).await != Deliverability::Undeliverable {
+            return Ok(());
+        }
+        let reason = format!("{} does not accept email.", email.domain());
+        Err(match suggestion {
+            Some(suggestion) => format!("{} Did you mean {}?", reason, suggestion),
+            None => reason,
+        })
+    }
+
+    #[tracing::instrument(name = "Check the domain of an email", skip(self))]
+    pub async fn deliverability(&self, domain: &str) -> Deliverability {
+        if let Some(cached) = self.cached(domain) {
+            return cached;
+        }
+        let deliverability = match tokio::time::timeout(self.timeout, self.lookup(domain)).await {
+            Ok(Ok(deliverability)) => deliverability,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to look up {}: {}", domain, e.0);
+                Deliverability::Unknown
+            }
+            Err(_) => {
+                tracing::warn!("Looking up {} timed out", domain);
+                Deliverability::Unknown
+            }
+        };
+        // Failures are not remembered: the next subscriber gets another try.
+        if deliverability != Deliverability::Unknown {
+            self.cache
+                .lock()
+                .unwrap()
+                .put(domain.to_string(), (deliverability, Instant::now()));
+        }
+        deliverability
+    }
+
+    fn cached(&self, domain: &str) -> Option<Deliverability> {
+        let mut cache = self.cache.lock().unwrap();
+        let (deliverability, checked_at) = cache.get(domain)?;
+        (checked_at.elapsed() < self.cache_ttl).then_some(*deliverability)
+    }
+
+    async fn lookup(&self, domain: &str) -> Result<Deliverability, LookupError> {
+        let mx_hosts = self.resolver.mx_hosts(domain).await?;
+        if !mx_hosts.is_empty() {
+            // A single `.` exchanger is a "null MX": the domain takes no mail
+            // (RFC 7505).
+            let null_mx = mx_hosts
+                .iter()
+                .all(|host| host.trim_end_matches('.').is_empty());
+            return Ok(if null_mx {
+                Deliverability::Undeliverable
+            } else {
+                Deliverability::Deliverable
+            });
+        }
+        Ok(if self.resolver.has_address(domain).await? {
+            Deliverability::Deliverable
+        } else {
+            Deliverability::Undeliverable
+        })
+    }
+}
+
+/// `email` at the common provider its domain is one or two typos away from.
+pub fn suggest(email: &SubscriberEmail) -> Option<String> {
+    let domain = email.domain();
+    if COMMON_DOMAINS.contains(&domain) {
+        return None;
+    }
+    // Short domains are close to many others: only allow one typo.
+    let max_distance = if domain.len() > 10 { 2 } else { 1 };
+    COMMON_DOMAINS
+        .iter()
+        .filter_map(|common| typo_distance(domain, common).map(|distance| (distance, common)))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, common)| format!("{}@{}", email.local_part(), common))
+}
+
+/// How many typos turn `domain` into `common`, `None` when they are different
+/// domains: `yahoo.gr` is Yahoo Greece, not a typo of `yahoo.fr`. A country
+/// code suffix is never a typo, only the labels before it are compared.
+fn typo_distance(domain: &str, common: &str) -> Option<usize> {
+    let (name, suffix) = split_suffix(domain);
+    let (common_name, common_suffix) = split_suffix(common);
+    if suffix == common_suffix {
+        Some(edit_distance(name, common_name))
+    } else if is_country_code(suffix) || is_country_code(common_suffix) {
+        None
+    } else {
+        // Generic suffixes can be mistyped: `gmail.con` is `gmail.com`.
+        Some(edit_distance(domain, common))
+    }
+}
+
+/// The labels of `domain` before its suffix, and the suffix: the last label,
+/// or the last two under `co` or `com` as in `yahoo.co.uk` or `qq.com.cn`.
+fn split_suffix(domain: &str) -> (&str, &str) {
+    let Some((rest, last)) = domain.rsplit_once('.') else {
+        return ("", domain);
+    };
+    match rest.rsplit_once('.') {
+        Some((name, "co" | "com")) => (name, &domain[name.len() + 1..]),
+        _ => (rest, last),
+    }
+}
+
+/// Whether `suffix` ends in a two-letter country code, e.g. `fr` or `co.uk`.
+fn is_country_code(suffix: &str) -> bool {
+    let country = suffix.rsplit('.').next().unwrap_or(suffix);
+    country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions
+/// and transpositions of adjacent characters each count as one typo.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Deliverability, DomainCheck, DomainResolver, LookupError, edit_distance, suggest};
+    use crate::domain::SubscriberEmail;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::num::NonZeroUsize;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Answers from a fixed table, counting the queries.
+    #[derive(Default)]
+    struct FakeResolver {
+        mx: HashMap<&'static str, Vec<&'static str>>,
+        addresses: Vec<&'static str>,
+        failing: bool,
+        slow: bool,
+        queries: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl DomainResolver for FakeResolver {
+        async fn mx_hosts(&self, domain: &str) -> Result<Vec<String>, LookupError> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            if self.slow {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            if self.failing {
+                return Err(LookupError("SERVFAIL".into()));
+            }
+            Ok(self
+                .mx
+                .get(domain)
+                .map(|hosts| hosts.iter().map(|host| host.to_string()).collect())
+                .unwrap_or_default())
+        }
+
+        async fn has_address(&self, domain: &str) -> Result<bool, LookupError> {
+            Ok(self.addresses.contains(&domain))
+        }
+    }
+
+    fn check(resolver: FakeResolver) -> (DomainCheck, Arc<FakeResolver>) {
+        let resolver = Arc::new(resolver);
+        let check = DomainCheck::new(
+            resolver.clone(),
+            Duration::from_millis(50),
+            Duration::from_secs(60),
+            false,
+        );
+        (check, resolver)
+    }
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn domains_without_mx_fall_back_to_addresses() {
+        let (check, _) = check(FakeResolver {
+            mx: HashMap::from([("example.com", vec!["mx.example.com."])]),
+            addresses: vec!["example.org"],
+            ..Default::default()
+        });
+        assert_eq!(
+            check.deliverability("example.com").await,
+            Deliverability::Deliverable
+        );
+        assert_eq!(
+            check.deliverability("example.org").await,
+            Deliverability::Deliverable
+        );
+        assert_eq!(
+            check.deliverability("example.invalid").await,
+            Deliverability::Undeliverable
+        );
+    }
+
+    #[tokio::test]
+    async fn a_null_mx_takes_no_mail() {
+        let (check, _) = check(FakeResolver {
+            mx: HashMap::from([("example.com", vec!["."])]),
+            addresses: vec!["example.com"],
+            ..Default::default()
+        });
+        assert_eq!(
+            check.deliverability("example.com").await,
+            Deliverability::Undeliverable
+        );
+    }
+
+    #[tokio::test]
+    async fn answers_are_cached_but_failures_are_not() {
+        let (check, resolver) = check(FakeResolver::default());
+        check.deliverability("example.invalid").await;
+        check.deliverability("example.invalid").await;
+        assert_eq!(resolver.queries.load(Ordering::SeqCst), 1);
+
+        let (check, resolver) = self::check(FakeResolver {
+            failing: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            check.deliverability("example.com").await,
+            Deliverability::Unknown
+        );
+        check.deliverability("example.com").await;
+        assert_eq!(resolver.queries.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn the_cache_forgets_the_least_recently_checked_domains() {
+        let resolver = Arc::new(FakeResolver::default());
+        let check = DomainCheck::with_cache_capacity(
+            resolver.clone(),
+            Duration::from_millis(50),
+            Duration::from_secs(60),
+            false,
+            NonZeroUsize::new(2).unwrap(),
+        );
+        for i in 0..100 {
+            check.deliverability(&format!("{}.invalid", i)).await;
+        }
+        assert_eq!(check.cache.lock().unwrap().len(), 2);
+
+        // The last two are still cached, the first is looked up again.
+        check.deliverability("99.invalid").await;
+        assert_eq!(resolver.queries.load(Ordering::SeqCst), 100);
+        check.deliverability("0.invalid").await;
+        assert_eq!(resolver.queries.load(Ordering::SeqCst), 101);
+    }
+
+    #[tokio::test]
+    async fn slow_lookups_give_up_and_let_the_address_through() {
+        let (check, _) = check(FakeResolver {
+            slow: true,
+            ..Default::default()
+        });
+        assert!(check.check(&email("ursula@example.com")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn undeliverable_typos_come_with_a_suggestion() {
+        let (check, _) = check(FakeResolver::default());
+        assert_eq!(
+            check.check(&email("ursula@gmial.com")).await,
+            Err("gmial.com does not accept email. Did you mean ursula@gmail.com?".into())
+        );
+    }
+
+    #[test]
+    fn common_typos_are_suggested_a_fix() {
+        let suggestion = |address: &str| suggest(&email(address));
+        assert_eq!(
+            suggestion("ursula@gmail.con").as_deref(),
+            Some("ursula@gmail.com")
+        );
+        assert_eq!(
+            suggestion("ursula@hotmial.com").as_deref(),
+            Some("ursula@hotmail.com")
+        );
+        assert_eq!(suggestion("ursula@gmail.com"), None);
+        assert_eq!(suggestion("ursula@mail.de"), None);
+        assert_eq!(suggestion("ursula@example.com"), None);
+    }
+
+    #[test]
+    fn other_countries_of_a_provider_are_not_typos() {
+        let suggestion = |address: &str| suggest(&email(address));
+        for address in [
+            "ursula@yahoo.co.jp",
+            "ursula@yahoo.co.in",
+            "ursula@yahoo.co.uj",
+            "ursula@hotmail.co.jp",
+            "ursula@yahoo.gr",
+            "ursula@yahoo.es",
+            "ursula@hotmail.fi",
+            "ursula@hotmail.it",
+            "ursula@gmx.at",
+        ] {
+            assert_eq!(suggestion(address), None, "{} was corrected", address);
+        }
+        assert_eq!(
+            suggestion("ursula@yahooo.co.uk").as_deref(),
+            Some("ursula@yahoo.co.uk")
+        );
+        assert_eq!(
+            suggestion("ursula@hotmial.fr").as_deref(),
+            Some("ursula@hotmail.fr")
+        );
+    }
+
+    #[test]
+    fn transpositions_count_as_one_typo() {
+        assert_eq!(edit_distance("gmial.com", "gmail.com"), 1);
+        assert_eq!(edit_distance("yaho.com", "yahoo.com"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Stops us from hammering an email provider that keeps failing.
+///
+/// After `failure_threshold` consecutive failures the breaker opens and the
+/// provider is skipped for `cooldown`. Once the cool-down is over the breaker
+/// is half-open: the first caller gets to send a single probe request while
+/// everyone else keeps skipping the provider. A success closes the breaker
+/// again, a failure re-opens it for another cool-down period. A probe whose
+/// outcome is never recorded only holds the breaker for one cool-down.
+///
+/// Clones share the same state.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    consecutive_failures: u32,
+    /// `None` while the breaker is closed.
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Whether a request may be sent to the provider right now. When it is
+    /// the probe of a half-open breaker, the caller must record its outcome.
+    pub fn allows_request(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            None => true,
+            Some(open_until) => {
+                let now = Instant::now();
+                if now < open_until {
+                    return false;
+                }
+                // Take the probe: the breaker stays open for everyone else
+                // until its outcome is recorded.
+                state.open_until = Some(now + self.cooldown);
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.consecutive_failures >= self.failure_threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitBreaker;
+    use std::time::Duration;
+
+    #[test]
+    fn a_new_breaker_allows_requests() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        assert!(breaker.allows_request());
+    }
+
+    #[test]
+    fn the_breaker_opens_after_the_failure_threshold() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.allows_request());
+        breaker.record_failure();
+        assert!(!breaker.allows_request());
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(breaker.allows_request());
+    }
+
+    #[test]
+    fn a_trial_request_is_allowed_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        assert!(!breaker.allows_request());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allows_request());
+        // The trial request fails: the breaker opens again straight away.
+        breaker.record_failure();
+        assert!(!breaker.allows_request());
+    }
+
+    #[test]
+    fn only_one_probe_is_let_through_while_half_open() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allows_request());
+        assert!(!breaker.allows_request());
+        assert!(!breaker.clone().allows_request());
+        // The probe succeeds: everyone gets through again.
+        breaker.record_success();
+        assert!(breaker.allows_request());
+        assert!(breaker.allows_request());
+    }
+
+    #[test]
+    fn an_unanswered_probe_only_holds_the_breaker_for_a_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allows_request());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allows_request());
+    }
+
+    #[test]
+    fn clones_share_the_same_state() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        let clone = breaker.clone();
+        clone.record_failure();
+        assert!(!breaker.allows_request());
+    }
+}
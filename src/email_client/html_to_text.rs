@@ -0,0 +1,568 @@
+//! A small HTML → plain-text converter used to build the `TextBody` of an
+//! email when the caller only supplies HTML.
+//!
+//! It is not a general purpose HTML renderer: it understands the subset of
+//! markup that shows up in newsletters (paragraphs, headings, lists, links,
+//! line breaks, layout tables) and produces something readable in a plain
+//! text mail client. Links are rendered as numbered footnotes.
+
+/// Convert an HTML document (or fragment) into a readable plain-text
+/// alternative.
+pub fn html_to_text(html: &str) -> String {
+    let mut renderer = Renderer::default();
+    for token in Tokenizer::new(html) {
+        renderer.handle(token);
+    }
+    renderer.finish()
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    End {
+        name: String,
+    },
+}
+
+struct Tokenizer<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    /// Skip forward until right after `needle`, or to the end of the input.
+    fn skip_past(&mut self, needle: &str) {
+        match self.rest().find(needle) {
+            Some(offset) => self.position += offset + needle.len(),
+            None => self.position = self.input.len(),
+        }
+    }
+
+    /// Skip the content of a raw-text element (`<script>`, `<style>`), which
+    /// may legitimately contain `<` characters.
+    fn skip_raw_text(&mut self, name: &str) {
+        let closing = format!("</{name}");
+        match self.rest().to_ascii_lowercase().find(&closing) {
+            Some(offset) => self.position += offset,
+            None => self.position = self.input.len(),
+        }
+    }
+
+    fn read_tag(&mut self) -> Option<Token> {
+        // `self.rest()` starts with `<`
+        let rest = self.rest();
+        let mut end = None;
+        let mut quote = None;
+        for (i, c) in rest.char_indices().skip(1) {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = end.unwrap_or(rest.len());
+        let inner = &rest[1..end];
+        self.position += (end + 1).min(rest.len());
+
+        let (is_end, inner) = match inner.strip_prefix('/') {
+            Some(inner) => (true, inner),
+            None => (false, inner),
+        };
+        let inner = inner.trim_end_matches('/');
+        let name_len = inner
+            .find(|c: char| c.is_whitespace())
+            .unwrap_or(inner.len());
+        let name = inner[..name_len].to_ascii_lowercase();
+        if name.is_empty() {
+            return None;
+        }
+        if is_end {
+            return Some(Token::End { name });
+        }
+        let attributes = parse_attributes(&inner[name_len..]);
+        if name == "script" || name == "style" {
+            self.skip_raw_text(&name);
+        }
+        Some(Token::Start { name, attributes })
+    }
+}
+
+impl Iterator for Tokenizer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return None;
+            }
+            if rest.starts_with("<!--") {
+                self.skip_past("-->");
+            } else if rest.starts_with("<!") || rest.starts_with("<?") {
+                self.skip_past(">");
+            } else if rest.starts_with('<')
+                && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/')
+            {
+                if let Some(token) = self.read_tag() {
+                    return Some(token);
+                }
+            } else {
+                // A lone `<` that does not open a tag is just text.
+                let end = rest
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| c == '<')
+                    .map(|(i, _)| i)
+                    .unwrap_or(rest.len());
+                self.position += end;
+                return Some(Token::Text(decode_entities(&rest[..end])));
+            }
+        }
+    }
+}
+
+fn parse_attributes(mut input: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    loop {
+        input = input.trim_start();
+        if input.is_empty() {
+            return attributes;
+        }
+        let name_len = input
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(input.len());
+        let name = input[..name_len].to_ascii_lowercase();
+        input = input[name_len..].trim_start();
+        let value = match input.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                match after.chars().next() {
+                    Some(q @ ('"' | '\'')) => {
+                        let close = after[1..].find(q).map(|i| i + 1).unwrap_or(after.len());
+                        input = after.get(close + 1..).unwrap_or("");
+                        &after[1..close]
+                    }
+                    _ => {
+                        let len = after.find(char::is_whitespace).unwrap_or(after.len());
+                        input = &after[len..];
+                        &after[..len]
+                    }
+                }
+            }
+            None => "",
+        };
+        if !name.is_empty() {
+            attributes.push((name, decode_entities(value)));
+        }
+    }
+}
+
+fn decode_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+        match decoded {
+            Some((c, end)) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    let c = match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bull" => '•',
+        "middot" => '·',
+        "euro" => '€',
+        _ => return None,
+    };
+    Some(c)
+}
+
+enum List {
+    Unordered,
+    Ordered(usize),
+}
+
+#[derive(Default)]
+struct Renderer {
+    output: String,
+    /// Newlines owed before the next piece of text.
+    pending_breaks: usize,
+    /// Whether the next word must be separated from the previous one.
+    needs_space: bool,
+    lists: Vec<List>,
+    links: Vec<String>,
+    /// `href` of the currently open `<a>` and where its text starts.
+    open_link: Option<(String, usize)>,
+    /// Underline character of the currently open heading and where it starts.
+    open_heading: Option<(char, usize)>,
+    /// Depth inside elements whose content is not rendered (`<head>`, ...).
+    hidden_depth: usize,
+    preformatted_depth: usize,
+}
+
+impl Renderer {
+    fn handle(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => self.text(&text),
+            Token::Start { name, attributes } => self.start(&name, &attributes),
+            Token::End { name } => self.end(&name),
+        }
+    }
+
+    fn start(&mut self, name: &str, attributes: &[(String, String)]) {
+        if matches!(name, "head" | "title" | "script" | "style") {
+            self.hidden_depth += 1;
+            return;
+        }
+        if self.hidden_depth > 0 {
+            return;
+        }
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.trim())
+        };
+        match name {
+            "br" => self.line_break(),
+            "p" | "blockquote" | "table" => self.block_break(2),
+            "div" | "tr" | "section" | "article" | "header" | "footer" | "center" => {
+                self.block_break(1)
+            }
+            "td" | "th" => self.needs_space = true,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block_break(2);
+                self.flush_breaks();
+                let underline = if name == "h1" { '=' } else { '-' };
+                self.open_heading = Some((underline, self.output.len()));
+            }
+            "hr" => {
+                self.block_break(2);
+                self.write_prefix(&"-".repeat(40));
+                self.block_break(2);
+            }
+            "ul" | "ol" => {
+                self.block_break(if self.lists.is_empty() { 2 } else { 1 });
+                self.lists.push(if name == "ul" {
+                    List::Unordered
+                } else {
+                    List::Ordered(0)
+                });
+            }
+            "li" => {
+                self.block_break(1);
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(List::Ordered(n)) => {
+                        *n += 1;
+                        format!("{n}. ")
+                    }
+                    _ => "* ".to_string(),
+                };
+                self.write_prefix(&format!("{indent}{marker}"));
+            }
+            "pre" => {
+                self.block_break(2);
+                self.preformatted_depth += 1;
+            }
+            "a" => {
+                let href = attribute("href").unwrap_or("").to_string();
+                self.open_link = Some((href, self.output.len()));
+            }
+            "img" => {
+                if let Some(alt) = attribute("alt").filter(|alt| !alt.is_empty()) {
+                    self.text(alt);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        if matches!(name, "head" | "title" | "script" | "style") {
+            self.hidden_depth = self.hidden_depth.saturating_sub(1);
+            return;
+        }
+        if self.hidden_depth > 0 {
+            return;
+        }
+        match name {
+            "p" | "blockquote" | "table" => self.block_break(2),
+            "div" | "tr" | "section" | "article" | "header" | "footer" | "center" | "li" => {
+                self.block_break(1)
+            }
+            "td" | "th" => self.needs_space = true,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if let Some((underline, start)) = self.open_heading.take() {
+                    let width = self.output[start..].trim().chars().count();
+                    if width > 0 {
+                        self.output.push('\n');
+                        self.output.extend(std::iter::repeat_n(underline, width));
+                    }
+                }
+                self.block_break(2);
+            }
+            "ul" | "ol" => {
+                self.lists.pop();
+                self.block_break(if self.lists.is_empty() { 2 } else { 1 });
+            }
+            "pre" => {
+                self.preformatted_depth = self.preformatted_depth.saturating_sub(1);
+                self.block_break(2);
+            }
+            "a" => self.close_link(),
+            _ => {}
+        }
+    }
+
+    fn close_link(&mut self) {
+        let Some((href, start)) = self.open_link.take() else {
+            return;
+        };
+        if href.is_empty() || href.starts_with('#') || href.starts_with("javascript:") {
+            return;
+        }
+        let text = self.output.get(start..).unwrap_or("").trim();
+        let href_without_scheme = href.strip_prefix("mailto:").unwrap_or(&href);
+        if text == href || text == href_without_scheme {
+            // The link is already readable as it is.
+            return;
+        }
+        let index = match self.links.iter().position(|link| *link == href) {
+            Some(i) => i + 1,
+            None => {
+                self.links.push(href);
+                self.links.len()
+            }
+        };
+        if text.is_empty() {
+            self.word(&format!("[{index}]"));
+        } else {
+            self.output.push_str(&format!(" [{index}]"));
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.hidden_depth > 0 {
+            return;
+        }
+        if self.preformatted_depth > 0 {
+            self.flush_breaks();
+            self.output.push_str(text);
+            return;
+        }
+        if text.starts_with(char::is_whitespace) {
+            self.needs_space = true;
+        }
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                self.needs_space = true;
+            }
+            self.word(word);
+        }
+        if text.ends_with(char::is_whitespace) {
+            self.needs_space = true;
+        }
+    }
+
+    fn word(&mut self, word: &str) {
+        if self.pending_breaks > 0 {
+            self.flush_breaks();
+        } else if self.needs_space && !self.output.is_empty() && !self.output.ends_with([' ', '\n'])
+        {
+            self.output.push(' ');
+        }
+        self.needs_space = false;
+        // `&nbsp;` survives `split_whitespace`, render it as a plain space.
+        self.output.push_str(&word.replace('\u{a0}', " "));
+    }
+
+    /// Start a new line with `prefix`, e.g. a list marker.
+    fn write_prefix(&mut self, prefix: &str) {
+        self.flush_breaks();
+        self.output.push_str(prefix);
+        self.needs_space = false;
+    }
+
+    fn line_break(&mut self) {
+        self.output.push('\n');
+        self.needs_space = false;
+    }
+
+    fn block_break(&mut self, breaks: usize) {
+        self.pending_breaks = self.pending_breaks.max(breaks);
+        self.needs_space = false;
+    }
+
+    fn flush_breaks(&mut self) {
+        if !self.output.is_empty() {
+            let existing = self.output.len() - self.output.trim_end_matches('\n').len();
+            for _ in existing..self.pending_breaks {
+                self.output.push('\n');
+            }
+        }
+        self.pending_breaks = 0;
+    }
+
+    fn finish(mut self) -> String {
+        self.close_link();
+        let mut text = String::with_capacity(self.output.len());
+        let mut blank_lines = 0;
+        for line in self.output.lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                blank_lines += 1;
+                continue;
+            }
+            if !text.is_empty() {
+                text.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+            }
+            blank_lines = 0;
+            text.push_str(line);
+        }
+        if !self.links.is_empty() {
+            text.push_str("\n\n");
+            for (i, link) in self.links.iter().enumerate() {
+                if i > 0 {
+                    text.push('\n');
+                }
+                text.push_str(&format!("[{}] {}", i + 1, link));
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::html_to_text;
+
+    /// Each snapshot is a pair of files under `snapshots/`: the HTML input
+    /// and the plain text we expect to produce from it.
+    macro_rules! assert_snapshot {
+        ($name:literal) => {
+            let html = include_str!(concat!("snapshots/", $name, ".html"));
+            let expected = include_str!(concat!("snapshots/", $name, ".txt"));
+            assert_eq!(html_to_text(html), expected.trim_end());
+        };
+    }
+
+    #[test]
+    fn weekly_digest_snapshot() {
+        assert_snapshot!("weekly_digest");
+    }
+
+    #[test]
+    fn product_announcement_snapshot() {
+        assert_snapshot!("product_announcement");
+    }
+
+    #[test]
+    fn confirmation_email_snapshot() {
+        assert_snapshot!("confirmation_email");
+    }
+
+    #[test]
+    fn links_become_numbered_footnotes() {
+        let text = html_to_text(
+            r#"<p>Read <a href="https://a.example">this</a> and <a href="https://b.example">that</a>, then <a href="https://a.example">this again</a>.</p>"#,
+        );
+        assert_eq!(
+            text,
+            "Read this [1] and that [2], then this again [1].\n\n\
+[1] https://a.example\n[2] https://b.example"
+        );
+    }
+
+    #[test]
+    fn links_whose_text_is_the_url_are_not_footnoted() {
+        let text = html_to_text(r#"Visit <a href="https://example.com">https://example.com</a>"#);
+        assert_eq!(text, "Visit https://example.com");
+    }
+
+    #[test]
+    fn nested_lists_are_indented() {
+        let text = html_to_text("<ol><li>One<ul><li>Nested</li></ul></li><li>Two</li></ol>");
+        assert_eq!(text, "1. One\n  * Nested\n2. Two");
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        let text = html_to_text("Fish &amp; chips &lt;3 &#8212; &#x263A; &unknown;");
+        assert_eq!(text, "Fish & chips <3 — ☺ &unknown;");
+    }
+
+    #[test]
+    fn head_script_and_style_are_ignored() {
+        let text = html_to_text(
+            "<html><head><title>Hidden</title><style>p { color: red; }</style></head>\
+<body><script>if (a < b) {}</script><p>Visible</p></body></html>",
+        );
+        assert_eq!(text, "Visible");
+    }
+
+    #[test]
+    fn non_ascii_text_and_stray_angle_brackets_are_kept() {
+        assert_eq!(html_to_text("été < hiver"), "été < hiver");
+    }
+
+    #[test]
+    fn plain_text_is_returned_unchanged() {
+        assert_eq!(html_to_text("Just some text."), "Just some text.");
+    }
+}
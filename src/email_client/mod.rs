@@ -0,0 +1,602 @@
+mod circuit_breaker;
+mod html_to_text;
+mod rate_limiter;
+
+pub use circuit_breaker::CircuitBreaker;
+pub use html_to_text::html_to_text;
+pub use rate_limiter::{RateLimiter, RateLimiterMetrics};
+
+use crate::domain::SubscriberEmail;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+
+/// A Postmark-compatible HTTP API we can deliver emails through.
+#[derive(Debug, Clone)]
+pub struct EmailProvider {
+    name: String,
+    base_url: url::Url,
+    authorization_token: SecretString,
+    circuit_breaker: CircuitBreaker,
+}
+
+impl EmailProvider {
+    pub fn new(
+        name: String,
+        base_url: url::Url,
+        authorization_token: SecretString,
+        circuit_breaker: CircuitBreaker,
+    ) -> Self {
+        Self {
+            name,
+            base_url,
+            authorization_token,
+            circuit_breaker,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SendEmailError {
+    /// The provider refused the message itself (4xx): sending it to another
+    /// provider would not help.
+    Rejected {
+        provider: String,
+        source: reqwest::Error,
+    },
+    /// Every provider either failed or was skipped because its circuit
+    /// breaker was open.
+    AllProvidersFailed(Vec<(String, reqwest::Error)>),
+}
+
+impl std::fmt::Display for SendEmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendEmailError::Rejected { provider, source } => {
+                write!(f, "The email was rejected by {}: {}", provider, source)
+            }
+            SendEmailError::AllProvidersFailed(failures) if failures.is_empty() => {
+                write!(f, "No email provider is currently available.")
+            }
+            SendEmailError::AllProvidersFailed(failures) => {
+                write!(f, "Every email provider failed:")?;
+                for (provider, error) in failures {
+                    write!(f, " [{}] {};", provider, error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SendEmailError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SendEmailError::Rejected { source, .. } => Some(source),
+            SendEmailError::AllProvidersFailed(failures) => failures
+                .last()
+                .map(|(_, e)| e as &(dyn std::error::Error + 'static)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailClient {
+    http_client: Client,
+    /// Tried in order: we only move on to the next provider when the
+    /// previous one is unreachable or returns a 5xx.
+    providers: Vec<EmailProvider>,
+    sender: SubscriberEmail,
+    rate_limiter: RateLimiter,
+}
+impl EmailClient {
+    pub fn new(
+        providers: Vec<EmailProvider>,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+        rate_limiter: RateLimiter,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            providers,
+            sender,
+            rate_limiter,
+        }
+    }
+
+    /// How long sends have been held back by the rate limiter so far.
+    pub fn rate_limiter_metrics(&self) -> RateLimiterMetrics {
+        self.rate_limiter.metrics()
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), SendEmailError> {
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+        };
+        let permit = self.rate_limiter.acquire().await;
+        if !permit.waited().is_zero() {
+            tracing::debug!(
+                waited_ms = permit.waited().as_millis() as u64,
+                "Outbound email was throttled by the rate limiter"
+            );
+        }
+        let mut failures = Vec::new();
+        for provider in &self.providers {
+            if !provider.circuit_breaker.allows_request() {
+                tracing::warn!(
+                    provider = %provider.name,
+                    "Skipping email provider, its circuit breaker is open"
+                );
+                continue;
+            }
+            match self.send_with(provider, &request_body).await {
+                Ok(()) => {
+                    provider.circuit_breaker.record_success();
+                    tracing::info!(provider = %provider.name, "Email delivered");
+                    return Ok(());
+                }
+                Err(e) if is_transient(&e) => {
+                    provider.circuit_breaker.record_failure();
+                    tracing::warn!(
+                        provider = %provider.name,
+                        error.cause_chain = ?e,
+                        "Email provider failed, falling over to the next one"
+                    );
+                    failures.push((provider.name.clone(), e));
+                }
+                Err(e) => {
+                    // The provider answered: it is up, whatever it made of
+                    // the email.
+                    provider.circuit_breaker.record_success();
+                    return Err(SendEmailError::Rejected {
+                        provider: provider.name.clone(),
+                        source: e,
+                    });
+                }
+            }
+        }
+        Err(SendEmailError::AllProvidersFailed(failures))
+    }
+
+    async fn send_with(
+        &self,
+        provider: &EmailProvider,
+        request_body: &SendEmailRequest<'_>,
+    ) -> Result<(), reqwest::Error> {
+        let url = provider.base_url.join("email").expect("Failed to join URL");
+        let _builder = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                provider.authorization_token.expose_secret(),
+            )
+            .json(request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Send an email for which only the HTML body is available: the plain-text
+    /// alternative is generated from it with [`html_to_text`].
+    pub async fn send_html_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+    ) -> Result<(), SendEmailError> {
+        let text_content = html_to_text(html_content);
+        self.send_email(recipient, subject, html_content, &text_content)
+            .await
+    }
+}
+
+/// Transport errors, timeouts, 5xx and 429 are worth retrying elsewhere.
+fn is_transient(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        }
+        None => true,
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+// Lifetime parameters always start with an apostrophe, `'`
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+}
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        CircuitBreaker, EmailClient, EmailProvider, RateLimiter, SendEmailError,
+    };
+    use claims::assert_err;
+    use claims::assert_ok;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::SecretString;
+    use wiremock::matchers::any;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn provider(name: &str, mock_server: &MockServer) -> EmailProvider {
+        EmailProvider::new(
+            name.into(),
+            mock_server.uri().parse().unwrap(),
+            SecretString::new(Faker.fake::<String>().into()),
+            CircuitBreaker::new(3, std::time::Duration::from_secs(60)),
+        )
+    }
+
+    fn email_client(mock_server: &MockServer) -> EmailClient {
+        EmailClient::new(
+            vec![provider("primary", mock_server)],
+            email(),
+            std::time::Duration::from_millis(200),
+            RateLimiter::new(0, 10),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server);
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let _ = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+    }
+
+    struct SendEmailBodyMatcher;
+    use wiremock::Request;
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Try to parse the body as a JSON value
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                // Check that all the mandatory fields are populated
+                // without inspecting the field values
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                // If parsing failed, do not match the request
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_ok!(outcome);
+    }
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server);
+        Mock::given(any())
+            // Not a 200 anymore!
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server);
+        let response = ResponseTemplate::new(200)
+            // 3 minutes!
+            .set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_html_email_generates_the_text_body() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_html_email(email(), &subject(), "<p>Hello <b>world</b></p>")
+            .await;
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["TextBody"], "Hello world");
+    }
+
+    #[tokio::test]
+    async fn clones_of_the_client_share_the_rate_limiter() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            vec![provider("primary", &mock_server)],
+            email(),
+            std::time::Duration::from_millis(200),
+            RateLimiter::new(1, 1),
+        );
+        let clone = email_client.clone();
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let (subject, content) = (subject(), content());
+        let first = email_client.send_email(email(), &subject, &content, &content);
+        let second = clone.send_email(email(), &subject, &content, &content);
+        let (first, second) = tokio::join!(first, second);
+        // Assert
+        assert_ok!(first);
+        assert_ok!(second);
+        let metrics = email_client.rate_limiter_metrics();
+        assert_eq!(metrics.permits_granted, 2);
+        assert!(metrics.max_wait >= std::time::Duration::from_millis(800));
+    }
+
+    fn failover_client(primary: &MockServer, secondary: &MockServer) -> EmailClient {
+        EmailClient::new(
+            vec![
+                provider("primary", primary),
+                provider("secondary", secondary),
+            ],
+            email(),
+            std::time::Duration::from_millis(200),
+            RateLimiter::new(0, 10),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_over_to_the_next_provider_on_a_500() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = failover_client(&primary, &secondary);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&secondary)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_over_to_the_next_provider_on_a_timeout() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = failover_client(&primary, &secondary);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&secondary)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_fall_over_when_the_email_is_rejected() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = failover_client(&primary, &secondary);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&secondary)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(matches!(outcome, Err(SendEmailError::Rejected { .. })));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_every_provider_fails() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = failover_client(&primary, &secondary);
+        for server in [&primary, &secondary] {
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(503))
+                .expect(1)
+                .mount(server)
+                .await;
+        }
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        match outcome {
+            Err(SendEmailError::AllProvidersFailed(failures)) => assert_eq!(failures.len(), 2),
+            other => panic!("Unexpected outcome: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_provider_with_an_open_circuit_breaker_is_skipped() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = EmailClient::new(
+            vec![
+                EmailProvider::new(
+                    "primary".into(),
+                    primary.uri().parse().unwrap(),
+                    SecretString::new(Faker.fake::<String>().into()),
+                    CircuitBreaker::new(1, std::time::Duration::from_secs(60)),
+                ),
+                provider("secondary", &secondary),
+            ],
+            email(),
+            std::time::Duration::from_millis(200),
+            RateLimiter::new(0, 10),
+        );
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            // Only the first email reaches the primary provider.
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&secondary)
+            .await;
+        // Act
+        let first = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        let second = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_ok!(first);
+        assert_ok!(second);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_probe_closes_the_circuit_breaker() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let circuit_breaker = CircuitBreaker::new(1, std::time::Duration::from_millis(20));
+        let email_client = EmailClient::new(
+            vec![EmailProvider::new(
+                "primary".into(),
+                mock_server.uri().parse().unwrap(),
+                SecretString::new(Faker.fake::<String>().into()),
+                circuit_breaker.clone(),
+            )],
+            email(),
+            std::time::Duration::from_millis(200),
+            RateLimiter::new(0, 10),
+        );
+        circuit_breaker.record_failure();
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert!(matches!(outcome, Err(SendEmailError::Rejected { .. })));
+        assert!(circuit_breaker.allows_request());
+    }
+}
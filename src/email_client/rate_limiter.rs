@@ -0,0 +1,204 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+
+/// Throttles outbound emails: a token bucket caps how many messages we send
+/// per second, a semaphore caps how many requests are in flight at once.
+///
+/// Cloning a `RateLimiter` is cheap and every clone shares the same budget,
+/// so the request handlers and any background sender holding a clone of the
+/// `EmailClient` are throttled together.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    bucket: Option<Mutex<TokenBucket>>,
+    in_flight: Semaphore,
+    permits_granted: AtomicU64,
+    total_wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(messages_per_second: u32) -> Self {
+        let capacity = f64::from(messages_per_second);
+        Self {
+            tokens: capacity,
+            capacity,
+            refill_per_second: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token if one is available, otherwise return how long we have
+    /// to wait before the next one is.
+    fn try_take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.refill_per_second,
+            ))
+        }
+    }
+}
+
+/// Held for the duration of a send: dropping it frees the in-flight slot.
+pub struct RateLimitPermit<'a> {
+    _in_flight: SemaphorePermit<'a>,
+    waited: Duration,
+}
+
+impl RateLimitPermit<'_> {
+    /// How long we were throttled before being allowed to send.
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+}
+
+/// A snapshot of how much the limiter has been slowing us down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimiterMetrics {
+    pub permits_granted: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
+impl RateLimiter {
+    /// `messages_per_second` set to `0` disables the token bucket, leaving
+    /// only the cap on concurrent requests.
+    pub fn new(messages_per_second: u32, max_concurrent_requests: usize) -> Self {
+        let bucket =
+            (messages_per_second > 0).then(|| Mutex::new(TokenBucket::new(messages_per_second)));
+        Self {
+            inner: Arc::new(Inner {
+                bucket,
+                in_flight: Semaphore::new(max_concurrent_requests.max(1)),
+                permits_granted: AtomicU64::new(0),
+                total_wait_micros: AtomicU64::new(0),
+                max_wait_micros: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Wait until we are allowed to send one more message.
+    pub async fn acquire(&self) -> RateLimitPermit<'_> {
+        let started = Instant::now();
+        let in_flight = self
+            .inner
+            .in_flight
+            .acquire()
+            .await
+            .expect("The rate limiter semaphore is never closed.");
+        if let Some(bucket) = &self.inner.bucket {
+            loop {
+                let outcome = bucket.lock().await.try_take();
+                match outcome {
+                    Ok(()) => break,
+                    Err(wait) => tokio::time::sleep(wait).await,
+                }
+            }
+        }
+        let waited = started.elapsed();
+        self.record(waited);
+        RateLimitPermit {
+            _in_flight: in_flight,
+            waited,
+        }
+    }
+
+    pub fn metrics(&self) -> RateLimiterMetrics {
+        RateLimiterMetrics {
+            permits_granted: self.inner.permits_granted.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(self.inner.total_wait_micros.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(self.inner.max_wait_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn record(&self, waited: Duration) {
+        let micros = u64::try_from(waited.as_micros()).unwrap_or(u64::MAX);
+        self.inner.permits_granted.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .total_wait_micros
+            .fetch_add(micros, Ordering::Relaxed);
+        self.inner
+            .max_wait_micros
+            .fetch_max(micros, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn a_full_bucket_does_not_make_us_wait() {
+        let limiter = RateLimiter::new(5, 5);
+        let started = Instant::now();
+        for _ in 0..5 {
+            let _permit = limiter.acquire().await;
+        }
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn an_empty_bucket_throttles_to_the_configured_rate() {
+        let limiter = RateLimiter::new(10, 10);
+        for _ in 0..10 {
+            let _permit = limiter.acquire().await;
+        }
+        let permit = limiter.acquire().await;
+        // One token is refilled every 100ms.
+        assert!(permit.waited() >= Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_are_capped() {
+        let limiter = RateLimiter::new(0, 1);
+        let first = limiter.acquire().await;
+        let second = tokio::time::timeout(Duration::from_millis(50), limiter.acquire()).await;
+        assert!(second.is_err());
+        drop(first);
+        let third = tokio::time::timeout(Duration::from_millis(50), limiter.acquire()).await;
+        assert!(third.is_ok());
+    }
+
+    #[tokio::test]
+    async fn clones_share_the_same_budget() {
+        let limiter = RateLimiter::new(1, 10);
+        let clone = limiter.clone();
+        drop(limiter.acquire().await);
+        let permit = clone.acquire().await;
+        assert!(permit.waited() >= Duration::from_millis(800));
+    }
+
+    #[tokio::test]
+    async fn metrics_record_time_spent_waiting() {
+        let limiter = RateLimiter::new(20, 20);
+        for _ in 0..21 {
+            drop(limiter.acquire().await);
+        }
+        let metrics = limiter.metrics();
+        assert_eq!(metrics.permits_granted, 21);
+        assert!(metrics.max_wait >= Duration::from_millis(30));
+        assert!(metrics.total_wait >= metrics.max_wait);
+    }
+}
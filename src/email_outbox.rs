@@ -0,0 +1,316 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, SendEmailError, html_to_text};
+use crate::entity::email_outbox;
+use crate::suppression::{is_suppressed, is_undeliverable};
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::{LockBehavior, LockType};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use std::time::Duration;
+use tracing::{Span, field::display};
+
+/// After this many failed attempts an email is given up on.
+const MAX_ATTEMPTS: i32 = 10;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Store an email in the outbox as part of `txn`: it is only delivered if the
+/// transaction commits, and it is delivered even if we crash right after.
+#[tracing::instrument(
+    name = "Enqueue an email in the outbox",
+    skip(txn, html_body, text_body)
+)]
+pub async fn enqueue_email(
+    txn: &DatabaseTransaction,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_body: &str,
+    text_body: Option<&str>,
+) -> Result<i32, DbErr> {
+    insert_email(txn, recipient, subject, html_body, text_body, false).await
+}
+
+/// Like [`enqueue_email`], for an email the recipient asked for, e.g. the
+/// answer to a privacy request. It is sent even to addresses that
+/// unsubscribed from everything, only bounces and complaints stop it.
+#[tracing::instrument(
+    name = "Enqueue a transactional email in the outbox",
+    skip(txn, html_body, text_body)
+)]
+pub async fn enqueue_transactional_email(
+    txn: &DatabaseTransaction,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_body: &str,
+    text_body: Option<&str>,
+) -> Result<i32, DbErr> {
+    insert_email(txn, recipient, subject, html_body, text_body, true).await
+}
+
+/// An email for [`enqueue_emails`].
+pub struct NewEmail {
+    pub recipient: SubscriberEmail,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: Option<String>,
+}
+
+/// Like [`enqueue_email`] for many emails, stored with a single statement.
+#[tracing::instrument(name = "Enqueue emails in the outbox", skip_all, fields(count = emails.len()))]
+pub async fn enqueue_emails(txn: &DatabaseTransaction, emails: Vec<NewEmail>) -> Result<(), DbErr> {
+    if emails.is_empty() {
+        return Ok(());
+    }
+    let emails = emails.iter().map(|email| {
+        pending_email(
+            &email.recipient,
+            &email.subject,
+            &email.html_body,
+            email.text_body.as_deref(),
+            false,
+        )
+    });
+    email_outbox::Entity::insert_many(emails)
+        .exec(txn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
+
+async fn insert_email(
+    txn: &DatabaseTransaction,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_body: &str,
+    text_body: Option<&str>,
+    transactional: bool,
+) -> Result<i32, DbErr> {
+    let email = pending_email(recipient, subject, html_body, text_body, transactional);
+
+    let result = email.insert(txn).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(result.id)
+}
+
+fn pending_email(
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_body: &str,
+    text_body: Option<&str>,
+    transactional: bool,
+) -> email_outbox::ActiveModel {
+    let now = Utc::now();
+    email_outbox::ActiveModel {
+        recipient: Set(recipient.as_ref().to_string()),
+        subject: Set(subject.to_string()),
+        html_body: Set(html_body.to_string()),
+        text_body: Set(text_body.map(str::to_string)),
+        status: Set("pending".to_string()),
+        attempts: Set(0),
+        created_at: Set(now),
+        next_attempt_at: Set(now),
+        transactional: Set(transactional),
+        ..Default::default()
+    }
+}
+
+/// Deliver the oldest email that is due, if any.
+#[tracing::instrument(
+    skip_all,
+    fields(outbox_id = tracing::field::Empty, recipient = tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    db: &DatabaseConnection,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, DbErr> {
+    let txn = db.begin().await?;
+    let Some(task) = dequeue_task(&txn).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("outbox_id", display(task.id))
+        .record("recipient", display(&task.recipient));
+
+    let suppressed = if task.transactional {
+        is_undeliverable(&txn, &task.recipient).await?
+    } else {
+        is_suppressed(&txn, &task.recipient).await?
+    };
+    if suppressed {
+        tracing::info!("Skipping an outbox email, the recipient is suppressed");
+        mark_as_suppressed(&txn, task).await?;
+        txn.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    match deliver(email_client, &task).await {
+        Ok(()) => mark_as_sent(&txn, task).await?,
+        Err(e) => {
+            tracing::error!(error.message = %e.message, "Failed to deliver an outbox email");
+            record_failure(&txn, task, e).await?
+        }
+    }
+    txn.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Keep delivering emails from the outbox, polling for new ones when it is
+/// empty.
+pub async fn run_dispatcher_until_stopped(
+    db: DatabaseConnection,
+    email_client: EmailClient,
+    poll_interval: Duration,
+) {
+    loop {
+        match try_execute_task(&db, &email_client).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(poll_interval).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(txn: &DatabaseTransaction) -> Result<Option<email_outbox::Model>, DbErr> {
+    // `SKIP LOCKED` lets several dispatchers work through the outbox
+    // concurrently without sending the same email twice.
+    email_outbox::Entity::find()
+        .filter(email_outbox::Column::Status.eq("pending"))
+        .filter(email_outbox::Column::NextAttemptAt.lte(Utc::now()))
+        .order_by_asc(email_outbox::Column::Id)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .one(txn)
+        .await
+}
+
+struct DeliveryError {
+    message: String,
+    /// Retrying will not help, e.g. the provider rejected the message.
+    permanent: bool,
+}
+
+async fn deliver(
+    email_client: &EmailClient,
+    task: &email_outbox::Model,
+) -> Result<(), DeliveryError> {
+    let recipient = SubscriberEmail::parse(task.recipient.clone()).map_err(|e| DeliveryError {
+        message: e,
+        permanent: true,
+    })?;
+    let text_body = match &task.text_body {
+        Some(text_body) => text_body.clone(),
+        None => html_to_text(&task.html_body),
+    };
+    email_client
+        .send_email(recipient, &task.subject, &task.html_body, &text_body)
+        .await
+        .map_err(|e| DeliveryError {
+            permanent: matches!(e, SendEmailError::Rejected { .. }),
+            message: e.to_string(),
+        })
+}
+
+async fn mark_as_sent(txn: &DatabaseTransaction, task: email_outbox::Model) -> Result<(), DbErr> {
+    let attempts = task.attempts + 1;
+    let transactional = task.transactional;
+    let mut task: email_outbox::ActiveModel = task.into();
+    if transactional {
+        forget_body(&mut task);
+    }
+    task.status = Set("sent".to_string());
+    task.attempts = Set(attempts);
+    task.sent_at = Set(Some(Utc::now()));
+    task.update(txn).await?;
+    Ok(())
+}
+
+async fn mark_as_suppressed(
+    txn: &DatabaseTransaction,
+    task: email_outbox::Model,
+) -> Result<(), DbErr> {
+    let transactional = task.transactional;
+    let mut task: email_outbox::ActiveModel = task.into();
+    if transactional {
+        forget_body(&mut task);
+    }
+    task.status = Set("suppressed".to_string());
+    task.update(txn).await?;
+    Ok(())
+}
+
+async fn record_failure(
+    txn: &DatabaseTransaction,
+    task: email_outbox::Model,
+    error: DeliveryError,
+) -> Result<(), DbErr> {
+    let attempts = task.attempts + 1;
+    let transactional = task.transactional;
+    let mut task: email_outbox::ActiveModel = task.into();
+    task.attempts = Set(attempts);
+    task.last_error = Set(Some(error.message));
+    if error.permanent || attempts >= MAX_ATTEMPTS {
+        if transactional {
+            forget_body(&mut task);
+        }
+        task.status = Set("failed".to_string());
+    } else {
+        task.next_attempt_at = Set(next_attempt_at(attempts));
+    }
+    task.update(txn).await?;
+    Ok(())
+}
+
+/// Transactional emails can carry personal data and working tokens, e.g. the
+/// answer to an access request: their bodies are not kept once the email is
+/// done with.
+fn forget_body(task: &mut email_outbox::ActiveModel) {
+    task.html_body = Set(String::new());
+    task.text_body = Set(None);
+}
+
+/// When to try again after `attempts` failed ones, see [`retry_delay`]. The
+/// scheduler backs off the same way.
+pub(crate) fn next_attempt_at(attempts: i32) -> DateTime<Utc> {
+    let delay = chrono::Duration::from_std(retry_delay(attempts))
+        .expect("The retry delay is always small enough.");
+    Utc::now() + delay
+}
+
+/// Exponential back-off: 30s after the first failure, doubling up to an hour.
+fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    Duration::from_secs(30)
+        .saturating_mul(2u32.pow(exponent))
+        .min(Duration::from_secs(60 * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retry_delay;
+    use std::time::Duration;
+
+    #[test]
+    fn retry_delay_doubles_after_each_attempt() {
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(2), Duration::from_secs(60));
+        assert_eq!(retry_delay(3), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn retry_delay_is_capped_at_an_hour() {
+        assert_eq!(retry_delay(9), Duration::from_secs(60 * 60));
+        assert_eq!(retry_delay(i32::MAX), Duration::from_secs(60 * 60));
+    }
+}
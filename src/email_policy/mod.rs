@@ -0,0 +1,175 @@
+//! Which addresses we accept, and when two addresses are the same
+//! subscriber.
+
+use crate::domain::SubscriberEmail;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// How addresses are reduced to their canonical form, the one compared to
+/// find out whether someone is subscribed already.
+///
+/// Changing the rules does not update the canonical form of existing
+/// subscribers.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Canonicalization {
+    /// Most providers ignore the case of the local part, although the
+    /// standard lets them tell `Ursula@` and `ursula@` apart.
+    pub case_insensitive_local_part: bool,
+    /// `ursula+news@example.com` is `ursula@example.com`.
+    pub strip_plus_tags: bool,
+    /// Gmail ignores dots in the local part, and `googlemail.com` is
+    /// `gmail.com`.
+    pub ignore_gmail_dots: bool,
+}
+
+impl Default for Canonicalization {
+    fn default() -> Self {
+        Self {
+            case_insensitive_local_part: true,
+            strip_plus_tags: false,
+            ignore_gmail_dots: false,
+        }
+    }
+}
+
+impl Canonicalization {
+    pub fn canonical(&self, email: &SubscriberEmail) -> String {
+        let mut local_part = email.local_part().to_string();
+        let mut domain = email.domain();
+        if self.strip_plus_tags
+            && let Some((base, _)) = local_part.split_once('+')
+            && !base.is_empty()
+        {
+            local_part = base.to_string();
+        }
+        if self.ignore_gmail_dots && (domain == "gmail.com" || domain == "googlemail.com") {
+            local_part = local_part.replace('.', "");
+            domain = "gmail.com";
+        }
+        if self.case_insensitive_local_part {
+            local_part = local_part.to_lowercase();
+        }
+        format!("{}@{}", local_part, domain)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct EmailPolicy {
+    pub canonicalization: Canonicalization,
+    /// Addresses at these domains, or their subdomains, are rejected.
+    disposable_domains: Arc<HashSet<String>>,
+}
+
+impl EmailPolicy {
+    pub fn new(canonicalization: Canonicalization, disposable_domains: HashSet<String>) -> Self {
+        Self {
+            canonicalization,
+            disposable_domains: Arc::new(disposable_domains),
+        }
+    }
+
+    pub fn canonical(&self, email: &SubscriberEmail) -> String {
+        self.canonicalization.canonical(email)
+    }
+
+    /// Whether we take `email`, with the reason when we do not, to be shown
+    /// to the person who typed it.
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        let mut domain = Some(email.domain());
+        while let Some(current) = domain {
+            if self.disposable_domains.contains(current) {
+                return Err(format!(
+                    "{} is a disposable email provider: please use a permanent address.",
+                    email.domain()
+                ));
+            }
+            domain = current.split_once('.').map(|(_, parent)| parent);
+        }
+        Ok(())
+    }
+}
+
+/// The disposable email domains of `file`, or the bundled ones when `None`.
+pub fn load_disposable_domains(file: Option<&Path>) -> std::io::Result<HashSet<String>> {
+    let list = match file {
+        Some(file) => std::fs::read_to_string(file)?,
+        None => BUNDLED_DISPOSABLE_DOMAINS.to_string(),
+    };
+    Ok(parse_domain_list(&list))
+}
+
+/// One domain per line; blank lines and `#` comments are skipped.
+fn parse_domain_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|domain| idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_lowercase()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Canonicalization, EmailPolicy, load_disposable_domains, parse_domain_list};
+    use crate::domain::SubscriberEmail;
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.to_string()).unwrap()
+    }
+
+    #[test]
+    fn by_default_only_case_is_ignored() {
+        let rules = Canonicalization::default();
+        assert_eq!(
+            rules.canonical(&email("Ursula.Le+News@Gmail.com")),
+            "ursula.le+news@gmail.com"
+        );
+    }
+
+    #[test]
+    fn plus_tags_and_gmail_dots_can_be_ignored() {
+        let rules = Canonicalization {
+            case_insensitive_local_part: false,
+            strip_plus_tags: true,
+            ignore_gmail_dots: true,
+        };
+        assert_eq!(
+            rules.canonical(&email("U.Le.Guin+news@googlemail.com")),
+            "ULeGuin@gmail.com"
+        );
+        assert_eq!(
+            rules.canonical(&email("u.le+news@example.com")),
+            "u.le@example.com"
+        );
+        // A tag alone is the whole local part.
+        assert_eq!(
+            rules.canonical(&email("+news@example.com")),
+            "+news@example.com"
+        );
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = EmailPolicy::new(
+            Canonicalization::default(),
+            load_disposable_domains(None).unwrap(),
+        );
+        let reason = policy.check(&email("bot@Mailinator.com")).unwrap_err();
+        assert!(reason.contains("mailinator.com is a disposable email provider"));
+        assert!(policy.check(&email("bot@eu.mailinator.com")).is_err());
+        assert!(policy.check(&email("ursula@gmail.com")).is_ok());
+        assert!(policy.check(&email("ursula@notmailinator.com")).is_ok());
+    }
+
+    #[test]
+    fn domain_lists_skip_comments_and_blank_lines() {
+        let domains =
+            parse_domain_list("# a comment\n\nTrash.example # inline\n  bücher.example\n");
+        assert_eq!(domains.len(), 2);
+        assert!(domains.contains("trash.example"));
+        assert!(domains.contains("xn--bcher-kva.example"));
+    }
+}
@@ -2,5 +2,26 @@
 
 pub mod prelude;
 
+pub mod ab_tests;
+pub mod audit_log;
+pub mod consent_events;
+pub mod email_clicks;
+pub mod email_opens;
+pub mod email_outbox;
+pub mod import_jobs;
+pub mod issue_recipients;
+pub mod issue_variants;
+pub mod list_subscriptions;
+pub mod lists;
+pub mod newsletter_issues;
+pub mod preference_changes;
+pub mod privacy_requests;
+pub mod rate_limit_buckets;
+pub mod segments;
+pub mod subscriber_tags;
 pub mod subscription_tokens;
 pub mod subscriptions;
+pub mod suppressions;
+pub mod tags;
+pub mod tracked_links;
+pub mod used_form_tokens;
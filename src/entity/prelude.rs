@@ -1,4 +1,25 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 2.0
 
+pub use super::ab_tests::Entity as AbTests;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::consent_events::Entity as ConsentEvents;
+pub use super::email_clicks::Entity as EmailClicks;
+pub use super::email_opens::Entity as EmailOpens;
+pub use super::email_outbox::Entity as EmailOutbox;
+pub use super::import_jobs::Entity as ImportJobs;
+pub use super::issue_recipients::Entity as IssueRecipients;
+pub use super::issue_variants::Entity as IssueVariants;
+pub use super::list_subscriptions::Entity as ListSubscriptions;
+pub use super::lists::Entity as Lists;
+pub use super::newsletter_issues::Entity as NewsletterIssues;
+pub use super::preference_changes::Entity as PreferenceChanges;
+pub use super::privacy_requests::Entity as PrivacyRequests;
+pub use super::rate_limit_buckets::Entity as RateLimitBuckets;
+pub use super::segments::Entity as Segments;
+pub use super::subscriber_tags::Entity as SubscriberTags;
 pub use super::subscription_tokens::Entity as SubscriptionTokens;
 pub use super::subscriptions::Entity as Subscriptions;
+pub use super::suppressions::Entity as Suppressions;
+pub use super::tags::Entity as Tags;
+pub use super::tracked_links::Entity as TrackedLinks;
+pub use super::used_form_tokens::Entity as UsedFormTokens;
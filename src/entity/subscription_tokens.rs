@@ -10,10 +10,21 @@ pub struct Model {
     #[sea_orm(unique)]
     pub subscription_token: String,
     pub subscriber_id: i32,
+    pub list_id: i32,
+    pub attributes: Option<Json>,
+    pub used_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::lists::Entity",
+        from = "Column::ListId",
+        to = "super::lists::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Lists,
     #[sea_orm(
         belongs_to = "super::subscriptions::Entity",
         from = "Column::SubscriberId",
@@ -24,6 +35,12 @@ pub enum Relation {
     Subscriptions,
 }
 
+impl Related<super::lists::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Lists.def()
+    }
+}
+
 impl Related<super::subscriptions::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Subscriptions.def()
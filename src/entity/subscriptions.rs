@@ -12,14 +12,72 @@ pub struct Model {
     pub name: String,
     pub subscribed_at: DateTimeUtc,
     pub status: String,
+    pub hard_bounce_count: i32,
+    pub soft_bounce_count: i32,
+    #[sea_orm(unique)]
+    pub preferences_token: String,
+    pub digest_frequency: String,
+    pub locale: Option<String>,
+    pub attributes: Option<Json>,
+    #[sea_orm(unique)]
+    pub canonical_email: String,
+    pub soft_bounced_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::consent_events::Entity")]
+    ConsentEvents,
+    #[sea_orm(has_many = "super::issue_recipients::Entity")]
+    IssueRecipients,
+    #[sea_orm(has_many = "super::list_subscriptions::Entity")]
+    ListSubscriptions,
+    #[sea_orm(has_many = "super::preference_changes::Entity")]
+    PreferenceChanges,
+    #[sea_orm(has_many = "super::privacy_requests::Entity")]
+    PrivacyRequests,
+    #[sea_orm(has_many = "super::subscriber_tags::Entity")]
+    SubscriberTags,
     #[sea_orm(has_many = "super::subscription_tokens::Entity")]
     SubscriptionTokens,
 }
 
+impl Related<super::consent_events::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ConsentEvents.def()
+    }
+}
+
+impl Related<super::issue_recipients::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IssueRecipients.def()
+    }
+}
+
+impl Related<super::list_subscriptions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ListSubscriptions.def()
+    }
+}
+
+impl Related<super::preference_changes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PreferenceChanges.def()
+    }
+}
+
+impl Related<super::privacy_requests::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PrivacyRequests.def()
+    }
+}
+
+impl Related<super::subscriber_tags::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SubscriberTags.def()
+    }
+}
+
 impl Related<super::subscription_tokens::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::SubscriptionTokens.def()
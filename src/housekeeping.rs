@@ -0,0 +1,37 @@
+//! Periodic clean-up of the tables that every visitor can add rows to, so
+//! that they do not grow forever.
+
+use crate::bot_protection::prune_used_form_tokens;
+use crate::rate_limit::RateLimits;
+use sea_orm::DatabaseConnection;
+use std::time::Duration;
+
+/// How often the tables are cleaned up.
+pub const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Clean up once, logging what went wrong: a failed clean-up is retried at
+/// the next round.
+pub async fn run_housekeeping(db: &DatabaseConnection, rate_limits: &RateLimits) {
+    match rate_limits.prune().await {
+        Ok(0) => {}
+        Ok(pruned) => tracing::info!("Pruned {} rate limit buckets", pruned),
+        Err(e) => tracing::error!("Failed to prune the rate limit buckets: {:?}", e),
+    }
+    match prune_used_form_tokens(db).await {
+        Ok(0) => {}
+        Ok(pruned) => tracing::info!("Pruned {} used form tokens", pruned),
+        Err(e) => tracing::error!("Failed to prune the used form tokens: {:?}", e),
+    }
+}
+
+/// Keep cleaning up every `interval`.
+pub async fn run_housekeeping_until_stopped(
+    db: DatabaseConnection,
+    rate_limits: RateLimits,
+    interval: Duration,
+) {
+    loop {
+        run_housekeeping(&db, &rate_limits).await;
+        tokio::time::sleep(interval).await;
+    }
+}
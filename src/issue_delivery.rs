@@ -0,0 +1,324 @@
+//! Fan a newsletter issue out to the confirmed subscribers of its list
+//! through the outbox.
+
+use crate::ab_testing::{apply_variant, assign, bucket, find_pending_test, start_test};
+use crate::archive::slugify;
+use crate::attributes::template_variables;
+use crate::digests::next_digest_at;
+use crate::domain::SubscriberEmail;
+use crate::email_outbox::{NewEmail, enqueue_emails};
+use crate::entity::{
+    issue_recipients, issue_variants, list_subscriptions, newsletter_issues, subscriptions,
+    tracked_links,
+};
+use crate::issue_template::{RenderedIssue, personalize, render_issue};
+use crate::preferences::{add_preferences_link, preferences_url};
+use crate::segments::{SegmentFilter, load_segment};
+use crate::tracking::{
+    add_open_pixel, click_token, extract_links, generate_tracking_token, rewrite_links,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::{Expr, ExprTrait, Query, SelectStatement};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, DbErr, EntityTrait, JoinType,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Select, Set,
+};
+use std::collections::HashMap;
+
+/// Subscribers are loaded, and their emails stored, this many at a time.
+const CHUNK_SIZE: u64 = 500;
+
+/// What publishing an issue did.
+pub struct IssueDelivery {
+    /// `published`, or `testing` for the sample of an A/B test.
+    pub status: &'static str,
+    pub recipients: u64,
+}
+
+/// Mark `issue` as published and enqueue its delivery as part of `txn`.
+///
+/// Issues with an A/B test that has not started yet only go to the sample of
+/// the test: they are published when `ab_testing` sends the winner.
+pub async fn publish(
+    txn: &DatabaseTransaction,
+    issue: newsletter_issues::Model,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<IssueDelivery, DbErr> {
+    if let Some(test) = find_pending_test(txn, issue.id).await? {
+        let recipients = start_test(txn, issue, test, base_url, tracking_enabled).await?;
+        return Ok(IssueDelivery {
+            status: "testing",
+            recipients,
+        });
+    }
+    let recipients = enqueue_issue_delivery(txn, &issue, base_url, tracking_enabled).await?;
+    let slug = unique_slug(txn, &issue).await?;
+    let mut issue: newsletter_issues::ActiveModel = issue.into();
+    issue.status = Set("published".to_string());
+    issue.published_at = Set(Some(Utc::now()));
+    issue.slug = Set(Some(slug));
+    issue.update(txn).await?;
+    Ok(IssueDelivery {
+        status: "published",
+        recipients,
+    })
+}
+
+/// The slug of the issue in the public archive, made unique with the issue id
+/// when another issue has the same title.
+async fn unique_slug(
+    txn: &DatabaseTransaction,
+    issue: &newsletter_issues::Model,
+) -> Result<String, DbErr> {
+    let slug = slugify(&issue.title);
+    if slug.is_empty() {
+        return Ok(format!("issue-{}", issue.id));
+    }
+    let taken = newsletter_issues::Entity::find()
+        .filter(newsletter_issues::Column::Slug.eq(&slug))
+        .count(txn)
+        .await?
+        > 0;
+    Ok(if taken {
+        format!("{}-{}", slug, issue.id)
+    } else {
+        slug
+    })
+}
+
+/// The sample of an A/B test: `percentage` percents of the audience, split
+/// across `variants`.
+pub struct Sample<'a> {
+    pub percentage: i32,
+    pub variants: &'a [issue_variants::Model],
+}
+
+/// Enqueue one email per confirmed subscriber of the issue's list, or of its
+/// segment of the list, as part of `txn`, returning how many were enqueued.
+/// Subscribers who chose a digest get the issue with their next one instead.
+///
+/// With `tracking_enabled` the links of each email are rewritten to our
+/// click-tracking route and an open pixel is added.
+pub async fn enqueue_issue_delivery(
+    txn: &DatabaseTransaction,
+    issue: &newsletter_issues::Model,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<u64, DbErr> {
+    enqueue_delivery(txn, issue, None, base_url, tracking_enabled).await
+}
+
+/// Same as [`enqueue_issue_delivery`], restricted to `sample` if any.
+/// Subscribers who already got the issue, e.g. in the sample of its A/B
+/// test, are skipped.
+#[tracing::instrument(
+    name = "Enqueue the delivery of a newsletter issue",
+    skip(txn, issue, sample, base_url),
+    fields(issue_id = issue.id)
+)]
+pub async fn enqueue_delivery(
+    txn: &DatabaseTransaction,
+    issue: &newsletter_issues::Model,
+    sample: Option<Sample<'_>>,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<u64, DbErr> {
+    // The content of every variant, or of the issue outside of tests.
+    let contents: Vec<(Option<i32>, newsletter_issues::Model)> = match &sample {
+        Some(sample) => sample
+            .variants
+            .iter()
+            .map(|variant| (Some(variant.id), apply_variant(issue, variant)))
+            .collect(),
+        None => vec![(None, issue.clone())],
+    };
+    let mut link_ids = HashMap::new();
+    if tracking_enabled {
+        // Links already stored when the sample of an A/B test got the issue.
+        link_ids = tracked_links::Entity::find()
+            .filter(tracked_links::Column::NewsletterIssueId.eq(issue.id))
+            .all(txn)
+            .await?
+            .into_iter()
+            .map(|link| (link.url, link.id))
+            .collect();
+        for (_, content) in &contents {
+            store_tracked_links(txn, issue.id, &content.html_content, &mut link_ids).await?;
+        }
+    }
+    let contents: Vec<(Option<i32>, RenderedIssue)> = contents
+        .iter()
+        .map(|(variant_id, content)| (*variant_id, render_issue(content)))
+        .collect();
+
+    let segment = match issue.segment_id {
+        Some(segment_id) => Some(load_segment(txn, segment_id).await?.ok_or_else(|| {
+            DbErr::RecordNotFound(format!("Segment {} does not exist", segment_id))
+        })?),
+        None => None,
+    };
+    let recipients = recipients(issue.list_id, segment.as_ref(), Utc::now())
+        .filter(subscriptions::Column::Id.not_in_subquery(already_sent(issue.id)));
+
+    let mut enqueued = 0;
+    let mut last_id = 0;
+    loop {
+        let subscribers = recipients
+            .clone()
+            .filter(subscriptions::Column::Id.gt(last_id))
+            .order_by_asc(subscriptions::Column::Id)
+            .limit(CHUNK_SIZE)
+            .all(txn)
+            .await?;
+        let Some(last) = subscribers.last() else {
+            break;
+        };
+        last_id = last.id;
+
+        let mut recipient_rows = Vec::with_capacity(subscribers.len());
+        let mut emails = Vec::with_capacity(subscribers.len());
+        for subscriber in subscribers {
+            let digest_due_at = next_digest_at(&subscriber.digest_frequency, Utc::now());
+            let (variant_id, rendered) = match &sample {
+                // Their digest would come after the test is decided: they get
+                // the winner.
+                Some(_) if digest_due_at.is_some() => continue,
+                Some(sample) => {
+                    let bucket = bucket(issue.id, subscriber.id);
+                    match assign(bucket, sample.percentage, contents.len()) {
+                        Some(index) => &contents[index],
+                        None => continue,
+                    }
+                }
+                None => &contents[0],
+            };
+            let recipient = match SubscriberEmail::parse(subscriber.email.clone()) {
+                Ok(recipient) => recipient,
+                Err(e) => {
+                    tracing::warn!(
+                        error.message = %e,
+                        "Skipping a confirmed subscriber. Their stored contact details are invalid",
+                    );
+                    continue;
+                }
+            };
+            let tracking_token = generate_tracking_token();
+            recipient_rows.push(issue_recipients::ActiveModel {
+                newsletter_issue_id: Set(issue.id),
+                subscriber_id: Set(Some(subscriber.id)),
+                email: Set(subscriber.email.clone()),
+                tracking_token: Set(tracking_token.clone()),
+                created_at: Set(Utc::now()),
+                variant_id: Set(*variant_id),
+                digest_due_at: Set(digest_due_at),
+                ..Default::default()
+            });
+            if digest_due_at.is_some() {
+                enqueued += 1;
+                continue;
+            }
+
+            let personalized = personalize(
+                rendered,
+                &template_variables(
+                    &subscriber.name,
+                    &subscriber.email,
+                    subscriber.attributes.as_ref(),
+                ),
+            );
+            let html_body = if tracking_enabled {
+                personalize_tracking(&personalized.html, &link_ids, base_url, &tracking_token)
+            } else {
+                personalized.html
+            };
+            let (html_body, text_body) = add_preferences_link(
+                &html_body,
+                &personalized.text,
+                &preferences_url(base_url, &subscriber.preferences_token),
+            );
+            emails.push(NewEmail {
+                recipient,
+                subject: personalized.subject,
+                html_body,
+                text_body: Some(text_body),
+            });
+            enqueued += 1;
+        }
+        if !recipient_rows.is_empty() {
+            issue_recipients::Entity::insert_many(recipient_rows)
+                .exec(txn)
+                .await?;
+        }
+        enqueue_emails(txn, emails).await?;
+    }
+    Ok(enqueued)
+}
+
+/// The subscribers the issue was already sent to.
+fn already_sent(issue_id: i32) -> SelectStatement {
+    Query::select()
+        .column(issue_recipients::Column::SubscriberId)
+        .from(issue_recipients::Entity)
+        .and_where(Expr::col(issue_recipients::Column::NewsletterIssueId).eq(issue_id))
+        .and_where(Expr::col(issue_recipients::Column::SubscriberId).is_not_null())
+        .to_owned()
+}
+
+/// The confirmed subscribers of the list, restricted to `segment` if any.
+pub fn recipients(
+    list_id: i32,
+    segment: Option<&SegmentFilter>,
+    now: DateTime<Utc>,
+) -> Select<subscriptions::Entity> {
+    let query = subscriptions::Entity::find()
+        .join(
+            JoinType::InnerJoin,
+            subscriptions::Relation::ListSubscriptions.def(),
+        )
+        .filter(list_subscriptions::Column::ListId.eq(list_id))
+        .filter(list_subscriptions::Column::Status.eq("confirmed"))
+        .filter(subscriptions::Column::Status.eq("confirmed"));
+    match segment {
+        Some(segment) => query.filter(segment.condition(now)),
+        None => query,
+    }
+}
+
+/// Store the links of `html` that are not in `link_ids` yet, adding their id
+/// by URL to it.
+async fn store_tracked_links(
+    txn: &DatabaseTransaction,
+    issue_id: i32,
+    html: &str,
+    link_ids: &mut HashMap<String, i32>,
+) -> Result<(), DbErr> {
+    for url in extract_links(html) {
+        if link_ids.contains_key(&url) {
+            continue;
+        }
+        let link = tracked_links::ActiveModel {
+            newsletter_issue_id: Set(issue_id),
+            url: Set(url.clone()),
+            ..Default::default()
+        }
+        .insert(txn)
+        .await?;
+        link_ids.insert(url, link.id);
+    }
+    Ok(())
+}
+
+/// `html` with its links going through the click-tracking route and an open
+/// pixel, for the recipient with `tracking_token`.
+pub fn personalize_tracking(
+    html: &str,
+    link_ids: &HashMap<String, i32>,
+    base_url: &str,
+    tracking_token: &str,
+) -> String {
+    let html = rewrite_links(html, link_ids, |link_id| {
+        format!("{}/t/c/{}", base_url, click_token(tracking_token, link_id))
+    });
+    add_open_pixel(&html, &format!("{}/t/o/{}", base_url, tracking_token))
+}
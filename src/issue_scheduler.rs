@@ -0,0 +1,145 @@
+//! Publishes scheduled newsletter issues once their send time has come, the
+//! winners of A/B tests once the tests have run for long enough, and the
+//! digests of daily and weekly subscribers.
+//!
+//! Schedules live in the `newsletter_issues` table, so they survive restarts:
+//! an issue that became due while the application was down is sent as soon
+//! as the scheduler is back.
+//!
+//! An issue that fails to publish is retried with the back-off of the outbox,
+//! so that it does not hold up the issues scheduled after it, and is marked
+//! `failed` after [`MAX_PUBLISH_ATTEMPTS`] attempts. Its `scheduled_at` keeps
+//! the send time the editor chose, retries go by `next_attempt_at`.
+
+use crate::ab_testing::try_send_due_winner;
+use crate::digests::try_send_due_digest;
+use crate::email_outbox::{ExecutionOutcome, next_attempt_at};
+use crate::entity::newsletter_issues;
+use crate::issue_delivery::publish;
+use chrono::Utc;
+use sea_orm::sea_query::{LockBehavior, LockType};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DatabaseTransaction, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use std::time::Duration;
+use tracing::{Span, field::display};
+
+/// Issues, and winners of A/B tests, that failed to publish this many times
+/// are given up on.
+pub const MAX_PUBLISH_ATTEMPTS: i32 = 5;
+
+/// Publish the oldest scheduled issue that is due, if any.
+#[tracing::instrument(skip_all, fields(issue_id = tracing::field::Empty), err)]
+pub async fn try_publish_due_issue(
+    db: &DatabaseConnection,
+    base_url: &str,
+    tracking_enabled: bool,
+) -> Result<ExecutionOutcome, DbErr> {
+    let txn = db.begin().await?;
+    let Some(issue) = dequeue_due_issue(&txn).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let issue_id = issue.id;
+    Span::current().record("issue_id", display(issue_id));
+    let delivery = match publish(&txn, issue, base_url, tracking_enabled).await {
+        Ok(delivery) => delivery,
+        Err(e) => {
+            // Nothing of the attempt is kept but the failure itself, recorded
+            // once the lock on the issue is released.
+            txn.rollback().await?;
+            record_failed_attempt(db, issue_id, &e).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    txn.commit().await?;
+    tracing::info!(
+        recipients = delivery.recipients,
+        status = delivery.status,
+        "Published a scheduled newsletter issue"
+    );
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Keep publishing scheduled issues, A/B test winners and digests as they
+/// become due.
+pub async fn run_scheduler_until_stopped(
+    db: DatabaseConnection,
+    base_url: String,
+    tracking_enabled: bool,
+    poll_interval: Duration,
+) {
+    loop {
+        let issue = try_publish_due_issue(&db, &base_url, tracking_enabled).await;
+        let winner = try_send_due_winner(&db, &base_url, tracking_enabled).await;
+        let digest = try_send_due_digest(&db, &base_url, tracking_enabled).await;
+        let outcomes = [issue, winner, digest];
+        if outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, Ok(ExecutionOutcome::TaskCompleted)))
+        {
+            continue;
+        }
+        if outcomes.iter().any(Result::is_err) {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        } else {
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+}
+
+async fn dequeue_due_issue(
+    txn: &DatabaseTransaction,
+) -> Result<Option<newsletter_issues::Model>, DbErr> {
+    // The lock also keeps an editor from rescheduling or cancelling the
+    // issue while it is being published.
+    newsletter_issues::Entity::find()
+        .filter(newsletter_issues::Column::Status.eq("scheduled"))
+        .filter(newsletter_issues::Column::ScheduledAt.lte(Utc::now()))
+        .filter(
+            Condition::any()
+                .add(newsletter_issues::Column::NextAttemptAt.is_null())
+                .add(newsletter_issues::Column::NextAttemptAt.lte(Utc::now())),
+        )
+        .order_by_asc(newsletter_issues::Column::ScheduledAt)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .one(txn)
+        .await
+}
+
+/// Push the issue back, or give up on it after [`MAX_PUBLISH_ATTEMPTS`].
+async fn record_failed_attempt(
+    db: &DatabaseConnection,
+    issue_id: i32,
+    error: &DbErr,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    let Some(issue) = newsletter_issues::Entity::find_by_id(issue_id)
+        .lock(LockType::Update)
+        .one(&txn)
+        .await?
+    else {
+        return Ok(());
+    };
+    // An editor may have cancelled or rescheduled it in the meantime.
+    if issue.status != "scheduled" {
+        return Ok(());
+    }
+    let attempts = issue.publish_attempts + 1;
+    let mut issue: newsletter_issues::ActiveModel = issue.into();
+    issue.publish_attempts = Set(attempts);
+    issue.last_error = Set(Some(error.to_string()));
+    if attempts >= MAX_PUBLISH_ATTEMPTS {
+        tracing::error!(
+            attempts,
+            "Gave up on publishing a newsletter issue: {}",
+            error
+        );
+        issue.status = Set("failed".to_string());
+    } else {
+        tracing::warn!(attempts, "Failed to publish a newsletter issue: {}", error);
+        issue.next_attempt_at = Set(Some(next_attempt_at(attempts)));
+    }
+    issue.update(&txn).await?;
+    txn.commit().await
+}
@@ -0,0 +1,270 @@
+//! The email template newsletter issues are rendered through, shared by the
+//! delivery to subscribers, test sends and the browser preview.
+//!
+//! Issues can be personalized with variables:
+//! - `{{ first_name }}` is replaced with the value of `first_name`, and
+//!   `{{ first_name | there }}` falls back to `there` when it is missing;
+//! - `{{#vip}}...{{/vip}}` is only kept when `vip` is set, and
+//!   `{{^vip}}...{{/vip}}` only when it is not.
+
+use crate::email_client::html_to_text;
+use crate::entity::newsletter_issues;
+use serde_json::{Map, Value};
+
+/// An issue as it appears in the inbox, before per-recipient tracking.
+pub struct RenderedIssue {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render_issue(issue: &newsletter_issues::Model) -> RenderedIssue {
+    let html = if is_full_document(&issue.html_content) {
+        issue.html_content.clone()
+    } else {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+<title>{}</title>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+            escape_html(&issue.title),
+            issue.html_content
+        )
+    };
+    let text = match &issue.text_content {
+        Some(text_content) => text_content.clone(),
+        None => html_to_text(&issue.html_content),
+    };
+    RenderedIssue {
+        subject: issue.title.clone(),
+        html,
+        text,
+    }
+}
+
+/// The issue as sent to the subscriber with these `variables`. Values are
+/// escaped in the HTML body.
+pub fn personalize(rendered: &RenderedIssue, variables: &Map<String, Value>) -> RenderedIssue {
+    RenderedIssue {
+        subject: render_template(&rendered.subject, variables, false),
+        html: render_template(&rendered.html, variables, true),
+        text: render_template(&rendered.text, variables, false),
+    }
+}
+
+/// `template` with every variable at its default, for anonymous readers.
+pub fn without_variables(template: &str) -> String {
+    render_template(template, &Map::new(), false)
+}
+
+fn render_template(template: &str, variables: &Map<String, Value>, escape: bool) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start..].find("}}") else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        let tag = rest[start + 2..start + length].trim();
+        rest = &rest[start + length + 2..];
+
+        if let Some(section) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^')) {
+            let key = section.trim();
+            let (body, after) = split_section(rest, key);
+            if is_truthy(variables.get(key)) == tag.starts_with('#') {
+                output.push_str(&render_template(body, variables, escape));
+            }
+            rest = after;
+        } else if tag.starts_with('/') {
+            // A section that was never opened.
+        } else {
+            let (key, fallback) = match tag.split_once('|') {
+                Some((key, fallback)) => (key.trim(), Some(fallback.trim().trim_matches('"'))),
+                None => (tag, None),
+            };
+            let value = display(variables.get(key));
+            if value.is_empty() {
+                output.push_str(fallback.unwrap_or_default());
+            } else if escape {
+                output.push_str(&escape_html(&value));
+            } else {
+                output.push_str(&value);
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// The body of the `key` section starting `template` and what follows its
+/// closing tag, or the whole of `template` when the section is never closed.
+fn split_section<'a>(template: &'a str, key: &str) -> (&'a str, &'a str) {
+    let mut depth = 0;
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{") {
+        let start = offset + start;
+        let Some(length) = template[start..].find("}}") else {
+            break;
+        };
+        let tag = template[start + 2..start + length].trim();
+        let end = start + length + 2;
+        if let Some(closed) = tag.strip_prefix('/')
+            && closed.trim() == key
+        {
+            if depth == 0 {
+                return (&template[..start], &template[end..]);
+            }
+            depth -= 1;
+        } else if let Some(opened) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^'))
+            && opened.trim() == key
+        {
+            depth += 1;
+        }
+        offset = end;
+    }
+    (template, "")
+}
+
+fn is_truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(Value::Number(n)) => n.as_f64() != Some(0.0),
+        Some(Value::Array(a)) => !a.is_empty(),
+        Some(Value::Object(o)) => !o.is_empty(),
+    }
+}
+
+fn display(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Number(n)) => n.to_string(),
+        Some(Value::Bool(b)) => b.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Whether `html` already comes with its own `<html>` element, in which case
+/// it is sent as it is.
+fn is_full_document(html: &str) -> bool {
+    let start = html.trim_start().to_ascii_lowercase();
+    start.starts_with("<!doctype") || start.starts_with("<html")
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_html, personalize, render_issue};
+    use crate::entity::newsletter_issues;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn issue(html_content: &str, text_content: Option<&str>) -> newsletter_issues::Model {
+        newsletter_issues::Model {
+            id: 1,
+            title: "News & <views>".into(),
+            html_content: html_content.into(),
+            text_content: text_content.map(Into::into),
+            status: "draft".into(),
+            created_at: Utc::now(),
+            published_at: None,
+            scheduled_at: None,
+            list_id: 1,
+            segment_id: None,
+            slug: None,
+            publish_attempts: 0,
+            last_error: None,
+            next_attempt_at: None,
+        }
+    }
+
+    #[test]
+    fn fragments_are_wrapped_in_the_layout() {
+        let rendered = render_issue(&issue("<p>Hello</p>", None));
+        assert!(rendered.html.starts_with("<!DOCTYPE html>"));
+        assert!(
+            rendered
+                .html
+                .contains("<title>News &amp; &lt;views&gt;</title>")
+        );
+        assert!(rendered.html.contains("<body>\n<p>Hello</p>\n</body>"));
+        assert_eq!(rendered.subject, "News & <views>");
+    }
+
+    #[test]
+    fn full_documents_are_kept_as_they_are() {
+        let html = "  <HTML><body><p>Hello</p></body></HTML>";
+        assert_eq!(render_issue(&issue(html, None)).html, html);
+    }
+
+    #[test]
+    fn the_text_body_is_derived_from_the_html_when_missing() {
+        assert_eq!(render_issue(&issue("<p>Hello</p>", None)).text, "Hello");
+        assert_eq!(
+            render_issue(&issue("<p>Hello</p>", Some("Hi there"))).text,
+            "Hi there"
+        );
+    }
+
+    #[test]
+    fn html_special_characters_are_escaped() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom's & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom&#39;s &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn variables_are_replaced_and_escaped_in_html() {
+        let mut issue = issue("<p>Hi {{ first_name }}, {{nickname | friend}}</p>", None);
+        issue.title = "News for {{ first_name }}".into();
+        let variables = json!({ "first_name": "Ursula <3" });
+        let rendered = personalize(&render_issue(&issue), variables.as_object().unwrap());
+        assert_eq!(rendered.subject, "News for Ursula <3");
+        assert!(rendered.html.contains("<p>Hi Ursula &lt;3, friend</p>"));
+        assert_eq!(rendered.text, "Hi Ursula <3, friend");
+    }
+
+    #[test]
+    fn sections_depend_on_the_variable() {
+        let issue = issue(
+            "x",
+            Some("{{#vip}}Thanks{{#age}} ({{age}}){{/age}}!{{/vip}}{{^vip}}Upgrade?{{/vip}}"),
+        );
+        let rendered = render_issue(&issue);
+        let vip = json!({ "vip": true, "age": 88 });
+        let regular = json!({ "vip": false });
+        assert_eq!(
+            personalize(&rendered, vip.as_object().unwrap()).text,
+            "Thanks (88)!"
+        );
+        assert_eq!(
+            personalize(&rendered, regular.as_object().unwrap()).text,
+            "Upgrade?"
+        );
+        assert_eq!(personalize(&rendered, &Default::default()).text, "Upgrade?");
+    }
+
+    #[test]
+    fn unterminated_tags_are_kept() {
+        let issue = issue("x", Some("a {{ b"));
+        assert_eq!(
+            personalize(&render_issue(&issue), &Default::default()).text,
+            "a {{ b"
+        );
+    }
+}
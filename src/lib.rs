@@ -1,7 +1,30 @@
+pub mod ab_testing;
+pub mod archive;
+pub mod attributes;
+pub mod authentication;
+pub mod bot_protection;
+pub mod client_ip;
 pub mod configuration;
+pub mod consent;
+pub mod digests;
 pub mod domain;
-mod email_client;
+pub mod domain_check;
+pub mod email_client;
+pub mod email_outbox;
+pub mod email_policy;
 pub mod entity;
+pub mod housekeeping;
+pub mod issue_delivery;
+pub mod issue_scheduler;
+pub mod issue_template;
+pub mod lists;
+pub mod preferences;
+pub mod privacy;
+pub mod rate_limit;
 pub mod routes;
+pub mod segments;
 pub mod startup; // 新增这一行，声明 entity 模块
+pub mod subscriber_import;
+pub mod suppression;
 pub mod telemetry;
+pub mod tracking;
@@ -0,0 +1,19 @@
+//! Mailing lists: one subscriber can be on several lists, with a separate
+//! confirmation for each of them.
+
+use crate::entity::lists;
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+
+/// The list subscriptions go to when no list is given, created by the
+/// `create_lists_tables` migration.
+pub const DEFAULT_LIST: &str = "newsletter";
+
+pub async fn find_list<C: ConnectionTrait>(
+    db: &C,
+    slug: &str,
+) -> Result<Option<lists::Model>, DbErr> {
+    lists::Entity::find()
+        .filter(lists::Column::Slug.eq(slug))
+        .one(db)
+        .await
+}
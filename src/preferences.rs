@@ -0,0 +1,204 @@
+//! The preference center: a page, reached through a per-subscriber token in
+//! every issue, where subscribers manage what they receive without logging in.
+
+use crate::entity::{lists, preference_changes, subscriptions};
+use crate::issue_template::escape_html;
+use chrono::Utc;
+use fake::RngExt;
+use fake::rand::distr::Alphanumeric;
+use fake::rand::rng;
+use sea_orm::{ActiveModelTrait, ConnectionTrait, DbErr, Set};
+
+/// How often subscribers want to hear from us, the first being the default.
+pub const DIGEST_FREQUENCIES: [&str; 3] = ["immediate", "daily", "weekly"];
+
+/// Random 32-characters-long alphanumeric token.
+pub fn generate_preferences_token() -> String {
+    let mut rng = rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+pub fn preferences_url(base_url: &str, preferences_token: &str) -> String {
+    format!("{}/preferences/{}", base_url, preferences_token)
+}
+
+/// Add a link to the preference center at the bottom of both bodies of an
+/// email.
+pub fn add_preferences_link(html: &str, text: &str, url: &str) -> (String, String) {
+    let footer = format!(
+        "<p><a href=\"{}\">Manage your preferences</a></p>",
+        escape_html(url)
+    );
+    // Lowercasing ASCII keeps byte offsets unchanged.
+    let html = match html.to_ascii_lowercase().rfind("</body") {
+        Some(index) => format!("{}{}{}", &html[..index], footer, &html[index..]),
+        None => format!("{}{}", html, footer),
+    };
+    let text = format!("{}\n\nManage your preferences: {}", text, url);
+    (html, text)
+}
+
+/// Record a change made in the preference center. `None` stands for "not
+/// set", e.g. a list the subscriber was never on.
+#[tracing::instrument(name = "Record a preference change", skip(db))]
+pub async fn record_change<C: ConnectionTrait>(
+    db: &C,
+    subscriber_id: i32,
+    field: &str,
+    old_value: Option<&str>,
+    new_value: Option<&str>,
+) -> Result<(), DbErr> {
+    preference_changes::ActiveModel {
+        subscriber_id: Set(subscriber_id),
+        field: Set(field.to_string()),
+        old_value: Set(old_value.map(str::to_string)),
+        new_value: Set(new_value.map(str::to_string)),
+        changed_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(())
+}
+
+/// The preference center of `subscriber`. `lists` pairs every list with
+/// whether the subscriber receives it.
+pub fn preferences_page(
+    subscriber: &subscriptions::Model,
+    lists: &[(lists::Model, bool)],
+    notice: Option<&str>,
+) -> String {
+    let body = if subscriber.status == "confirmed" {
+        preferences_form(subscriber, lists)
+    } else {
+        "<p>You are not subscribed to any of our emails.</p>\n".to_string()
+    };
+    let notice = notice
+        .map(|notice| format!("<p role=\"status\">{}</p>\n", escape_html(notice)))
+        .unwrap_or_default();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<title>Your preferences</title>\n</head>\n<body>\n<h1>Your preferences</h1>\n{}{}</body>\n</html>\n",
+        notice, body
+    )
+}
+
+fn preferences_form(subscriber: &subscriptions::Model, lists: &[(lists::Model, bool)]) -> String {
+    let mut list_options = String::new();
+    for (list, subscribed) in lists {
+        list_options.push_str(&format!(
+            "<label><input type=\"checkbox\" name=\"lists\" value=\"{}\"{}> {}</label><br>\n",
+            escape_html(&list.slug),
+            if *subscribed { " checked" } else { "" },
+            escape_html(&list.name),
+        ));
+    }
+    let mut frequency_options = String::new();
+    for frequency in DIGEST_FREQUENCIES {
+        frequency_options.push_str(&format!(
+            "<option value=\"{}\"{}>{}</option>\n",
+            frequency,
+            if subscriber.digest_frequency == frequency {
+                " selected"
+            } else {
+                ""
+            },
+            frequency,
+        ));
+    }
+    format!(
+        "<form method=\"post\">\n\
+<label>Name <input type=\"text\" name=\"name\" value=\"{}\"></label>\n\
+<fieldset>\n<legend>Lists</legend>\n{}</fieldset>\n\
+<label>Digest frequency <select name=\"digest_frequency\">\n{}</select></label>\n\
+<label><input type=\"checkbox\" name=\"unsubscribe_all\" value=\"true\"> \
+Unsubscribe from everything</label>\n\
+<button type=\"submit\">Save</button>\n</form>\n",
+        escape_html(&subscriber.name),
+        list_options,
+        frequency_options
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_preferences_link, generate_preferences_token, preferences_page};
+    use crate::entity::{lists, subscriptions};
+    use chrono::Utc;
+
+    fn subscriber(status: &str) -> subscriptions::Model {
+        subscriptions::Model {
+            id: 1,
+            email: "ursula_le_guin@gmail.com".into(),
+            name: "Ursula <Le Guin>".into(),
+            subscribed_at: Utc::now(),
+            status: status.into(),
+            hard_bounce_count: 0,
+            soft_bounce_count: 0,
+            preferences_token: generate_preferences_token(),
+            digest_frequency: "weekly".into(),
+            locale: None,
+            attributes: None,
+            canonical_email: "ursula_le_guin@gmail.com".into(),
+            soft_bounced_at: None,
+        }
+    }
+
+    fn list(id: i32, slug: &str) -> lists::Model {
+        lists::Model {
+            id,
+            slug: slug.into(),
+            name: slug.to_uppercase(),
+            created_at: Utc::now(),
+            attribute_schema: None,
+        }
+    }
+
+    #[test]
+    fn tokens_are_32_alphanumeric_characters() {
+        let token = generate_preferences_token();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn the_link_goes_at_the_end_of_the_body() {
+        let (html, text) = add_preferences_link(
+            "<HTML><BODY><p>Hi</p></BODY></HTML>",
+            "Hi",
+            "https://example.com/preferences/abc",
+        );
+        assert_eq!(
+            html,
+            "<HTML><BODY><p>Hi</p><p><a href=\"https://example.com/preferences/abc\">\
+Manage your preferences</a></p></BODY></HTML>"
+        );
+        assert_eq!(
+            text,
+            "Hi\n\nManage your preferences: https://example.com/preferences/abc"
+        );
+    }
+
+    #[test]
+    fn the_page_shows_the_current_preferences() {
+        let page = preferences_page(
+            &subscriber("confirmed"),
+            &[(list(1, "newsletter"), true), (list(2, "releases"), false)],
+            None,
+        );
+        assert!(page.contains("value=\"Ursula &lt;Le Guin&gt;\""));
+        assert!(page.contains("value=\"newsletter\" checked>"));
+        assert!(page.contains("value=\"releases\"> RELEASES"));
+        assert!(page.contains("<option value=\"weekly\" selected>"));
+    }
+
+    #[test]
+    fn unsubscribed_subscribers_get_no_form() {
+        let page = preferences_page(&subscriber("unsubscribed"), &[], Some("Saved"));
+        assert!(!page.contains("<form"));
+        assert!(page.contains("<p role=\"status\">Saved</p>"));
+    }
+}
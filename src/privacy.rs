@@ -0,0 +1,347 @@
+//! Data subject requests: subscribers can get a copy of everything we store
+//! about them, or have it erased. Requests are confirmed through a link sent
+//! to the address, so nobody can act on someone else's data.
+//!
+//! Erasure keeps the address on the suppression list, as a hash, so that the
+//! person is never emailed again even if the address comes back through an
+//! import.
+
+use crate::consent::{ConsentEvent, consent_history};
+use crate::entity::{
+    audit_log, email_clicks, email_opens, email_outbox, issue_recipients, list_subscriptions,
+    lists, newsletter_issues, preference_changes, subscriber_tags, subscription_tokens,
+    subscriptions, tags, tracked_links,
+};
+use crate::issue_template::escape_html;
+use crate::suppression::suppress;
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, JoinType, QueryFilter, QueryOrder,
+    QuerySelect, RelationTrait,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+/// `access` to get a copy of the data, `erasure` to have it erased.
+pub const REQUEST_KINDS: [&str; 2] = ["access", "erasure"];
+
+/// How long the link of a request can be used.
+pub fn request_lifetime() -> Duration {
+    Duration::hours(24)
+}
+
+pub fn request_url(base_url: &str, token: &str) -> String {
+    format!("{}/privacy/requests/{}", base_url, token)
+}
+
+/// Everything stored about a subscriber.
+#[derive(Serialize, Debug)]
+pub struct DataBundle {
+    pub generated_at: DateTime<Utc>,
+    pub subscriber: SubscriberData,
+    pub lists: Vec<ListData>,
+    pub tags: Vec<String>,
+    pub subscription_tokens: Vec<String>,
+    pub deliveries: Vec<DeliveryData>,
+    pub preference_changes: Vec<PreferenceChangeData>,
+    pub consent_events: Vec<ConsentEvent>,
+    pub audit_entries: Vec<AuditData>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscriberData {
+    pub id: i32,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub locale: Option<String>,
+    pub digest_frequency: String,
+    pub hard_bounce_count: i32,
+    pub soft_bounce_count: i32,
+    pub attributes: Option<Value>,
+    pub preferences_token: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListData {
+    pub list: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+/// An issue sent to the subscriber, with what they opened and clicked.
+#[derive(Serialize, Debug)]
+pub struct DeliveryData {
+    pub issue: String,
+    pub sent_at: DateTime<Utc>,
+    pub opened_at: Vec<DateTime<Utc>>,
+    pub clicks: Vec<ClickData>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ClickData {
+    pub url: String,
+    pub clicked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PreferenceChangeData {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Something an admin did to the subscriber.
+#[derive(Serialize, Debug)]
+pub struct AuditData {
+    pub action: String,
+    pub actor: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Collect the data of a subscriber", skip_all, fields(subscriber_id = subscriber.id))]
+pub async fn collect_data<C: ConnectionTrait>(
+    db: &C,
+    subscriber: subscriptions::Model,
+) -> Result<DataBundle, DbErr> {
+    let lists = list_subscriptions::Entity::find()
+        .join(
+            JoinType::InnerJoin,
+            list_subscriptions::Relation::Lists.def(),
+        )
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber.id))
+        .select_only()
+        .column(lists::Column::Slug)
+        .column(list_subscriptions::Column::Status)
+        .column(list_subscriptions::Column::SubscribedAt)
+        .order_by_asc(lists::Column::Slug)
+        .into_tuple::<(String, String, DateTime<Utc>)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|(list, status, subscribed_at)| ListData {
+            list,
+            status,
+            subscribed_at,
+        })
+        .collect();
+    let tags = subscriber_tags::Entity::find()
+        .join(JoinType::InnerJoin, subscriber_tags::Relation::Tags.def())
+        .filter(subscriber_tags::Column::SubscriberId.eq(subscriber.id))
+        .select_only()
+        .column(tags::Column::Name)
+        .order_by_asc(tags::Column::Name)
+        .into_tuple::<String>()
+        .all(db)
+        .await?;
+    let subscription_tokens = subscription_tokens::Entity::find()
+        .filter(subscription_tokens::Column::SubscriberId.eq(subscriber.id))
+        .select_only()
+        .column(subscription_tokens::Column::SubscriptionToken)
+        .into_tuple::<String>()
+        .all(db)
+        .await?;
+    let deliveries = collect_deliveries(db, &subscriber).await?;
+    let preference_changes = preference_changes::Entity::find()
+        .filter(preference_changes::Column::SubscriberId.eq(subscriber.id))
+        .order_by_asc(preference_changes::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|change| PreferenceChangeData {
+            field: change.field,
+            old_value: change.old_value,
+            new_value: change.new_value,
+            changed_at: change.changed_at,
+        })
+        .collect();
+    let consent_events = consent_history(db, subscriber.id).await?;
+    let audit_entries = audit_log::Entity::find()
+        .filter(audit_log::Column::TargetType.eq("subscriber"))
+        .filter(audit_log::Column::TargetId.eq(subscriber.id.to_string()))
+        .order_by_asc(audit_log::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|entry| AuditData {
+            action: entry.action,
+            actor: entry.actor,
+            before: entry.before,
+            after: entry.after,
+            created_at: entry.created_at,
+        })
+        .collect();
+    Ok(DataBundle {
+        generated_at: Utc::now(),
+        subscriber: SubscriberData {
+            id: subscriber.id,
+            email: subscriber.email,
+            name: subscriber.name,
+            status: subscriber.status,
+            subscribed_at: subscriber.subscribed_at,
+            locale: subscriber.locale,
+            digest_frequency: subscriber.digest_frequency,
+            hard_bounce_count: subscriber.hard_bounce_count,
+            soft_bounce_count: subscriber.soft_bounce_count,
+            attributes: subscriber.attributes,
+            preferences_token: subscriber.preferences_token,
+        },
+        lists,
+        tags,
+        subscription_tokens,
+        deliveries,
+        preference_changes,
+        consent_events,
+        audit_entries,
+    })
+}
+
+async fn collect_deliveries<C: ConnectionTrait>(
+    db: &C,
+    subscriber: &subscriptions::Model,
+) -> Result<Vec<DeliveryData>, DbErr> {
+    let recipients = issue_recipients::Entity::find()
+        .find_also_related(newsletter_issues::Entity)
+        .filter(issue_recipients::Column::SubscriberId.eq(subscriber.id))
+        .order_by_asc(issue_recipients::Column::Id)
+        .all(db)
+        .await?;
+    let mut deliveries = Vec::with_capacity(recipients.len());
+    for (recipient, issue) in recipients {
+        let opened_at = email_opens::Entity::find()
+            .filter(email_opens::Column::IssueRecipientId.eq(recipient.id))
+            .select_only()
+            .column(email_opens::Column::OpenedAt)
+            .order_by_asc(email_opens::Column::Id)
+            .into_tuple::<DateTime<Utc>>()
+            .all(db)
+            .await?;
+        let clicks = email_clicks::Entity::find()
+            .join(
+                JoinType::InnerJoin,
+                email_clicks::Relation::TrackedLinks.def(),
+            )
+            .filter(email_clicks::Column::IssueRecipientId.eq(recipient.id))
+            .select_only()
+            .column(tracked_links::Column::Url)
+            .column(email_clicks::Column::ClickedAt)
+            .order_by_asc(email_clicks::Column::Id)
+            .into_tuple::<(String, DateTime<Utc>)>()
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|(url, clicked_at)| ClickData { url, clicked_at })
+            .collect();
+        deliveries.push(DeliveryData {
+            issue: issue.map(|issue| issue.title).unwrap_or_default(),
+            sent_at: recipient.created_at,
+            opened_at,
+            clicks,
+        });
+    }
+    Ok(deliveries)
+}
+
+/// Erase `subscriber` as part of `db`'s transaction. Rows that only make
+/// sense for them are deleted; deliveries are kept, unlinked and without the
+/// address, so that the stats of past issues do not change, and so are the
+/// audit entries about them, without the data.
+#[tracing::instrument(name = "Erase a subscriber", skip_all, fields(subscriber_id = subscriber.id))]
+pub async fn erase_subscriber<C: ConnectionTrait>(
+    db: &C,
+    subscriber: &subscriptions::Model,
+) -> Result<(), DbErr> {
+    issue_recipients::Entity::update_many()
+        .col_expr(
+            issue_recipients::Column::SubscriberId,
+            Expr::value(None::<i32>),
+        )
+        .col_expr(issue_recipients::Column::Email, Expr::value(""))
+        .filter(
+            Condition::any()
+                .add(issue_recipients::Column::SubscriberId.eq(subscriber.id))
+                .add(issue_recipients::Column::Email.eq(&subscriber.email)),
+        )
+        .exec(db)
+        .await?;
+    // The log keeps that the actions happened, not what they showed.
+    audit_log::Entity::update_many()
+        .col_expr(audit_log::Column::Before, Expr::value(None::<Value>))
+        .col_expr(audit_log::Column::After, Expr::value(None::<Value>))
+        .filter(audit_log::Column::TargetType.eq("subscriber"))
+        .filter(audit_log::Column::TargetId.eq(subscriber.id.to_string()))
+        .exec(db)
+        .await?;
+    email_outbox::Entity::delete_many()
+        .filter(email_outbox::Column::Recipient.eq(&subscriber.email))
+        .exec(db)
+        .await?;
+    subscription_tokens::Entity::delete_many()
+        .filter(subscription_tokens::Column::SubscriberId.eq(subscriber.id))
+        .exec(db)
+        .await?;
+    // List subscriptions, tags, preference changes, consent events and
+    // privacy requests go with the subscriber.
+    subscriptions::Entity::delete_by_id(subscriber.id)
+        .exec(db)
+        .await?;
+    suppress(db, &subscriber.email, "erased", "privacy_request").await
+}
+
+/// The page where the subscriber confirms a request.
+pub fn confirmation_page(kind: &str) -> String {
+    let (question, button) = if kind == "erasure" {
+        (
+            "Erase all the data we hold about you? You will no longer receive any of our emails. This cannot be undone.",
+            "Erase my data",
+        )
+    } else {
+        (
+            "Get a copy of the data we hold about you? We will email it to you.",
+            "Send me my data",
+        )
+    };
+    page(&format!(
+        "<p>{}</p>\n<form method=\"post\">\n<button type=\"submit\">{}</button>\n</form>\n",
+        escape_html(question),
+        escape_html(button)
+    ))
+}
+
+pub fn message_page(message: &str) -> String {
+    page(&format!(
+        "<p role=\"status\">{}</p>\n",
+        escape_html(message)
+    ))
+}
+
+fn page(body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<title>Your data</title>\n</head>\n<body>\n<h1>Your data</h1>\n{}</body>\n</html>\n",
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{confirmation_page, message_page};
+
+    #[test]
+    fn the_confirmation_page_posts_back_to_itself() {
+        let page = confirmation_page("erasure");
+        assert!(page.contains("<form method=\"post\">"));
+        assert!(page.contains("Erase my data"));
+        assert!(confirmation_page("access").contains("Send me my data"));
+    }
+
+    #[test]
+    fn messages_are_escaped() {
+        assert!(message_page("<b>").contains("&lt;b&gt;"));
+    }
+}
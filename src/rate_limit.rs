@@ -0,0 +1,450 @@
+//! Rate limits of the public forms: token buckets per client address and per
+//! email address, so that bots cannot make us send confirmation emails and
+//! write to the database as fast as they can post.
+
+use crate::client_ip::ClientIp;
+use crate::domain::SubscriberEmail;
+use crate::entity::rate_limit_buckets;
+use crate::startup::AppState;
+use crate::suppression::hash_email;
+use axum::body::{Body, to_bytes};
+use axum::extract::{Request, State};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use lru::LruCache;
+use sea_orm::sea_query::LockType;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QuerySelect, Set, TransactionTrait,
+};
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Forms are a few fields long: anything bigger is not worth buffering.
+const MAX_FORM_BYTES: usize = 64 * 1024;
+/// Buckets the in-memory store keeps per limit, forgetting the least recently
+/// used ones past that.
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+/// How many requests a bucket lets through at once, and how fast it refills.
+#[derive(Debug, Clone, Copy)]
+pub struct BucketLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl BucketLimit {
+    /// A limit refilling `0` tokens per minute does not limit anything.
+    fn is_enabled(&self) -> bool {
+        self.per_minute > 0
+    }
+
+    fn refill_per_millisecond(&self) -> f64 {
+        f64::from(self.per_minute) / 60_000.0
+    }
+
+    /// How long an empty bucket takes to fill up again: a bucket left alone
+    /// that long is as good as a new one.
+    fn refill_duration_ms(&self) -> i64 {
+        (f64::from(self.burst) / self.refill_per_millisecond()).ceil() as i64
+    }
+}
+
+/// The tokens left for one client or email address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    tokens: f64,
+    updated_at_ms: i64,
+}
+
+impl Bucket {
+    fn full(limit: &BucketLimit, now_ms: i64) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            updated_at_ms: now_ms,
+        }
+    }
+
+    fn refill(&mut self, limit: &BucketLimit, now_ms: i64) {
+        // Clocks of different instances can disagree a little: never refill
+        // backwards.
+        let elapsed = (now_ms - self.updated_at_ms).max(0) as f64;
+        self.tokens =
+            (self.tokens + elapsed * limit.refill_per_millisecond()).min(f64::from(limit.burst));
+        self.updated_at_ms = self.updated_at_ms.max(now_ms);
+    }
+
+    /// Take a token if one is available, otherwise return how long until
+    /// the next one is.
+    fn take(&mut self, limit: &BucketLimit, now_ms: i64) -> Result<(), Duration> {
+        self.refill(limit, now_ms);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / limit.refill_per_millisecond() / 1000.0,
+            ))
+        }
+    }
+}
+
+/// Which limit a bucket counts for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BucketKind {
+    Ip,
+    Email,
+}
+
+/// The in-memory buckets, one map per limit so that a flood of email
+/// addresses does not push the client addresses out.
+pub struct MemoryBuckets {
+    per_ip: Mutex<LruCache<String, Bucket>>,
+    per_email: Mutex<LruCache<String, Bucket>>,
+}
+
+impl MemoryBuckets {
+    fn with_capacity(capacity: NonZeroUsize) -> Self {
+        Self {
+            per_ip: Mutex::new(LruCache::new(capacity)),
+            per_email: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    fn of(&self, kind: BucketKind) -> &Mutex<LruCache<String, Bucket>> {
+        match kind {
+            BucketKind::Ip => &self.per_ip,
+            BucketKind::Email => &self.per_email,
+        }
+    }
+}
+
+/// Where the buckets live.
+#[derive(Clone)]
+pub enum BucketStore {
+    /// In the process: each instance limits on its own. Forgetting a bucket
+    /// refills it, which is fine for the least recently used ones.
+    Memory(Arc<MemoryBuckets>),
+    /// In the `rate_limit_buckets` table, shared by every instance.
+    MySql(DatabaseConnection),
+}
+
+impl BucketStore {
+    pub fn memory() -> Self {
+        Self::memory_with_capacity(NonZeroUsize::new(MAX_MEMORY_BUCKETS).unwrap())
+    }
+
+    fn memory_with_capacity(capacity: NonZeroUsize) -> Self {
+        Self::Memory(Arc::new(MemoryBuckets::with_capacity(capacity)))
+    }
+
+    async fn take(
+        &self,
+        kind: BucketKind,
+        key: &str,
+        limit: &BucketLimit,
+    ) -> Result<(), TakeError> {
+        let now_ms = Utc::now().timestamp_millis();
+        match self {
+            Self::Memory(buckets) => buckets
+                .of(kind)
+                .lock()
+                .unwrap()
+                .get_or_insert_mut(key.to_string(), || Bucket::full(limit, now_ms))
+                .take(limit, now_ms)
+                .map_err(TakeError::Limited),
+            Self::MySql(db) => take_from_database(db, key, limit, now_ms).await,
+        }
+    }
+}
+
+enum TakeError {
+    Limited(Duration),
+    Database(DbErr),
+}
+
+impl From<DbErr> for TakeError {
+    fn from(e: DbErr) -> Self {
+        Self::Database(e)
+    }
+}
+
+async fn take_from_database(
+    db: &DatabaseConnection,
+    key: &str,
+    limit: &BucketLimit,
+    now_ms: i64,
+) -> Result<(), TakeError> {
+    // 1️⃣ 确保桶存在（并发插入时只有一个生效）
+    let txn = db.begin().await?;
+    let full = Bucket::full(limit, now_ms);
+    rate_limit_buckets::Entity::insert(rate_limit_buckets::ActiveModel {
+        bucket_key: Set(key.to_string()),
+        tokens: Set(full.tokens),
+        updated_at_ms: Set(full.updated_at_ms),
+    })
+    .on_conflict_do_nothing_on([rate_limit_buckets::Column::BucketKey])
+    .exec(&txn)
+    .await?;
+
+    // 2️⃣ 锁住这一行，取令牌
+    let row = rate_limit_buckets::Entity::find_by_id(key.to_string())
+        .lock(LockType::Update)
+        .one(&txn)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(key.to_string()))?;
+    let mut bucket = Bucket {
+        tokens: row.tokens,
+        updated_at_ms: row.updated_at_ms,
+    };
+    let taken = bucket.take(limit, now_ms);
+
+    // 3️⃣ 写回并提交
+    let mut row: rate_limit_buckets::ActiveModel = row.into();
+    row.tokens = Set(bucket.tokens);
+    row.updated_at_ms = Set(bucket.updated_at_ms);
+    row.update(&txn).await?;
+    txn.commit().await?;
+    taken.map_err(TakeError::Limited)
+}
+
+/// The limits of the public forms and the buckets enforcing them.
+#[derive(Clone)]
+pub struct RateLimits {
+    pub enabled: bool,
+    pub per_ip: BucketLimit,
+    pub per_email: BucketLimit,
+    pub store: BucketStore,
+}
+
+impl RateLimits {
+    /// Take a token from each bucket the request draws from, the client
+    /// address first so that a flood from one address does not lock its
+    /// victims out. `email` is the canonical form of the address, so that
+    /// its spellings share a bucket.
+    async fn check(&self, scope: &str, ip: IpAddr, email: Option<&str>) -> Result<(), Duration> {
+        let mut buckets = vec![(
+            BucketKind::Ip,
+            format!("{}:ip:{}", scope, ip_key(ip)),
+            &self.per_ip,
+        )];
+        if let Some(email) = email {
+            buckets.push((
+                BucketKind::Email,
+                format!("{}:email:{}", scope, hash_email(email)),
+                &self.per_email,
+            ));
+        }
+        for (kind, key, limit) in buckets {
+            if !limit.is_enabled() {
+                continue;
+            }
+            match self.store.take(kind, &key, limit).await {
+                Ok(()) => {}
+                Err(TakeError::Limited(retry_after)) => return Err(retry_after),
+                // Better to let a few requests too many in than to turn
+                // every subscriber away while the database struggles.
+                Err(TakeError::Database(e)) => {
+                    tracing::error!("Failed to check the rate limit: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RateLimits {
+    /// Delete the shared buckets that filled up again since they were last
+    /// used, returning how many. Every client and email address gets a row,
+    /// which would otherwise stay forever.
+    #[tracing::instrument(name = "Prune the rate limit buckets", skip(self))]
+    pub async fn prune(&self) -> Result<u64, DbErr> {
+        let BucketStore::MySql(db) = &self.store else {
+            return Ok(0);
+        };
+        let Some(refill_duration_ms) = [self.per_ip, self.per_email]
+            .iter()
+            .filter(|limit| limit.is_enabled())
+            .map(BucketLimit::refill_duration_ms)
+            .max()
+        else {
+            return Ok(0);
+        };
+        let cutoff_ms = Utc::now().timestamp_millis() - refill_duration_ms;
+        let result = rate_limit_buckets::Entity::delete_many()
+            .filter(rate_limit_buckets::Column::UpdatedAtMs.lt(cutoff_ms))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
+
+/// IPv6 clients usually get a whole /64: limit the prefix, not each of its
+/// addresses.
+pub(crate) fn ip_key(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!(
+                "{:x}:{:x}:{:x}:{:x}::/64",
+                segments[0], segments[1], segments[2], segments[3]
+            )
+        }
+    }
+}
+
+/// Middleware answering `429 Too Many Requests`, with a `Retry-After` in
+/// seconds, once a client address or an email address used up its bucket.
+pub async fn rate_limit(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limits = &state.rate_limits;
+    if !limits.enabled {
+        return next.run(request).await;
+    }
+    let scope = request.uri().path().to_string();
+    // The email is in the form: read it, then hand the body on untouched.
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, MAX_FORM_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let email = url::form_urlencoded::parse(&bytes)
+        .find(|(name, _)| name == "email")
+        .map(
+            |(_, value)| match SubscriberEmail::parse(value.clone().into_owned()) {
+                Ok(email) => state.email_policy.canonical(&email),
+                // The handler turns it away, the address bucket still counts it.
+                Err(_) => value.trim().to_lowercase(),
+            },
+        );
+    if let Err(retry_after) = limits.check(&scope, ip, email.as_deref()).await {
+        tracing::warn!(client_ip = %ip, path = %scope, "Rate limited a request");
+        return too_many_requests(retry_after);
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, HeaderValue::from(seconds))],
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bucket, BucketKind, BucketLimit, BucketStore, ip_key, too_many_requests};
+    use axum::http::header;
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    const LIMIT: BucketLimit = BucketLimit {
+        burst: 2,
+        per_minute: 6,
+    };
+
+    /// Seconds to wait, to the millisecond.
+    fn wait(taken: Result<(), Duration>) -> f64 {
+        (taken.unwrap_err().as_secs_f64() * 1000.0).round() / 1000.0
+    }
+
+    #[test]
+    fn buckets_left_alone_for_the_refill_duration_are_full() {
+        assert_eq!(LIMIT.refill_duration_ms(), 20_000);
+        let mut bucket = Bucket::full(&LIMIT, 0);
+        bucket.take(&LIMIT, 0).unwrap();
+        bucket.take(&LIMIT, 0).unwrap();
+        bucket.refill(&LIMIT, LIMIT.refill_duration_ms());
+        assert_eq!(bucket, Bucket::full(&LIMIT, LIMIT.refill_duration_ms()));
+    }
+
+    #[test]
+    fn a_full_bucket_lets_a_burst_through() {
+        let mut bucket = Bucket::full(&LIMIT, 0);
+        assert!(bucket.take(&LIMIT, 0).is_ok());
+        assert!(bucket.take(&LIMIT, 0).is_ok());
+        // One token every ten seconds.
+        assert_eq!(wait(bucket.take(&LIMIT, 0)), 10.0);
+    }
+
+    #[test]
+    fn buckets_refill_over_time_up_to_the_burst() {
+        let mut bucket = Bucket::full(&LIMIT, 0);
+        bucket.tokens = 0.0;
+        assert_eq!(wait(bucket.take(&LIMIT, 4_000)), 6.0);
+        assert!(bucket.take(&LIMIT, 10_000).is_ok());
+        bucket.refill(&LIMIT, 1_000_000);
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn clocks_going_backwards_do_not_drain_buckets() {
+        let mut bucket = Bucket::full(&LIMIT, 10_000);
+        bucket.refill(&LIMIT, 0);
+        assert_eq!(bucket.tokens, 2.0);
+        assert_eq!(bucket.updated_at_ms, 10_000);
+    }
+
+    #[tokio::test]
+    async fn the_memory_store_keeps_buckets_apart() {
+        let store = BucketStore::memory();
+        let limit = BucketLimit {
+            burst: 1,
+            per_minute: 1,
+        };
+        assert!(store.take(BucketKind::Ip, "a", &limit).await.is_ok());
+        assert!(store.take(BucketKind::Ip, "a", &limit).await.is_err());
+        assert!(store.take(BucketKind::Ip, "b", &limit).await.is_ok());
+        assert!(store.take(BucketKind::Email, "a", &limit).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn the_memory_store_forgets_the_least_recently_used_buckets() {
+        let store = BucketStore::memory_with_capacity(NonZeroUsize::new(3).unwrap());
+        let limit = BucketLimit {
+            burst: 1,
+            per_minute: 1,
+        };
+        assert!(store.take(BucketKind::Email, "kept", &limit).await.is_ok());
+        for i in 0..1_000 {
+            let _ = store
+                .take(BucketKind::Email, &format!("bot-{}", i), &limit)
+                .await;
+            // Used all along, so never the least recently used.
+            assert!(store.take(BucketKind::Email, "kept", &limit).await.is_err());
+        }
+        let BucketStore::Memory(buckets) = &store else {
+            unreachable!()
+        };
+        assert_eq!(buckets.per_email.lock().unwrap().len(), 3);
+        assert_eq!(buckets.per_ip.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn ipv6_clients_are_limited_per_prefix() {
+        assert_eq!(ip_key("203.0.113.7".parse().unwrap()), "203.0.113.7");
+        assert_eq!(
+            ip_key("2001:db8:1:2:3:4:5:6".parse().unwrap()),
+            "2001:db8:1:2::/64"
+        );
+    }
+
+    #[test]
+    fn retry_after_is_rounded_up_to_whole_seconds() {
+        let response = too_many_requests(Duration::from_millis(1_200));
+        assert_eq!(response.status().as_u16(), 429);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
@@ -0,0 +1,133 @@
+use crate::ab_testing::{METRICS, VariantStats, find_variants, variant_stats};
+use crate::entity::{ab_tests, issue_variants};
+use crate::routes::admin::AdminUser;
+use crate::startup::AppState;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+
+/// Labels of the variants, in the order they are given.
+const LABELS: [&str; 3] = ["A", "B", "C"];
+/// A week.
+const MAX_WAIT_MINUTES: i32 = 7 * 24 * 60;
+
+#[derive(Deserialize, Debug)]
+pub struct NewVariant {
+    title: String,
+    /// The content of the issue when missing.
+    html_content: Option<String>,
+    text_content: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewAbTest {
+    /// The share of the audience the variants are tested on, e.g. `20` to
+    /// send each of two variants to 10% of it.
+    sample_percentage: i32,
+    /// How long to wait before sending the winner to everyone else.
+    wait_minutes: i32,
+    /// `opens` or `clicks`, `opens` when missing.
+    metric: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AbTestReport {
+    pub issue_id: i32,
+    pub status: String,
+    pub sample_percentage: i32,
+    pub metric: String,
+    pub decide_at: Option<DateTime<Utc>>,
+    /// Label of the winning variant, once it has been sent.
+    pub winner: Option<String>,
+    pub variants: Vec<VariantStats>,
+}
+
+/// Store the variants and the A/B test of a new issue as part of `txn`.
+/// Tests need open and click tracking, and two or three variants.
+pub(super) async fn create_ab_test(
+    txn: &DatabaseTransaction,
+    state: &AppState,
+    issue_id: i32,
+    variants: Vec<NewVariant>,
+    test: NewAbTest,
+) -> Result<(), StatusCode> {
+    let metric = test.metric.unwrap_or_else(|| METRICS[0].to_string());
+    let is_valid = state.tracking.enabled
+        && (2..=LABELS.len()).contains(&variants.len())
+        && (1..=99).contains(&test.sample_percentage)
+        && (1..=MAX_WAIT_MINUTES).contains(&test.wait_minutes)
+        && METRICS.contains(&metric.as_str())
+        && variants.iter().all(|variant| {
+            !variant.title.trim().is_empty()
+                && !variant
+                    .html_content
+                    .as_ref()
+                    .is_some_and(|html| html.trim().is_empty())
+        });
+    if !is_valid {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    for (variant, label) in variants.into_iter().zip(LABELS) {
+        issue_variants::ActiveModel {
+            newsletter_issue_id: Set(issue_id),
+            label: Set(label.to_string()),
+            title: Set(variant.title),
+            html_content: Set(variant.html_content),
+            text_content: Set(variant.text_content),
+            ..Default::default()
+        }
+        .insert(txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    ab_tests::ActiveModel {
+        newsletter_issue_id: Set(issue_id),
+        sample_percentage: Set(test.sample_percentage),
+        wait_minutes: Set(test.wait_minutes),
+        metric: Set(metric),
+        status: Set("pending".to_string()),
+        ..Default::default()
+    }
+    .insert(txn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get the A/B test of a newsletter issue", skip(state))]
+pub async fn ab_test_report(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+) -> Result<Json<AbTestReport>, StatusCode> {
+    let test = ab_tests::Entity::find()
+        .filter(ab_tests::Column::NewsletterIssueId.eq(issue_id))
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let winner = match test.winner_variant_id {
+        Some(winner_id) => find_variants(&state.db, issue_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .into_iter()
+            .find(|variant| variant.id == winner_id)
+            .map(|variant| variant.label),
+        None => None,
+    };
+    let variants = variant_stats(&state.db, issue_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(AbTestReport {
+        issue_id,
+        status: test.status,
+        sample_percentage: test.sample_percentage,
+        metric: test.metric,
+        decide_at: test.decide_at,
+        winner,
+        variants,
+    }))
+}
@@ -0,0 +1,226 @@
+use crate::entity::audit_log;
+use crate::routes::admin::AdminUser;
+use crate::startup::AppState;
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// An action taken by an admin: who did what to which entity, with the
+/// entity before and after when it makes sense.
+pub struct AuditEntry {
+    actor: String,
+    action: String,
+    target_type: String,
+    target_id: Option<String>,
+    before: Option<Value>,
+    after: Option<Value>,
+    request_id: Option<String>,
+}
+
+impl AuditEntry {
+    /// `action` is `<target type>.<verb>`, e.g. `issue.publish`.
+    pub fn new(
+        admin: &AdminUser,
+        action: &str,
+        target_type: &str,
+        target_id: impl ToString,
+    ) -> Self {
+        Self {
+            actor: admin.username.clone(),
+            action: action.to_string(),
+            target_type: target_type.to_string(),
+            target_id: Some(target_id.to_string()),
+            before: None,
+            after: None,
+            request_id: admin.request_id.clone(),
+        }
+    }
+
+    /// An action on no entity in particular, e.g. an export.
+    pub fn without_target(admin: &AdminUser, action: &str, target_type: &str) -> Self {
+        Self {
+            target_id: None,
+            ..Self::new(admin, action, target_type, "")
+        }
+    }
+
+    /// The target as it was before the action.
+    pub fn before(mut self, before: &impl Serialize) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self
+    }
+
+    /// The target as the action left it.
+    pub fn after(mut self, after: &impl Serialize) -> Self {
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+
+    /// Store the entry, in the transaction of the action when there is one
+    /// so that both are kept or neither.
+    pub async fn record<C: ConnectionTrait>(self, db: &C) -> Result<(), DbErr> {
+        audit_log::ActiveModel {
+            actor: Set(self.actor),
+            action: Set(self.action),
+            target_type: Set(self.target_type),
+            target_id: Set(self.target_id),
+            before: Set(self.before),
+            after: Set(self.after),
+            // Client supplied ids can be anything: keep what fits.
+            request_id: Set(self.request_id.map(|id| id.chars().take(64).collect())),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListAuditParameters {
+    actor: Option<String>,
+    action: Option<String>,
+    target_type: Option<String>,
+    /// Only used together with `target_type`.
+    target_id: Option<String>,
+    request_id: Option<String>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<i32>,
+    #[serde(default = "default_limit")]
+    limit: u64,
+}
+
+fn default_limit() -> u64 {
+    100
+}
+
+#[derive(Serialize)]
+pub struct AuditLogEntry {
+    pub id: i32,
+    pub actor: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<audit_log::Model> for AuditLogEntry {
+    fn from(model: audit_log::Model) -> Self {
+        Self {
+            id: model.id,
+            actor: model.actor,
+            action: model.action,
+            target_type: model.target_type,
+            target_id: model.target_id,
+            before: model.before,
+            after: model.after,
+            request_id: model.request_id,
+            created_at: model.created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditLogEntry>,
+    /// Pass it as `cursor` to get the next page; `None` on the last page.
+    pub next_cursor: Option<i32>,
+}
+
+/// The audit log, newest entries first.
+#[tracing::instrument(name = "List the audit log", skip(state))]
+pub async fn list_audit_entries(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Query(parameters): Query<ListAuditParameters>,
+) -> Result<Json<AuditPage>, StatusCode> {
+    if parameters.target_id.is_some() && parameters.target_type.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = parameters.limit.clamp(1, 1000);
+    let mut query = audit_log::Entity::find();
+    if let Some(actor) = &parameters.actor {
+        query = query.filter(audit_log::Column::Actor.eq(actor));
+    }
+    if let Some(action) = &parameters.action {
+        query = query.filter(audit_log::Column::Action.eq(action));
+    }
+    if let Some(target_type) = &parameters.target_type {
+        query = query.filter(audit_log::Column::TargetType.eq(target_type));
+    }
+    if let Some(target_id) = &parameters.target_id {
+        query = query.filter(audit_log::Column::TargetId.eq(target_id));
+    }
+    if let Some(request_id) = &parameters.request_id {
+        query = query.filter(audit_log::Column::RequestId.eq(request_id));
+    }
+    if let Some(after) = parameters.after {
+        query = query.filter(audit_log::Column::CreatedAt.gte(after));
+    }
+    if let Some(before) = parameters.before {
+        query = query.filter(audit_log::Column::CreatedAt.lt(before));
+    }
+    if let Some(cursor) = parameters.cursor {
+        query = query.filter(audit_log::Column::Id.lt(cursor));
+    }
+    // One more than asked for tells whether there is a next page.
+    let mut entries = query
+        .order_by_desc(audit_log::Column::Id)
+        .limit(limit + 1)
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let next_cursor = if entries.len() as u64 > limit {
+        entries.truncate(limit as usize);
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+    Ok(Json(AuditPage {
+        entries: entries.into_iter().map(AuditLogEntry::from).collect(),
+        next_cursor,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditEntry;
+    use crate::routes::admin::AdminUser;
+
+    fn admin() -> AdminUser {
+        AdminUser {
+            username: "admin".into(),
+            request_id: Some("42".into()),
+        }
+    }
+
+    #[test]
+    fn entries_capture_the_actor_and_the_request() {
+        let entry = AuditEntry::new(&admin(), "subscriber.delete", "subscriber", 7)
+            .before(&serde_json::json!({ "name": "le guin" }));
+        assert_eq!(entry.actor, "admin");
+        assert_eq!(entry.target_id.as_deref(), Some("7"));
+        assert_eq!(entry.request_id.as_deref(), Some("42"));
+        assert_eq!(entry.before.unwrap()["name"], "le guin");
+        assert!(entry.after.is_none());
+    }
+
+    #[test]
+    fn entries_can_have_no_target() {
+        let entry = AuditEntry::without_target(&admin(), "subscribers.export", "subscriber");
+        assert!(entry.target_id.is_none());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::attributes::template_variables;
+use crate::domain::SubscriberEmail;
+use crate::entity::newsletter_issues;
+use crate::issue_template::{personalize, render_issue};
+use crate::routes::admin::issues::{IssueSnapshot, IssueState, release};
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::startup::AppState;
+use crate::suppression::is_suppressed;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{Html, IntoResponse};
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::LockType;
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DbErr, EntityTrait, QuerySelect, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug)]
+pub struct DraftChanges {
+    title: Option<String>,
+    html_content: Option<String>,
+    /// `null` goes back to deriving the text from the HTML content.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    text_content: Option<Option<String>>,
+}
+
+/// Tell a `null` field (`Some(None)`) apart from a missing one (`None`).
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct TestSend {
+    /// Defaults to the `test_recipients` from the `newsletter` settings.
+    ///
+    /// Any address is allowed, so that an editor can check the issue in
+    /// their own mailbox: the addresses end up in the audit log, and
+    /// suppressed ones are skipped like for any other send.
+    recipients: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct TestSendReport {
+    pub recipients: Vec<String>,
+    /// Recipients on the suppression list, who were not sent the issue.
+    pub suppressed: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Release {
+    /// Send the issue at this time instead of straight away.
+    scheduled_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(
+    name = "Edit a draft newsletter issue",
+    skip(admin, state, body),
+    fields(admin = %admin.username)
+)]
+pub async fn update_draft(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+    Json(body): Json<DraftChanges>,
+) -> Result<StatusCode, StatusCode> {
+    let is_blank = |field: &Option<String>| field.as_ref().is_some_and(|f| f.trim().is_empty());
+    if is_blank(&body.title) || is_blank(&body.html_content) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let issue = find_draft(&txn, issue_id).await?;
+    let before = IssueSnapshot::from(&issue);
+    let mut issue: newsletter_issues::ActiveModel = issue.into();
+    if let Some(title) = body.title {
+        issue.title = Set(title);
+    }
+    if let Some(html_content) = body.html_content {
+        issue.html_content = Set(html_content);
+    }
+    if let Some(text_content) = body.text_content {
+        issue.text_content = Set(text_content);
+    }
+    let issue = issue
+        .update(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AuditEntry::new(&admin, "issue.update", "issue", issue_id)
+        .before(&before)
+        .after(&IssueSnapshot::from(&issue))
+        .record(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The issue as subscribers will see it, without tracking.
+#[tracing::instrument(name = "Preview a newsletter issue", skip(state))]
+pub async fn preview_issue(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let issue = newsletter_issues::Entity::find_by_id(issue_id)
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let rendered = personalize(&render_issue(&issue), &Default::default());
+    Ok(([(header::CACHE_CONTROL, "no-store")], Html(rendered.html)))
+}
+
+#[tracing::instrument(
+    name = "Test-send a newsletter issue",
+    skip(admin, state, body),
+    fields(admin = %admin.username)
+)]
+pub async fn test_send_issue(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+    body: Option<Json<TestSend>>,
+) -> Result<Json<TestSendReport>, StatusCode> {
+    let recipients = body
+        .and_then(|Json(body)| body.recipients)
+        .unwrap_or_else(|| state.newsletter.test_recipients.clone())
+        .into_iter()
+        .map(SubscriberEmail::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if recipients.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let issue = newsletter_issues::Entity::find_by_id(issue_id)
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let rendered = render_issue(&issue);
+    let mut sent = Vec::with_capacity(recipients.len());
+    let mut suppressed = Vec::new();
+    for recipient in recipients {
+        let address = recipient.as_ref().to_string();
+        if is_suppressed(&state.db, &address)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            suppressed.push(address);
+            continue;
+        }
+        // Test recipients have no attributes: variables show their default.
+        let rendered = personalize(&rendered, &template_variables("", &address, None));
+        let subject = format!("[TEST] {}", rendered.subject);
+        // Sent straight away rather than through the outbox: the editor is
+        // waiting for it.
+        state
+            .email_client
+            .send_email(recipient, &subject, &rendered.html, &rendered.text)
+            .await
+            .map_err(|e| {
+                tracing::error!(error.message = %e, "Failed to send a test email");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        sent.push(address);
+    }
+    let report = TestSendReport {
+        recipients: sent,
+        suppressed,
+    };
+    AuditEntry::new(&admin, "issue.test_send", "issue", issue_id)
+        .after(&report)
+        .record(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(report))
+}
+
+/// Release a draft to confirmed subscribers, now or at `scheduled_at`.
+#[tracing::instrument(
+    name = "Release a draft newsletter issue",
+    skip(admin, state, body),
+    fields(admin = %admin.username)
+)]
+pub async fn publish_draft(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+    body: Option<Json<Release>>,
+) -> Result<Json<IssueState>, StatusCode> {
+    let Json(body) = body.unwrap_or_default();
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let issue = find_draft(&txn, issue_id).await?;
+    let before = IssueSnapshot::from(&issue);
+    let after = IssueSnapshot::from(&issue);
+    let issue_state = release(&txn, issue, body.scheduled_at, &state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AuditEntry::new(&admin, "issue.publish", "issue", issue_id)
+        .before(&before)
+        .after(&after.released(&issue_state))
+        .record(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(issue_state))
+}
+
+/// Lock a draft for update: `404` if the issue does not exist, `409` if it
+/// is no longer a draft.
+async fn find_draft<C: ConnectionTrait>(
+    db: &C,
+    issue_id: i32,
+) -> Result<newsletter_issues::Model, StatusCode> {
+    let issue = newsletter_issues::Entity::find_by_id(issue_id)
+        .lock(LockType::Update)
+        .one(db)
+        .await
+        .map_err(|e: DbErr| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if issue.status != "draft" {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(issue)
+}
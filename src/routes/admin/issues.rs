@@ -0,0 +1,428 @@
+use crate::entity::{
+    email_clicks, email_opens, issue_recipients, newsletter_issues, segments, tracked_links,
+};
+use crate::issue_delivery::publish;
+use crate::lists::{DEFAULT_LIST, find_list};
+use crate::routes::admin::ab_tests::{NewAbTest, NewVariant, create_ab_test};
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::startup::AppState;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::{Expr, Func, LockType, SimpleExpr};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait,
+    JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug)]
+pub struct NewIssue {
+    title: String,
+    html_content: String,
+    /// Derived from the HTML content when missing.
+    text_content: Option<String>,
+    /// Keep the issue as a draft instead of releasing it.
+    #[serde(default)]
+    draft: bool,
+    /// Send the issue at this time instead of straight away.
+    scheduled_at: Option<DateTime<Utc>>,
+    /// Slug of the list the issue goes to, the default list when missing.
+    list: Option<String>,
+    /// Only send the issue to the subscribers of the list in this segment.
+    segment_id: Option<i32>,
+    /// Versions of the issue to A/B test, together with `ab_test`.
+    #[serde(default)]
+    variants: Vec<NewVariant>,
+    ab_test: Option<NewAbTest>,
+}
+
+#[derive(Serialize)]
+pub struct IssueState {
+    pub id: i32,
+    pub status: String,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// Emails enqueued so far: zero for drafts and scheduled issues.
+    pub recipients: u64,
+}
+
+/// What the audit log keeps of an issue.
+#[derive(Serialize)]
+pub(super) struct IssueSnapshot {
+    id: i32,
+    title: String,
+    html_content: String,
+    text_content: Option<String>,
+    status: String,
+    scheduled_at: Option<DateTime<Utc>>,
+    list_id: i32,
+    segment_id: Option<i32>,
+}
+
+impl IssueSnapshot {
+    /// The issue once `release` is done with it.
+    pub(super) fn released(mut self, state: &IssueState) -> Self {
+        self.status = state.status.clone();
+        self.scheduled_at = state.scheduled_at;
+        self
+    }
+}
+
+impl From<&newsletter_issues::Model> for IssueSnapshot {
+    fn from(issue: &newsletter_issues::Model) -> Self {
+        Self {
+            id: issue.id,
+            title: issue.title.clone(),
+            html_content: issue.html_content.clone(),
+            text_content: issue.text_content.clone(),
+            status: issue.status.clone(),
+            scheduled_at: issue.scheduled_at,
+            list_id: issue.list_id,
+            segment_id: issue.segment_id,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Schedule {
+    scheduled_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct IssueStats {
+    pub issue_id: i32,
+    pub recipients: u64,
+    pub opens: i64,
+    pub unique_opens: i64,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+    pub links: Vec<LinkStats>,
+}
+
+#[derive(Serialize)]
+pub struct LinkStats {
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+#[tracing::instrument(
+    name = "Create a newsletter issue",
+    skip(admin, state, body),
+    fields(admin = %admin.username, title = %body.title)
+)]
+pub async fn create_issue(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<NewIssue>,
+) -> Result<(StatusCode, Json<IssueState>), StatusCode> {
+    if body.title.trim().is_empty() || body.html_content.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Drafts are scheduled when they are released.
+    if body.draft && body.scheduled_at.is_some() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if body.variants.is_empty() != body.ab_test.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let list = find_list(&txn, body.list.as_deref().unwrap_or(DEFAULT_LIST))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if let Some(segment_id) = body.segment_id {
+        segments::Entity::find_by_id(segment_id)
+            .one(&txn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::BAD_REQUEST)?;
+    }
+    let issue = newsletter_issues::ActiveModel {
+        list_id: Set(list.id),
+        segment_id: Set(body.segment_id),
+        title: Set(body.title),
+        html_content: Set(body.html_content),
+        text_content: Set(body.text_content),
+        status: Set("draft".to_string()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(ab_test) = body.ab_test {
+        create_ab_test(&txn, &state, issue.id, body.variants, ab_test).await?;
+    }
+    let snapshot = IssueSnapshot::from(&issue);
+    let issue_state = if body.draft {
+        IssueState {
+            id: issue.id,
+            status: issue.status,
+            scheduled_at: None,
+            recipients: 0,
+        }
+    } else {
+        release(&txn, issue, body.scheduled_at, &state)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    };
+    AuditEntry::new(&admin, "issue.create", "issue", issue_state.id)
+        .after(&snapshot.released(&issue_state))
+        .record(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((StatusCode::CREATED, Json(issue_state)))
+}
+
+/// Schedule `issue` for `scheduled_at`, or publish it straight away if no
+/// send time is given or it is in the past.
+pub(super) async fn release(
+    txn: &DatabaseTransaction,
+    issue: newsletter_issues::Model,
+    scheduled_at: Option<DateTime<Utc>>,
+    state: &AppState,
+) -> Result<IssueState, DbErr> {
+    let id = issue.id;
+    if let Some(scheduled_at) = scheduled_at.filter(|at| *at > Utc::now()) {
+        let mut issue: newsletter_issues::ActiveModel = issue.into();
+        issue.status = Set("scheduled".to_string());
+        issue.scheduled_at = Set(Some(scheduled_at));
+        issue.update(txn).await?;
+        return Ok(IssueState {
+            id,
+            status: "scheduled".to_string(),
+            scheduled_at: Some(scheduled_at),
+            recipients: 0,
+        });
+    }
+    let delivery = publish(txn, issue, &state.base_url, state.tracking.enabled).await?;
+    Ok(IssueState {
+        id,
+        status: delivery.status.to_string(),
+        scheduled_at,
+        recipients: delivery.recipients,
+    })
+}
+
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip(admin, state, body),
+    fields(admin = %admin.username)
+)]
+pub async fn reschedule_issue(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+    Json(body): Json<Schedule>,
+) -> Result<StatusCode, StatusCode> {
+    update_schedule(&state.db, &admin, "issue.reschedule", issue_id, |issue| {
+        // Also gives a `failed` issue another round of attempts.
+        issue.status = Set("scheduled".to_string());
+        issue.scheduled_at = Set(Some(body.scheduled_at));
+    })
+    .await
+}
+
+#[tracing::instrument(
+    name = "Cancel a scheduled newsletter issue",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn cancel_issue_schedule(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+) -> Result<StatusCode, StatusCode> {
+    // The issue goes back to being a draft, which can be edited and
+    // released again.
+    update_schedule(
+        &state.db,
+        &admin,
+        "issue.cancel_schedule",
+        issue_id,
+        |issue| {
+            issue.status = Set("draft".to_string());
+            issue.scheduled_at = Set(None);
+        },
+    )
+    .await
+}
+
+/// Apply `change` to a scheduled issue, or one the scheduler failed to
+/// publish. Issues that are already being sent or were sent can no longer be
+/// changed: `409 Conflict`.
+async fn update_schedule(
+    db: &DatabaseConnection,
+    admin: &AdminUser,
+    action: &str,
+    issue_id: i32,
+    change: impl FnOnce(&mut newsletter_issues::ActiveModel),
+) -> Result<StatusCode, StatusCode> {
+    let txn = db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Waits for the scheduler if it is publishing the issue right now, and
+    // keeps it from picking the issue up until we are done.
+    let issue = newsletter_issues::Entity::find_by_id(issue_id)
+        .lock(LockType::Update)
+        .one(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if issue.status != "scheduled" && issue.status != "failed" {
+        return Err(StatusCode::CONFLICT);
+    }
+    let before = IssueSnapshot::from(&issue);
+    let mut issue: newsletter_issues::ActiveModel = issue.into();
+    change(&mut issue);
+    issue.publish_attempts = Set(0);
+    issue.last_error = Set(None);
+    issue.next_attempt_at = Set(None);
+    let issue = issue
+        .update(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AuditEntry::new(admin, action, "issue", issue_id)
+        .before(&before)
+        .after(&IssueSnapshot::from(&issue))
+        .record(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(name = "Get the stats of a newsletter issue", skip(state))]
+pub async fn issue_stats(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Path(issue_id): Path<i32>,
+) -> Result<Json<IssueStats>, StatusCode> {
+    newsletter_issues::Entity::find_by_id(issue_id)
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let stats = compute_stats(&state.db, issue_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(stats))
+}
+
+async fn compute_stats(db: &DatabaseConnection, issue_id: i32) -> Result<IssueStats, DbErr> {
+    let recipients = issue_recipients::Entity::find()
+        .filter(issue_recipients::Column::NewsletterIssueId.eq(issue_id))
+        .count(db)
+        .await?;
+
+    let (opens, unique_opens) = email_opens::Entity::find()
+        .join(
+            JoinType::InnerJoin,
+            email_opens::Relation::IssueRecipients.def(),
+        )
+        .filter(issue_recipients::Column::NewsletterIssueId.eq(issue_id))
+        .select_only()
+        .column_as(
+            SimpleExpr::from(Func::count(Expr::col((
+                email_opens::Entity,
+                email_opens::Column::Id,
+            )))),
+            "opens",
+        )
+        .column_as(
+            SimpleExpr::from(Func::count_distinct(Expr::col((
+                email_opens::Entity,
+                email_opens::Column::IssueRecipientId,
+            )))),
+            "unique_opens",
+        )
+        .into_tuple::<(i64, i64)>()
+        .one(db)
+        .await?
+        .unwrap_or_default();
+
+    let (clicks, unique_clicks) = email_clicks::Entity::find()
+        .join(
+            JoinType::InnerJoin,
+            email_clicks::Relation::IssueRecipients.def(),
+        )
+        .filter(issue_recipients::Column::NewsletterIssueId.eq(issue_id))
+        .select_only()
+        .column_as(
+            SimpleExpr::from(Func::count(Expr::col((
+                email_clicks::Entity,
+                email_clicks::Column::Id,
+            )))),
+            "clicks",
+        )
+        .column_as(
+            SimpleExpr::from(Func::count_distinct(Expr::col((
+                email_clicks::Entity,
+                email_clicks::Column::IssueRecipientId,
+            )))),
+            "unique_clicks",
+        )
+        .into_tuple::<(i64, i64)>()
+        .one(db)
+        .await?
+        .unwrap_or_default();
+
+    let links = tracked_links::Entity::find()
+        .join(
+            JoinType::LeftJoin,
+            tracked_links::Relation::EmailClicks.def(),
+        )
+        .filter(tracked_links::Column::NewsletterIssueId.eq(issue_id))
+        .select_only()
+        .column(tracked_links::Column::Url)
+        .column_as(
+            SimpleExpr::from(Func::count(Expr::col((
+                email_clicks::Entity,
+                email_clicks::Column::Id,
+            )))),
+            "clicks",
+        )
+        .column_as(
+            SimpleExpr::from(Func::count_distinct(Expr::col((
+                email_clicks::Entity,
+                email_clicks::Column::IssueRecipientId,
+            )))),
+            "unique_clicks",
+        )
+        .group_by(tracked_links::Column::Id)
+        .group_by(tracked_links::Column::Url)
+        .order_by_asc(tracked_links::Column::Id)
+        .into_tuple::<(String, i64, i64)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|(url, clicks, unique_clicks)| LinkStats {
+            url,
+            clicks,
+            unique_clicks,
+        })
+        .collect();
+
+    Ok(IssueStats {
+        issue_id,
+        recipients,
+        opens,
+        unique_opens,
+        clicks,
+        unique_clicks,
+        links,
+    })
+}
@@ -0,0 +1,152 @@
+use crate::archive::slugify;
+use crate::attributes::AttributeSchema;
+use crate::entity::lists;
+use crate::lists::find_list;
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::startup::AppState;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, EntityTrait, QueryOrder, Set};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct List {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// The attributes its subscribe form accepts.
+    pub attributes: AttributeSchema,
+}
+
+impl From<lists::Model> for List {
+    fn from(model: lists::Model) -> Self {
+        Self {
+            id: model.id,
+            slug: model.slug,
+            name: model.name,
+            created_at: model.created_at,
+            attributes: AttributeSchema::from_column(model.attribute_schema.as_ref())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewList {
+    /// What the subscribe form and the admin API refer to the list by.
+    slug: String,
+    name: String,
+    #[serde(default)]
+    attributes: AttributeSchema,
+}
+
+#[tracing::instrument(name = "List mailing lists", skip(state))]
+pub async fn list_lists(
+    _: AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<List>>, StatusCode> {
+    let lists = lists::Entity::find()
+        .order_by_asc(lists::Column::Id)
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(lists.into_iter().map(List::from).collect()))
+}
+
+#[tracing::instrument(
+    name = "Create a mailing list",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn create_list(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<NewList>,
+) -> Result<(StatusCode, Json<List>), StatusCode> {
+    // Slugs end up in forms and URLs: only accept what `slugify` would produce.
+    if body.slug.is_empty() || slugify(&body.slug) != body.slug || body.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if find_list(&state.db, &body.slug)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some()
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+    let attribute_schema = schema_column(&body.attributes, &state)?;
+    let list = lists::ActiveModel {
+        slug: Set(body.slug),
+        name: Set(body.name),
+        created_at: Set(Utc::now()),
+        attribute_schema: Set(attribute_schema),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let list = List::from(list);
+    AuditEntry::new(&admin, "list.create", "list", list.id)
+        .after(&list)
+        .record(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((StatusCode::CREATED, Json(list)))
+}
+
+/// Replace the attribute schema of a list. Attributes already stored are
+/// kept, even when the schema no longer has them.
+#[tracing::instrument(
+    name = "Update the attributes of a mailing list",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn update_list_attributes(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(slug): Path<String>,
+    Json(schema): Json<AttributeSchema>,
+) -> Result<Json<List>, StatusCode> {
+    let attribute_schema = schema_column(&schema, &state)?;
+    let list = find_list(&state.db, &slug)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let before = List::from(list.clone());
+    let mut list: lists::ActiveModel = list.into();
+    list.attribute_schema = Set(attribute_schema);
+    let list = List::from(
+        list.update(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    );
+    AuditEntry::new(&admin, "list.update_attributes", "list", list.id)
+        .before(&before)
+        .after(&list)
+        .record(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(list))
+}
+
+/// The value of the `attribute_schema` column for a valid `schema`.
+fn schema_column(
+    schema: &AttributeSchema,
+    state: &AppState,
+) -> Result<Option<serde_json::Value>, StatusCode> {
+    schema
+        .validate(&state.bot_protection.fields())
+        .map_err(|e| {
+            tracing::warn!("Rejected attribute schema: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    if schema.0.is_empty() {
+        return Ok(None);
+    }
+    serde_json::to_value(schema)
+        .map(Some)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -0,0 +1,62 @@
+mod ab_tests;
+mod audit;
+mod issue_drafts;
+mod issues;
+mod lists;
+mod segments;
+mod subscriber_export;
+mod subscriber_import;
+mod subscribers;
+mod suppressions;
+mod tags;
+
+pub use ab_tests::*;
+pub use audit::*;
+pub use issue_drafts::*;
+pub use issues::*;
+pub use lists::*;
+pub use segments::*;
+pub use subscriber_export::*;
+pub use subscriber_import::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use tags::*;
+
+use crate::authentication::{basic_authentication, secrets_match};
+use crate::startup::{AppState, request_id};
+use axum::extract::FromRequestParts;
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+
+/// An administrator authenticated with the basic auth credentials from the
+/// `admin` settings. Add it to a handler's arguments to protect the route.
+pub struct AdminUser {
+    pub username: String,
+    /// The `X-Request-Id` of the request, recorded in the audit log.
+    pub request_id: Option<String>,
+}
+
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let credentials = basic_authentication(&parts.headers).map_err(|e| {
+            tracing::warn!("Rejected admin request: {}", e);
+            StatusCode::UNAUTHORIZED
+        })?;
+        if credentials.username == state.admin.username
+            && secrets_match(&credentials.password, &state.admin.password)
+        {
+            Ok(AdminUser {
+                username: credentials.username,
+                request_id: request_id(&parts.headers),
+            })
+        } else {
+            tracing::warn!(username = %credentials.username, "Rejected admin request: invalid credentials");
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
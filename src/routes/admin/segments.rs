@@ -0,0 +1,188 @@
+use crate::entity::{newsletter_issues, segments};
+use crate::issue_delivery::recipients;
+use crate::lists::{DEFAULT_LIST, find_list};
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::segments::{SegmentFilter, load_segment};
+use crate::startup::AppState;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct Segment {
+    pub id: i32,
+    pub name: String,
+    pub definition: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<segments::Model> for Segment {
+    fn from(model: segments::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            definition: serde_json::from_str(&model.definition).unwrap_or_default(),
+            created_at: model.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewSegment {
+    name: String,
+    definition: SegmentFilter,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DryRun {
+    /// Slug of the list, the default list when missing.
+    list: Option<String>,
+    /// A saved segment...
+    segment_id: Option<i32>,
+    /// ...or one being drafted. Without either, the whole list is counted.
+    definition: Option<SegmentFilter>,
+}
+
+#[derive(Serialize)]
+pub struct DryRunReport {
+    pub recipients: u64,
+}
+
+#[tracing::instrument(name = "List segments", skip(state))]
+pub async fn list_segments(
+    _: AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Segment>>, StatusCode> {
+    let segments = segments::Entity::find()
+        .order_by_asc(segments::Column::Id)
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(segments.into_iter().map(Segment::from).collect()))
+}
+
+#[tracing::instrument(
+    name = "Create a segment",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn create_segment(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<NewSegment>,
+) -> Result<(StatusCode, Json<Segment>), StatusCode> {
+    if body.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    body.definition.validate().map_err(|e| {
+        tracing::warn!("Rejected segment: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    let taken = segments::Entity::find()
+        .filter(segments::Column::Name.eq(&body.name))
+        .count(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        > 0;
+    if taken {
+        return Err(StatusCode::CONFLICT);
+    }
+    let definition =
+        serde_json::to_string(&body.definition).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let segment = segments::ActiveModel {
+        name: Set(body.name),
+        definition: Set(definition),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let segment = Segment::from(segment);
+    AuditEntry::new(&admin, "segment.create", "segment", segment.id)
+        .after(&segment)
+        .record(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((StatusCode::CREATED, Json(segment)))
+}
+
+/// Segments still targeted by an issue cannot be deleted: `409`.
+#[tracing::instrument(
+    name = "Delete a segment",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn delete_segment(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(segment_id): Path<i32>,
+) -> Result<StatusCode, StatusCode> {
+    let in_use = newsletter_issues::Entity::find()
+        .filter(newsletter_issues::Column::SegmentId.eq(segment_id))
+        .count(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        > 0;
+    if in_use {
+        return Err(StatusCode::CONFLICT);
+    }
+    let segment = segments::Entity::find_by_id(segment_id)
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let result = segments::Entity::delete_by_id(segment_id)
+        .exec(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if result.rows_affected == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    AuditEntry::new(&admin, "segment.delete", "segment", segment_id)
+        .before(&Segment::from(segment))
+        .record(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// How many subscribers an issue sent now would reach, without sending it.
+#[tracing::instrument(name = "Count the recipients of a segment", skip(state))]
+pub async fn dry_run_segment(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<DryRun>,
+) -> Result<Json<DryRunReport>, StatusCode> {
+    let segment = match (body.segment_id, body.definition) {
+        (Some(_), Some(_)) => return Err(StatusCode::BAD_REQUEST),
+        (Some(segment_id), None) => Some(
+            load_segment(&state.db, segment_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?,
+        ),
+        (None, Some(definition)) => {
+            definition.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+            Some(definition)
+        }
+        (None, None) => None,
+    };
+    let list = find_list(&state.db, body.list.as_deref().unwrap_or(DEFAULT_LIST))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let recipients = recipients(list.id, segment.as_ref(), Utc::now())
+        .count(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to count recipients: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(DryRunReport { recipients }))
+}
@@ -0,0 +1,240 @@
+use crate::entity::subscriptions;
+use crate::routes::admin::{AdminUser, AuditEntry, Subscriber};
+use crate::startup::AppState;
+use axum::body::{Body, Bytes};
+use axum::extract::{Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::SecondsFormat;
+use futures_util::{StreamExt, stream};
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait};
+use serde::{Deserialize, Serialize};
+
+/// Subscribers read from the database at a time.
+const CHUNK_SIZE: u64 = 1000;
+
+const CSV_COLUMNS: [&str; 10] = [
+    "id",
+    "email",
+    "name",
+    "status",
+    "subscribed_at",
+    "locale",
+    "digest_frequency",
+    "hard_bounce_count",
+    "soft_bounce_count",
+    "attributes",
+];
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportParameters {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+impl ExportFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "subscribers.csv",
+            ExportFormat::Jsonl => "subscribers.jsonl",
+        }
+    }
+
+    fn header(self) -> Option<String> {
+        match self {
+            ExportFormat::Csv => Some(format!("{}\r\n", CSV_COLUMNS.join(","))),
+            ExportFormat::Jsonl => None,
+        }
+    }
+
+    fn line(self, subscriber: &Subscriber) -> String {
+        match self {
+            ExportFormat::Csv => {
+                let fields = [
+                    subscriber.id.to_string(),
+                    subscriber.email.clone(),
+                    subscriber.name.clone(),
+                    subscriber.status.clone(),
+                    subscriber
+                        .subscribed_at
+                        .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                    subscriber.locale.clone().unwrap_or_default(),
+                    subscriber.digest_frequency.clone(),
+                    subscriber.hard_bounce_count.to_string(),
+                    subscriber.soft_bounce_count.to_string(),
+                    subscriber.attributes.to_string(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                format!("{}\r\n", fields.join(","))
+            }
+            ExportFormat::Jsonl => {
+                // Serializing plain strings and numbers cannot fail.
+                format!(
+                    "{}\n",
+                    serde_json::to_string(subscriber).unwrap_or_default()
+                )
+            }
+        }
+    }
+}
+
+/// `field`, quoted if it has to be. Fields a spreadsheet would run as a
+/// formula, e.g. a name starting with `=`, are prefixed with `'`.
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Stream every subscriber, minus their preferences token, as CSV or JSON
+/// lines. The table is read a chunk at a time, so its size does not matter.
+#[tracing::instrument(
+    name = "Export subscribers",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn export_subscribers(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Query(parameters): Query<ExportParameters>,
+) -> Response {
+    let format = parameters.format;
+    if AuditEntry::without_target(&admin, "subscribers.export", "subscriber")
+        .after(&serde_json::json!({ "format": format }))
+        .record(&state.db)
+        .await
+        .is_err()
+    {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let header = stream::iter(format.header().map(|header| Ok(Bytes::from(header))));
+    let rows = stream::try_unfold(Some(0), move |after| {
+        let db = state.db.clone();
+        async move {
+            let Some(after) = after else {
+                return Ok(None);
+            };
+            let (chunk, next) = read_chunk(&db, after, format).await?;
+            Ok::<_, DbErr>(Some((chunk, next)))
+        }
+    });
+    let body = Body::from_stream(header.chain(rows).map(|chunk| {
+        chunk.inspect_err(|e| tracing::error!("Failed to export subscribers: {:?}", e))
+    }));
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", format.file_name()),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// The subscribers after the id `after`, encoded, along with where the next
+/// chunk starts if there is one.
+async fn read_chunk(
+    db: &DatabaseConnection,
+    after: i32,
+    format: ExportFormat,
+) -> Result<(Bytes, Option<i32>), DbErr> {
+    let subscribers = subscriptions::Entity::find()
+        .cursor_by(subscriptions::Column::Id)
+        .after(after)
+        .first(CHUNK_SIZE)
+        .all(db)
+        .await?;
+    let next = if subscribers.len() as u64 == CHUNK_SIZE {
+        subscribers.last().map(|subscriber| subscriber.id)
+    } else {
+        None
+    };
+    let chunk: String = subscribers
+        .into_iter()
+        .map(|subscriber| format.line(&subscriber.into()))
+        .collect();
+    Ok((Bytes::from(chunk), next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExportFormat, csv_field};
+    use crate::routes::admin::Subscriber;
+    use chrono::{TimeZone, Utc};
+
+    fn subscriber() -> Subscriber {
+        Subscriber {
+            id: 7,
+            email: "ursula@example.com".into(),
+            name: "Le Guin, \"Ursula\"".into(),
+            status: "confirmed".into(),
+            subscribed_at: Utc.with_ymd_and_hms(2020, 1, 31, 10, 0, 0).unwrap(),
+            locale: None,
+            digest_frequency: "immediate".into(),
+            hard_bounce_count: 0,
+            soft_bounce_count: 1,
+            attributes: serde_json::json!({ "plan": "pro" }),
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("\tx"), "'\tx");
+        assert_eq!(csv_field("\rx"), "\"'\rx\"");
+        assert_eq!(csv_field("a=b"), "a=b");
+    }
+
+    #[test]
+    fn csv_lines_match_the_header() {
+        let line = ExportFormat::Csv.line(&subscriber());
+        assert_eq!(
+            line,
+            "7,ursula@example.com,\"Le Guin, \"\"Ursula\"\"\",confirmed,\
+             2020-01-31T10:00:00Z,,immediate,0,1,\"{\"\"plan\"\":\"\"pro\"\"}\"\r\n"
+        );
+    }
+
+    #[test]
+    fn json_lines_are_one_object_per_line() {
+        let line = ExportFormat::Jsonl.line(&subscriber());
+        assert!(line.ends_with('\n'));
+        let value: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(value["email"], "ursula@example.com");
+        assert!(value.get("preferences_token").is_none());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::entity::import_jobs;
+use crate::lists::{DEFAULT_LIST, find_list};
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::startup::AppState;
+use crate::subscriber_import::{Columns, CsvRecords, Import, ImportReport, SkippedRow};
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use sea_orm::{ActiveModelTrait, DatabaseConnection, DbErr, EntityTrait, Set};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug)]
+pub struct ImportParameters {
+    /// Slug of the list to import into, the default list when missing.
+    list: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportJob {
+    pub id: i32,
+    pub status: String,
+    pub accepted: i32,
+    pub duplicates: i32,
+    pub rejected: i32,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The skipped rows, once the import is over.
+    pub report: Option<ImportReport>,
+}
+
+impl From<import_jobs::Model> for ImportJob {
+    fn from(model: import_jobs::Model) -> Self {
+        Self {
+            id: model.id,
+            status: model.status,
+            accepted: model.accepted,
+            duplicates: model.duplicates,
+            rejected: model.rejected,
+            created_at: model.created_at,
+            finished_at: model.finished_at,
+            report: model
+                .report
+                .and_then(|report| serde_json::from_value(report).ok()),
+        }
+    }
+}
+
+/// Why an import stopped before the end of the file.
+enum ImportFailure {
+    InvalidHeader,
+    Upload,
+    Database(DbErr),
+}
+
+/// Import the subscribers of a CSV file streamed as the request body. The
+/// response is the finished job; its report can be fetched again later.
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(admin, state, body),
+    fields(admin = %admin.username, job_id = tracing::field::Empty)
+)]
+pub async fn import_subscribers(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Query(parameters): Query<ImportParameters>,
+    body: Body,
+) -> Result<(StatusCode, Json<ImportJob>), StatusCode> {
+    let list = find_list(
+        &state.db,
+        parameters.list.as_deref().unwrap_or(DEFAULT_LIST),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::BAD_REQUEST)?;
+    let job = import_jobs::ActiveModel {
+        created_by: Set(admin.username.clone()),
+        status: Set("running".to_string()),
+        accepted: Set(0),
+        duplicates: Set(0),
+        rejected: Set(0),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::Span::current().record("job_id", job.id);
+
+    let mut import = Import::new(state.email_policy.clone());
+    let outcome = run_import(&state.db, job.id, list.id, body, &mut import).await;
+    let (status, response_status) = match &outcome {
+        Ok(()) => ("completed", StatusCode::CREATED),
+        Err(ImportFailure::InvalidHeader | ImportFailure::Upload) => {
+            ("failed", StatusCode::BAD_REQUEST)
+        }
+        Err(ImportFailure::Database(e)) => {
+            tracing::error!("Failed to import subscribers: {:?}", e);
+            ("failed", StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    };
+    let job = save_progress(&state.db, job.id, &import.report, Some(status))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::info!(
+        accepted = job.accepted,
+        duplicates = job.duplicates,
+        rejected = job.rejected,
+        status,
+        "Imported subscribers"
+    );
+    // The skipped rows hold addresses: the log only keeps the counts.
+    AuditEntry::new(&admin, "subscribers.import", "import_job", job.id)
+        .after(&serde_json::json!({
+            "list": list.slug,
+            "status": job.status,
+            "accepted": job.accepted,
+            "duplicates": job.duplicates,
+            "rejected": job.rejected,
+        }))
+        .record(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if response_status == StatusCode::INTERNAL_SERVER_ERROR {
+        return Err(response_status);
+    }
+    Ok((response_status, Json(job.into())))
+}
+
+async fn run_import(
+    db: &DatabaseConnection,
+    job_id: i32,
+    list_id: i32,
+    body: Body,
+    import: &mut Import,
+) -> Result<(), ImportFailure> {
+    let mut parser = CsvRecords::default();
+    let mut columns: Option<Columns> = None;
+    let mut row = 0;
+    let mut stream = body.into_data_stream();
+    loop {
+        let (records, done) = match stream.next().await {
+            Some(Ok(bytes)) => (parser.push(&bytes), false),
+            Some(Err(e)) => {
+                tracing::warn!("Failed to read the uploaded file: {}", e);
+                return Err(ImportFailure::Upload);
+            }
+            None => (parser.finish(), true),
+        };
+        for record in records {
+            row += 1;
+            let Some(columns) = &columns else {
+                // 1️⃣ 第一行是表头
+                columns = Some(
+                    Columns::from_header(&record)
+                        .map_err(|reason| invalid_header(import, reason))?,
+                );
+                continue;
+            };
+            // 2️⃣ 每满一个 chunk 就写入数据库，并更新 job 的计数
+            if import.add(row, columns, &record) {
+                import
+                    .flush(db, list_id)
+                    .await
+                    .map_err(ImportFailure::Database)?;
+                save_progress(db, job_id, &import.report, None)
+                    .await
+                    .map_err(ImportFailure::Database)?;
+            }
+        }
+        if done {
+            break;
+        }
+    }
+    if columns.is_none() {
+        return Err(invalid_header(import, "The file is empty.".to_string()));
+    }
+    // 3️⃣ 最后一个不满的 chunk
+    import
+        .flush(db, list_id)
+        .await
+        .map_err(ImportFailure::Database)
+}
+
+fn invalid_header(import: &mut Import, reason: String) -> ImportFailure {
+    import.report.rejected.push(SkippedRow {
+        row: 1,
+        email: String::new(),
+        reason,
+    });
+    ImportFailure::InvalidHeader
+}
+
+/// Store the counts of `report`, and the report itself along with `status`
+/// once the import is over.
+async fn save_progress(
+    db: &DatabaseConnection,
+    job_id: i32,
+    report: &ImportReport,
+    status: Option<&str>,
+) -> Result<import_jobs::Model, DbErr> {
+    let mut job = import_jobs::ActiveModel {
+        id: Set(job_id),
+        accepted: Set(report.accepted as i32),
+        duplicates: Set(report.duplicates.len() as i32),
+        rejected: Set(report.rejected.len() as i32),
+        ..Default::default()
+    };
+    if let Some(status) = status {
+        job.status = Set(status.to_string());
+        job.report = Set(Some(
+            serde_json::to_value(report).map_err(|e| DbErr::Json(e.to_string()))?,
+        ));
+        job.finished_at = Set(Some(Utc::now()));
+    }
+    job.update(db).await
+}
+
+#[tracing::instrument(name = "Get a subscriber import", skip(state))]
+pub async fn get_import_job(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Path(job_id): Path<i32>,
+) -> Result<Json<ImportJob>, StatusCode> {
+    let job = import_jobs::Entity::find_by_id(job_id)
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(job.into()))
+}
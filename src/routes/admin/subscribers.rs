@@ -0,0 +1,389 @@
+use crate::consent::{ConsentEvent, consent_history};
+use crate::domain::SubscriberName;
+use crate::entity::{
+    email_outbox, list_subscriptions, lists, subscriber_tags, subscription_tokens, subscriptions,
+    tags,
+};
+use crate::preferences::DIGEST_FREQUENCIES;
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::routes::parse_locale;
+use crate::startup::AppState;
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, JoinType, QueryFilter,
+    QueryOrder, QuerySelect, RelationTrait, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Every status a subscriber can be in.
+pub const STATUSES: [&str; 5] = [
+    "pending_confirmation",
+    "confirmed",
+    "unsubscribed",
+    "bounced",
+    "complained",
+];
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    /// Newest subscribers first.
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListSubscribersParameters {
+    status: Option<String>,
+    subscribed_after: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>,
+    /// Only return subscribers whose address contains this.
+    email: Option<String>,
+    /// Order of the ids, which is also the order of subscription.
+    #[serde(default)]
+    order: Order,
+    /// The `next_cursor` of the previous page.
+    cursor: Option<i32>,
+    #[serde(default = "default_limit")]
+    limit: u64,
+}
+
+fn default_limit() -> u64 {
+    100
+}
+
+#[derive(Serialize)]
+pub struct Subscriber {
+    pub id: i32,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub locale: Option<String>,
+    pub digest_frequency: String,
+    pub hard_bounce_count: i32,
+    pub soft_bounce_count: i32,
+    pub attributes: Value,
+}
+
+impl From<subscriptions::Model> for Subscriber {
+    fn from(model: subscriptions::Model) -> Self {
+        Self {
+            id: model.id,
+            email: model.email,
+            name: model.name,
+            status: model.status,
+            subscribed_at: model.subscribed_at,
+            locale: model.locale,
+            digest_frequency: model.digest_frequency,
+            hard_bounce_count: model.hard_bounce_count,
+            soft_bounce_count: model.soft_bounce_count,
+            attributes: model
+                .attributes
+                .unwrap_or_else(|| Value::Object(Map::new())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SubscriberPage {
+    pub subscribers: Vec<Subscriber>,
+    /// Pass it as `cursor` to get the next page; `None` on the last page.
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberDetails {
+    #[serde(flatten)]
+    pub subscriber: Subscriber,
+    pub lists: Vec<ListMembership>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListMembership {
+    pub list: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+/// Fields to change; missing fields are left as they are.
+#[derive(Deserialize, Debug)]
+pub struct SubscriberChanges {
+    name: Option<String>,
+    status: Option<String>,
+    /// A blank locale removes the locale.
+    locale: Option<String>,
+    digest_frequency: Option<String>,
+    /// Replaces all the attributes of the subscriber.
+    attributes: Option<Map<String, Value>>,
+}
+
+#[tracing::instrument(name = "List subscribers", skip(state))]
+pub async fn list_subscribers(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Query(parameters): Query<ListSubscribersParameters>,
+) -> Result<Json<SubscriberPage>, StatusCode> {
+    if parameters
+        .status
+        .as_deref()
+        .is_some_and(|status| !STATUSES.contains(&status))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = parameters.limit.clamp(1, 1000);
+    let mut query = subscriptions::Entity::find();
+    if let Some(status) = &parameters.status {
+        query = query.filter(subscriptions::Column::Status.eq(status));
+    }
+    if let Some(after) = parameters.subscribed_after {
+        query = query.filter(subscriptions::Column::SubscribedAt.gte(after));
+    }
+    if let Some(before) = parameters.subscribed_before {
+        query = query.filter(subscriptions::Column::SubscribedAt.lt(before));
+    }
+    if let Some(email) = parameters.email.as_deref().map(str::trim) {
+        query = query.filter(subscriptions::Column::Email.contains(email));
+    }
+    query = match (parameters.order, parameters.cursor) {
+        (Order::Asc, Some(cursor)) => query.filter(subscriptions::Column::Id.gt(cursor)),
+        (Order::Desc, Some(cursor)) => query.filter(subscriptions::Column::Id.lt(cursor)),
+        (_, None) => query,
+    };
+    query = match parameters.order {
+        Order::Asc => query.order_by_asc(subscriptions::Column::Id),
+        Order::Desc => query.order_by_desc(subscriptions::Column::Id),
+    };
+    // One more than asked for tells whether there is a next page.
+    let mut subscribers = query
+        .limit(limit + 1)
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let next_cursor = if subscribers.len() as u64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|subscriber| subscriber.id)
+    } else {
+        None
+    };
+    Ok(Json(SubscriberPage {
+        subscribers: subscribers.into_iter().map(Subscriber::from).collect(),
+        next_cursor,
+    }))
+}
+
+#[tracing::instrument(name = "Get a subscriber", skip(state))]
+pub async fn get_subscriber(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Path(subscriber_id): Path<i32>,
+) -> Result<Json<SubscriberDetails>, StatusCode> {
+    let subscriber = subscriptions::Entity::find_by_id(subscriber_id)
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let details = subscriber_details(&state.db, subscriber)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(details))
+}
+
+/// The consents recorded for a subscriber, oldest first.
+#[tracing::instrument(name = "List the consent events of a subscriber", skip(state))]
+pub async fn list_consent_events(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Path(subscriber_id): Path<i32>,
+) -> Result<Json<Vec<ConsentEvent>>, StatusCode> {
+    subscriptions::Entity::find_by_id(subscriber_id)
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let events = consent_history(&state.db, subscriber_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(events))
+}
+
+#[tracing::instrument(
+    name = "Update a subscriber",
+    skip(admin, state, changes),
+    fields(admin = %admin.username)
+)]
+pub async fn update_subscriber(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(subscriber_id): Path<i32>,
+    Json(changes): Json<SubscriberChanges>,
+) -> Result<Json<SubscriberDetails>, StatusCode> {
+    let name = changes
+        .name
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let locale = changes
+        .locale
+        .map(|locale| parse_locale(Some(locale)))
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let is_valid = changes
+        .status
+        .as_deref()
+        .is_none_or(|status| STATUSES.contains(&status))
+        && changes
+            .digest_frequency
+            .as_deref()
+            .is_none_or(|frequency| DIGEST_FREQUENCIES.contains(&frequency));
+    if !is_valid {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let subscriber = subscriptions::Entity::find_by_id(subscriber_id)
+        .one(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let before = subscriber_details(&txn, subscriber.clone())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut subscriber: subscriptions::ActiveModel = subscriber.into();
+    if let Some(name) = name {
+        subscriber.name = Set(name.as_ref().to_string());
+    }
+    if let Some(status) = changes.status {
+        subscriber.status = Set(status);
+    }
+    if let Some(locale) = locale {
+        subscriber.locale = Set(locale);
+    }
+    if let Some(digest_frequency) = changes.digest_frequency {
+        subscriber.digest_frequency = Set(digest_frequency);
+    }
+    if let Some(attributes) = changes.attributes {
+        subscriber.attributes = Set((!attributes.is_empty()).then_some(Value::Object(attributes)));
+    }
+    let subscriber = subscriber
+        .update(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let details = subscriber_details(&txn, subscriber)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AuditEntry::new(&admin, "subscriber.update", "subscriber", subscriber_id)
+        .before(&before)
+        .after(&details)
+        .record(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(details))
+}
+
+/// Delete a subscriber along with their list subscriptions, tags and the
+/// emails still waiting for them. Past deliveries are kept for the stats,
+/// without the subscriber.
+#[tracing::instrument(
+    name = "Delete a subscriber",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn delete_subscriber(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(subscriber_id): Path<i32>,
+) -> Result<StatusCode, StatusCode> {
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let subscriber = subscriptions::Entity::find_by_id(subscriber_id)
+        .one(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let before = subscriber_details(&txn, subscriber)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Nothing would stop a queued confirmation or issue otherwise.
+    email_outbox::Entity::delete_many()
+        .filter(email_outbox::Column::Recipient.eq(&before.subscriber.email))
+        .filter(email_outbox::Column::Status.eq("pending"))
+        .exec(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    subscription_tokens::Entity::delete_many()
+        .filter(subscription_tokens::Column::SubscriberId.eq(subscriber_id))
+        .exec(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    subscriptions::Entity::delete_by_id(subscriber_id)
+        .exec(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AuditEntry::new(&admin, "subscriber.delete", "subscriber", subscriber_id)
+        .before(&before)
+        .record(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn subscriber_details<C: ConnectionTrait>(
+    db: &C,
+    subscriber: subscriptions::Model,
+) -> Result<SubscriberDetails, DbErr> {
+    let lists = list_subscriptions::Entity::find()
+        .join(
+            JoinType::InnerJoin,
+            list_subscriptions::Relation::Lists.def(),
+        )
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber.id))
+        .select_only()
+        .column(lists::Column::Slug)
+        .column(list_subscriptions::Column::Status)
+        .column(list_subscriptions::Column::SubscribedAt)
+        .order_by_asc(lists::Column::Slug)
+        .into_tuple::<(String, String, DateTime<Utc>)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|(list, status, subscribed_at)| ListMembership {
+            list,
+            status,
+            subscribed_at,
+        })
+        .collect();
+    let tags = subscriber_tags::Entity::find()
+        .join(JoinType::InnerJoin, subscriber_tags::Relation::Tags.def())
+        .filter(subscriber_tags::Column::SubscriberId.eq(subscriber.id))
+        .select_only()
+        .column(tags::Column::Name)
+        .order_by_asc(tags::Column::Name)
+        .into_tuple::<String>()
+        .all(db)
+        .await?;
+    Ok(SubscriberDetails {
+        subscriber: subscriber.into(),
+        lists,
+        tags,
+    })
+}
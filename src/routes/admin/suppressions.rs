@@ -0,0 +1,136 @@
+use crate::domain::SubscriberEmail;
+use crate::entity::suppressions;
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::startup::AppState;
+use crate::suppression::{hash_email, suppress, unsuppress};
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug)]
+pub struct ListSuppressionsParameters {
+    /// Only return the entry for this address, if any.
+    email: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: u64,
+    #[serde(default)]
+    offset: u64,
+}
+
+fn default_limit() -> u64 {
+    100
+}
+
+#[derive(Serialize)]
+pub struct Suppression {
+    pub id: i32,
+    pub email_hash: String,
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<suppressions::Model> for Suppression {
+    fn from(model: suppressions::Model) -> Self {
+        Self {
+            id: model.id,
+            email_hash: model.email_hash,
+            reason: model.reason,
+            source: model.source,
+            created_at: model.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewSuppression {
+    email: String,
+    #[serde(default = "default_reason")]
+    reason: String,
+}
+
+fn default_reason() -> String {
+    "manual".into()
+}
+
+#[tracing::instrument(name = "List suppressed addresses", skip(state))]
+pub async fn list_suppressions(
+    _: AdminUser,
+    State(state): State<AppState>,
+    Query(parameters): Query<ListSuppressionsParameters>,
+) -> Result<Json<Vec<Suppression>>, StatusCode> {
+    let mut query = suppressions::Entity::find().order_by_desc(suppressions::Column::Id);
+    if let Some(email) = parameters.email {
+        let email = SubscriberEmail::parse(email).map_err(|_| StatusCode::BAD_REQUEST)?;
+        query = query.filter(suppressions::Column::EmailHash.eq(hash_email(email.as_ref())));
+    }
+    let entries = query
+        .limit(parameters.limit.min(1000))
+        .offset(parameters.offset)
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(entries.into_iter().map(Suppression::from).collect()))
+}
+
+#[tracing::instrument(
+    name = "Add an address to the suppression list",
+    skip(admin, state, body),
+    fields(admin = %admin.username)
+)]
+pub async fn add_suppression(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Json(body): Json<NewSuppression>,
+) -> Result<StatusCode, StatusCode> {
+    let email = SubscriberEmail::parse(body.email).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let source = format!("admin:{}", admin.username);
+    suppress(&state.db, email.as_ref(), &body.reason, &source)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Like the list itself, the log only knows the hash of the address.
+    AuditEntry::new(
+        &admin,
+        "suppression.add",
+        "suppression",
+        hash_email(email.as_ref()),
+    )
+    .after(&serde_json::json!({ "reason": body.reason, "source": source }))
+    .record(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::CREATED)
+}
+
+#[tracing::instrument(
+    name = "Remove an address from the suppression list",
+    skip(admin, state, email),
+    fields(admin = %admin.username)
+)]
+pub async fn remove_suppression(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    // Normalized as when it was added, or the hashes would not match.
+    let email = SubscriberEmail::parse(email).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let removed = unsuppress(&state.db, email.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !removed {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    AuditEntry::new(
+        &admin,
+        "suppression.remove",
+        "suppression",
+        hash_email(email.as_ref()),
+    )
+    .record(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,147 @@
+use crate::archive::slugify;
+use crate::entity::{subscriber_tags, subscriptions, tags};
+use crate::routes::admin::{AdminUser, AuditEntry};
+use crate::startup::AppState;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::Utc;
+use sea_orm::sea_query::{Expr, Func, SimpleExpr};
+use sea_orm::{
+    ColumnTrait, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Set,
+    TransactionTrait,
+};
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Tag {
+    pub name: String,
+    pub subscribers: i64,
+}
+
+#[tracing::instrument(name = "List tags", skip(state))]
+pub async fn list_tags(
+    _: AdminUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Tag>>, StatusCode> {
+    let tags = tags::Entity::find()
+        .select_only()
+        .column(tags::Column::Name)
+        .column_as(
+            SimpleExpr::from(Func::count(Expr::col((
+                subscriber_tags::Entity,
+                subscriber_tags::Column::Id,
+            )))),
+            "subscribers",
+        )
+        .join(JoinType::LeftJoin, tags::Relation::SubscriberTags.def())
+        .group_by(tags::Column::Id)
+        .order_by_asc(tags::Column::Name)
+        .into_tuple::<(String, i64)>()
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(
+        tags.into_iter()
+            .map(|(name, subscribers)| Tag { name, subscribers })
+            .collect(),
+    ))
+}
+
+/// Tag a subscriber, creating the tag on first use.
+#[tracing::instrument(
+    name = "Tag a subscriber",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn tag_subscriber(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path((subscriber_id, tag)): Path<(i32, String)>,
+) -> Result<StatusCode, StatusCode> {
+    // Same rules as list slugs, so that segments can refer to tags safely.
+    if tag.is_empty() || slugify(&tag) != tag {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    subscriptions::Entity::find_by_id(subscriber_id)
+        .one(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    tags::Entity::insert(tags::ActiveModel {
+        name: Set(tag.clone()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    })
+    .on_conflict_do_nothing_on([tags::Column::Name])
+    .exec(&txn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let tag = tags::Entity::find()
+        .filter(tags::Column::Name.eq(&tag))
+        .one(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    subscriber_tags::Entity::insert(subscriber_tags::ActiveModel {
+        subscriber_id: Set(subscriber_id),
+        tag_id: Set(tag.id),
+        ..Default::default()
+    })
+    .on_conflict_do_nothing_on([
+        subscriber_tags::Column::SubscriberId,
+        subscriber_tags::Column::TagId,
+    ])
+    .exec(&txn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AuditEntry::new(&admin, "subscriber.tag", "subscriber", subscriber_id)
+        .after(&serde_json::json!({ "tag": tag.name }))
+        .record(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(
+    name = "Untag a subscriber",
+    skip(admin, state),
+    fields(admin = %admin.username)
+)]
+pub async fn untag_subscriber(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path((subscriber_id, tag)): Path<(i32, String)>,
+) -> Result<StatusCode, StatusCode> {
+    let Some(tag) = tags::Entity::find()
+        .filter(tags::Column::Name.eq(&tag))
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let result = subscriber_tags::Entity::delete_many()
+        .filter(subscriber_tags::Column::SubscriberId.eq(subscriber_id))
+        .filter(subscriber_tags::Column::TagId.eq(tag.id))
+        .exec(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if result.rows_affected == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    AuditEntry::new(&admin, "subscriber.untag", "subscriber", subscriber_id)
+        .before(&serde_json::json!({ "tag": tag.name }))
+        .record(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,170 @@
+use crate::archive::{FEED_LENGTH, Validators, archive_page, atom_feed, rss_feed};
+use crate::entity::{lists, newsletter_issues};
+use crate::issue_template::{personalize, render_issue};
+use crate::lists::DEFAULT_LIST;
+use crate::startup::AppState;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::{Expr, Func, SimpleExpr};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter, QueryOrder,
+    QuerySelect, RelationTrait, Select,
+};
+
+#[tracing::instrument(name = "Show the archive", skip_all)]
+pub async fn archive(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let validators = archive_validators(&state.db).await?;
+    if validators.is_fresh(&headers) {
+        return Ok(not_modified(&validators));
+    }
+    let issues = published_issues()
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((validators.headers(), Html(archive_page(&issues))).into_response())
+}
+
+#[tracing::instrument(name = "Show an archived issue", skip(state, headers))]
+pub async fn archived_issue(
+    State(state): State<AppState>,
+    Path(slug): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let issue = public_issues()
+        .filter(newsletter_issues::Column::Slug.eq(slug))
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // Published issues can no longer be edited.
+    let published_at = issue.published_at.unwrap_or(issue.created_at);
+    let validators = Validators::new(
+        &format!("{}-{}", issue.id, published_at.timestamp()),
+        published_at,
+    );
+    if validators.is_fresh(&headers) {
+        return Ok(not_modified(&validators));
+    }
+    // Variables fall back to their default for anonymous readers.
+    let rendered = personalize(&render_issue(&issue), &Default::default());
+    Ok((validators.headers(), Html(rendered.html)).into_response())
+}
+
+#[tracing::instrument(name = "Serve the Atom feed", skip_all)]
+pub async fn atom(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    feed(
+        &state,
+        &headers,
+        "application/atom+xml; charset=utf-8",
+        atom_feed,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Serve the RSS feed", skip_all)]
+pub async fn rss(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    feed(
+        &state,
+        &headers,
+        "application/rss+xml; charset=utf-8",
+        rss_feed,
+    )
+    .await
+}
+
+async fn feed(
+    state: &AppState,
+    headers: &HeaderMap,
+    content_type: &'static str,
+    render: fn(&str, &[newsletter_issues::Model]) -> String,
+) -> Result<Response, StatusCode> {
+    let validators = archive_validators(&state.db).await?;
+    if validators.is_fresh(headers) {
+        return Ok(not_modified(&validators));
+    }
+    let issues = published_issues()
+        .limit(FEED_LENGTH)
+        .all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        validators.headers(),
+        [(header::CONTENT_TYPE, content_type)],
+        render(&state.base_url, &issues),
+    )
+        .into_response())
+}
+
+/// Issues anyone may read: those sent to the whole default list. Issues of
+/// other lists or of a segment were only meant for their recipients.
+fn public_issues() -> Select<newsletter_issues::Entity> {
+    newsletter_issues::Entity::find()
+        .join(
+            JoinType::InnerJoin,
+            newsletter_issues::Relation::Lists.def(),
+        )
+        .filter(newsletter_issues::Column::Status.eq("published"))
+        .filter(lists::Column::Slug.eq(DEFAULT_LIST))
+        .filter(newsletter_issues::Column::SegmentId.is_null())
+}
+
+fn published_issues() -> Select<newsletter_issues::Entity> {
+    public_issues()
+        .order_by_desc(newsletter_issues::Column::PublishedAt)
+        .order_by_desc(newsletter_issues::Column::Id)
+}
+
+fn not_modified(validators: &Validators) -> Response {
+    (StatusCode::NOT_MODIFIED, validators.headers()).into_response()
+}
+
+/// Published issues are never edited, so the number of issues and the
+/// latest publication time are enough to tell whether the archive changed,
+/// without loading any issue.
+async fn archive_validators(db: &DatabaseConnection) -> Result<Validators, StatusCode> {
+    let (count, last_published_at) = latest_publication(db).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let last_modified = last_published_at.unwrap_or(DateTime::UNIX_EPOCH);
+    Ok(Validators::new(
+        &format!("{}-{}", count, last_modified.timestamp()),
+        last_modified,
+    ))
+}
+
+async fn latest_publication(
+    db: &DatabaseConnection,
+) -> Result<(i64, Option<DateTime<Utc>>), DbErr> {
+    let latest = public_issues()
+        .select_only()
+        .column_as(
+            SimpleExpr::from(Func::count(Expr::col((
+                newsletter_issues::Entity,
+                newsletter_issues::Column::Id,
+            )))),
+            "count",
+        )
+        .column_as(
+            SimpleExpr::from(Func::max(Expr::col((
+                newsletter_issues::Entity,
+                newsletter_issues::Column::PublishedAt,
+            )))),
+            "last_published_at",
+        )
+        .into_tuple::<(i64, Option<DateTime<Utc>>)>()
+        .one(db)
+        .await?;
+    Ok(latest.unwrap_or_default())
+}
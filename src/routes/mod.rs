@@ -1,7 +1,18 @@
+pub mod admin;
+mod archive;
 mod health_check;
+mod postmark_webhooks;
+mod preferences;
+mod privacy;
 mod subscriptions;
 mod subscriptions_confirm;
+mod tracking;
 
+pub use archive::*;
 pub use health_check::*;
+pub use postmark_webhooks::*;
+pub use preferences::*;
+pub use privacy::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use tracking::*;
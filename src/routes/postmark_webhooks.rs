@@ -0,0 +1,272 @@
+use crate::authentication::{basic_authentication, secrets_match};
+use crate::configuration::PostmarkWebhookSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_policy::EmailPolicy;
+use crate::entity::subscriptions;
+use crate::startup::AppState;
+use crate::suppression::suppress;
+use axum::Json;
+use axum::extract::{FromRequestParts, State};
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::{Expr, ExprTrait};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+};
+use secrecy::SecretString;
+use serde::Deserialize;
+
+/// Header Postmark can be configured to send with every webhook call, as an
+/// alternative to basic auth credentials embedded in the webhook URL.
+const SHARED_SECRET_HEADER: &str = "X-Webhook-Secret";
+
+/// Rejects webhook calls that carry neither the configured basic auth
+/// credentials nor the shared secret.
+pub struct AuthorizedWebhook;
+
+impl FromRequestParts<AppState> for AuthorizedWebhook {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let settings = &state.postmark_webhooks;
+        if let Some(secret) = parts.headers.get(SHARED_SECRET_HEADER) {
+            let secret = SecretString::from(secret.to_str().unwrap_or_default());
+            return if secrets_match(&secret, &settings.shared_secret) {
+                Ok(AuthorizedWebhook)
+            } else {
+                Err(StatusCode::UNAUTHORIZED)
+            };
+        }
+        let credentials = basic_authentication(&parts.headers).map_err(|e| {
+            tracing::warn!("Rejected webhook call: {}", e);
+            StatusCode::UNAUTHORIZED
+        })?;
+        if credentials.username == settings.username
+            && secrets_match(&credentials.password, &settings.password)
+        {
+            Ok(AuthorizedWebhook)
+        } else {
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
+/// The subset of Postmark's bounce webhook payload we care about.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct BouncePayload {
+    #[serde(rename = "Type")]
+    pub bounce_type: String,
+    pub email: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// The subset of Postmark's spam complaint webhook payload we care about.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct SpamComplaintPayload {
+    pub email: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum BounceKind {
+    /// The address does not exist or will never accept our emails.
+    Hard,
+    /// A temporary problem, e.g. a full mailbox.
+    Soft,
+    /// Auto-responders, subscription requests, ...: not a delivery failure.
+    Ignored,
+}
+
+impl BounceKind {
+    /// Map a Postmark bounce `Type` to how we treat it.
+    fn from_postmark_type(bounce_type: &str) -> Self {
+        match bounce_type {
+            "HardBounce" | "BadEmailAddress" | "ManuallyDeactivated" | "Blocked" => Self::Hard,
+            "SoftBounce" | "Transient" | "DnsError" | "ChallengeVerification" => Self::Soft,
+            _ => Self::Ignored,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Handle a Postmark bounce webhook",
+    skip(state, payload),
+    fields(bounce_type = %payload.bounce_type)
+)]
+pub async fn postmark_bounce(
+    _: AuthorizedWebhook,
+    State(state): State<AppState>,
+    Json(payload): Json<BouncePayload>,
+) -> Result<StatusCode, StatusCode> {
+    let kind = BounceKind::from_postmark_type(&payload.bounce_type);
+    if kind == BounceKind::Ignored {
+        return Ok(StatusCode::OK);
+    }
+    tracing::info!(
+        description = payload.description.as_deref().unwrap_or_default(),
+        "Recording a bounce"
+    );
+    record_bounce(
+        &state.db,
+        &state.email_policy,
+        &payload.email,
+        kind,
+        &state.postmark_webhooks,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // We answer 200 even for addresses we do not know about, otherwise
+    // Postmark keeps retrying the call.
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(
+    name = "Handle a Postmark spam complaint webhook",
+    skip(state, payload)
+)]
+pub async fn postmark_spam_complaint(
+    _: AuthorizedWebhook,
+    State(state): State<AppState>,
+    Json(payload): Json<SpamComplaintPayload>,
+) -> Result<StatusCode, StatusCode> {
+    let subscriber = find_subscriber(&state.db, &state.email_policy, &payload.email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(subscriber) = subscriber {
+        let mut subscriber: subscriptions::ActiveModel = subscriber.into();
+        subscriber.status = Set("complained".to_string());
+        subscriber
+            .update(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    // The address is suppressed even if it is not one of our subscribers,
+    // e.g. the recipient of a test send.
+    suppress(&state.db, &payload.email, "complained", "postmark_webhook")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+#[tracing::instrument(name = "Record a bounce", skip(db, policy, email, settings))]
+async fn record_bounce(
+    db: &DatabaseConnection,
+    policy: &EmailPolicy,
+    email: &str,
+    kind: BounceKind,
+    settings: &PostmarkWebhookSettings,
+) -> Result<(), DbErr> {
+    let Some(subscriber) = find_subscriber(db, policy, email).await? else {
+        // There is nothing to count for addresses that are not our
+        // subscribers, e.g. the recipient of a test send: a hard bounce is
+        // enough to stop mailing them.
+        if kind == BounceKind::Hard {
+            suppress(db, email, "bounced", "postmark_webhook").await?;
+        }
+        return Ok(());
+    };
+    // Incremented by the database, so that concurrent or retried webhook
+    // calls all count.
+    let update = subscriptions::Entity::update_many();
+    let update = match kind {
+        BounceKind::Hard => update.col_expr(
+            subscriptions::Column::HardBounceCount,
+            Expr::col(subscriptions::Column::HardBounceCount).add(1),
+        ),
+        // A soft bounce long after the previous one starts counting again:
+        // the problem went away in between. MySQL assigns columns in order,
+        // so the count sees the previous `soft_bounced_at`.
+        BounceKind::Soft => update
+            .col_expr(
+                subscriptions::Column::SoftBounceCount,
+                Expr::case(
+                    Expr::col(subscriptions::Column::SoftBouncedAt)
+                        .gte(Utc::now() - Duration::days(settings.soft_bounce_window_days)),
+                    Expr::col(subscriptions::Column::SoftBounceCount).add(1),
+                )
+                .finally(1)
+                .into(),
+            )
+            .col_expr(
+                subscriptions::Column::SoftBouncedAt,
+                Expr::value(Some(Utc::now())),
+            ),
+        BounceKind::Ignored => return Ok(()),
+    };
+    update
+        .filter(subscriptions::Column::Id.eq(subscriber.id))
+        .exec(db)
+        .await?;
+    let Some(subscriber) = subscriptions::Entity::find_by_id(subscriber.id)
+        .one(db)
+        .await?
+    else {
+        return Ok(());
+    };
+    let bounced = subscriber.hard_bounce_count >= settings.hard_bounce_threshold
+        || subscriber.soft_bounce_count >= settings.soft_bounce_threshold;
+    if !bounced {
+        return Ok(());
+    }
+    subscriptions::Entity::update_many()
+        .col_expr(subscriptions::Column::Status, Expr::value("bounced"))
+        .filter(subscriptions::Column::Id.eq(subscriber.id))
+        .exec(db)
+        .await?;
+    suppress(db, email, "bounced", "postmark_webhook").await
+}
+
+/// The subscriber `email` is the address of, compared in its canonical form:
+/// the provider does not necessarily report it as the subscriber typed it.
+async fn find_subscriber(
+    db: &DatabaseConnection,
+    policy: &EmailPolicy,
+    email: &str,
+) -> Result<Option<subscriptions::Model>, DbErr> {
+    let Ok(email) = SubscriberEmail::parse(email.to_string()) else {
+        return Ok(None);
+    };
+    subscriptions::Entity::find()
+        .filter(subscriptions::Column::CanonicalEmail.eq(policy.canonical(&email)))
+        .one(db)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BounceKind;
+
+    #[test]
+    fn postmark_bounce_types_are_classified() {
+        assert_eq!(
+            BounceKind::from_postmark_type("HardBounce"),
+            BounceKind::Hard
+        );
+        assert_eq!(
+            BounceKind::from_postmark_type("BadEmailAddress"),
+            BounceKind::Hard
+        );
+        assert_eq!(
+            BounceKind::from_postmark_type("SoftBounce"),
+            BounceKind::Soft
+        );
+        assert_eq!(
+            BounceKind::from_postmark_type("Transient"),
+            BounceKind::Soft
+        );
+        assert_eq!(
+            BounceKind::from_postmark_type("AutoResponder"),
+            BounceKind::Ignored
+        );
+        assert_eq!(
+            BounceKind::from_postmark_type("Unknown"),
+            BounceKind::Ignored
+        );
+    }
+}
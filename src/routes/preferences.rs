@@ -0,0 +1,326 @@
+use crate::domain::SubscriberName;
+use crate::entity::{list_subscriptions, lists, subscriptions};
+use crate::preferences::{DIGEST_FREQUENCIES, preferences_page, record_change};
+use crate::startup::AppState;
+use crate::suppression::suppress;
+use axum::Form;
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use chrono::Utc;
+use sea_orm::sea_query::LockType;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set, TransactionTrait,
+};
+use std::collections::HashMap;
+
+/// The preference center form. Browsers send one `lists` pair per checked
+/// list, which `serde_urlencoded` cannot collect into a struct, hence the
+/// manual parsing from the raw pairs.
+#[derive(Debug, Default)]
+pub struct PreferencesForm {
+    /// Left unchanged when missing.
+    name: Option<String>,
+    /// Slugs of the lists to receive, all others are unsubscribed from.
+    lists: Vec<String>,
+    /// Left unchanged when missing.
+    digest_frequency: Option<String>,
+    unsubscribe_all: bool,
+}
+
+impl From<Vec<(String, String)>> for PreferencesForm {
+    fn from(pairs: Vec<(String, String)>) -> Self {
+        let mut form = Self::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "name" => form.name = Some(value),
+                "lists" => form.lists.push(value),
+                "digest_frequency" => form.digest_frequency = Some(value),
+                "unsubscribe_all" => form.unsubscribe_all = value != "false",
+                _ => {}
+            }
+        }
+        form
+    }
+}
+
+#[tracing::instrument(name = "Show the preference center", skip_all)]
+pub async fn show_preferences(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, StatusCode> {
+    let subscriber = find_subscriber(&state.db, &token, false).await?;
+    let memberships = load_memberships(&state.db, subscriber.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(page(&subscriber, &memberships, None))
+}
+
+#[tracing::instrument(name = "Update subscriber preferences", skip_all)]
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Form(pairs): Form<Vec<(String, String)>>,
+) -> Result<Response, StatusCode> {
+    let form = PreferencesForm::from(pairs);
+    // 1️⃣ 开启事务，锁住 subscriber
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let subscriber = find_subscriber(&txn, &token, true).await?;
+    // Unsubscribed, bounced or complained: the address is suppressed and
+    // there is nothing left to manage.
+    if subscriber.status != "confirmed" {
+        return Err(StatusCode::CONFLICT);
+    }
+    let memberships = load_memberships(&txn, subscriber.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // 2️⃣ 应用修改，每一项都记录下来
+    let notice = if form.unsubscribe_all {
+        unsubscribe_all(&txn, &subscriber, &memberships)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        "You have been unsubscribed from all our emails."
+    } else {
+        apply_changes(&txn, &subscriber, &memberships, form).await?;
+        "Your preferences have been saved."
+    };
+
+    // 3️⃣ 提交事务
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let subscriber = find_subscriber(&state.db, &token, false).await?;
+    let memberships = load_memberships(&state.db, subscriber.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(page(&subscriber, &memberships, Some(notice)))
+}
+
+/// Every list, with the subscriber's subscription to it if any.
+type Memberships = Vec<(lists::Model, Option<list_subscriptions::Model>)>;
+
+fn page(
+    subscriber: &subscriptions::Model,
+    memberships: &Memberships,
+    notice: Option<&str>,
+) -> Response {
+    let lists: Vec<_> = memberships
+        .iter()
+        .map(|(list, subscription)| {
+            let receives = subscription
+                .as_ref()
+                .is_some_and(|subscription| subscription.status == "confirmed");
+            (list.clone(), receives)
+        })
+        .collect();
+    // The page is personal: keep it out of shared caches.
+    (
+        [(header::CACHE_CONTROL, "no-store")],
+        Html(preferences_page(subscriber, &lists, notice)),
+    )
+        .into_response()
+}
+
+async fn apply_changes<C: ConnectionTrait>(
+    db: &C,
+    subscriber: &subscriptions::Model,
+    memberships: &Memberships,
+    form: PreferencesForm,
+) -> Result<(), StatusCode> {
+    // Validate everything before writing anything.
+    let name = form
+        .name
+        .filter(|name| *name != subscriber.name)
+        .map(SubscriberName::parse)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let digest_frequency = form
+        .digest_frequency
+        .filter(|frequency| *frequency != subscriber.digest_frequency);
+    if digest_frequency
+        .as_ref()
+        .is_some_and(|frequency| !DIGEST_FREQUENCIES.contains(&frequency.as_str()))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if form
+        .lists
+        .iter()
+        .any(|slug| !memberships.iter().any(|(list, _)| list.slug == *slug))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let internal_error = |e: DbErr| {
+        tracing::error!("Failed to update preferences: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    if name.is_some() || digest_frequency.is_some() {
+        let mut updated: subscriptions::ActiveModel = subscriber.clone().into();
+        if let Some(name) = &name {
+            updated.name = Set(name.as_ref().to_string());
+            record_change(
+                db,
+                subscriber.id,
+                "name",
+                Some(&subscriber.name),
+                Some(name.as_ref()),
+            )
+            .await
+            .map_err(internal_error)?;
+        }
+        if let Some(digest_frequency) = &digest_frequency {
+            updated.digest_frequency = Set(digest_frequency.clone());
+            record_change(
+                db,
+                subscriber.id,
+                "digest_frequency",
+                Some(&subscriber.digest_frequency),
+                Some(digest_frequency),
+            )
+            .await
+            .map_err(internal_error)?;
+        }
+        updated.update(db).await.map_err(internal_error)?;
+    }
+
+    for (list, subscription) in memberships {
+        let current = subscription.as_ref().map(|s| s.status.as_str());
+        let wanted = form.lists.contains(&list.slug);
+        let new_status = match current {
+            // Following the link proves the address is theirs: no need to
+            // confirm lists chosen here.
+            Some("confirmed") if wanted => continue,
+            _ if wanted => "confirmed",
+            Some("confirmed") | Some("pending_confirmation") => "unsubscribed",
+            _ => continue,
+        };
+        set_list_status(db, subscriber.id, list, subscription, new_status)
+            .await
+            .map_err(internal_error)?;
+    }
+    Ok(())
+}
+
+/// Leave every list and suppress the address, so that nothing is sent to it
+/// anymore.
+async fn unsubscribe_all<C: ConnectionTrait>(
+    db: &C,
+    subscriber: &subscriptions::Model,
+    memberships: &Memberships,
+) -> Result<(), DbErr> {
+    for (list, subscription) in memberships {
+        if let Some(subscription) = subscription
+            && subscription.status != "unsubscribed"
+        {
+            set_list_status(
+                db,
+                subscriber.id,
+                list,
+                &Some(subscription.clone()),
+                "unsubscribed",
+            )
+            .await?;
+        }
+    }
+    let mut updated: subscriptions::ActiveModel = subscriber.clone().into();
+    updated.status = Set("unsubscribed".to_string());
+    updated.update(db).await?;
+    record_change(
+        db,
+        subscriber.id,
+        "status",
+        Some(&subscriber.status),
+        Some("unsubscribed"),
+    )
+    .await?;
+    suppress(db, &subscriber.email, "unsubscribed", "preferences").await
+}
+
+async fn set_list_status<C: ConnectionTrait>(
+    db: &C,
+    subscriber_id: i32,
+    list: &lists::Model,
+    subscription: &Option<list_subscriptions::Model>,
+    status: &str,
+) -> Result<(), DbErr> {
+    match subscription {
+        Some(subscription) => {
+            let mut updated: list_subscriptions::ActiveModel = subscription.clone().into();
+            updated.status = Set(status.to_string());
+            updated.update(db).await?;
+        }
+        None => {
+            list_subscriptions::ActiveModel {
+                list_id: Set(list.id),
+                subscriber_id: Set(subscriber_id),
+                status: Set(status.to_string()),
+                subscribed_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+        }
+    }
+    record_change(
+        db,
+        subscriber_id,
+        &format!("list:{}", list.slug),
+        subscription.as_ref().map(|s| s.status.as_str()),
+        Some(status),
+    )
+    .await
+}
+
+/// `404` for unknown tokens.
+async fn find_subscriber<C: ConnectionTrait>(
+    db: &C,
+    token: &str,
+    for_update: bool,
+) -> Result<subscriptions::Model, StatusCode> {
+    let mut query =
+        subscriptions::Entity::find().filter(subscriptions::Column::PreferencesToken.eq(token));
+    if for_update {
+        query = query.lock(LockType::Update);
+    }
+    query
+        .one(db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn load_memberships<C: ConnectionTrait>(
+    db: &C,
+    subscriber_id: i32,
+) -> Result<Memberships, DbErr> {
+    let all_lists = lists::Entity::find()
+        .order_by_asc(lists::Column::Id)
+        .all(db)
+        .await?;
+    let mut subscriptions: HashMap<i32, list_subscriptions::Model> =
+        list_subscriptions::Entity::find()
+            .filter(list_subscriptions::Column::SubscriberId.eq(subscriber_id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|subscription| (subscription.list_id, subscription))
+            .collect();
+    Ok(all_lists
+        .into_iter()
+        .map(|list| {
+            let subscription = subscriptions.remove(&list.id);
+            (list, subscription)
+        })
+        .collect())
+}
@@ -0,0 +1,201 @@
+use crate::domain::SubscriberEmail;
+use crate::email_outbox::enqueue_transactional_email;
+use crate::entity::{privacy_requests, subscriptions};
+use crate::issue_template::escape_html;
+use crate::preferences::generate_preferences_token;
+use crate::privacy::{
+    REQUEST_KINDS, collect_data, confirmation_page, erase_subscriber, message_page,
+    request_lifetime, request_url,
+};
+use crate::startup::AppState;
+use axum::Form;
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{Html, IntoResponse, Response};
+use chrono::Utc;
+use sea_orm::sea_query::LockType;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseTransaction, DbErr, EntityTrait,
+    QueryFilter, QuerySelect, Set, TransactionTrait,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct PrivacyRequestForm {
+    email: String,
+    kind: String,
+}
+
+/// Start a data subject request. The answer is the same whether the address
+/// is known or not, so the form cannot be used to find out who subscribed.
+#[tracing::instrument(name = "Request a privacy action", skip_all, fields(kind = %form.kind))]
+pub async fn request_privacy_action(
+    State(state): State<AppState>,
+    Form(form): Form<PrivacyRequestForm>,
+) -> Result<Response, StatusCode> {
+    if !REQUEST_KINDS.contains(&form.kind.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let email = SubscriberEmail::parse(form.email).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let subscriber = subscriptions::Entity::find()
+        .filter(subscriptions::Column::CanonicalEmail.eq(state.email_policy.canonical(&email)))
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(subscriber) = subscriber {
+        send_verification(&state, &subscriber, &form.kind)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to record the privacy request: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+    Ok(page(message_page(
+        "If this address is subscribed, we have sent it a link to confirm your request.",
+    )))
+}
+
+async fn send_verification(
+    state: &AppState,
+    subscriber: &subscriptions::Model,
+    kind: &str,
+) -> Result<(), DbErr> {
+    // The address we know, which may be spelled differently from the form.
+    let email = SubscriberEmail::parse(subscriber.email.clone()).map_err(DbErr::Custom)?;
+    let token = generate_preferences_token();
+    // 1️⃣ 请求和验证邮件在同一个事务里写入
+    let txn = state.db.begin().await?;
+    privacy_requests::ActiveModel {
+        subscriber_id: Set(subscriber.id),
+        kind: Set(kind.to_string()),
+        token: Set(token.clone()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    let link = request_url(&state.base_url, &token);
+    let action = if kind == "erasure" {
+        "erase the data we hold about you"
+    } else {
+        "get a copy of the data we hold about you"
+    };
+    let html_body = format!(
+        "Someone asked to {} using this address.<br />\
+Click <a href=\"{}\">here</a> to confirm. The link is valid for 24 hours; \
+if you did not ask for this, ignore this email.",
+        action, link
+    );
+    let plain_body = format!(
+        "Someone asked to {} using this address.\nVisit {} to confirm. \
+The link is valid for 24 hours; if you did not ask for this, ignore this email.",
+        action, link
+    );
+    enqueue_transactional_email(
+        &txn,
+        &email,
+        "Confirm your data request",
+        &html_body,
+        Some(&plain_body),
+    )
+    .await?;
+    // 2️⃣ 提交事务
+    txn.commit().await
+}
+
+#[tracing::instrument(name = "Show a privacy request", skip_all)]
+pub async fn show_privacy_request(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, StatusCode> {
+    let request = find_request(&state.db, &token, false).await?;
+    Ok(page(confirmation_page(&request.kind)))
+}
+
+/// Carry out a confirmed request: email the data bundle, or erase it.
+#[tracing::instrument(name = "Confirm a privacy request", skip_all)]
+pub async fn confirm_privacy_request(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, StatusCode> {
+    // 1️⃣ 开启事务，锁住请求
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let request = find_request(&txn, &token, true).await?;
+    let subscriber = subscriptions::Entity::find_by_id(request.subscriber_id)
+        .one(&txn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // 2️⃣ 执行请求
+    let internal_error = |e: DbErr| {
+        tracing::error!("Failed to carry out the privacy request: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let message = if request.kind == "erasure" {
+        // The request goes with the subscriber.
+        erase_subscriber(&txn, &subscriber)
+            .await
+            .map_err(internal_error)?;
+        "Your data has been erased. You will not receive any more emails from us."
+    } else {
+        send_data(&txn, subscriber).await.map_err(internal_error)?;
+        let mut completed: privacy_requests::ActiveModel = request.into();
+        completed.completed_at = Set(Some(Utc::now()));
+        completed.update(&txn).await.map_err(internal_error)?;
+        "We have emailed you a copy of your data."
+    };
+
+    // 3️⃣ 提交事务
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(page(message_page(message)))
+}
+
+async fn send_data(
+    txn: &DatabaseTransaction,
+    subscriber: subscriptions::Model,
+) -> Result<(), DbErr> {
+    let email = SubscriberEmail::parse(subscriber.email.clone()).map_err(DbErr::Custom)?;
+    let bundle = collect_data(txn, subscriber).await?;
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| DbErr::Json(e.to_string()))?;
+    let html_body = format!(
+        "Here is all the data we hold about you.<br />\n<pre>{}</pre>",
+        escape_html(&json)
+    );
+    enqueue_transactional_email(txn, &email, "Your data", &html_body, Some(&json)).await?;
+    Ok(())
+}
+
+/// `404` for unknown, expired and already used tokens.
+async fn find_request<C: ConnectionTrait>(
+    db: &C,
+    token: &str,
+    for_update: bool,
+) -> Result<privacy_requests::Model, StatusCode> {
+    let mut query = privacy_requests::Entity::find()
+        .filter(privacy_requests::Column::Token.eq(token))
+        .filter(privacy_requests::Column::CompletedAt.is_null())
+        .filter(privacy_requests::Column::CreatedAt.gt(Utc::now() - request_lifetime()));
+    if for_update {
+        query = query.lock(LockType::Update);
+    }
+    query
+        .one(db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn page(html: String) -> Response {
+    // The pages are personal: keep them out of shared caches.
+    ([(header::CACHE_CONTROL, "no-store")], Html(html)).into_response()
+}
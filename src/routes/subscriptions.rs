@@ -1,28 +1,85 @@
-use crate::email_client::EmailClient;
-use crate::entity::subscription_tokens;
+use crate::attributes::{AttributeSchema, merge_new};
+use crate::bot_protection::{Rejection, Submission, form_token};
+use crate::client_ip::ClientIp;
+use crate::consent::{Consent, record_consent};
+use crate::email_outbox::enqueue_transactional_email;
+use crate::entity::{list_subscriptions, subscription_tokens};
+use crate::lists::{DEFAULT_LIST, find_list};
+use crate::preferences::{DIGEST_FREQUENCIES, generate_preferences_token};
 use crate::startup::AppState;
 use crate::{
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
     entity::subscriptions,
 };
-use axum::{Form, extract::State, http::StatusCode};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Form, Json};
 use chrono::Utc;
 use fake::RngExt;
 use fake::rand::distr::Alphanumeric;
 use fake::rand::rng;
-use sea_orm::{ActiveModelTrait, DatabaseTransaction, Set, TransactionTrait};
-use serde::Deserialize;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::net::IpAddr;
 use tracing;
 
 #[derive(Deserialize, Debug)]
 pub struct FormData {
     pub email: String,
     pub name: String,
+    /// Slug of the list to subscribe to, the default list when missing.
+    #[serde(default)]
+    pub list: Option<String>,
+    /// E.g. `fr` or `pt-BR`, used to target segments.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// The form or page the person subscribed on, recorded with their
+    /// consent.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// The version of the consent wording the form showed, the configured
+    /// one when missing.
+    #[serde(default)]
+    pub consent_text_version: Option<String>,
+    /// Every other field, read according to the attribute schema of the
+    /// list.
+    #[serde(flatten)]
+    pub attributes: HashMap<String, String>,
+}
+
+/// Why a subscription was refused.
+#[derive(Debug)]
+pub enum SubscribeError {
+    /// We do not take this address; the reason is sent back so the person
+    /// can use another one.
+    RejectedEmail(String),
+    Status(StatusCode),
+}
+
+impl From<StatusCode> for SubscribeError {
+    fn from(status: StatusCode) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl IntoResponse for SubscribeError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::RejectedEmail(reason) => (StatusCode::BAD_REQUEST, reason).into_response(),
+            Self::Status(status) => status.into_response(),
+        }
+    }
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, state),
+    skip(form, state, headers),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -30,9 +87,45 @@ pub struct FormData {
 )]
 pub async fn subscribe(
     State(state): State<AppState>,
-    Form(form): Form<FormData>,
-) -> Result<StatusCode, StatusCode> {
+    ClientIp(client_ip): ClientIp,
+    headers: HeaderMap,
+    Form(mut form): Form<FormData>,
+) -> Result<StatusCode, SubscribeError> {
+    // 1️⃣ 先过人机校验
+    let submission = Submission {
+        fields: &form.attributes,
+        client_ip,
+    };
+    if let Err(rejection) = state.bot_protection.verify(&submission).await {
+        return rejected(rejection, client_ip);
+    }
+
+    let list_slug = form
+        .list
+        .clone()
+        .unwrap_or_else(|| DEFAULT_LIST.to_string());
+    let locale = parse_locale(form.locale.clone()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let consent = Consent::parse(
+        Some(client_ip),
+        &headers,
+        form.source.take(),
+        form.consent_text_version.take(),
+        &state.newsletter.consent_text_version,
+    )
+    .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let fields = std::mem::take(&mut form.attributes);
     let new_subscriber: NewSubscriber = form.try_into().map_err(|_| StatusCode::BAD_REQUEST)?;
+    if let Err(reason) = state.email_policy.check(&new_subscriber.email) {
+        tracing::info!("Rejected the email of a subscriber: {}", reason);
+        return Err(SubscribeError::RejectedEmail(reason));
+    }
+    if let Some(domain_check) = &state.domain_check
+        && let Err(reason) = domain_check.check(&new_subscriber.email).await
+    {
+        tracing::info!("Rejected the domain of a subscriber: {}", reason);
+        return Err(SubscribeError::RejectedEmail(reason));
+    }
+    let canonical_email = state.email_policy.canonical(&new_subscriber.email);
 
     let db = &state.db;
     // 2️⃣ 开启事务（SeaORM 方式）
@@ -41,51 +134,126 @@ pub async fn subscribe(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // 3️⃣ 插入 subscriber
-    let subscriber_id = insert_subscriber(&txn, &new_subscriber)
+    // 3️⃣ 找到要订阅的 list
+    let list = find_list(&txn, &list_slug)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    // 4️⃣ 生成 token
-    let subscription_token = generate_subscription_token();
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let attributes = AttributeSchema::from_column(list.attribute_schema.as_ref())
+        .map_err(|e| {
+            tracing::error!("List {} has an invalid schema: {}", list.slug, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .parse_form(&fields)
+        .map_err(|e| {
+            tracing::warn!("Rejected subscriber attributes: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    // The form token is only used up once the subscription is saved: the
+    // transaction rolls it back with everything else otherwise.
+    let submission = Submission {
+        fields: &fields,
+        client_ip,
+    };
+    if let Err(rejection) = state.bot_protection.accept(&txn, &submission).await {
+        return rejected(rejection, client_ip);
+    }
 
-    // 5️⃣ 存 token
-    store_token(&txn, subscriber_id, &subscription_token)
+    // 4️⃣ 插入 subscriber（已订阅其他 list 的邮箱直接复用）
+    let subscriber_id =
+        find_or_insert_subscriber(&txn, &new_subscriber, &canonical_email, locale.as_deref())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let needs_confirmation = request_list_subscription(&txn, list.id, subscriber_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // 6️⃣ 提交事务
-    txn.commit()
+    if !needs_confirmation {
+        // Already confirmed: we answer as for a new subscription, so the
+        // form does not reveal who is subscribed. Nothing is saved: anyone can
+        // post the form, so its attributes wait for a confirmation.
+        return Ok(StatusCode::OK);
+    }
+    record_consent(&txn, subscriber_id, list.id, "subscribe", &consent)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // 7️⃣ 发邮件（事务之后）
-    send_confirmation_email(
-        &state.email_client,
-        new_subscriber,
-        &state.base_url,
+    // 5️⃣ 生成 token 并存储（token 对应 subscriber + list，表单属性确认后才写入）
+    let subscription_token = generate_subscription_token();
+    store_token(
+        &txn,
+        subscriber_id,
+        list.id,
         &subscription_token,
+        attributes,
     )
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let _email_client = &state.email_client;
-    let _base_url = &state.base_url;
+    // 6️⃣ 确认邮件写入 outbox（同一个事务，由后台 dispatcher 发送）
+    enqueue_confirmation_email(&txn, &new_subscriber, &state.base_url, &subscription_token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // 7️⃣ 提交事务
+    txn.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(StatusCode::OK)
 }
 
+/// The answer to a form the bot protection turned away.
+fn rejected(rejection: Rejection, client_ip: IpAddr) -> Result<StatusCode, SubscribeError> {
+    match rejection {
+        Rejection::Bot(reason) => {
+            tracing::warn!(client_ip = %client_ip, reason, "Dropped a subscription from a bot");
+            Ok(StatusCode::OK)
+        }
+        Rejection::Failed(reason) => {
+            tracing::info!(client_ip = %client_ip, "Failed the bot protection: {}", reason);
+            Err(StatusCode::BAD_REQUEST.into())
+        }
+        Rejection::Unavailable(_) => Err(StatusCode::SERVICE_UNAVAILABLE.into()),
+    }
+}
+
+#[derive(Serialize)]
+pub struct FormToken {
+    form_token: String,
+}
+
+/// A token to post back with the subscription form, telling when and to whom
+/// the form was served.
+pub async fn issue_form_token(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+) -> Response {
+    let form_token = form_token(&state.form_token_secret, Utc::now().timestamp(), client_ip);
+    // Each form needs a fresh one.
+    (
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(FormToken { form_token }),
+    )
+        .into_response()
+}
+
 #[tracing::instrument(
     name = "Store subscription token in the database",
-    skip(subscription_token, txn)
+    skip(subscription_token, txn, attributes)
 )]
 pub async fn store_token(
     txn: &DatabaseTransaction,
     subscriber_id: i32,
+    list_id: i32,
     subscription_token: &str,
+    attributes: Map<String, Value>,
 ) -> Result<(), sea_orm::DbErr> {
     let token = subscription_tokens::ActiveModel {
         subscriber_id: Set(subscriber_id),
+        list_id: Set(list_id),
         subscription_token: Set(subscription_token.to_string()),
+        attributes: Set(merge_new(None, attributes)),
         ..Default::default()
     };
 
@@ -104,13 +272,19 @@ pub async fn store_token(
 pub async fn insert_subscriber(
     db: &DatabaseTransaction,
     new_subscriber: &NewSubscriber,
+    canonical_email: &str,
+    locale: Option<&str>,
 ) -> Result<i32, sea_orm::DbErr> {
     let subscription = subscriptions::ActiveModel {
         // ❗ 不要设置 id
         email: Set(new_subscriber.email.as_ref().to_string()),
+        canonical_email: Set(canonical_email.to_string()),
         name: Set(new_subscriber.name.as_ref().to_string()),
         subscribed_at: Set(Utc::now()),
         status: Set("pending_confirmation".to_string()),
+        preferences_token: Set(generate_preferences_token()),
+        digest_frequency: Set(DIGEST_FREQUENCIES[0].to_string()),
+        locale: Set(locale.map(str::to_string)),
         ..Default::default()
     };
 
@@ -119,6 +293,82 @@ pub async fn insert_subscriber(
     Ok(result.id) // 👈 这里拿到数据库生成的 id
 }
 
+/// The id of the subscriber whose email has the canonical form
+/// `canonical_email`, inserting them if they are not subscribed to any list
+/// yet.
+pub async fn find_or_insert_subscriber(
+    txn: &DatabaseTransaction,
+    new_subscriber: &NewSubscriber,
+    canonical_email: &str,
+    locale: Option<&str>,
+) -> Result<i32, sea_orm::DbErr> {
+    let existing = subscriptions::Entity::find()
+        .filter(subscriptions::Column::CanonicalEmail.eq(canonical_email))
+        .one(txn)
+        .await?;
+    match existing {
+        Some(subscriber) => Ok(subscriber.id),
+        None => insert_subscriber(txn, new_subscriber, canonical_email, locale).await,
+    }
+}
+
+/// Record that the subscriber asked to join the list, returning whether a
+/// confirmation is needed, i.e. they are not confirmed on it already.
+#[tracing::instrument(name = "Request a list subscription", skip(txn))]
+pub async fn request_list_subscription(
+    txn: &DatabaseTransaction,
+    list_id: i32,
+    subscriber_id: i32,
+) -> Result<bool, sea_orm::DbErr> {
+    let existing = list_subscriptions::Entity::find()
+        .filter(list_subscriptions::Column::ListId.eq(list_id))
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber_id))
+        .one(txn)
+        .await?;
+    match existing {
+        Some(subscription) if subscription.status == "confirmed" => Ok(false),
+        Some(subscription) => {
+            let mut subscription: list_subscriptions::ActiveModel = subscription.into();
+            subscription.status = Set("pending_confirmation".to_string());
+            subscription.update(txn).await?;
+            Ok(true)
+        }
+        None => {
+            list_subscriptions::ActiveModel {
+                list_id: Set(list_id),
+                subscriber_id: Set(subscriber_id),
+                status: Set("pending_confirmation".to_string()),
+                subscribed_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(txn)
+            .await?;
+            Ok(true)
+        }
+    }
+}
+
+/// A language tag such as `fr` or `pt-BR`: up to 16 ASCII letters, digits
+/// and dashes. A blank locale is no locale.
+pub(crate) fn parse_locale(locale: Option<String>) -> Result<Option<String>, String> {
+    let Some(locale) = locale.map(|l| l.trim().to_string()) else {
+        return Ok(None);
+    };
+    if locale.is_empty() {
+        return Ok(None);
+    }
+    let is_valid = locale.len() <= 16
+        && locale.starts_with(|c: char| c.is_ascii_alphabetic())
+        && locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if is_valid {
+        Ok(Some(locale))
+    } else {
+        Err(format!("{} is not a valid locale.", locale))
+    }
+}
+
 /// Generate a random 25-characters-long case-sensitive subscription token.
 fn generate_subscription_token() -> String {
     let mut rng = rng();
@@ -137,15 +387,15 @@ impl TryFrom<FormData> for NewSubscriber {
 }
 
 #[tracing::instrument(
-    name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url)
+    name = "Enqueue a confirmation email for a new subscriber",
+    skip(txn, new_subscriber, base_url, subscription_token)
 )]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+pub async fn enqueue_confirmation_email(
+    txn: &DatabaseTransaction,
+    new_subscriber: &NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), sea_orm::DbErr> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -159,7 +409,15 @@ pub async fn send_confirmation_email(
 Click <a href=\"{}\">here</a> to confirm your subscription.",
         confirmation_link
     );
-    email_client
-        .send_email(new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+    // Transactional: it reaches addresses that unsubscribed from everything,
+    // whose suppression is only lifted once they confirm.
+    enqueue_transactional_email(
+        txn,
+        &new_subscriber.email,
+        "Welcome!",
+        &html_body,
+        Some(&plain_body),
+    )
+    .await?;
+    Ok(())
 }
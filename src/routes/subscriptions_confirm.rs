@@ -1,81 +1,152 @@
+use crate::attributes::merge_new;
+use crate::client_ip::ClientIp;
+use crate::consent::{
+    CONFIRMATION_SOURCE, Consent, record_consent, subscribed_text_version, user_agent,
+};
+use crate::entity::{list_subscriptions, subscription_tokens, subscriptions};
 use crate::startup::AppState;
+use crate::suppression::lift_unsubscribe;
 use axum::extract::{Query, State};
-use axum::http::StatusCode;
-use sea_orm::{DatabaseConnection, RelationTrait};
+use axum::http::{HeaderMap, StatusCode};
+use chrono::Utc;
+use sea_orm::sea_query::{Expr, LockType};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, DbErr, EntityTrait, QueryFilter,
+    QuerySelect, Set, TransactionTrait,
+};
+use serde_json::Value;
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, state))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, state, headers)
+)]
 pub async fn confirm(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    headers: HeaderMap,
     Query(parameters): Query<Parameters>,
 ) -> Result<StatusCode, StatusCode> {
-    let id = match get_subscriber_id_from_token(&state.db, &parameters.subscription_token).await {
-        Ok(id) => id,
+    let txn = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let token = match find_token(&txn, &parameters.subscription_token).await {
+        Ok(token) => token,
         Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
     };
 
-    match id {
+    match token {
         // Non-existing token
         None => Err(StatusCode::UNAUTHORIZED),
 
-        Some(subscriber_id) => {
-            if confirm_subscriber(&state.db, subscriber_id).await.is_err() {
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        // Already redeemed: another click on the link, or a link scanner.
+        Some(token) if token.used_at.is_some() => Ok(StatusCode::OK),
+
+        Some(token) => {
+            let (subscriber_id, list_id) = (token.subscriber_id, token.list_id);
+            let confirmed = confirm_subscriber(&txn, token)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if confirmed {
+                // The confirmation is a consent to the wording shown when
+                // subscribing.
+                let text_version = subscribed_text_version(&txn, subscriber_id, list_id)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                    .unwrap_or_else(|| state.newsletter.consent_text_version.clone());
+                let consent = Consent {
+                    ip_address: Some(client_ip),
+                    user_agent: user_agent(&headers),
+                    source: CONFIRMATION_SOURCE.to_string(),
+                    text_version,
+                };
+                record_consent(&txn, subscriber_id, list_id, "confirm", &consent)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
             }
+            txn.commit()
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
             Ok(StatusCode::OK)
         }
     }
 }
 
-use sea_orm::{ColumnTrait, EntityTrait, JoinType, QueryFilter, QuerySelect};
-
-use crate::entity::{subscription_tokens, subscriptions};
-#[tracing::instrument(name = "Get subscriber_id from token", skip(db, token))]
-pub async fn get_subscriber_id_from_token(
-    db: &DatabaseConnection,
+/// The token, locked until the transaction ends so that concurrent clicks
+/// redeem it only once.
+#[tracing::instrument(name = "Find a subscription token", skip(txn, token))]
+pub async fn find_token(
+    txn: &DatabaseTransaction,
     token: &str,
-) -> Result<Option<i32>, DbErr> {
-    let result = subscription_tokens::Entity::find()
-        // 👉 JOIN subscriptions
-        .join(
-            JoinType::InnerJoin,
-            subscription_tokens::Relation::Subscriptions.def(),
-        )
-        // 👉 WHERE token
+) -> Result<Option<subscription_tokens::Model>, DbErr> {
+    subscription_tokens::Entity::find()
         .filter(subscription_tokens::Column::SubscriptionToken.eq(token))
-        // 👉 只选 subscriber_id（可选优化）
-        .select_only()
-        .column(subscription_tokens::Column::SubscriberId)
-        .into_tuple::<i32>() // 👈 关键
-        .one(db)
-        .await?;
-
-    Ok(result)
+        .lock(LockType::Update)
+        .one(txn)
+        .await
 }
 
-use sea_orm::{ActiveModelTrait, DbErr, Set};
+/// Redeem `token`: confirm the subscriber on its list, giving them the
+/// attributes of the form they did not have yet. Returns whether the list
+/// subscription was still waiting for this confirmation; lists confirmed
+/// already or left since are not touched.
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip_all)]
+pub async fn confirm_subscriber(
+    txn: &DatabaseTransaction,
+    token: subscription_tokens::Model,
+) -> Result<bool, DbErr> {
+    let (subscriber_id, list_id) = (token.subscriber_id, token.list_id);
+    let attributes = token.attributes.clone();
+    // 1️⃣ token 只能使用一次
+    let mut token: subscription_tokens::ActiveModel = token.into();
+    token.used_at = Set(Some(Utc::now()));
+    token.update(txn).await?;
+
+    // 2️⃣ 确认 token 对应的 list（仅限待确认的）
+    let confirmed = list_subscriptions::Entity::update_many()
+        .col_expr(list_subscriptions::Column::Status, Expr::value("confirmed"))
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber_id))
+        .filter(list_subscriptions::Column::ListId.eq(list_id))
+        .filter(list_subscriptions::Column::Status.eq("pending_confirmation"))
+        .exec(txn)
+        .await?;
+    if confirmed.rows_affected == 0 {
+        return Ok(false);
+    }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(db, subscriber_id))]
-pub async fn confirm_subscriber(db: &DatabaseConnection, subscriber_id: i32) -> Result<(), DbErr> {
-    // 1️⃣ 查找 subscriber
+    // 3️⃣ 查找 subscriber
     let subscriber = subscriptions::Entity::find_by_id(subscriber_id)
-        .one(db)
+        .one(txn)
         .await?
         .ok_or_else(|| DbErr::Custom("Subscriber not found".into()))?;
 
-    // 2️⃣ 转 ActiveModel
-    let mut subscriber: subscriptions::ActiveModel = subscriber.into();
-
-    // 3️⃣ 修改状态
-    subscriber.status = Set("confirmed".to_string());
-
-    // 4️⃣ 更新
-    subscriber.update(db).await?;
+    // 4️⃣ 第一次确认、或全部退订后重新订阅时修改 subscriber 状态（退信、投诉的不改动）
+    let reactivated =
+        subscriber.status == "pending_confirmation" || subscriber.status == "unsubscribed";
+    // 5️⃣ 写入表单属性（已有的值不覆盖）
+    let merged = match attributes {
+        Some(Value::Object(attributes)) => merge_new(subscriber.attributes.as_ref(), attributes),
+        _ => subscriber.attributes.clone(),
+    };
+    if reactivated || merged != subscriber.attributes {
+        let mut subscriber: subscriptions::ActiveModel = subscriber.into();
+        if reactivated {
+            subscriber.status = Set("confirmed".to_string());
+        }
+        subscriber.attributes = Set(merged);
+        let subscriber = subscriber.update(txn).await?;
+        // Someone who unsubscribed from everything opted in again.
+        if reactivated {
+            lift_unsubscribe(txn, &subscriber.email).await?;
+        }
+    }
 
-    Ok(())
+    Ok(true)
 }
@@ -0,0 +1,93 @@
+use crate::entity::{email_clicks, email_opens, issue_recipients, tracked_links};
+use crate::startup::AppState;
+use crate::tracking::parse_click_token;
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Redirect, Response};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+};
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[tracing::instrument(name = "Track an email open", skip(state, token))]
+pub async fn track_open(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    if state.tracking.enabled {
+        // A broken image in the reader's inbox is worse than a missed open:
+        // failures are logged and the pixel is served regardless.
+        if let Err(e) = record_open(&state.db, &token).await {
+            tracing::error!("Failed to record an email open: {:?}", e);
+        }
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "image/gif"),
+            // Every open has to reach us, not a cache.
+            (header::CACHE_CONTROL, "no-store, no-cache, must-revalidate"),
+        ],
+        PIXEL,
+    )
+        .into_response()
+}
+
+#[tracing::instrument(name = "Track a link click", skip(state, token))]
+pub async fn track_click(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Redirect, StatusCode> {
+    let (recipient_token, link_id) = parse_click_token(&token).ok_or(StatusCode::NOT_FOUND)?;
+    let recipient = find_recipient(&state.db, recipient_token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let link = tracked_links::Entity::find_by_id(link_id)
+        .filter(tracked_links::Column::NewsletterIssueId.eq(recipient.newsletter_issue_id))
+        .one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Links keep working after tracking is turned off.
+    if state.tracking.enabled {
+        let click = email_clicks::ActiveModel {
+            issue_recipient_id: Set(recipient.id),
+            tracked_link_id: Set(link.id),
+            clicked_at: Set(Utc::now()),
+            ..Default::default()
+        };
+        if let Err(e) = click.insert(&state.db).await {
+            tracing::error!("Failed to record a link click: {:?}", e);
+        }
+    }
+    Ok(Redirect::to(&link.url))
+}
+
+async fn record_open(db: &DatabaseConnection, token: &str) -> Result<(), DbErr> {
+    let Some(recipient) = find_recipient(db, token).await? else {
+        return Ok(());
+    };
+    email_opens::ActiveModel {
+        issue_recipient_id: Set(recipient.id),
+        opened_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(())
+}
+
+async fn find_recipient(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<Option<issue_recipients::Model>, DbErr> {
+    issue_recipients::Entity::find()
+        .filter(issue_recipients::Column::TrackingToken.eq(token))
+        .one(db)
+        .await
+}
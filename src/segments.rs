@@ -0,0 +1,310 @@
+//! Segments: saved boolean filters over subscribers, used to send an issue to
+//! part of a list only.
+//!
+//! A segment is stored as the JSON of a [`SegmentFilter`], e.g.
+//! `{"all": [{"tag": "rust"}, {"not": {"opened_within_days": 90}}]}`, and
+//! compiled into a SeaORM [`Condition`] on `subscriptions` when it is used.
+
+use crate::entity::{
+    email_clicks, email_opens, issue_recipients, segments, subscriber_tags, subscriptions, tags,
+};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::{Expr, ExprTrait, LikeExpr, Query, SelectStatement};
+use sea_orm::{ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait};
+use serde::{Deserialize, Serialize};
+
+/// How deeply `all`, `any` and `not` can be nested.
+const MAX_DEPTH: usize = 10;
+
+/// The longest engagement window, in days.
+const MAX_WINDOW_DAYS: u32 = 3650;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentFilter {
+    /// Every filter matches; an empty `all` matches everyone.
+    All(Vec<SegmentFilter>),
+    /// At least one filter matches; an empty `any` matches no one.
+    Any(Vec<SegmentFilter>),
+    Not(Box<SegmentFilter>),
+    /// Tagged with this tag.
+    Tag(String),
+    /// The subscriber status, e.g. `confirmed`.
+    Status(String),
+    SubscribedBefore(DateTime<Utc>),
+    SubscribedAfter(DateTime<Utc>),
+    /// The locale or one of its variants: `fr` matches `fr` and `fr-CA`.
+    Locale(String),
+    /// Opened at least one issue in the last days.
+    OpenedWithinDays(u32),
+    /// Clicked at least one link in the last days.
+    ClickedWithinDays(u32),
+}
+
+impl SegmentFilter {
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_at(0)
+    }
+
+    fn validate_at(&self, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err(format!(
+                "Segments cannot be nested more than {} levels deep.",
+                MAX_DEPTH
+            ));
+        }
+        match self {
+            Self::All(filters) | Self::Any(filters) => filters
+                .iter()
+                .try_for_each(|filter| filter.validate_at(depth + 1)),
+            Self::Not(filter) => filter.validate_at(depth + 1),
+            Self::Tag(value) | Self::Status(value) | Self::Locale(value)
+                if value.trim().is_empty() =>
+            {
+                Err("Tags, statuses and locales cannot be empty.".into())
+            }
+            Self::OpenedWithinDays(0) | Self::ClickedWithinDays(0) => {
+                Err("Engagement windows must be at least one day long.".into())
+            }
+            Self::OpenedWithinDays(days) | Self::ClickedWithinDays(days)
+                if *days > MAX_WINDOW_DAYS =>
+            {
+                Err(format!(
+                    "Engagement windows cannot be longer than {} days.",
+                    MAX_WINDOW_DAYS
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The condition on `subscriptions` matching the subscribers in the
+    /// segment, engagement windows ending at `now`.
+    pub fn condition(&self, now: DateTime<Utc>) -> Condition {
+        match self {
+            Self::All(filters) => filters.iter().fold(Condition::all(), |all, filter| {
+                all.add(filter.condition(now))
+            }),
+            Self::Any(filters) => filters.iter().fold(Condition::any(), |any, filter| {
+                any.add(filter.condition(now))
+            }),
+            Self::Not(filter) => filter.condition(now).not(),
+            Self::Tag(tag) => {
+                Condition::all().add(subscriptions::Column::Id.in_subquery(tagged_with(tag)))
+            }
+            Self::Status(status) => {
+                Condition::all().add(subscriptions::Column::Status.eq(status.as_str()))
+            }
+            Self::SubscribedBefore(date) => {
+                Condition::all().add(subscriptions::Column::SubscribedAt.lt(*date))
+            }
+            Self::SubscribedAfter(date) => {
+                Condition::all().add(subscriptions::Column::SubscribedAt.gt(*date))
+            }
+            // Without a locale, `NOT` must match: `NULL`s would make it match
+            // no one.
+            Self::Locale(locale) => Condition::all()
+                .add(subscriptions::Column::Locale.is_not_null())
+                .add(
+                    Condition::any()
+                        .add(subscriptions::Column::Locale.eq(locale.as_str()))
+                        .add(subscriptions::Column::Locale.like(variants_of(locale))),
+                ),
+            Self::OpenedWithinDays(days) => Condition::all()
+                .add(subscriptions::Column::Id.in_subquery(opened_since(window_start(now, *days)))),
+            Self::ClickedWithinDays(days) => Condition::all().add(
+                subscriptions::Column::Id.in_subquery(clicked_since(window_start(now, *days))),
+            ),
+        }
+    }
+}
+
+/// The filter of a saved segment.
+pub async fn load_segment<C: ConnectionTrait>(
+    db: &C,
+    segment_id: i32,
+) -> Result<Option<SegmentFilter>, DbErr> {
+    let Some(segment) = segments::Entity::find_by_id(segment_id).one(db).await? else {
+        return Ok(None);
+    };
+    let filter = serde_json::from_str(&segment.definition).map_err(|e| {
+        DbErr::Custom(format!(
+            "Segment {} has an invalid definition: {}",
+            segment_id, e
+        ))
+    })?;
+    Ok(Some(filter))
+}
+
+/// The `LIKE` pattern of the variants of `locale`, e.g. `fr-%`.
+fn variants_of(locale: &str) -> LikeExpr {
+    let escaped = locale
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    LikeExpr::new(format!("{}-%", escaped)).escape('\\')
+}
+
+/// The start of a window of `days` ending at `now`. Saved segments are not
+/// validated again when loaded: a window reaching before the earliest date
+/// there is starts there.
+fn window_start(now: DateTime<Utc>, days: u32) -> DateTime<Utc> {
+    now.checked_sub_signed(Duration::days(days.into()))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+fn tagged_with(tag: &str) -> SelectStatement {
+    Query::select()
+        .column((
+            subscriber_tags::Entity,
+            subscriber_tags::Column::SubscriberId,
+        ))
+        .from(subscriber_tags::Entity)
+        .inner_join(
+            tags::Entity,
+            Expr::col((tags::Entity, tags::Column::Id))
+                .equals((subscriber_tags::Entity, subscriber_tags::Column::TagId)),
+        )
+        .and_where(Expr::col((tags::Entity, tags::Column::Name)).eq(tag))
+        .to_owned()
+}
+
+/// Recipients are kept when a subscriber is deleted, without their id: skip
+/// those, as `NULL`s would make `NOT IN` match no one.
+fn recipients_since<E, C>(entity: E, recipient: C, at: C, since: DateTime<Utc>) -> SelectStatement
+where
+    E: EntityTrait,
+    C: ColumnTrait,
+{
+    Query::select()
+        .column((
+            issue_recipients::Entity,
+            issue_recipients::Column::SubscriberId,
+        ))
+        .from(issue_recipients::Entity)
+        .inner_join(
+            entity,
+            Expr::col((entity, recipient))
+                .equals((issue_recipients::Entity, issue_recipients::Column::Id)),
+        )
+        .and_where(Expr::col((entity, at)).gte(since))
+        .and_where(
+            Expr::col((
+                issue_recipients::Entity,
+                issue_recipients::Column::SubscriberId,
+            ))
+            .is_not_null(),
+        )
+        .to_owned()
+}
+
+fn opened_since(since: DateTime<Utc>) -> SelectStatement {
+    recipients_since(
+        email_opens::Entity,
+        email_opens::Column::IssueRecipientId,
+        email_opens::Column::OpenedAt,
+        since,
+    )
+}
+
+fn clicked_since(since: DateTime<Utc>) -> SelectStatement {
+    recipients_since(
+        email_clicks::Entity,
+        email_clicks::Column::IssueRecipientId,
+        email_clicks::Column::ClickedAt,
+        since,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentFilter;
+    use crate::entity::subscriptions;
+    use chrono::{TimeZone, Utc};
+    use sea_orm::{DbBackend, EntityTrait, QueryFilter, QueryTrait};
+
+    fn sql(filter: &SegmentFilter) -> String {
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap();
+        subscriptions::Entity::find()
+            .filter(filter.condition(now))
+            .build(DbBackend::MySql)
+            .to_string()
+    }
+
+    #[test]
+    fn segments_are_read_from_json() {
+        let filter: SegmentFilter = serde_json::from_str(
+            r#"{"all": [{"tag": "rust"}, {"not": {"opened_within_days": 90}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            filter,
+            SegmentFilter::All(vec![
+                SegmentFilter::Tag("rust".into()),
+                SegmentFilter::Not(Box::new(SegmentFilter::OpenedWithinDays(90))),
+            ])
+        );
+    }
+
+    #[test]
+    fn tags_are_matched_through_a_subquery() {
+        let sql = sql(&SegmentFilter::Tag("rust".into()));
+        assert!(sql.contains("`subscriptions`.`id` IN (SELECT `subscriber_tags`.`subscriber_id`"));
+        assert!(sql.contains("`tags`.`name` = 'rust'"));
+    }
+
+    #[test]
+    fn boolean_operators_are_compiled_to_sql() {
+        let sql = sql(&SegmentFilter::Any(vec![
+            SegmentFilter::Status("confirmed".into()),
+            SegmentFilter::Not(Box::new(SegmentFilter::Locale("fr".into()))),
+        ]));
+        assert!(sql.contains("`subscriptions`.`status` = 'confirmed' OR (NOT"));
+        assert!(sql.contains("`subscriptions`.`locale` LIKE 'fr-%'"));
+        // Subscribers without a locale are not in `fr`.
+        assert!(sql.contains("NOT (`subscriptions`.`locale` IS NOT NULL AND"));
+    }
+
+    #[test]
+    fn locale_wildcards_are_matched_literally() {
+        let sql = sql(&SegmentFilter::Locale("f_%".into()));
+        assert!(sql.contains(r"LIKE 'f\\_\\%-%' ESCAPE '\\'"));
+    }
+
+    #[test]
+    fn engagement_windows_end_now() {
+        let sql = sql(&SegmentFilter::ClickedWithinDays(30));
+        assert!(sql.contains("`email_clicks`.`clicked_at` >= '2026-09-19 09:00:00"));
+        assert!(sql.contains("`issue_recipients`.`subscriber_id` IS NOT NULL"));
+    }
+
+    #[test]
+    fn overlong_saved_windows_start_at_the_earliest_date() {
+        // Saved segments are not validated again when they are loaded.
+        let sql = sql(&SegmentFilter::OpenedWithinDays(u32::MAX));
+        assert!(sql.contains("`email_opens`.`opened_at` >="));
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        assert!(SegmentFilter::Tag(" ".into()).validate().is_err());
+        assert!(SegmentFilter::OpenedWithinDays(0).validate().is_err());
+        assert!(SegmentFilter::ClickedWithinDays(3651).validate().is_err());
+        assert!(
+            SegmentFilter::OpenedWithinDays(u32::MAX)
+                .validate()
+                .is_err()
+        );
+        assert!(SegmentFilter::OpenedWithinDays(3650).validate().is_ok());
+        let mut deep = SegmentFilter::Tag("rust".into());
+        for _ in 0..12 {
+            deep = SegmentFilter::Not(Box::new(deep));
+        }
+        assert!(deep.validate().is_err());
+        assert!(
+            SegmentFilter::All(vec![SegmentFilter::Tag("rust".into())])
+                .validate()
+                .is_ok()
+        );
+    }
+}
@@ -1,20 +1,48 @@
 use axum::{
     Router,
-    routing::{get, post},
+    extract::connect_info::{ConnectInfo, IntoMakeServiceWithConnectInfo},
+    http::HeaderMap,
+    middleware::{self, AddExtension},
+    routing::{delete, get, post, put},
     serve::Serve,
 };
 use sea_orm::{Database, DatabaseConnection};
+use secrecy::SecretString;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::net::TcpListener;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
 use tracing::info_span;
-use uuid::Uuid;
 
-use crate::configuration::DatabaseSettings;
+use crate::bot_protection::BotProtection;
+use crate::client_ip::TrustedProxies;
+use crate::configuration::{
+    AdminSettings, DatabaseSettings, NewsletterSettings, PostmarkWebhookSettings, TrackingSettings,
+};
+use crate::domain_check::{DomainCheck, DomainResolver, SystemResolver};
 use crate::email_client::EmailClient;
+use crate::email_outbox::run_dispatcher_until_stopped;
+use crate::email_policy::EmailPolicy;
+use crate::housekeeping::{HOUSEKEEPING_INTERVAL, run_housekeeping_until_stopped};
+use crate::issue_scheduler::run_scheduler_until_stopped;
+use crate::rate_limit::{RateLimits, rate_limit};
+use crate::routes::admin::{
+    ab_test_report, add_suppression, cancel_issue_schedule, create_issue, create_list,
+    create_segment, delete_segment, delete_subscriber, dry_run_segment, export_subscribers,
+    get_import_job, get_subscriber, import_subscribers, issue_stats, list_audit_entries,
+    list_consent_events, list_lists, list_segments, list_subscribers, list_suppressions, list_tags,
+    preview_issue, publish_draft, remove_suppression, reschedule_issue, tag_subscriber,
+    test_send_issue, untag_subscriber, update_draft, update_list_attributes, update_subscriber,
+};
 use crate::routes::confirm;
 use crate::{
     configuration::Settings,
-    routes::{health_check, subscribe},
+    routes::{
+        archive, archived_issue, atom, confirm_privacy_request, health_check, issue_form_token,
+        postmark_bounce, postmark_spam_complaint, request_privacy_action, rss, show_preferences,
+        show_privacy_request, subscribe, track_click, track_open, update_preferences,
+    },
 };
 
 #[derive(Clone)]
@@ -22,33 +50,126 @@ pub struct AppState {
     pub db: DatabaseConnection,
     pub email_client: EmailClient,
     pub base_url: String,
+    pub postmark_webhooks: PostmarkWebhookSettings,
+    pub admin: AdminSettings,
+    pub tracking: TrackingSettings,
+    pub newsletter: NewsletterSettings,
+    pub trusted_proxies: TrustedProxies,
+    pub rate_limits: RateLimits,
+    pub bot_protection: BotProtection,
+    /// Signs the tokens of `GET /subscriptions/form-token`.
+    pub form_token_secret: SecretString,
+    pub email_policy: EmailPolicy,
+    /// `None` when the domains of new subscribers are not looked up.
+    pub domain_check: Option<DomainCheck>,
 }
 
-pub fn run(
-    listener: TcpListener,
-    connection: DatabaseConnection,
-    email_client: EmailClient,
-    base_url: String,
-) -> Serve<TcpListener, Router, Router> {
-    let state = AppState {
-        db: connection,
-        email_client,
-        base_url,
-    };
+/// The server, with the peer address of each connection available to the
+/// handlers through `ConnectInfo`.
+pub type Server = Serve<
+    TcpListener,
+    IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+    AddExtension<Router, ConnectInfo<SocketAddr>>,
+>;
 
+pub fn run(listener: TcpListener, state: AppState) -> Server {
     let router = build_router(state);
-    axum::serve(listener, router)
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
 }
 
 fn build_router(state: AppState) -> Router {
-    Router::new()
-        .route("/health_check", get(health_check))
+    // Each of these can make us send an email or write to the database.
+    let public_forms = Router::new()
         .route("/subscriptions", post(subscribe))
         .route("/subscriptions/confirm", get(confirm))
+        .route("/privacy/requests", post(request_privacy_action))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit));
+    Router::new()
+        .route("/health_check", get(health_check))
+        .merge(public_forms)
+        .route("/subscriptions/form-token", get(issue_form_token))
+        .route(
+            "/preferences/{token}",
+            get(show_preferences).post(update_preferences),
+        )
+        .route(
+            "/privacy/requests/{token}",
+            get(show_privacy_request).post(confirm_privacy_request),
+        )
+        .route("/webhooks/postmark/bounce", post(postmark_bounce))
+        .route(
+            "/webhooks/postmark/spam-complaint",
+            post(postmark_spam_complaint),
+        )
+        .route(
+            "/admin/api/suppressions",
+            get(list_suppressions).post(add_suppression),
+        )
+        .route(
+            "/admin/api/suppressions/{email}",
+            delete(remove_suppression),
+        )
+        .route("/admin/api/audit", get(list_audit_entries))
+        .route("/admin/api/lists", get(list_lists).post(create_list))
+        .route(
+            "/admin/api/lists/{slug}/attributes",
+            put(update_list_attributes),
+        )
+        .route("/admin/api/subscribers", get(list_subscribers))
+        .route("/admin/api/subscribers/export", get(export_subscribers))
+        .route("/admin/api/subscribers/import", post(import_subscribers))
+        .route(
+            "/admin/api/subscribers/import/{job_id}",
+            get(get_import_job),
+        )
+        .route(
+            "/admin/api/subscribers/{subscriber_id}",
+            get(get_subscriber)
+                .patch(update_subscriber)
+                .delete(delete_subscriber),
+        )
+        .route(
+            "/admin/api/subscribers/{subscriber_id}/consent-events",
+            get(list_consent_events),
+        )
+        .route("/admin/api/tags", get(list_tags))
+        .route(
+            "/admin/api/subscribers/{subscriber_id}/tags/{tag}",
+            put(tag_subscriber).delete(untag_subscriber),
+        )
+        .route(
+            "/admin/api/segments",
+            get(list_segments).post(create_segment),
+        )
+        .route("/admin/api/segments/dry-run", post(dry_run_segment))
+        .route("/admin/api/segments/{segment_id}", delete(delete_segment))
+        .route("/admin/api/issues", post(create_issue))
+        .route("/admin/api/issues/{issue_id}", put(update_draft))
+        .route("/admin/api/issues/{issue_id}/publish", post(publish_draft))
+        .route(
+            "/admin/api/issues/{issue_id}/test-send",
+            post(test_send_issue),
+        )
+        .route("/admin/issues/{issue_id}/preview", get(preview_issue))
+        .route(
+            "/admin/api/issues/{issue_id}/schedule",
+            put(reschedule_issue).delete(cancel_issue_schedule),
+        )
+        .route("/admin/api/issues/{issue_id}/stats", get(issue_stats))
+        .route("/admin/api/issues/{issue_id}/ab-test", get(ab_test_report))
+        .route("/archive", get(archive))
+        .route("/archive/{slug}", get(archived_issue))
+        .route("/feed.xml", get(atom))
+        .route("/rss.xml", get(rss))
+        .route("/t/o/{token}", get(track_open))
+        .route("/t/c/{token}", get(track_click))
         .with_state(state)
         .layer(
             TraceLayer::new_for_http().make_span_with(|request: &axum::http::Request<_>| {
-                let request_id: String = Uuid::new_v4().to_string();
+                let request_id = request_id(request.headers()).unwrap_or_default();
                 info_span!(
                     "http_request",
                     request_id = %request_id,
@@ -58,6 +179,18 @@ fn build_router(state: AppState) -> Router {
                 )
             }),
         )
+        // Every request gets an `X-Request-Id`, unless a proxy in front of us
+        // already set one, which is sent back with the response.
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+/// The id of the request, as set by `SetRequestIdLayer`.
+pub fn request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
 }
 
 pub async fn get_connection_pool(configuration: &DatabaseSettings) -> DatabaseConnection {
@@ -68,35 +201,74 @@ pub async fn get_connection_pool(configuration: &DatabaseSettings) -> DatabaseCo
 
 pub struct Application {
     port: u16,
-    server: Serve<TcpListener, Router, Router>,
+    server: Server,
 }
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+        Self::build_with_resolver(configuration, None).await
+    }
+
+    /// Build the application with `resolver` answering the DNS queries of
+    /// the domain check, or the system resolver when `None`.
+    pub async fn build_with_resolver(
+        configuration: Settings,
+        resolver: Option<Arc<dyn DomainResolver>>,
+    ) -> Result<Self, std::io::Error> {
         let connection_pool = get_connection_pool(&configuration.database).await;
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
+        let email_client = configuration.email_client.client();
+        if configuration.email_outbox.dispatcher_enabled {
+            // The dispatcher shares the email client, and therefore its rate
+            // limiter, with the request handlers.
+            tokio::spawn(run_dispatcher_until_stopped(
+                connection_pool.clone(),
+                email_client.clone(),
+                configuration.email_outbox.poll_interval(),
+            ));
+        }
+        if configuration.issue_scheduler.enabled {
+            tokio::spawn(run_scheduler_until_stopped(
+                connection_pool.clone(),
+                configuration.application.base_url.clone(),
+                configuration.tracking.enabled,
+                configuration.issue_scheduler.poll_interval(),
+            ));
+        }
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
         );
         let listener = TcpListener::bind(address).await?;
         let port = listener.local_addr()?.port();
-        let server = run(
-            listener,
-            connection_pool,
+        let rate_limits = configuration.rate_limit.limits(&connection_pool);
+        tokio::spawn(run_housekeeping_until_stopped(
+            connection_pool.clone(),
+            rate_limits.clone(),
+            HOUSEKEEPING_INTERVAL,
+        ));
+        let domain_check = match resolver {
+            Some(resolver) => configuration.domain_check.check(resolver),
+            None if configuration.domain_check.enabled => configuration
+                .domain_check
+                .check(Arc::new(SystemResolver::from_system_conf()?)),
+            None => None,
+        };
+        let state = AppState {
+            db: connection_pool,
             email_client,
-            configuration.application.base_url,
-        );
+            base_url: configuration.application.base_url,
+            postmark_webhooks: configuration.postmark_webhooks,
+            admin: configuration.admin,
+            tracking: configuration.tracking,
+            newsletter: configuration.newsletter,
+            trusted_proxies: configuration.application.trusted_proxies,
+            rate_limits,
+            bot_protection: configuration.bot_protection.protection(),
+            form_token_secret: configuration.bot_protection.form_token_secret,
+            email_policy: configuration.email_policy.policy()?,
+            domain_check,
+        };
+        let server = run(listener, state);
 
         Ok(Self { port, server })
     }
@@ -0,0 +1,414 @@
+//! Imports of existing subscribers from CSV files with an `email`, `name`,
+//! `status` and `subscribed_at` column, e.g. when moving over from another
+//! platform.
+//!
+//! The file is parsed as it is uploaded and the subscribers are inserted in
+//! chunks, each in its own transaction, so that large files neither sit in
+//! memory nor hold locks for long.
+
+use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::email_policy::EmailPolicy;
+use crate::entity::{list_subscriptions, subscriptions};
+use crate::preferences::{DIGEST_FREQUENCIES, generate_preferences_token};
+use crate::routes::admin::STATUSES;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use csv_core::{ReadRecordResult, Reader};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QuerySelect, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Rows inserted per transaction.
+pub const CHUNK_SIZE: usize = 500;
+
+/// A CSV parser fed with the chunks of a request body as they arrive.
+pub struct CsvRecords {
+    reader: Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+}
+
+impl Default for CsvRecords {
+    fn default() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+        }
+    }
+}
+
+impl CsvRecords {
+    /// The records completed by `input`. A record split across chunks is
+    /// returned with the chunk that completes it.
+    pub fn push(&mut self, input: &[u8]) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        if !input.is_empty() {
+            self.read(input, &mut records);
+        }
+        records
+    }
+
+    /// The last record, when the data does not end with a newline.
+    pub fn finish(&mut self) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        // An empty input tells the parser there is no data left.
+        self.read(&[], &mut records);
+        records
+    }
+
+    fn read(&mut self, mut input: &[u8], records: &mut Vec<Vec<String>>) {
+        let at_end = input.is_empty();
+        loop {
+            let (result, read, written, ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[read..];
+            self.output_len += written;
+            self.ends_len += ends;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    records.push(self.take_record());
+                    if input.is_empty() && !at_end {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn take_record(&mut self) -> Vec<String> {
+        let mut start = 0;
+        let record = self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = String::from_utf8_lossy(&self.output[start..end]).into_owned();
+                start = end;
+                field
+            })
+            .collect();
+        self.output_len = 0;
+        self.ends_len = 0;
+        record
+    }
+}
+
+/// Where each column is, read from the header row.
+#[derive(Debug)]
+pub struct Columns {
+    email: usize,
+    name: Option<usize>,
+    status: Option<usize>,
+    subscribed_at: Option<usize>,
+}
+
+impl Columns {
+    pub fn from_header(header: &[String]) -> Result<Self, String> {
+        let position = |column: &str| {
+            header
+                .iter()
+                .position(|name| name.trim().eq_ignore_ascii_case(column))
+        };
+        Ok(Self {
+            email: position("email").ok_or("The file has no email column.")?,
+            name: position("name"),
+            status: position("status"),
+            subscribed_at: position("subscribed_at"),
+        })
+    }
+}
+
+/// A valid row of the file.
+#[derive(Debug)]
+pub struct ImportedSubscriber {
+    pub row: u64,
+    pub email: SubscriberEmail,
+    pub name: SubscriberName,
+    /// `confirmed` when missing.
+    pub status: String,
+    /// Now when missing.
+    pub subscribed_at: DateTime<Utc>,
+}
+
+impl ImportedSubscriber {
+    pub fn parse(row: u64, columns: &Columns, record: &[String]) -> Result<Self, String> {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .map(|value| value.trim())
+                .unwrap_or_default()
+        };
+        let email = SubscriberEmail::parse(field(Some(columns.email)).to_string())?;
+        let name = SubscriberName::parse(field(columns.name).to_string())?;
+        let status = match field(columns.status) {
+            "" => "confirmed",
+            status if STATUSES.contains(&status) => status,
+            status => return Err(format!("{} is not a valid status.", status)),
+        };
+        let subscribed_at = match field(columns.subscribed_at) {
+            "" => Utc::now(),
+            date => parse_date(date).ok_or_else(|| format!("{} is not a valid date.", date))?,
+        };
+        Ok(Self {
+            row,
+            email,
+            name,
+            status: status.to_string(),
+            subscribed_at,
+        })
+    }
+}
+
+/// RFC 3339, `YYYY-MM-DD HH:MM:SS` in UTC or `YYYY-MM-DD`.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S") {
+        return Some(date.and_utc());
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc())
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ImportReport {
+    pub accepted: u64,
+    pub duplicates: Vec<SkippedRow>,
+    pub rejected: Vec<SkippedRow>,
+}
+
+/// A row that was not imported. Rows are numbered from 1, the header.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SkippedRow {
+    pub row: u64,
+    pub email: String,
+    pub reason: String,
+}
+
+/// An import in progress.
+#[derive(Default)]
+pub struct Import {
+    pub report: ImportReport,
+    chunk: Vec<ImportedSubscriber>,
+    /// Canonical addresses seen so far, to spot duplicates within the file.
+    seen: HashSet<String>,
+    policy: EmailPolicy,
+}
+
+impl Import {
+    /// An import of the addresses `policy` accepts.
+    pub fn new(policy: EmailPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Add a row, returning whether a chunk is ready to be inserted.
+    pub fn add(&mut self, row: u64, columns: &Columns, record: &[String]) -> bool {
+        let parsed = ImportedSubscriber::parse(row, columns, record).and_then(|subscriber| {
+            self.policy.check(&subscriber.email)?;
+            Ok(subscriber)
+        });
+        match parsed {
+            Ok(subscriber) => {
+                if self.seen.insert(self.policy.canonical(&subscriber.email)) {
+                    self.chunk.push(subscriber);
+                } else {
+                    self.report.duplicates.push(SkippedRow {
+                        row,
+                        email: subscriber.email.as_ref().to_string(),
+                        reason: "Already in the file.".to_string(),
+                    });
+                }
+            }
+            Err(reason) => self.report.rejected.push(SkippedRow {
+                row,
+                email: record.get(columns.email).cloned().unwrap_or_default(),
+                reason,
+            }),
+        }
+        self.chunk.len() >= CHUNK_SIZE
+    }
+
+    /// Insert the pending rows into `list_id` in one transaction. Addresses
+    /// that are already subscribed are reported as duplicates.
+    #[tracing::instrument(name = "Import a chunk of subscribers", skip(self, db))]
+    pub async fn flush(&mut self, db: &DatabaseConnection, list_id: i32) -> Result<(), DbErr> {
+        let chunk = std::mem::take(&mut self.chunk);
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        let txn = db.begin().await?;
+        let chunk: Vec<_> = chunk
+            .into_iter()
+            .map(|s| {
+                let canonical_email = self.policy.canonical(&s.email);
+                (s, canonical_email)
+            })
+            .collect();
+        let existing: HashSet<String> = subscriptions::Entity::find()
+            .select_only()
+            .column(subscriptions::Column::CanonicalEmail)
+            .filter(
+                subscriptions::Column::CanonicalEmail
+                    .is_in(chunk.iter().map(|(_, canonical_email)| canonical_email)),
+            )
+            .into_tuple::<String>()
+            .all(&txn)
+            .await?
+            .into_iter()
+            .collect();
+        let (duplicates, new): (Vec<_>, Vec<_>) = chunk
+            .into_iter()
+            .partition(|(_, canonical_email)| existing.contains(canonical_email));
+        self.report
+            .duplicates
+            .extend(duplicates.into_iter().map(|(s, _)| SkippedRow {
+                row: s.row,
+                email: s.email.as_ref().to_string(),
+                reason: "Already subscribed.".to_string(),
+            }));
+        if new.is_empty() {
+            return txn.commit().await;
+        }
+
+        subscriptions::Entity::insert_many(new.iter().map(|(s, canonical_email)| {
+            subscriptions::ActiveModel {
+                email: Set(s.email.as_ref().to_string()),
+                canonical_email: Set(canonical_email.clone()),
+                name: Set(s.name.as_ref().to_string()),
+                subscribed_at: Set(s.subscribed_at),
+                status: Set(s.status.clone()),
+                preferences_token: Set(generate_preferences_token()),
+                digest_frequency: Set(DIGEST_FREQUENCIES[0].to_string()),
+                ..Default::default()
+            }
+        }))
+        .exec(&txn)
+        .await?;
+        let ids = subscriptions::Entity::find()
+            .select_only()
+            .column(subscriptions::Column::Id)
+            .column(subscriptions::Column::Email)
+            .filter(subscriptions::Column::Email.is_in(new.iter().map(|(s, _)| s.email.as_ref())))
+            .into_tuple::<(i32, String)>()
+            .all(&txn)
+            .await?;
+        list_subscriptions::Entity::insert_many(new.iter().filter_map(|(s, _)| {
+            let (id, _) = ids.iter().find(|(_, email)| email == s.email.as_ref())?;
+            Some(list_subscriptions::ActiveModel {
+                list_id: Set(list_id),
+                subscriber_id: Set(*id),
+                status: Set(list_status(&s.status).to_string()),
+                subscribed_at: Set(s.subscribed_at),
+                ..Default::default()
+            })
+        }))
+        .exec(&txn)
+        .await?;
+        txn.commit().await?;
+        self.report.accepted += new.len() as u64;
+        Ok(())
+    }
+}
+
+/// The status on the list of a subscriber imported with `status`.
+fn list_status(status: &str) -> &'static str {
+    match status {
+        "confirmed" => "confirmed",
+        "pending_confirmation" => "pending_confirmation",
+        _ => "unsubscribed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Columns, CsvRecords, Import, ImportedSubscriber};
+
+    fn records(chunks: &[&str]) -> Vec<Vec<String>> {
+        let mut parser = CsvRecords::default();
+        let mut records: Vec<Vec<String>> = chunks
+            .iter()
+            .flat_map(|chunk| parser.push(chunk.as_bytes()))
+            .collect();
+        records.extend(parser.finish());
+        records
+    }
+
+    fn columns() -> Columns {
+        Columns::from_header(&["Email".into(), "name".into(), "status".into()]).unwrap()
+    }
+
+    #[test]
+    fn records_can_span_chunks() {
+        let parsed = records(&[
+            "email,name\nursula@exa",
+            "mple.com,\"Le Guin, U",
+            "rsula\"\r\n",
+        ]);
+        assert_eq!(
+            parsed,
+            [
+                vec!["email", "name"],
+                vec!["ursula@example.com", "Le Guin, Ursula"]
+            ]
+        );
+    }
+
+    #[test]
+    fn the_last_record_does_not_need_a_newline() {
+        let parsed = records(&["email\na@example.com\n\nb@example.com"]);
+        assert_eq!(
+            parsed,
+            [vec!["email"], vec!["a@example.com"], vec!["b@example.com"]]
+        );
+    }
+
+    #[test]
+    fn the_email_column_is_required() {
+        assert!(Columns::from_header(&["name".into()]).is_err());
+    }
+
+    #[test]
+    fn rows_are_validated() {
+        let row = |fields: &[&str]| {
+            let record: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+            ImportedSubscriber::parse(2, &columns(), &record)
+        };
+        assert_eq!(
+            row(&["a@example.com", "Ursula", ""]).unwrap().status,
+            "confirmed"
+        );
+        assert!(row(&["not-an-email", "Ursula", ""]).is_err());
+        assert!(row(&["a@example.com", "", ""]).is_err());
+        assert!(row(&["a@example.com", "Ursula", "sleeping"]).is_err());
+        assert!(row(&["a@example.com"]).is_err());
+    }
+
+    #[test]
+    fn repeated_addresses_are_duplicates() {
+        let mut import = Import::default();
+        let record: Vec<String> = vec!["a@example.com".into(), "Ursula".into()];
+        import.add(2, &columns(), &record);
+        import.add(3, &columns(), &["A@example.com".into(), "Ursula".into()]);
+        import.add(4, &columns(), &["nope".into(), "Ursula".into()]);
+        assert_eq!(import.chunk.len(), 1);
+        assert_eq!(import.report.duplicates[0].row, 3);
+        assert_eq!(import.report.rejected[0].email, "nope");
+    }
+}
@@ -0,0 +1,116 @@
+//! The global suppression list: addresses we must never email again, no
+//! matter how they got back into the system.
+//!
+//! Only a hash of each address is stored, so the list can outlive the
+//! subscriber's data.
+
+use crate::entity::suppressions;
+use chrono::Utc;
+use sea_orm::{ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter, Set};
+use sha2::{Digest, Sha256};
+
+/// Hex-encoded SHA-256 of the trimmed, lowercased address.
+///
+/// Keep in sync with the import in the `create_suppressions_table` migration.
+pub fn hash_email(email: &str) -> String {
+    let normalized = email.trim().to_lowercase();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+#[tracing::instrument(name = "Check the suppression list", skip_all)]
+pub async fn is_suppressed<C: ConnectionTrait>(db: &C, email: &str) -> Result<bool, DbErr> {
+    let count = suppressions::Entity::find()
+        .filter(suppressions::Column::EmailHash.eq(hash_email(email)))
+        .count(db)
+        .await?;
+    Ok(count > 0)
+}
+
+/// Suppression reasons that also stop the emails people ask for, e.g. the
+/// answer to a privacy request: their mail cannot be delivered, or must not
+/// be sent.
+pub const UNDELIVERABLE_REASONS: [&str; 2] = ["bounced", "complained"];
+
+/// Whether `email` is suppressed for one of the [`UNDELIVERABLE_REASONS`].
+#[tracing::instrument(name = "Check the suppression list for delivery failures", skip_all)]
+pub async fn is_undeliverable<C: ConnectionTrait>(db: &C, email: &str) -> Result<bool, DbErr> {
+    let count = suppressions::Entity::find()
+        .filter(suppressions::Column::EmailHash.eq(hash_email(email)))
+        .filter(suppressions::Column::Reason.is_in(UNDELIVERABLE_REASONS))
+        .count(db)
+        .await?;
+    Ok(count > 0)
+}
+
+/// Add an address to the suppression list. Suppressing an address twice
+/// keeps the original entry.
+#[tracing::instrument(name = "Add an address to the suppression list", skip(db, email))]
+pub async fn suppress<C: ConnectionTrait>(
+    db: &C,
+    email: &str,
+    reason: &str,
+    source: &str,
+) -> Result<(), DbErr> {
+    let suppression = suppressions::ActiveModel {
+        email_hash: Set(hash_email(email)),
+        reason: Set(reason.to_string()),
+        source: Set(source.to_string()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+    suppressions::Entity::insert(suppression)
+        .on_conflict_do_nothing_on([suppressions::Column::EmailHash])
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Remove an address from the suppression list, returning whether it was on it.
+#[tracing::instrument(name = "Remove an address from the suppression list", skip(db, email))]
+pub async fn unsuppress<C: ConnectionTrait>(db: &C, email: &str) -> Result<bool, DbErr> {
+    let result = suppressions::Entity::delete_many()
+        .filter(suppressions::Column::EmailHash.eq(hash_email(email)))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// Lift the suppression of an address that unsubscribed from everything,
+/// now that they opted in again. Bounces, complaints and other reasons stay
+/// suppressed.
+#[tracing::instrument(name = "Lift an unsubscribe suppression", skip(db, email))]
+pub async fn lift_unsubscribe<C: ConnectionTrait>(db: &C, email: &str) -> Result<bool, DbErr> {
+    let result = suppressions::Entity::delete_many()
+        .filter(suppressions::Column::EmailHash.eq(hash_email(email)))
+        .filter(suppressions::Column::Reason.eq("unsubscribed"))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hash_email;
+
+    #[test]
+    fn hashes_are_hex_encoded_sha256() {
+        // SHA-256 test vector for "abc"
+        assert_eq!(
+            hash_email("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn hashes_ignore_case_and_surrounding_whitespace() {
+        assert_eq!(
+            hash_email("  Ursula@Example.COM "),
+            hash_email("ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn different_addresses_have_different_hashes() {
+        assert_ne!(hash_email("a@example.com"), hash_email("b@example.com"));
+    }
+}
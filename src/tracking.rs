@@ -0,0 +1,243 @@
+//! Open and click tracking for newsletter issues.
+//!
+//! Every recipient of an issue gets a random token. Links in the issue HTML
+//! are rewritten to `/t/c/{token}.{link_id}`, which records the click and
+//! redirects to the original URL, and a 1x1 pixel pointing at `/t/o/{token}`
+//! records opens.
+
+use fake::RngExt;
+use fake::rand::distr::Alphanumeric;
+use fake::rand::rng;
+use std::collections::HashMap;
+use url::Url;
+
+/// Generate a random 32-characters-long tracking token.
+pub fn generate_tracking_token() -> String {
+    let mut rng = rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+/// The token of a click URL, identifying both the recipient and the link.
+pub fn click_token(recipient_token: &str, link_id: i32) -> String {
+    format!("{}.{}", recipient_token, link_id)
+}
+
+/// Split a click token back into the recipient token and the link id.
+pub fn parse_click_token(token: &str) -> Option<(&str, i32)> {
+    let (recipient_token, link_id) = token.rsplit_once('.')?;
+    Some((recipient_token, link_id.parse().ok()?))
+}
+
+/// The `http(s)` links of the `<a>` tags in `html`, normalized, without
+/// duplicates, in the order they first appear.
+pub fn extract_links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for anchor in anchors(html) {
+        let Some(url) = trackable_url(&html[anchor.href.clone()]) else {
+            continue;
+        };
+        if !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    links
+}
+
+/// Replace every trackable link in `html` by the URL `tracking_url` returns
+/// for it; links missing from `link_ids` are left untouched.
+pub fn rewrite_links(
+    html: &str,
+    link_ids: &HashMap<String, i32>,
+    tracking_url: impl Fn(i32) -> String,
+) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut position = 0;
+    for anchor in anchors(html) {
+        let link_id = trackable_url(&html[anchor.href.clone()]).and_then(|url| link_ids.get(&url));
+        if let Some(link_id) = link_id {
+            rewritten.push_str(&html[position..anchor.href.start]);
+            rewritten.push_str(&tracking_url(*link_id));
+            position = anchor.href.end;
+        }
+    }
+    rewritten.push_str(&html[position..]);
+    rewritten
+}
+
+/// Add an invisible tracking pixel at the end of the body of `html`.
+pub fn add_open_pixel(html: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display:none" />"#,
+        pixel_url
+    );
+    match find_ignore_ascii_case(html, "</body", 0) {
+        Some(index) => format!("{}{}{}", &html[..index], pixel, &html[index..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+struct Anchor {
+    /// Byte range of the `href` value, without the quotes.
+    href: std::ops::Range<usize>,
+}
+
+/// The `<a>` tags of `html` that have a quoted `href` attribute.
+fn anchors(html: &str) -> Vec<Anchor> {
+    let mut anchors = Vec::new();
+    let mut position = 0;
+    while let Some(start) = find_ignore_ascii_case(html, "<a", position) {
+        position = start + 2;
+        // `<abbr>`, `<aside>`, ... are not links.
+        if !html[position..].starts_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let Some(end) = html[position..].find('>').map(|end| position + end) else {
+            break;
+        };
+        if let Some(href) = href_value(&html[position..end]) {
+            anchors.push(Anchor {
+                href: position + href.start..position + href.end,
+            });
+        }
+        position = end;
+    }
+    anchors
+}
+
+/// Byte range of the value of the `href` attribute in the inside of a tag.
+fn href_value(attributes: &str) -> Option<std::ops::Range<usize>> {
+    let mut position = 0;
+    while let Some(start) = find_ignore_ascii_case(attributes, "href", position) {
+        position = start + 4;
+        let preceded_by_space = attributes[..start].ends_with(|c: char| c.is_ascii_whitespace());
+        let rest = attributes[position..].trim_start();
+        let Some(rest) = rest.strip_prefix('=').filter(|_| preceded_by_space) else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value_start = attributes.len() - rest.len() + 1;
+        let value_end = value_start + attributes[value_start..].find(quote)?;
+        return Some(value_start..value_end);
+    }
+    None
+}
+
+fn find_ignore_ascii_case(haystack: &str, needle: &str, from: usize) -> Option<usize> {
+    haystack.as_bytes()[from..]
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+        .map(|index| from + index)
+}
+
+/// The `http(s)` URL of an `href` value, normalized by `Url`: non-ASCII
+/// characters are percent-encoded, so that it can be redirected to. Links
+/// that do not parse are left alone.
+fn trackable_url(href: &str) -> Option<String> {
+    let url = Url::parse(&decode_ampersands(href)).ok()?;
+    matches!(url.scheme(), "http" | "https").then(|| url.into())
+}
+
+fn decode_ampersands(url: &str) -> String {
+    url.trim().replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_open_pixel, click_token, extract_links, generate_tracking_token, parse_click_token,
+        rewrite_links,
+    };
+    use std::collections::HashMap;
+
+    fn link_ids(links: &[(&str, i32)]) -> HashMap<String, i32> {
+        links
+            .iter()
+            .map(|(url, id)| (url.to_string(), *id))
+            .collect()
+    }
+
+    #[test]
+    fn links_are_extracted_once_in_order() {
+        let html = r#"<p><a href="https://b.example.com">B</a>
+            <A class="x" HREF='https://a.example.com/?x=1&amp;y=2'>A</A>
+            <a href="https://b.example.com">B again</a></p>"#;
+        assert_eq!(
+            extract_links(html),
+            vec!["https://b.example.com/", "https://a.example.com/?x=1&y=2"]
+        );
+    }
+
+    #[test]
+    fn links_are_normalized() {
+        let html = r#"<a href="https://example.com/café">Café</a>
+            <a href="HTTPS://Example.com/caf%C3%A9">Same</a>"#;
+        assert_eq!(extract_links(html), vec!["https://example.com/caf%C3%A9"]);
+    }
+
+    #[test]
+    fn links_that_do_not_parse_are_not_tracked() {
+        let html = r#"<a href="https://exa mple.com">Broken</a><a href="https://">Empty</a>"#;
+        assert!(extract_links(html).is_empty());
+        assert_eq!(
+            rewrite_links(html, &HashMap::new(), |_| unreachable!()),
+            html
+        );
+    }
+
+    #[test]
+    fn non_http_links_are_not_tracked() {
+        let html = r##"<a href="mailto:a@example.com">Mail</a><a href="#top">Top</a>
+            <link href="https://example.com/style.css" /><abbr href="https://example.com">x</abbr>"##;
+        assert!(extract_links(html).is_empty());
+    }
+
+    #[test]
+    fn attributes_ending_in_href_are_ignored() {
+        let html = r#"<a data-href="https://a.example.com" href="https://b.example.com">B</a>"#;
+        assert_eq!(extract_links(html), vec!["https://b.example.com/"]);
+    }
+
+    #[test]
+    fn links_are_rewritten_to_their_tracking_url() {
+        let html = r#"<a href="https://a.example.com">A</a> <a href='https://b.example.com'>B</a> <a href="mailto:x@example.com">X</a>"#;
+        let rewritten = rewrite_links(
+            html,
+            &link_ids(&[("https://a.example.com/", 1), ("https://b.example.com/", 2)]),
+            |id| format!("https://t.example.com/t/c/token.{}", id),
+        );
+        assert_eq!(
+            rewritten,
+            r#"<a href="https://t.example.com/t/c/token.1">A</a> <a href='https://t.example.com/t/c/token.2'>B</a> <a href="mailto:x@example.com">X</a>"#
+        );
+    }
+
+    #[test]
+    fn the_pixel_is_added_before_the_end_of_the_body() {
+        let html = "<html><body><p>Hi</p></BODY></html>";
+        assert_eq!(
+            add_open_pixel(html, "https://t.example.com/t/o/token"),
+            r#"<html><body><p>Hi</p><img src="https://t.example.com/t/o/token" width="1" height="1" alt="" style="display:none" /></BODY></html>"#
+        );
+    }
+
+    #[test]
+    fn the_pixel_is_appended_to_fragments() {
+        assert!(add_open_pixel("<p>Hi</p>", "https://t.example.com/t/o/token").ends_with(" />"));
+    }
+
+    #[test]
+    fn click_tokens_round_trip() {
+        let recipient_token = generate_tracking_token();
+        assert_eq!(recipient_token.len(), 32);
+        assert_eq!(
+            parse_click_token(&click_token(&recipient_token, 42)),
+            Some((recipient_token.as_str(), 42))
+        );
+        assert_eq!(parse_click_token("no-link-id"), None);
+        assert_eq!(parse_click_token("token.not-a-number"), None);
+    }
+}
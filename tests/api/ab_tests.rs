@@ -0,0 +1,340 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+use chrono::{Duration, Utc};
+use reqwest::Method;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set};
+use std::collections::HashMap;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::{ab_tests, newsletter_issues, segments};
+use z2p_axum::issue_scheduler::MAX_PUBLISH_ATTEMPTS;
+
+const SUBSCRIBERS: usize = 6;
+
+fn ab_tested_issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "variants": [
+            { "title": "Subject A" },
+            { "title": "Subject B" },
+        ],
+        "ab_test": { "sample_percentage": 50, "wait_minutes": 60 },
+    })
+}
+
+async fn subscribe_everyone(app: &TestApp) {
+    for i in 0..SUBSCRIBERS {
+        app.create_confirmed_subscriber(
+            &format!("reader {}", i),
+            &format!("reader{}@gmail.com", i),
+        )
+        .await;
+    }
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+/// The subjects of the emails sent so far, by recipient.
+async fn sent_subjects(app: &TestApp) -> HashMap<String, Vec<String>> {
+    let mut subjects: HashMap<String, Vec<String>> = HashMap::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        if let Some(subject) = body["Subject"]
+            .as_str()
+            .filter(|s| s.starts_with("Subject"))
+        {
+            subjects
+                .entry(body["To"].as_str().unwrap().to_owned())
+                .or_default()
+                .push(subject.to_owned());
+        }
+    }
+    subjects
+}
+
+async fn report(app: &TestApp, issue_id: i64) -> serde_json::Value {
+    app.admin_request(
+        Method::GET,
+        &format!("/admin/api/issues/{}/ab-test", issue_id),
+    )
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+/// Make the test of the issue due right away.
+async fn end_test_now(app: &TestApp, issue_id: i64) {
+    ab_tests::Entity::update_many()
+        .col_expr(
+            ab_tests::Column::DecideAt,
+            Expr::value(Utc::now() - Duration::minutes(1)),
+        )
+        .filter(ab_tests::Column::NewsletterIssueId.eq(issue_id as i32))
+        .exec(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn invalid_ab_tests_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "variants": [{ "title": "Only one" }] }),
+            "a single variant",
+        ),
+        (
+            serde_json::json!({ "ab_test": { "sample_percentage": 100 } }),
+            "a sample of everyone",
+        ),
+        (
+            serde_json::json!({ "ab_test": { "sample_percentage": 20, "wait_minutes": 0 } }),
+            "no wait",
+        ),
+        (
+            serde_json::json!({ "ab_test": { "sample_percentage": 20, "wait_minutes": 60, "metric": "bounces" } }),
+            "an unknown metric",
+        ),
+        (
+            serde_json::json!({ "variants": [{ "title": "A" }, { "title": " " }] }),
+            "a blank subject",
+        ),
+        (
+            serde_json::json!({ "ab_test": null }),
+            "variants without a test",
+        ),
+    ];
+
+    for (change, description) in test_cases {
+        let mut issue = ab_tested_issue();
+        for (key, value) in change.as_object().unwrap() {
+            match (issue[key].as_object_mut(), value.as_object()) {
+                (Some(fields), Some(new_fields)) => fields.extend(new_fields.clone()),
+                _ => issue[key] = value.clone(),
+            }
+        }
+
+        // Act
+        let response = app.publish_issue(&issue).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the test had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn ab_tests_need_tracking() {
+    // Arrange
+    let app = spawn_app_with(|c| c.tracking.enabled = false).await;
+
+    // Act
+    let response = app.publish_issue(&ab_tested_issue()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_variants_are_sent_to_a_sample_first() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe_everyone(&app).await;
+
+    // Act
+    let response = app.publish_issue(&ab_tested_issue()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let published: serde_json::Value = response.json().await.unwrap();
+    app.dispatch_all_pending_emails().await;
+    // Not due yet.
+    app.send_due_ab_test_winners().await;
+
+    // Assert
+    assert_eq!(published["status"], "testing");
+    let subjects = sent_subjects(&app).await;
+    assert_eq!(
+        subjects.len() as u64,
+        published["recipients"].as_u64().unwrap()
+    );
+    assert!(subjects.len() < SUBSCRIBERS);
+    let report = report(&app, published["id"].as_i64().unwrap()).await;
+    assert_eq!(report["status"], "testing");
+    assert_eq!(report["metric"], "opens");
+    assert!(report["winner"].is_null());
+    let variants = report["variants"].as_array().unwrap();
+    assert_eq!(variants.len(), 2);
+    let recipients: u64 = variants
+        .iter()
+        .map(|v| v["recipients"].as_u64().unwrap())
+        .sum();
+    assert_eq!(recipients as usize, subjects.len());
+}
+
+#[tokio::test]
+async fn the_winner_is_sent_to_everyone_else_once_the_test_is_over() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe_everyone(&app).await;
+    let published: serde_json::Value = app
+        .publish_issue(&ab_tested_issue())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = published["id"].as_i64().unwrap();
+    app.dispatch_all_pending_emails().await;
+    let sample = sent_subjects(&app).await;
+
+    // Act
+    end_test_now(&app, issue_id).await;
+    app.send_due_ab_test_winners().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Nobody opened anything: the first variant wins the tie.
+    let subjects = sent_subjects(&app).await;
+    assert_eq!(subjects.len(), SUBSCRIBERS);
+    for (recipient, received) in &subjects {
+        assert_eq!(received.len(), 1, "{} got more than one email", recipient);
+        if !sample.contains_key(recipient) {
+            assert_eq!(received[0], "Subject A");
+        }
+    }
+    let report = report(&app, issue_id).await;
+    assert_eq!(report["status"], "completed");
+    assert_eq!(report["winner"], "A");
+    let issue = newsletter_issues::Entity::find_by_id(issue_id as i32)
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    assert_eq!(issue.title, "Subject A");
+}
+
+async fn saved_test(app: &TestApp, issue_id: i64) -> ab_tests::Model {
+    ab_tests::Entity::find()
+        .filter(ab_tests::Column::NewsletterIssueId.eq(issue_id as i32))
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .expect("A/B test not found")
+}
+
+/// Publish an A/B tested issue whose segment no longer parses by the time
+/// the winner is due, and return its id.
+async fn publish_broken_issue(app: &TestApp) -> i64 {
+    let published: serde_json::Value = app
+        .publish_issue(&ab_tested_issue())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = published["id"].as_i64().unwrap();
+    let segment = segments::ActiveModel {
+        name: Set("broken".into()),
+        definition: Set("not a segment".into()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&app.db_pool)
+    .await
+    .unwrap();
+    newsletter_issues::Entity::update_many()
+        .col_expr(
+            newsletter_issues::Column::SegmentId,
+            Expr::value(segment.id),
+        )
+        .filter(newsletter_issues::Column::Id.eq(issue_id as i32))
+        .exec(&app.db_pool)
+        .await
+        .unwrap();
+    // Due before any other test.
+    ab_tests::Entity::update_many()
+        .col_expr(
+            ab_tests::Column::DecideAt,
+            Expr::value(Utc::now() - Duration::minutes(5)),
+        )
+        .filter(ab_tests::Column::NewsletterIssueId.eq(issue_id as i32))
+        .exec(&app.db_pool)
+        .await
+        .unwrap();
+    issue_id
+}
+
+#[tokio::test]
+async fn a_winner_failing_to_send_does_not_block_later_tests() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe_everyone(&app).await;
+    let broken_id = publish_broken_issue(&app).await;
+    let published: serde_json::Value = app
+        .publish_issue(&ab_tested_issue())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = published["id"].as_i64().unwrap();
+
+    // Act
+    end_test_now(&app, issue_id).await;
+    app.send_due_ab_test_winners().await;
+
+    // Assert
+    assert_eq!(report(&app, issue_id).await["status"], "completed");
+    let broken = saved_test(&app, broken_id).await;
+    assert_eq!(broken.status, "testing");
+    assert_eq!(broken.send_attempts, 1);
+    assert!(broken.last_error.unwrap().contains("invalid definition"));
+    // Retried later rather than straight away.
+    assert!(broken.decide_at.unwrap() > Utc::now());
+}
+
+#[tokio::test]
+async fn tests_whose_winner_fails_too_often_are_marked_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe_everyone(&app).await;
+    let issue_id = publish_broken_issue(&app).await;
+    let mut test: ab_tests::ActiveModel = saved_test(&app, issue_id).await.into();
+    test.send_attempts = Set(MAX_PUBLISH_ATTEMPTS - 1);
+    test.update(&app.db_pool).await.unwrap();
+
+    // Act
+    app.send_due_ab_test_winners().await;
+
+    // Assert
+    let test = saved_test(&app, issue_id).await;
+    assert_eq!(test.status, "failed");
+    assert_eq!(test.send_attempts, MAX_PUBLISH_ATTEMPTS);
+    assert_eq!(report(&app, issue_id).await["status"], "failed");
+}
+
+#[tokio::test]
+async fn issues_without_a_test_have_no_report() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_request(Method::GET, "/admin/api/issues/4242/ab-test")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,254 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::Method;
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter};
+use z2p_axum::entity::email_outbox;
+
+async fn list(app: &TestApp, query: &str) -> serde_json::Value {
+    app.admin_request(Method::GET, &format!("/admin/api/subscribers{}", query))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn emails(page: &serde_json::Value) -> Vec<&str> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|subscriber| subscriber["email"].as_str().unwrap())
+        .collect()
+}
+
+async fn three_subscribers(app: &TestApp) {
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("jemisin", "nk_jemisin@example.com")
+        .await;
+}
+
+#[tokio::test]
+async fn subscriber_routes_require_admin_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let requests = [
+        client.get(format!("{}/admin/api/subscribers", app.address)),
+        client.get(format!("{}/admin/api/subscribers/1", app.address)),
+        client
+            .patch(format!("{}/admin/api/subscribers/1", app.address))
+            .json(&serde_json::json!({ "name": "le guin" })),
+        client.delete(format!("{}/admin/api/subscribers/1", app.address)),
+    ];
+
+    for request in requests {
+        // Act
+        let response = request.send().await.expect("Failed to execute request.");
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_with_a_cursor() {
+    // Arrange
+    let app = spawn_app().await;
+    three_subscribers(&app).await;
+
+    // Act
+    let first = list(&app, "?limit=2").await;
+    let cursor = first["next_cursor"].as_i64().unwrap();
+    let second = list(&app, &format!("?limit=2&cursor={}", cursor)).await;
+    let ascending = list(&app, "?order=asc").await;
+
+    // Assert - Newest first by default.
+    assert_eq!(
+        emails(&first),
+        ["nk_jemisin@example.com", "octavia_butler@gmail.com"]
+    );
+    assert_eq!(emails(&second), ["ursula_le_guin@gmail.com"]);
+    assert!(second["next_cursor"].is_null());
+    assert_eq!(
+        emails(&ascending),
+        [
+            "ursula_le_guin@gmail.com",
+            "octavia_butler@gmail.com",
+            "nk_jemisin@example.com"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered() {
+    // Arrange
+    let app = spawn_app().await;
+    three_subscribers(&app).await;
+    let response = app
+        .post_subscriptions("name=wolfe&email=gene_wolfe%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let by_email = list(&app, "?email=gmail").await;
+    let pending = list(&app, "?status=pending_confirmation").await;
+    let future = list(&app, "?subscribed_after=2999-01-01T00:00:00Z").await;
+    let invalid = app
+        .admin_request(Method::GET, "/admin/api/subscribers?status=sleeping")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        emails(&by_email),
+        [
+            "gene_wolfe@gmail.com",
+            "octavia_butler@gmail.com",
+            "ursula_le_guin@gmail.com"
+        ]
+    );
+    assert_eq!(emails(&pending), ["gene_wolfe@gmail.com"]);
+    assert!(emails(&future).is_empty());
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn an_admin_can_get_update_and_delete_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let id = list(&app, "").await["subscribers"][0]["id"]
+        .as_i64()
+        .unwrap();
+    let path = format!("/admin/api/subscribers/{}", id);
+
+    // Act - Part 1 - Get
+    let subscriber: serde_json::Value = app
+        .admin_request(Method::GET, &path)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(subscriber["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(subscriber["lists"][0]["list"], "newsletter");
+
+    // Act - Part 2 - Update
+    let response = app
+        .admin_request(Method::PATCH, &path)
+        .json(&serde_json::json!({
+            "name": "Ursula K. Le Guin",
+            "locale": "en-US",
+            "digest_frequency": "weekly",
+            "attributes": { "plan": "pro" },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let updated: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(updated["name"], "Ursula K. Le Guin");
+    assert_eq!(updated["locale"], "en-US");
+    assert_eq!(updated["digest_frequency"], "weekly");
+    assert_eq!(updated["attributes"], serde_json::json!({ "plan": "pro" }));
+    assert_eq!(updated["status"], "confirmed");
+
+    // Act - Part 3 - Delete
+    let response = app
+        .admin_request(Method::DELETE, &path)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app.admin_request(Method::GET, &path).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn deleted_subscribers_get_none_of_the_emails_queued_for_them() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "ursula_le_guin@gmail.com";
+    app.subscribe(email).await.error_for_status().unwrap();
+    let subscriber = app.saved_subscriber(email).await;
+
+    // Act
+    let response = app
+        .admin_request(
+            Method::DELETE,
+            &format!("/admin/api/subscribers/{}", subscriber.id),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let queued = email_outbox::Entity::find()
+        .filter(email_outbox::Column::Recipient.eq(email))
+        .filter(email_outbox::Column::Status.eq("pending"))
+        .count(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[tokio::test]
+async fn invalid_changes_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let id = list(&app, "").await["subscribers"][0]["id"]
+        .as_i64()
+        .unwrap();
+    let test_cases = vec![
+        (serde_json::json!({ "name": "" }), "an empty name"),
+        (
+            serde_json::json!({ "status": "sleeping" }),
+            "an unknown status",
+        ),
+        (
+            serde_json::json!({ "locale": "<script>" }),
+            "an invalid locale",
+        ),
+        (
+            serde_json::json!({ "digest_frequency": "hourly" }),
+            "an unknown digest frequency",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app
+            .admin_request(Method::PATCH, &format!("/admin/api/subscribers/{}", id))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+    let response = app
+        .admin_request(Method::PATCH, "/admin/api/subscribers/4242")
+        .json(&serde_json::json!({ "name": "nobody" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
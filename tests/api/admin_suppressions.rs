@@ -0,0 +1,160 @@
+use crate::helpers::spawn_app;
+use reqwest::Method;
+use sea_orm::EntityTrait;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::email_outbox;
+use z2p_axum::suppression::hash_email;
+
+#[tokio::test]
+async fn suppression_routes_require_admin_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let requests = [
+        client.get(format!("{}/admin/api/suppressions", app.address)),
+        client
+            .post(format!("{}/admin/api/suppressions", app.address))
+            .json(&serde_json::json!({ "email": "a@example.com" })),
+        client.delete(format!(
+            "{}/admin/api/suppressions/a@example.com",
+            app.address
+        )),
+        client
+            .get(format!("{}/admin/api/suppressions", app.address))
+            .basic_auth(&app.admin.username, Some("wrong-password")),
+    ];
+
+    for request in requests {
+        // Act
+        let response = request.send().await.expect("Failed to execute request.");
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn an_admin_can_add_list_and_remove_suppressions() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Add
+    let response = app
+        .admin_request(Method::POST, "/admin/api/suppressions")
+        .json(&serde_json::json!({ "email": "Ursula@Example.com", "reason": "legal" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Act - Part 2 - List
+    let entries: Vec<serde_json::Value> = app
+        .admin_request(
+            Method::GET,
+            "/admin/api/suppressions?email=ursula@example.com",
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["email_hash"], hash_email("ursula@example.com"));
+    assert_eq!(entries[0]["reason"], "legal");
+    assert_eq!(entries[0]["source"], "admin:admin");
+
+    // Act - Part 3 - Remove
+    let response = app
+        .admin_request(Method::DELETE, "/admin/api/suppressions/ursula@example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app
+        .admin_request(Method::DELETE, "/admin/api/suppressions/ursula@example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn addresses_are_removed_as_they_were_added() {
+    // Arrange
+    let app = spawn_app().await;
+    app.admin_request(Method::POST, "/admin/api/suppressions")
+        .json(&serde_json::json!({ "email": " ursula@Bücher.example" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let removed = app
+        .admin_request(
+            Method::DELETE,
+            "/admin/api/suppressions/ursula@b%C3%BCcher.example",
+        )
+        .send()
+        .await
+        .unwrap();
+    let invalid = app
+        .admin_request(Method::DELETE, "/admin/api/suppressions/not-an-email")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(removed.status().as_u16(), 204);
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn adding_an_invalid_address_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_request(Method::POST, "/admin/api/suppressions")
+        .json(&serde_json::json!({ "email": "definitely-not-an-email" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn emails_to_suppressed_addresses_are_not_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.admin_request(Method::POST, "/admin/api/suppressions")
+        .json(&serde_json::json!({ "email": "ursula_le_guin@gmail.com" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let saved = email_outbox::Entity::find()
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.status, "suppressed");
+}
@@ -0,0 +1,221 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::{Method, header};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use z2p_axum::entity::newsletter_issues;
+
+async fn publish(app: &TestApp, title: &str, draft: bool) {
+    app.publish_issue(&serde_json::json!({
+        "title": title,
+        "html_content": format!("<p>Content of {}</p>", title),
+        "draft": draft,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::get(format!("{}{}", app.address, path))
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn the_archive_lists_published_issues_only() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "First issue", false).await;
+    publish(&app, "Secret draft", true).await;
+
+    // Act
+    let response = get(&app, "/archive").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<a href="/archive/first-issue">First issue</a>"#));
+    assert!(!html.contains("Secret draft"));
+}
+
+#[tokio::test]
+async fn published_issues_can_be_read_in_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "First issue", false).await;
+
+    // Act
+    let response = get(&app, "/archive/first-issue").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("<p>Content of First issue</p>")
+    );
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_get_different_slugs() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "Weekly", false).await;
+    publish(&app, "Weekly", false).await;
+
+    // Act
+    let html = get(&app, "/archive").await.text().await.unwrap();
+
+    // Assert
+    assert!(html.contains(r#"href="/archive/weekly""#));
+    assert!(html.contains(r#"href="/archive/weekly-"#));
+}
+
+#[tokio::test]
+async fn unknown_slugs_are_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get(&app, "/archive/does-not-exist").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn feeds_list_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "First issue", false).await;
+    publish(&app, "Secret draft", true).await;
+
+    for (path, content_type, entry) in [
+        ("/feed.xml", "application/atom+xml", "<entry>"),
+        ("/rss.xml", "application/rss+xml", "<item>"),
+    ] {
+        // Act
+        let response = get(&app, path).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(
+            response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with(content_type)
+        );
+        let feed = response.text().await.unwrap();
+        assert_eq!(feed.matches(entry).count(), 1);
+        assert!(feed.contains("<title>First issue</title>"));
+        assert!(feed.contains("/archive/first-issue"));
+    }
+}
+
+#[tokio::test]
+async fn issues_of_other_lists_or_segments_stay_out_of_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "First issue", false).await;
+    app.create_list("members", "Members")
+        .await
+        .error_for_status()
+        .unwrap();
+    let segment: serde_json::Value = app
+        .admin_request(Method::POST, "/admin/api/segments")
+        .json(&serde_json::json!({ "tag": "rust" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    for body in [
+        serde_json::json!({
+            "title": "Members only",
+            "html_content": "<p>Members only</p>",
+            "list": "members",
+        }),
+        serde_json::json!({
+            "title": "Rust news",
+            "html_content": "<p>Rust news</p>",
+            "segment_id": segment["id"],
+        }),
+    ] {
+        app.publish_issue(&body).await.error_for_status().unwrap();
+    }
+
+    // Act
+    let html = get(&app, "/archive").await.text().await.unwrap();
+    let atom = get(&app, "/feed.xml").await.text().await.unwrap();
+    let rss = get(&app, "/rss.xml").await.text().await.unwrap();
+
+    // Assert
+    for page in [&html, &atom, &rss] {
+        assert!(page.contains("First issue"));
+        assert!(!page.contains("Members only"));
+        assert!(!page.contains("Rust news"));
+    }
+    let private = newsletter_issues::Entity::find()
+        .filter(newsletter_issues::Column::Title.ne("First issue"))
+        .all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(private.len(), 2);
+    for issue in private {
+        let response = get(&app, &format!("/archive/{}", issue.slug.unwrap())).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn unchanged_feeds_are_not_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "First issue", false).await;
+    let response = get(&app, "/feed.xml").await;
+    let etag = response.headers()[header::ETAG].clone();
+    let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+    let client = reqwest::Client::new();
+
+    // Act
+    let by_etag = client
+        .get(format!("{}/feed.xml", app.address))
+        .header(header::IF_NONE_MATCH, etag.clone())
+        .send()
+        .await
+        .unwrap();
+    let by_date = client
+        .get(format!("{}/feed.xml", app.address))
+        .header(header::IF_MODIFIED_SINCE, last_modified)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(by_etag.status().as_u16(), 304);
+    assert_eq!(by_date.status().as_u16(), 304);
+    assert!(by_etag.text().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn feeds_are_sent_again_once_a_new_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "First issue", false).await;
+    let etag = get(&app, "/rss.xml").await.headers()[header::ETAG].clone();
+    publish(&app, "Second issue", false).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/rss.xml", app.address))
+        .header(header::IF_NONE_MATCH, etag)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Second issue"));
+}
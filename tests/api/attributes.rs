@@ -0,0 +1,261 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn set_attributes(app: &TestApp, schema: serde_json::Value) -> reqwest::Response {
+    app.admin_request(Method::PUT, "/admin/api/lists/newsletter/attributes")
+        .json(&schema)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn schema() -> serde_json::Value {
+    serde_json::json!([
+        { "name": "first_name", "type": "string", "required": true },
+        { "name": "vip", "type": "boolean" },
+    ])
+}
+
+/// Subscribe with the extra form `fields` and confirm the subscription.
+async fn subscribe_with(app: &TestApp, email: &str, fields: &[(&str, &str)]) {
+    let mut body = url::form_urlencoded::Serializer::new(String::new());
+    body.append_pair("name", "le guin")
+        .append_pair("email", email);
+    for (name, value) in fields {
+        body.append_pair(name, value);
+    }
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.finish())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn stored_attributes(app: &TestApp, email: &str) -> Option<serde_json::Value> {
+    app.saved_subscriber(email).await.attributes
+}
+
+#[tokio::test]
+async fn lists_have_an_attribute_schema() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = set_attributes(&app, schema()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let list: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(list["attributes"][0]["name"], "first_name");
+    assert_eq!(list["attributes"][1]["required"], false);
+}
+
+#[tokio::test]
+async fn invalid_schemas_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act & Assert
+    let reserved = serde_json::json!([{ "name": "email", "type": "string" }]);
+    assert_eq!(set_attributes(&app, reserved).await.status().as_u16(), 400);
+    let unknown_type = serde_json::json!([{ "name": "age", "type": "color" }]);
+    assert_eq!(
+        set_attributes(&app, unknown_type).await.status().as_u16(),
+        422
+    );
+    let response = app
+        .admin_request(Method::PUT, "/admin/api/lists/unknown/attributes")
+        .json(&schema())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn attributes_are_stored_on_subscribe() {
+    // Arrange
+    let app = spawn_app().await;
+    set_attributes(&app, schema())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    subscribe_with(
+        &app,
+        "ursula_le_guin@gmail.com",
+        &[("first_name", "Ursula"), ("vip", "on"), ("unknown", "x")],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(
+        stored_attributes(&app, "ursula_le_guin@gmail.com").await,
+        Some(serde_json::json!({ "first_name": "Ursula", "vip": true }))
+    );
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_keep_their_attributes_when_the_form_is_posted_again() {
+    // Arrange
+    let app = spawn_app().await;
+    set_attributes(&app, schema())
+        .await
+        .error_for_status()
+        .unwrap();
+    subscribe_with(
+        &app,
+        "ursula_le_guin@gmail.com",
+        &[("first_name", "Ursula")],
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&first_name=Someone&vip=on".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        stored_attributes(&app, "ursula_le_guin@gmail.com").await,
+        Some(serde_json::json!({ "first_name": "Ursula" }))
+    );
+}
+
+#[tokio::test]
+async fn attributes_wait_for_the_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    set_attributes(&app, schema())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&first_name=Ursula&vip=on".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        stored_attributes(&app, "ursula_le_guin@gmail.com").await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_attributes_are_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    set_attributes(&app, schema())
+        .await
+        .error_for_status()
+        .unwrap();
+    let test_cases = vec![
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com",
+            "missing first_name",
+        ),
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&first_name=Ursula&vip=maybe",
+            "invalid boolean",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_subscriptions(body.into()).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn issues_are_personalized_with_attributes() {
+    // Arrange
+    let app = spawn_app().await;
+    set_attributes(&app, schema())
+        .await
+        .error_for_status()
+        .unwrap();
+    subscribe_with(
+        &app,
+        "ursula_le_guin@gmail.com",
+        &[("first_name", "Ursula"), ("vip", "true")],
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.publish_issue(&serde_json::json!({
+        "title": "News for {{ first_name }}",
+        "html_content": "<p>Hi {{ first_name | there }}{{#vip}}, our VIP{{/vip}}!</p>",
+        "text_content": "Hi {{ first_name | there }}{{^vip}}, upgrade?{{/vip}}",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["Subject"], "News for Ursula");
+    assert!(
+        body["HtmlBody"]
+            .as_str()
+            .unwrap()
+            .contains("<p>Hi Ursula, our VIP!</p>")
+    );
+    assert!(
+        body["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Hi Ursula\n")
+    );
+}
@@ -0,0 +1,148 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::Method;
+use z2p_axum::privacy::erase_subscriber;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn audit(app: &TestApp, query: &str) -> serde_json::Value {
+    app.admin_request(Method::GET, &format!("/admin/api/audit{}", query))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_audit_log_requires_admin_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/api/audit", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admin_actions_are_recorded_with_the_request_id() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    let id = app.saved_subscriber(EMAIL).await.id;
+    let path = format!("/admin/api/subscribers/{}", id);
+
+    // Act
+    app.admin_request(Method::PATCH, &path)
+        .json(&serde_json::json!({ "name": "Ursula K. Le Guin" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let response = app
+        .admin_request(Method::DELETE, &path)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+
+    // Assert
+    let log = audit(&app, "").await;
+    let entries = log["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    // Newest first.
+    let deletion = &entries[0];
+    assert_eq!(deletion["action"], "subscriber.delete");
+    assert_eq!(deletion["actor"], app.admin.username);
+    assert_eq!(deletion["target_type"], "subscriber");
+    assert_eq!(deletion["target_id"], id.to_string());
+    assert_eq!(deletion["before"]["name"], "Ursula K. Le Guin");
+    assert!(deletion["after"].is_null());
+    assert_eq!(deletion["request_id"], request_id);
+    let update = &entries[1];
+    assert_eq!(update["action"], "subscriber.update");
+    assert_eq!(update["before"]["name"], "le guin");
+    assert_eq!(update["after"]["name"], "Ursula K. Le Guin");
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered() {
+    // Arrange
+    let app = spawn_app().await;
+    for slug in ["fiction", "poetry"] {
+        app.create_list(slug, slug)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    app.admin_request(Method::POST, "/admin/api/suppressions")
+        .json(&serde_json::json!({ "email": EMAIL }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let lists = audit(&app, "?action=list.create").await;
+    let first = audit(&app, "?target_type=list&limit=1").await;
+    let cursor = first["next_cursor"].as_i64().unwrap();
+    let second = audit(
+        &app,
+        &format!("?target_type=list&limit=1&cursor={}", cursor),
+    )
+    .await;
+    let suppressions = audit(&app, "?target_type=suppression").await;
+    let invalid = app
+        .admin_request(Method::GET, "/admin/api/audit?target_id=1")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(lists["entries"].as_array().unwrap().len(), 2);
+    assert_eq!(first["entries"][0]["after"]["slug"], "poetry");
+    assert_eq!(second["entries"][0]["after"]["slug"], "fiction");
+    assert!(second["next_cursor"].is_null());
+    // Suppressions are logged by hash, never by address.
+    let suppression = &suppressions["entries"][0];
+    assert_eq!(suppression["action"], "suppression.add");
+    assert!(!suppression.to_string().contains(EMAIL));
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn erasure_keeps_the_audit_entries_without_the_data() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    let subscriber = app.saved_subscriber(EMAIL).await;
+    app.admin_request(
+        Method::PATCH,
+        &format!("/admin/api/subscribers/{}", subscriber.id),
+    )
+    .json(&serde_json::json!({ "name": "Ursula K. Le Guin" }))
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    erase_subscriber(&app.db_pool, &subscriber).await.unwrap();
+
+    // Assert
+    let log = audit(&app, "?action=subscriber.update").await;
+    let entry = &log["entries"][0];
+    assert_eq!(entry["target_id"], subscriber.id.to_string());
+    assert!(entry["before"].is_null());
+    assert!(entry["after"].is_null());
+}
@@ -0,0 +1,230 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+use chrono::Utc;
+use secrecy::SecretString;
+use std::net::IpAddr;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use z2p_axum::bot_protection::form_token;
+use z2p_axum::configuration::CaptchaSettings;
+
+const SUBSCRIPTION: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+/// The test client connects from there.
+fn localhost() -> IpAddr {
+    "127.0.0.1".parse().unwrap()
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_is_silently_dropped() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions(format!(
+            "{}&website=https%3A%2F%2Fspam.example",
+            SUBSCRIPTION
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 0);
+}
+
+#[tokio::test]
+async fn forms_need_a_token_old_enough() {
+    // Arrange
+    let app = spawn_app_with(|c| c.bot_protection.min_submit_seconds = 3).await;
+    let secret = SecretString::from("my-form-token-secret");
+
+    // Act - Part 1 - No token
+    let response = app.post_subscriptions(SUBSCRIPTION.into()).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // Act - Part 2 - A token fetched just now
+    let token: serde_json::Value =
+        reqwest::get(format!("{}/subscriptions/form-token", app.address))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+    let token = token["form_token"].as_str().unwrap();
+    let response = app
+        .post_subscriptions(format!("{}&form_token={}", SUBSCRIPTION, token))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 0);
+
+    // Act - Part 3 - A token of a form served a while ago
+    let token = form_token(&secret, Utc::now().timestamp() - 10, localhost());
+    let response = app
+        .post_subscriptions(format!("{}&form_token={}", SUBSCRIPTION, token))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 1);
+}
+
+#[tokio::test]
+async fn form_tokens_only_let_one_subscription_through() {
+    // Arrange
+    let app = spawn_app_with(|c| c.bot_protection.min_submit_seconds = 3).await;
+    let secret = SecretString::from("my-form-token-secret");
+    let token = form_token(&secret, Utc::now().timestamp() - 10, localhost());
+    let response = app
+        .post_subscriptions(format!("{}&form_token={}", SUBSCRIPTION, token))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - the same token, another address
+    let response = app
+        .post_subscriptions(format!(
+            "name=le%20guin&email=octavia_butler%40gmail.com&form_token={}",
+            token
+        ))
+        .await;
+
+    // Assert - dropped as silently as other bots
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 1);
+}
+
+#[tokio::test]
+async fn form_tokens_of_rejected_forms_can_be_used_again() {
+    // Arrange
+    let app = spawn_app_with(|c| c.bot_protection.min_submit_seconds = 3).await;
+    let secret = SecretString::from("my-form-token-secret");
+    let token = form_token(&secret, Utc::now().timestamp() - 10, localhost());
+    let response = app
+        .post_subscriptions(format!(
+            "name=le%20guin&email=bot%40mailinator.com&form_token={}",
+            token
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // Act - the address fixed, the same token
+    let response = app
+        .post_subscriptions(format!("{}&form_token={}", SUBSCRIPTION, token))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 1);
+}
+
+#[tokio::test]
+async fn form_tokens_served_to_another_client_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| c.bot_protection.min_submit_seconds = 3).await;
+    let secret = SecretString::from("my-form-token-secret");
+    let elsewhere = "198.51.100.1".parse().unwrap();
+    let token = form_token(&secret, Utc::now().timestamp() - 10, elsewhere);
+
+    // Act
+    let response = app
+        .post_subscriptions(format!("{}&form_token={}", SUBSCRIPTION, token))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(app.subscriber_count().await, 0);
+}
+
+async fn spawn_app_with_captcha(captcha_server: &MockServer) -> TestApp {
+    let verify_url = format!("{}/siteverify", captcha_server.uri());
+    spawn_app_with(|c| {
+        c.bot_protection.captcha = Some(CaptchaSettings {
+            verify_url: verify_url.parse().unwrap(),
+            secret: SecretString::from("my-captcha-secret"),
+            response_field: "cf-turnstile-response".into(),
+            timeout_milliseconds: 1000,
+        })
+    })
+    .await
+}
+
+#[tokio::test]
+async fn a_solved_captcha_lets_the_subscription_through() {
+    // Arrange
+    let captcha_server = MockServer::start().await;
+    let app = spawn_app_with_captcha(&captcha_server).await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("secret=my-captcha-secret"))
+        .and(body_string_contains("response=solved"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true
+        })))
+        .expect(1)
+        .mount(&captcha_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(format!("{}&cf-turnstile-response=solved", SUBSCRIPTION))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 1);
+}
+
+#[tokio::test]
+async fn missing_or_wrong_captchas_are_rejected() {
+    // Arrange
+    let captcha_server = MockServer::start().await;
+    let app = spawn_app_with_captcha(&captcha_server).await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"]
+        })))
+        .expect(1)
+        .mount(&captcha_server)
+        .await;
+    let test_cases = vec![
+        (SUBSCRIPTION.to_string(), "no CAPTCHA response"),
+        (
+            format!("{}&cf-turnstile-response=wrong", SUBSCRIPTION),
+            "a wrong CAPTCHA response",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_subscriptions(body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the form had {}.",
+            description
+        );
+    }
+    assert_eq!(app.subscriber_count().await, 0);
+}
+
+#[tokio::test]
+async fn an_unreachable_captcha_provider_fails_the_subscription() {
+    // Arrange
+    let captcha_server = MockServer::start().await;
+    let app = spawn_app_with_captcha(&captcha_server).await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&captcha_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(format!("{}&cf-turnstile-response=solved", SUBSCRIPTION))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(app.subscriber_count().await, 0);
+}
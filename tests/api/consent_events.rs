@@ -0,0 +1,142 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn consent_events(app: &TestApp, subscriber_id: i32) -> serde_json::Value {
+    app.admin_request(
+        Method::GET,
+        &format!("/admin/api/subscribers/{}/consent-events", subscriber_id),
+    )
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn subscribing_and_confirming_record_a_consent_each() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "consent-test/1.0")
+        .body(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&source=footer&consent_text_version=v2",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let events = consent_events(&app, app.saved_subscriber(EMAIL).await.id).await;
+    let events = events.as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["event"], "subscribe");
+    assert_eq!(events[0]["list"], "newsletter");
+    assert_eq!(events[0]["source"], "footer");
+    assert_eq!(events[0]["consent_text_version"], "v2");
+    assert_eq!(events[0]["user_agent"], "consent-test/1.0");
+    assert_eq!(events[0]["ip_address"], "127.0.0.1");
+    assert_eq!(events[1]["event"], "confirm");
+    assert_eq!(events[1]["source"], "confirmation_link");
+    // Confirming agrees to the wording shown when subscribing.
+    assert_eq!(events[1]["consent_text_version"], "v2");
+}
+
+#[tokio::test]
+async fn the_configured_consent_text_version_is_the_default() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+
+    // Assert
+    let events = consent_events(&app, app.saved_subscriber(EMAIL).await.id).await;
+    assert_eq!(events[0]["source"], "subscription_form");
+    assert_eq!(events[0]["consent_text_version"], "2026-10-19");
+}
+
+#[tokio::test]
+async fn clicking_the_confirmation_link_again_records_no_new_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = app.subscribe_to_list("le guin", EMAIL, None).await;
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let events = consent_events(&app, app.saved_subscriber(EMAIL).await.id).await;
+    let events: Vec<_> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(events, vec!["subscribe", "confirm"]);
+}
+
+#[tokio::test]
+async fn overlong_consent_fields_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let source = "a".repeat(65);
+
+    // Act
+    let response = app
+        .post_subscriptions(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&source={}",
+            source
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn consent_events_of_an_unknown_subscriber_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_request(Method::GET, "/admin/api/subscribers/4242/consent-events")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
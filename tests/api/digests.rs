@@ -0,0 +1,92 @@
+use crate::helpers::{TestApp, spawn_app};
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::issue_recipients;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+/// A confirmed subscriber who chose a weekly digest.
+async fn weekly_subscriber(app: &TestApp) {
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    let subscriber = app.saved_subscriber(EMAIL).await;
+    reqwest::Client::new()
+        .post(format!(
+            "{}/preferences/{}",
+            app.address, subscriber.preferences_token
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("lists=newsletter&digest_frequency=weekly")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn sent_emails(app: &TestApp) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn digest_subscribers_get_the_issues_together_once_the_digest_is_due() {
+    // Arrange
+    let app = spawn_app().await;
+    weekly_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let confirmation_emails = sent_emails(&app).await.len();
+    for title in ["First issue", "Second issue"] {
+        app.publish_issue(&serde_json::json!({
+            "title": title,
+            "html_content": format!("<p>{}</p>", title),
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+
+    // Act - Part 1 - Nothing is sent before the digest is due
+    app.dispatch_all_pending_emails().await;
+    app.send_due_digests().await;
+    assert_eq!(sent_emails(&app).await.len(), confirmation_emails);
+
+    // Act - Part 2 - The digest is due
+    issue_recipients::Entity::update_many()
+        .col_expr(
+            issue_recipients::Column::DigestDueAt,
+            Expr::value(Utc::now() - Duration::minutes(1)),
+        )
+        .filter(issue_recipients::Column::DigestDueAt.is_not_null())
+        .exec(&app.db_pool)
+        .await
+        .unwrap();
+    app.send_due_digests().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let emails = sent_emails(&app).await;
+    assert_eq!(emails.len(), confirmation_emails + 1);
+    let digest = emails.last().unwrap();
+    assert_eq!(digest["Subject"], "Your digest: 2 new issues");
+    let html = digest["HtmlBody"].as_str().unwrap();
+    assert!(html.contains("<p>First issue</p>"));
+    assert!(html.contains("<p>Second issue</p>"));
+    let pending = issue_recipients::Entity::find()
+        .filter(issue_recipients::Column::DigestDueAt.is_not_null())
+        .all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(pending.is_empty());
+}
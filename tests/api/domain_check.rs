@@ -0,0 +1,109 @@
+use crate::helpers::{TestApp, spawn_app_with_resolver};
+use async_trait::async_trait;
+use std::sync::Arc;
+use z2p_axum::domain_check::{DomainResolver, LookupError};
+
+/// Only `gmail.com`, `yahoo.co.jp` and `example.com` take email;
+/// `down.example` cannot be looked up.
+struct FakeResolver;
+
+#[async_trait]
+impl DomainResolver for FakeResolver {
+    async fn mx_hosts(&self, domain: &str) -> Result<Vec<String>, LookupError> {
+        match domain {
+            "gmail.com" => Ok(vec!["gmail-smtp-in.l.google.com.".into()]),
+            "yahoo.co.jp" => Ok(vec!["mx.mail.yahoo.co.jp.".into()]),
+            "down.example" => Err(LookupError("SERVFAIL".into())),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    async fn has_address(&self, domain: &str) -> Result<bool, LookupError> {
+        Ok(domain == "example.com")
+    }
+}
+
+async fn spawn_app_with_domain_check(reject_suspected_typos: bool) -> TestApp {
+    spawn_app_with_resolver(
+        |c| {
+            c.domain_check.enabled = true;
+            c.domain_check.reject_suspected_typos = reject_suspected_typos;
+        },
+        Some(Arc::new(FakeResolver)),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn domains_taking_email_are_accepted() {
+    // Arrange
+    let app = spawn_app_with_domain_check(false).await;
+
+    // Act
+    let with_mx = app.subscribe("ursula_le_guin@gmail.com").await;
+    let with_address_only = app.subscribe("octavia_butler@example.com").await;
+
+    // Assert
+    assert_eq!(with_mx.status().as_u16(), 200);
+    assert_eq!(with_address_only.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 2);
+}
+
+#[tokio::test]
+async fn undeliverable_domains_are_rejected_with_a_suggestion() {
+    // Arrange
+    let app = spawn_app_with_domain_check(false).await;
+
+    // Act
+    let response = app.subscribe("ursula_le_guin@gmial.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "gmial.com does not accept email. Did you mean ursula_le_guin@gmail.com?"
+    );
+    assert_eq!(app.subscriber_count().await, 0);
+}
+
+#[tokio::test]
+async fn domains_that_cannot_be_looked_up_are_accepted() {
+    // Arrange
+    let app = spawn_app_with_domain_check(false).await;
+
+    // Act
+    let response = app.subscribe("ursula_le_guin@down.example").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 1);
+}
+
+#[tokio::test]
+async fn suspected_typos_can_be_rejected_even_when_they_resolve() {
+    // Arrange
+    let app = spawn_app_with_domain_check(true).await;
+
+    // Act
+    let response = app.subscribe("ursula_le_guin@gmail.con").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Did you mean ursula_le_guin@gmail.com?"
+    );
+}
+
+#[tokio::test]
+async fn other_countries_of_common_providers_are_not_taken_for_typos() {
+    // Arrange
+    let app = spawn_app_with_domain_check(true).await;
+
+    // Act
+    let response = app.subscribe("ursula_le_guin@yahoo.co.jp").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.subscriber_count().await, 1);
+}
@@ -0,0 +1,111 @@
+use crate::helpers::spawn_app;
+use sea_orm::EntityTrait;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::email_outbox;
+
+#[tokio::test]
+async fn subscribe_stores_the_confirmation_email_in_the_outbox() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        // Nothing is sent until the outbox is dispatched
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = email_outbox::Entity::find()
+        .one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the outbox.")
+        .expect("No email in the outbox");
+    assert_eq!(saved.recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending");
+    assert_eq!(saved.attempts, 0);
+}
+
+#[tokio::test]
+async fn dispatching_the_outbox_marks_the_email_as_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let saved = email_outbox::Entity::find()
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.status, "sent");
+    assert_eq!(saved.attempts, 1);
+    assert!(saved.sent_at.is_some());
+}
+
+#[tokio::test]
+async fn subscribe_succeeds_even_if_the_email_provider_is_down() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = email_outbox::Entity::find()
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    // The email is kept for a later retry
+    assert_eq!(saved.status, "pending");
+    assert_eq!(saved.attempts, 1);
+    assert!(saved.last_error.is_some());
+    assert!(saved.next_attempt_at > saved.created_at);
+}
+
+#[tokio::test]
+async fn emails_rejected_by_the_provider_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let saved = email_outbox::Entity::find()
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.status, "failed");
+}
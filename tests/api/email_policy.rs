@@ -0,0 +1,110 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use reqwest::Method;
+use sea_orm::EntityTrait;
+use z2p_axum::entity::subscriptions;
+
+#[tokio::test]
+async fn addresses_differing_in_case_are_one_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let first = app.subscribe("  Ursula_Le_Guin@Gmail.COM ").await;
+    let second = app.subscribe("ursula_le_guin@gmail.com").await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let saved = subscriptions::Entity::find()
+        .all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "Ursula_Le_Guin@gmail.com");
+    assert_eq!(saved[0].canonical_email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn plus_tags_can_be_one_subscriber() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_policy.canonicalization.strip_plus_tags = true).await;
+
+    // Act
+    app.subscribe("ursula_le_guin+news@gmail.com").await;
+    app.subscribe("ursula_le_guin@gmail.com").await;
+
+    // Assert
+    assert_eq!(app.subscriber_count().await, 1);
+}
+
+#[tokio::test]
+async fn disposable_addresses_are_rejected_with_a_reason() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.subscribe("bot@mailinator.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("mailinator.com is a disposable email provider")
+    );
+    assert_eq!(app.subscriber_count().await, 0);
+}
+
+#[tokio::test]
+async fn disposable_addresses_are_accepted_when_blocking_is_disabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_policy.block_disposable_domains = false).await;
+
+    // Act
+    let response = app.subscribe("bot@mailinator.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn imports_apply_the_email_policy() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let csv = "email,name\n\
+               URSULA_LE_GUIN@gmail.com,le guin\n\
+               bot@yopmail.com,bot\n\
+               octavia_butler@gmail.com,butler\n\
+               Octavia_Butler@GMAIL.com,butler again\n";
+
+    // Act
+    let job: serde_json::Value = app.import_subscribers(csv).await.json().await.unwrap();
+
+    // Assert
+    assert_eq!(job["accepted"], 1);
+    assert_eq!(job["duplicates"], 2);
+    assert_eq!(job["rejected"], 1);
+    let report: serde_json::Value = app
+        .admin_request(
+            Method::GET,
+            &format!("/admin/api/subscribers/import/{}", job["id"]),
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let rejected = &report["report"]["rejected"][0];
+    assert_eq!(rejected["email"], "bot@yopmail.com");
+    assert!(
+        rejected["reason"]
+            .as_str()
+            .unwrap()
+            .contains("disposable email provider")
+    );
+}
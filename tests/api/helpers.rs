@@ -1,7 +1,22 @@
-use sea_orm::{ConnectionTrait, Database, DatabaseConnection};
-use wiremock::MockServer;
-use z2p_axum::configuration::DatabaseSettings;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, Database, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter,
+};
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use z2p_axum::ab_testing::try_send_due_winner;
 use z2p_axum::configuration::get_configuration;
+use z2p_axum::configuration::{
+    AdminSettings, DatabaseSettings, PostmarkWebhookSettings, Settings, TrackingSettings,
+};
+use z2p_axum::digests::try_send_due_digest;
+use z2p_axum::domain_check::DomainResolver;
+use z2p_axum::email_client::EmailClient;
+use z2p_axum::email_outbox::{ExecutionOutcome, try_execute_task};
+use z2p_axum::entity::{newsletter_issues, subscriptions};
+use z2p_axum::issue_scheduler::try_publish_due_issue;
 
 use migration::{Migrator, MigratorTrait};
 use uuid::Uuid;
@@ -12,6 +27,11 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub db_pool: DatabaseConnection,
     pub port: u16,
+    pub email_client: EmailClient,
+    pub postmark_webhooks: PostmarkWebhookSettings,
+    pub admin: AdminSettings,
+    pub base_url: String,
+    pub tracking: TrackingSettings,
 }
 
 pub struct ConfirmationLinks {
@@ -20,6 +40,20 @@ pub struct ConfirmationLinks {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after letting `configure` adjust its settings.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    spawn_app_with_resolver(configure, None).await
+}
+
+/// Spawn the application with `resolver` answering the DNS queries of the
+/// domain check.
+pub async fn spawn_app_with_resolver(
+    configure: impl FnOnce(&mut Settings),
+    resolver: Option<Arc<dyn DomainResolver>>,
+) -> TestApp {
     dotenvy::dotenv().ok();
     let email_server = MockServer::start().await;
 
@@ -28,10 +62,19 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri().parse().unwrap();
+        // Tests drive the outbox themselves, see `dispatch_all_pending_emails`.
+        c.email_outbox.dispatcher_enabled = false;
+        // ...and the scheduler, see `publish_due_issues`.
+        c.issue_scheduler.enabled = false;
+        // Tests post the forms far faster than anyone would, see `rate_limit.rs`.
+        c.rate_limit.enabled = false;
+        // ...and without form tokens, see `bot_protection.rs`.
+        c.bot_protection.min_submit_seconds = 0;
+        configure(&mut c);
         c
     };
     configure_database(&configuration.database).await;
-    let application = Application::build(configuration.clone())
+    let application = Application::build_with_resolver(configuration.clone(), resolver)
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
@@ -43,6 +86,11 @@ pub async fn spawn_app() -> TestApp {
         db_pool: get_connection_pool(&configuration.database).await,
         email_server,
         port: application_port,
+        email_client: configuration.email_client.client(),
+        postmark_webhooks: configuration.postmark_webhooks,
+        admin: configuration.admin,
+        base_url: configuration.application.base_url,
+        tracking: configuration.tracking,
     }
 }
 
@@ -69,6 +117,56 @@ async fn configure_database(config: &DatabaseSettings) -> DatabaseConnection {
 }
 
 impl TestApp {
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.db_pool, &self.email_client)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Run the scheduler until no scheduled issue is due.
+    pub async fn publish_due_issues(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_publish_due_issue(&self.db_pool, &self.base_url, self.tracking.enabled)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn send_due_ab_test_winners(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_send_due_winner(&self.db_pool, &self.base_url, self.tracking.enabled)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Send every digest that is due.
+    pub async fn send_due_digests(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_send_due_digest(&self.db_pool, &self.base_url, self.tracking.enabled)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
             .post(&format!("{}/subscriptions", self.address))
@@ -79,6 +177,153 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Post the subscription form for `email`, leaving it unconfirmed.
+    pub async fn subscribe(&self, email: &str) -> reqwest::Response {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("name", "le guin")
+            .append_pair("email", email)
+            .finish();
+        self.post_subscriptions(body).await
+    }
+
+    /// How many subscribers were saved, whatever their status.
+    pub async fn subscriber_count(&self) -> u64 {
+        subscriptions::Entity::find()
+            .count(&self.db_pool)
+            .await
+            .unwrap()
+    }
+
+    /// The saved subscriber with `email`, whatever their status.
+    pub async fn saved_subscriber(&self, email: &str) -> subscriptions::Model {
+        subscriptions::Entity::find()
+            .filter(subscriptions::Column::Email.eq(email))
+            .one(&self.db_pool)
+            .await
+            .unwrap()
+            .expect("Subscriber not found")
+    }
+
+    /// Subscribe `email` and click on the confirmation link.
+    pub async fn create_confirmed_subscriber(&self, name: &str, email: &str) {
+        self.create_confirmed_list_subscriber(name, email, None)
+            .await;
+    }
+
+    /// Subscribe `email` to `list`, the default one when `None`, and click on
+    /// the confirmation link.
+    pub async fn create_confirmed_list_subscriber(
+        &self,
+        name: &str,
+        email: &str,
+        list: Option<&str>,
+    ) {
+        let confirmation_links = self.subscribe_to_list(name, email, list).await;
+        reqwest::get(confirmation_links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    /// Subscribe `email` to `list`, the default one when `None`, returning
+    /// the links of the confirmation email.
+    pub async fn subscribe_to_list(
+        &self,
+        name: &str,
+        email: &str,
+        list: Option<&str>,
+    ) -> ConfirmationLinks {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Subscribe to a list")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        let mut body = url::form_urlencoded::Serializer::new(String::new());
+        body.append_pair("name", name).append_pair("email", email);
+        if let Some(list) = list {
+            body.append_pair("list", list);
+        }
+        self.post_subscriptions(body.finish())
+            .await
+            .error_for_status()
+            .unwrap();
+        self.dispatch_all_pending_emails().await;
+
+        let email_request = self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_confirmation_links(&email_request)
+    }
+
+    pub async fn publish_issue(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.admin_request(reqwest::Method::POST, "/admin/api/issues")
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn saved_issue(&self, issue_id: i32) -> newsletter_issues::Model {
+        newsletter_issues::Entity::find_by_id(issue_id)
+            .one(&self.db_pool)
+            .await
+            .unwrap()
+            .expect("Issue not found")
+    }
+
+    pub async fn create_list(&self, slug: &str, name: &str) -> reqwest::Response {
+        self.admin_request(reqwest::Method::POST, "/admin/api/lists")
+            .json(&serde_json::json!({ "slug": slug, "name": name }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn import_subscribers(&self, csv: &str) -> reqwest::Response {
+        self.admin_request(reqwest::Method::POST, "/admin/api/subscribers/import")
+            .header("Content-Type", "text/csv")
+            .body(csv.to_owned())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// A request to an `/admin` route, authenticated with the configured
+    /// admin credentials.
+    pub fn admin_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .request(method, format!("{}{}", self.address, path))
+            .basic_auth(
+                &self.admin.username,
+                Some(self.admin.password.expose_secret()),
+            )
+    }
+
+    /// Call a Postmark webhook endpoint with the configured basic auth credentials.
+    pub async fn post_postmark_webhook(
+        &self,
+        endpoint: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/postmark/{}", self.address, endpoint))
+            .basic_auth(
+                &self.postmark_webhooks.username,
+                Some(self.postmark_webhooks.password.expose_secret()),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         // Extract the link from one of the request fields.
@@ -0,0 +1,284 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Create a draft issue and return its id.
+async fn create_draft(app: &TestApp) -> i32 {
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Draft title",
+            "html_content": "<p>Work in progress</p>",
+            "draft": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["status"], "draft");
+    created["id"].as_i64().unwrap() as i32
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered_to_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    create_draft(&app).await;
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .admin_request(Method::PUT, &format!("/admin/api/issues/{}", issue_id))
+        .json(&serde_json::json!({ "title": "Final title", "text_content": "Plain" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let issue = app.saved_issue(issue_id).await;
+    assert_eq!(issue.title, "Final title");
+    assert_eq!(issue.html_content, "<p>Work in progress</p>");
+    assert_eq!(issue.text_content.as_deref(), Some("Plain"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_previewed_in_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .admin_request(Method::GET, &format!("/admin/issues/{}/preview", issue_id))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<title>Draft title</title>"));
+    assert!(html.contains("<p>Work in progress</p>"));
+}
+
+#[tokio::test]
+async fn previews_require_admin_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/issues/{}/preview", app.address, issue_id))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn drafts_are_test_sent_to_the_configured_internal_addresses() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.newsletter.test_recipients = vec!["editor@example.com".into(), "qa@example.com".into()]
+    })
+    .await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let issue_id = create_draft(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .admin_request(
+            Method::POST,
+            &format!("/admin/api/issues/{}/test-send", issue_id),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let recipients: Vec<String> = requests
+        .iter()
+        .rev()
+        .take(2)
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            assert_eq!(body["Subject"], "[TEST] Draft title");
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert!(recipients.contains(&"editor@example.com".to_string()));
+    assert!(recipients.contains(&"qa@example.com".to_string()));
+    assert_eq!(app.saved_issue(issue_id).await.status, "draft");
+}
+
+#[tokio::test]
+async fn test_sends_skip_suppressed_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app).await;
+    app.admin_request(Method::POST, "/admin/api/suppressions")
+        .json(&serde_json::json!({ "email": "bounced@example.com" }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .admin_request(
+            Method::POST,
+            &format!("/admin/api/issues/{}/test-send", issue_id),
+        )
+        .json(&serde_json::json!({
+            "recipients": ["editor@example.com", "Bounced@Example.com"]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        report["recipients"],
+        serde_json::json!(["editor@example.com"])
+    );
+    assert_eq!(
+        report["suppressed"],
+        serde_json::json!(["Bounced@example.com"])
+    );
+    // Mock verifies on Drop that only the editor got the issue
+}
+
+#[tokio::test]
+async fn test_sends_without_recipients_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .admin_request(
+            Method::POST,
+            &format!("/admin/api/issues/{}/test-send", issue_id),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn released_drafts_are_delivered_to_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let issue_id = create_draft(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .admin_request(
+            Method::POST,
+            &format!("/admin/api/issues/{}/publish", issue_id),
+        )
+        .send()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let released: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(released["status"], "published");
+    assert_eq!(released["recipients"], 1);
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
+
+#[tokio::test]
+async fn published_issues_can_no_longer_be_edited_or_released() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = create_draft(&app).await;
+    app.admin_request(
+        Method::POST,
+        &format!("/admin/api/issues/{}/publish", issue_id),
+    )
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let edit = app
+        .admin_request(Method::PUT, &format!("/admin/api/issues/{}", issue_id))
+        .json(&serde_json::json!({ "title": "Too late" }))
+        .send()
+        .await
+        .unwrap();
+    let release = app
+        .admin_request(
+            Method::POST,
+            &format!("/admin/api/issues/{}/publish", issue_id),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(edit.status().as_u16(), 409);
+    assert_eq!(release.status().as_u16(), 409);
+}
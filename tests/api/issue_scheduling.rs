@@ -0,0 +1,259 @@
+use crate::helpers::{TestApp, spawn_app};
+use chrono::{Duration, Utc};
+use reqwest::Method;
+use sea_orm::{ActiveModelTrait, Set};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::{newsletter_issues, segments};
+use z2p_axum::issue_scheduler::MAX_PUBLISH_ATTEMPTS;
+
+/// Create an issue scheduled an hour from now and return its id.
+async fn schedule_issue(app: &TestApp) -> i32 {
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Monday issue",
+            "html_content": "<p>Good morning!</p>",
+            "scheduled_at": Utc::now() + Duration::hours(1),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["status"], "scheduled");
+    assert_eq!(created["recipients"], 0);
+    created["id"].as_i64().unwrap() as i32
+}
+
+/// Pretend the send time of the issue has come.
+async fn make_due(app: &TestApp, issue_id: i32) {
+    let mut issue: newsletter_issues::ActiveModel = app.saved_issue(issue_id).await.into();
+    issue.scheduled_at = Set(Some(Utc::now() - Duration::minutes(1)));
+    issue.update(&app.db_pool).await.unwrap();
+}
+
+/// Schedule an issue for a segment whose definition cannot be parsed, due a
+/// few minutes ago, and return its id.
+async fn schedule_broken_issue(app: &TestApp) -> i32 {
+    let segment = segments::ActiveModel {
+        name: Set("broken".into()),
+        definition: Set("not a segment".into()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&app.db_pool)
+    .await
+    .unwrap();
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Broken issue",
+            "html_content": "<p>Never sent</p>",
+            "scheduled_at": Utc::now() + Duration::hours(1),
+            "segment_id": segment.id,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    let issue_id = created["id"].as_i64().unwrap() as i32;
+    let mut issue: newsletter_issues::ActiveModel = app.saved_issue(issue_id).await.into();
+    issue.scheduled_at = Set(Some(Utc::now() - Duration::minutes(5)));
+    issue.update(&app.db_pool).await.unwrap();
+    issue_id
+}
+
+async fn create_confirmed_subscriber_and_expect_emails(app: &TestApp, emails: u64) {
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(emails)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_sent_before_their_send_time() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_and_expect_emails(&app, 0).await;
+
+    // Act
+    let issue_id = schedule_issue(&app).await;
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(app.saved_issue(issue_id).await.status, "scheduled");
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_sent_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_and_expect_emails(&app, 1).await;
+    let issue_id = schedule_issue(&app).await;
+    make_due(&app, issue_id).await;
+
+    // Act
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = app.saved_issue(issue_id).await;
+    assert_eq!(issue.status, "published");
+    assert!(issue.published_at.is_some());
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = schedule_issue(&app).await;
+    let new_time = Utc::now() + Duration::days(3);
+
+    // Act
+    let response = app
+        .admin_request(
+            Method::PUT,
+            &format!("/admin/api/issues/{}/schedule", issue_id),
+        )
+        .json(&serde_json::json!({ "scheduled_at": new_time }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let scheduled_at = app.saved_issue(issue_id).await.scheduled_at.unwrap();
+    assert!((scheduled_at - new_time).num_seconds().abs() <= 1);
+}
+
+#[tokio::test]
+async fn cancelled_issues_go_back_to_being_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_and_expect_emails(&app, 0).await;
+    let issue_id = schedule_issue(&app).await;
+
+    // Act
+    let response = app
+        .admin_request(
+            Method::DELETE,
+            &format!("/admin/api/issues/{}/schedule", issue_id),
+        )
+        .send()
+        .await
+        .unwrap();
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(app.saved_issue(issue_id).await.status, "draft");
+}
+
+#[tokio::test]
+async fn sent_issues_can_no_longer_be_rescheduled_or_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = schedule_issue(&app).await;
+    make_due(&app, issue_id).await;
+    app.publish_due_issues().await;
+    let schedule_path = format!("/admin/api/issues/{}/schedule", issue_id);
+
+    // Act
+    let reschedule = app
+        .admin_request(Method::PUT, &schedule_path)
+        .json(&serde_json::json!({ "scheduled_at": Utc::now() + Duration::days(1) }))
+        .send()
+        .await
+        .unwrap();
+    let cancel = app
+        .admin_request(Method::DELETE, &schedule_path)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(reschedule.status().as_u16(), 409);
+    assert_eq!(cancel.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn scheduling_unknown_issues_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_request(Method::DELETE, "/admin/api/issues/42/schedule")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn an_issue_failing_to_publish_does_not_block_later_ones() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_and_expect_emails(&app, 1).await;
+    let broken_id = schedule_broken_issue(&app).await;
+    let issue_id = schedule_issue(&app).await;
+    make_due(&app, issue_id).await;
+
+    // Act
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(app.saved_issue(issue_id).await.status, "published");
+    let broken = app.saved_issue(broken_id).await;
+    assert_eq!(broken.status, "scheduled");
+    assert_eq!(broken.publish_attempts, 1);
+    assert!(broken.last_error.unwrap().contains("invalid definition"));
+    // Retried later rather than straight away, still showing when it was
+    // meant to go out.
+    assert!(broken.next_attempt_at.unwrap() > Utc::now());
+    assert!(broken.scheduled_at.unwrap() < Utc::now());
+}
+
+#[tokio::test]
+async fn issues_failing_too_often_are_marked_failed_until_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = schedule_broken_issue(&app).await;
+    let mut issue: newsletter_issues::ActiveModel = app.saved_issue(issue_id).await.into();
+    issue.publish_attempts = Set(MAX_PUBLISH_ATTEMPTS - 1);
+    issue.update(&app.db_pool).await.unwrap();
+
+    // Act - Part 1 - The last attempt
+    app.publish_due_issues().await;
+
+    // Assert - Part 1
+    let issue = app.saved_issue(issue_id).await;
+    assert_eq!(issue.status, "failed");
+    assert_eq!(issue.publish_attempts, MAX_PUBLISH_ATTEMPTS);
+
+    // Act - Part 2 - Rescheduled once the segment is fixed
+    let response = app
+        .admin_request(
+            Method::PUT,
+            &format!("/admin/api/issues/{}/schedule", issue_id),
+        )
+        .json(&serde_json::json!({ "scheduled_at": Utc::now() + Duration::days(1) }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert - Part 2
+    assert_eq!(response.status().as_u16(), 204);
+    let issue = app.saved_issue(issue_id).await;
+    assert_eq!(issue.status, "scheduled");
+    assert_eq!(issue.publish_attempts, 0);
+    assert_eq!(issue.last_error, None);
+    assert_eq!(issue.next_attempt_at, None);
+}
@@ -0,0 +1,219 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::Method;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::{list_subscriptions, lists, subscriptions};
+
+/// The status of the subscriptions of `email`, by list slug.
+async fn list_statuses(app: &TestApp, email: &str) -> Vec<(String, String)> {
+    let subscriber = app.saved_subscriber(email).await;
+    let rows = list_subscriptions::Entity::find()
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber.id))
+        .order_by_asc(list_subscriptions::Column::ListId)
+        .find_also_related(lists::Entity)
+        .all(&app.db_pool)
+        .await
+        .unwrap();
+    rows.into_iter()
+        .map(|(subscription, list)| (list.unwrap().slug, subscription.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn admins_can_create_lists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.create_list("release-notes", "Release notes").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let lists: serde_json::Value = app
+        .admin_request(Method::GET, "/admin/api/lists")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let slugs: Vec<_> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|list| list["slug"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(slugs, vec!["newsletter", "release-notes"]);
+}
+
+#[tokio::test]
+async fn list_slugs_must_be_valid_and_unique() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act & Assert
+    assert_eq!(
+        app.create_list("Release Notes", "Notes").await.status(),
+        400
+    );
+    assert_eq!(app.create_list("", "Notes").await.status(), 400);
+    assert_eq!(app.create_list("newsletter", "Again").await.status(), 409);
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&list=nope".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_same_email_can_subscribe_to_several_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("release-notes", "Release notes").await;
+
+    // Act
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.subscribe_to_list("le guin", "ursula_le_guin@gmail.com", Some("release-notes"))
+        .await;
+
+    // Assert
+    let subscribers = subscriptions::Entity::find()
+        .all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(
+        list_statuses(&app, "ursula_le_guin@gmail.com").await,
+        vec![
+            ("newsletter".to_string(), "confirmed".to_string()),
+            (
+                "release-notes".to_string(),
+                "pending_confirmation".to_string()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_confirmation_link_only_confirms_its_own_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("release-notes", "Release notes").await;
+    app.subscribe_to_list("le guin", "ursula_le_guin@gmail.com", None)
+        .await;
+
+    // Act
+    app.create_confirmed_list_subscriber(
+        "le guin",
+        "ursula_le_guin@gmail.com",
+        Some("release-notes"),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(
+        list_statuses(&app, "ursula_le_guin@gmail.com").await,
+        vec![
+            ("newsletter".to_string(), "pending_confirmation".to_string()),
+            ("release-notes".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_not_asked_to_confirm_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // Mock verifies on Drop that no confirmation email was sent
+}
+
+#[tokio::test]
+async fn issues_are_only_delivered_to_their_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("release-notes", "Release notes").await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_list_subscriber(
+        "butler",
+        "octavia_butler@gmail.com",
+        Some("release-notes"),
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Version 2.0",
+            "html_content": "<p>Out now</p>",
+            "list": "release-notes",
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["recipients"], 1);
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["To"], "octavia_butler@gmail.com");
+}
+
+#[tokio::test]
+async fn issues_for_unknown_lists_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Version 2.0",
+            "html_content": "<p>Out now</p>",
+            "list": "nope",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -1,4 +1,27 @@
+mod ab_tests;
+mod admin_subscribers;
+mod admin_suppressions;
+mod archive;
+mod attributes;
+mod audit;
+mod bot_protection;
+mod consent_events;
+mod digests;
+mod domain_check;
+mod email_outbox;
+mod email_policy;
 mod health_check;
 mod helpers;
+mod issue_drafts;
+mod issue_scheduling;
+mod lists;
+mod postmark_webhooks;
+mod preferences;
+mod privacy;
+mod rate_limit;
+mod segments;
+mod subscriber_export;
+mod subscriber_import;
 mod subscriptions;
 mod subscriptions_confirm;
+mod tracking;
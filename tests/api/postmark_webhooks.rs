@@ -0,0 +1,307 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+use chrono::{Duration, Utc};
+use futures_util::future::join_all;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use secrecy::ExposeSecret;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::{email_outbox, subscriptions};
+use z2p_axum::suppression::is_suppressed;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn create_subscriber(app: &TestApp) {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+fn bounce(bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807u64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "Name": "Hard bounce",
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Description": "The server was unable to deliver your message.",
+        "Email": EMAIL,
+        "From": "sender@example.com",
+        "BouncedAt": "2026-10-19T08:00:00Z",
+        "Inactive": true,
+        "CanActivate": true
+    })
+}
+
+#[tokio::test]
+async fn webhooks_without_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for endpoint in ["bounce", "spam-complaint"] {
+        // Act
+        let response = reqwest::Client::new()
+            .post(format!("{}/webhooks/postmark/{}", app.address, endpoint))
+            .json(&bounce("HardBounce"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401, "endpoint: {}", endpoint);
+    }
+}
+
+#[tokio::test]
+async fn webhooks_with_an_invalid_password_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark/bounce", app.address))
+        .basic_auth(&app.postmark_webhooks.username, Some("wrong-password"))
+        .json(&bounce("HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn webhooks_can_be_authenticated_with_the_shared_secret() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/postmark/bounce", app.address))
+        .header(
+            "X-Webhook-Secret",
+            app.postmark_webhooks.shared_secret.expose_secret(),
+        )
+        .json(&bounce("HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook("bounce", &bounce("HardBounce"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = app.saved_subscriber(EMAIL).await;
+    assert_eq!(saved.status, "bounced");
+    assert_eq!(saved.hard_bounce_count, 1);
+}
+
+#[tokio::test]
+async fn soft_bounces_mark_the_subscriber_as_bounced_once_the_threshold_is_reached() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let threshold = app.postmark_webhooks.soft_bounce_threshold;
+
+    // Act & Assert
+    for _ in 1..threshold {
+        app.post_postmark_webhook("bounce", &bounce("SoftBounce"))
+            .await
+            .error_for_status()
+            .unwrap();
+        assert_eq!(
+            app.saved_subscriber(EMAIL).await.status,
+            "pending_confirmation"
+        );
+    }
+    app.post_postmark_webhook("bounce", &bounce("SoftBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+    let saved = app.saved_subscriber(EMAIL).await;
+    assert_eq!(saved.status, "bounced");
+    assert_eq!(saved.soft_bounce_count, threshold);
+}
+
+#[tokio::test]
+async fn soft_bounces_far_apart_start_counting_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let threshold = app.postmark_webhooks.soft_bounce_threshold;
+    for _ in 1..threshold {
+        app.post_postmark_webhook("bounce", &bounce("SoftBounce"))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    let window = app.postmark_webhooks.soft_bounce_window_days;
+    subscriptions::Entity::update_many()
+        .col_expr(
+            subscriptions::Column::SoftBouncedAt,
+            Expr::value(Utc::now() - Duration::days(window + 1)),
+        )
+        .filter(subscriptions::Column::Email.eq(EMAIL))
+        .exec(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.post_postmark_webhook("bounce", &bounce("SoftBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = app.saved_subscriber(EMAIL).await;
+    assert_eq!(saved.status, "pending_confirmation");
+    assert_eq!(saved.soft_bounce_count, 1);
+}
+
+#[tokio::test]
+async fn bounces_are_matched_on_the_canonical_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let mut payload = bounce("HardBounce");
+    payload["Email"] = "Ursula_Le_Guin@Gmail.com".into();
+
+    // Act
+    let response = app.post_postmark_webhook("bounce", &payload).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.saved_subscriber(EMAIL).await.status, "bounced");
+}
+
+#[tokio::test]
+async fn concurrent_bounces_are_all_counted() {
+    // Arrange
+    let app = spawn_app_with(|c| c.postmark_webhooks.soft_bounce_threshold = 100).await;
+    create_subscriber(&app).await;
+    let payload = bounce("SoftBounce");
+
+    // Act
+    let responses = join_all((0..10).map(|_| app.post_postmark_webhook("bounce", &payload))).await;
+
+    // Assert
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    assert_eq!(app.saved_subscriber(EMAIL).await.soft_bounce_count, 10);
+}
+
+#[tokio::test]
+async fn bounces_that_are_not_delivery_failures_are_ignored() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook("bounce", &bounce("AutoResponder"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = app.saved_subscriber(EMAIL).await;
+    assert_eq!(saved.status, "pending_confirmation");
+    assert_eq!(saved.hard_bounce_count + saved.soft_bounce_count, 0);
+}
+
+#[tokio::test]
+async fn hard_bounces_for_unknown_addresses_are_acknowledged_and_suppressed() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook("bounce", &bounce("HardBounce"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(is_suppressed(&app.db_pool, EMAIL).await.unwrap());
+}
+
+#[tokio::test]
+async fn soft_bounces_for_unknown_addresses_are_not_suppressed() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook("bounce", &bounce("SoftBounce"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!is_suppressed(&app.db_pool, EMAIL).await.unwrap());
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let complaint = serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "ID": 42,
+        "Type": "SpamComplaint",
+        "TypeCode": 512,
+        "Email": EMAIL,
+        "BouncedAt": "2026-10-19T08:00:00Z"
+    });
+
+    // Act
+    let response = app
+        .post_postmark_webhook("spam-complaint", &complaint)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.saved_subscriber(EMAIL).await.status, "complained");
+}
+
+#[tokio::test]
+async fn emails_to_bounced_subscribers_are_not_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    create_subscriber(&app).await;
+    app.post_postmark_webhook("bounce", &bounce("HardBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let saved = email_outbox::Entity::find()
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.status, "suppressed");
+}
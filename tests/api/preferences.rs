@@ -0,0 +1,307 @@
+use crate::helpers::{TestApp, spawn_app};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::{list_subscriptions, preference_changes};
+use z2p_axum::suppression::is_suppressed;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+/// A confirmed subscriber, returning the URL of their preference center.
+async fn confirmed_subscriber(app: &TestApp) -> String {
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    let subscriber = app.saved_subscriber(EMAIL).await;
+    format!(
+        "{}/preferences/{}",
+        app.address, subscriber.preferences_token
+    )
+}
+
+async fn post_preferences(url: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn changes(app: &TestApp) -> Vec<(String, Option<String>, Option<String>)> {
+    preference_changes::Entity::find()
+        .order_by_asc(preference_changes::Column::Id)
+        .all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|change| (change.field, change.old_value, change.new_value))
+        .collect()
+}
+
+#[tokio::test]
+async fn issues_link_to_the_preference_center() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.publish_issue(&serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Hello</p>",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let path = url.trim_start_matches(&app.address);
+    assert!(body["HtmlBody"].as_str().unwrap().contains(path));
+    assert!(body["TextBody"].as_str().unwrap().contains(path));
+}
+
+#[tokio::test]
+async fn the_preference_center_shows_the_current_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = confirmed_subscriber(&app).await;
+
+    // Act
+    let response = reqwest::get(&url).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Cache-Control"], "no-store");
+    let page = response.text().await.unwrap();
+    assert!(page.contains("value=\"le guin\""));
+    assert!(page.contains("value=\"newsletter\" checked>"));
+    assert!(page.contains("<option value=\"immediate\" selected>"));
+}
+
+#[tokio::test]
+async fn unknown_tokens_are_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/preferences/unknown", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_and_digest_frequency() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = confirmed_subscriber(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &url,
+        "name=Ursula%20K.%20Le%20Guin&lists=newsletter&digest_frequency=weekly",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = app.saved_subscriber(EMAIL).await;
+    assert_eq!(subscriber.name, "Ursula K. Le Guin");
+    assert_eq!(subscriber.digest_frequency, "weekly");
+    assert_eq!(
+        changes(&app).await,
+        vec![
+            (
+                "name".to_string(),
+                Some("le guin".to_string()),
+                Some("Ursula K. Le Guin".to_string())
+            ),
+            (
+                "digest_frequency".to_string(),
+                Some("immediate".to_string()),
+                Some("weekly".to_string())
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = confirmed_subscriber(&app).await;
+    let test_cases = vec![
+        ("name=%3Cscript%3E&lists=newsletter", "invalid name"),
+        ("name=%20&lists=newsletter", "blank name"),
+        (
+            "lists=newsletter&digest_frequency=hourly",
+            "unknown frequency",
+        ),
+        ("lists=unknown", "unknown list"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = post_preferences(&url, body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with a 400 when the payload had an {}.",
+            description
+        );
+    }
+    assert_eq!(app.saved_subscriber(EMAIL).await.name, "le guin");
+    assert!(changes(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_can_choose_their_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("releases", "releases")
+        .await
+        .error_for_status()
+        .unwrap();
+    let url = confirmed_subscriber(&app).await;
+
+    // Act
+    let response = post_preferences(&url, "name=le%20guin&lists=releases").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = app.saved_subscriber(EMAIL).await;
+    let statuses: Vec<_> = list_subscriptions::Entity::find()
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber.id))
+        .order_by_asc(list_subscriptions::Column::ListId)
+        .all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|subscription| subscription.status)
+        .collect();
+    assert_eq!(statuses, vec!["unsubscribed", "confirmed"]);
+    assert_eq!(
+        changes(&app).await,
+        vec![
+            (
+                "list:newsletter".to_string(),
+                Some("confirmed".to_string()),
+                Some("unsubscribed".to_string())
+            ),
+            (
+                "list:releases".to_string(),
+                None,
+                Some("confirmed".to_string())
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_everything() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = confirmed_subscriber(&app).await;
+
+    // Act - Part 1 - Unsubscribe
+    let response = post_preferences(&url, "lists=newsletter&unsubscribe_all=true").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = app.saved_subscriber(EMAIL).await;
+    assert_eq!(subscriber.status, "unsubscribed");
+    assert!(is_suppressed(&app.db_pool, EMAIL).await.unwrap());
+    let fields: Vec<_> = changes(&app)
+        .await
+        .into_iter()
+        .map(|(field, _, _)| field)
+        .collect();
+    assert_eq!(fields, vec!["list:newsletter", "status"]);
+
+    // Act - Part 2 - There is nothing left to manage
+    let response = post_preferences(&url, "lists=newsletter").await;
+    assert_eq!(response.status().as_u16(), 409);
+    let page = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    assert!(!page.contains("<form"));
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribed_from_everything_can_subscribe_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = confirmed_subscriber(&app).await;
+    post_preferences(&url, "lists=newsletter&unsubscribe_all=true")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act - Part 1 - The confirmation email is sent, not suppressed
+    let confirmation_links = app.subscribe_to_list("le guin", EMAIL, None).await;
+    // Only the owner of the address can lift the suppression.
+    assert!(is_suppressed(&app.db_pool, EMAIL).await.unwrap());
+
+    // Act - Part 2 - Confirm
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(app.saved_subscriber(EMAIL).await.status, "confirmed");
+    assert!(!is_suppressed(&app.db_pool, EMAIL).await.unwrap());
+}
+
+#[tokio::test]
+async fn old_confirmation_links_do_not_undo_unsubscribing_from_everything() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = app.subscribe_to_list("le guin", EMAIL, None).await;
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let subscriber = app.saved_subscriber(EMAIL).await;
+    let url = format!(
+        "{}/preferences/{}",
+        app.address, subscriber.preferences_token
+    );
+    post_preferences(&url, "lists=newsletter&unsubscribe_all=true")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.saved_subscriber(EMAIL).await.status, "unsubscribed");
+    assert!(is_suppressed(&app.db_pool, EMAIL).await.unwrap());
+    let status = list_subscriptions::Entity::find()
+        .filter(list_subscriptions::Column::SubscriberId.eq(subscriber.id))
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap()
+        .status;
+    assert_eq!(status, "unsubscribed");
+}
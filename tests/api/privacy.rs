@@ -0,0 +1,245 @@
+use crate::helpers::{TestApp, spawn_app};
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::{email_outbox, privacy_requests, subscriptions};
+use z2p_axum::suppression::{is_suppressed, suppress};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn post_privacy_request(app: &TestApp, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/privacy/requests", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// The last email queued for `EMAIL` with `subject`.
+async fn last_email(app: &TestApp, subject: &str) -> email_outbox::Model {
+    email_outbox::Entity::find()
+        .filter(email_outbox::Column::Recipient.eq(EMAIL))
+        .filter(email_outbox::Column::Subject.eq(subject))
+        .order_by_desc(email_outbox::Column::Id)
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .expect("Email not queued")
+}
+
+/// Ask for `kind` and return the link of the verification email.
+async fn request_link(app: &TestApp, kind: &str) -> String {
+    let response = post_privacy_request(
+        app,
+        &format!("email=ursula_le_guin%40gmail.com&kind={}", kind),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let email = last_email(app, "Confirm your data request").await;
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(email.text_body.as_deref().unwrap())
+        .filter(|l| *l.kind() == linkify::LinkKind::Url)
+        .collect();
+    assert_eq!(links.len(), 1);
+    let mut link = reqwest::Url::parse(links[0].as_str()).unwrap();
+    assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+    link.set_port(Some(app.port)).unwrap();
+    link.to_string()
+}
+
+#[tokio::test]
+async fn an_unknown_address_gets_the_same_answer_and_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_privacy_request(&app, "email=nobody%40example.com&kind=access").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("If this address is subscribed")
+    );
+    let requests = privacy_requests::Entity::find()
+        .count(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(requests, 0);
+}
+
+#[tokio::test]
+async fn invalid_requests_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            "email=ursula_le_guin%40gmail.com&kind=sell",
+            "an unknown kind",
+        ),
+        ("email=not-an-email&kind=access", "an invalid email"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = post_privacy_request(&app, body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_confirmed_access_request_emails_the_data() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    let link = request_link(&app, "access").await;
+
+    // Act - Part 1 - The link shows a confirmation form
+    let response = reqwest::get(&link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Send me my data"));
+
+    // Act - Part 2 - Confirm
+    let response = reqwest::Client::new().post(&link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    let email = last_email(&app, "Your data").await;
+    let bundle: serde_json::Value =
+        serde_json::from_str(email.text_body.as_deref().unwrap()).unwrap();
+    assert_eq!(bundle["subscriber"]["email"], EMAIL);
+    assert_eq!(bundle["subscriber"]["name"], "le guin");
+    assert_eq!(bundle["lists"][0]["list"], "newsletter");
+    assert_eq!(bundle["subscription_tokens"].as_array().unwrap().len(), 1);
+    // The link cannot be used twice.
+    let response = reqwest::Client::new().post(&link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_confirmed_erasure_request_erases_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    let link = request_link(&app, "erasure").await;
+
+    // Act
+    let response = reqwest::Client::new().post(&link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = subscriptions::Entity::find()
+        .filter(subscriptions::Column::Email.eq(EMAIL))
+        .one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscriber.is_none());
+    let emails = email_outbox::Entity::find()
+        .filter(email_outbox::Column::Recipient.eq(EMAIL))
+        .count(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(emails, 0);
+    assert!(is_suppressed(&app.db_pool, EMAIL).await.unwrap());
+    let response = reqwest::get(&link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn unknown_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/privacy/requests/unknown", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribed_from_everything_still_get_their_data() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    suppress(&app.db_pool, EMAIL, "unsubscribed", "preferences")
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let link = request_link(&app, "access").await;
+    app.dispatch_all_pending_emails().await;
+    let response = reqwest::Client::new().post(&link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    for subject in ["Confirm your data request", "Your data"] {
+        assert_eq!(last_email(&app, subject).await.status, "sent");
+    }
+}
+
+#[tokio::test]
+async fn the_data_is_not_kept_in_the_outbox_once_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let link = request_link(&app, "access").await;
+    let response = reqwest::Client::new().post(&link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    for subject in ["Confirm your data request", "Your data"] {
+        let email = last_email(&app, subject).await;
+        assert_eq!(email.status, "sent");
+        assert_eq!(email.html_body, "");
+        assert_eq!(email.text_body, None);
+    }
+}
+
+#[tokio::test]
+async fn privacy_emails_are_not_sent_to_bounced_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", EMAIL).await;
+    suppress(&app.db_pool, EMAIL, "bounced", "postmark_webhook")
+        .await
+        .unwrap();
+
+    // Act
+    request_link(&app, "access").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email = last_email(&app, "Confirm your data request").await;
+    assert_eq!(email.status, "suppressed");
+}
@@ -0,0 +1,214 @@
+use crate::helpers::{TestApp, spawn_app_with};
+use sea_orm::{EntityTrait, PaginatorTrait, Set};
+use z2p_axum::client_ip::TrustedProxies;
+use z2p_axum::configuration::{RateLimitStore, Settings};
+use z2p_axum::entity::rate_limit_buckets;
+use z2p_axum::rate_limit::{BucketLimit, BucketStore, RateLimits};
+
+/// Enable the rate limits, `per_minute` low enough that no token comes back
+/// while the test runs.
+fn limited(c: &mut Settings, per_ip_burst: u32, per_email_burst: u32) {
+    c.rate_limit.enabled = true;
+    c.rate_limit.per_ip_burst = per_ip_burst;
+    c.rate_limit.per_ip_per_minute = 1;
+    c.rate_limit.per_email_burst = per_email_burst;
+    c.rate_limit.per_email_per_minute = 1;
+}
+
+async fn subscribe(app: &TestApp, email: &str, forwarded_for: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!("name=le%20guin&email={}", email));
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("X-Forwarded-For", forwarded_for);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn too_many_subscriptions_from_one_address_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| limited(c, 2, 10)).await;
+
+    // Act
+    for i in 0..2 {
+        let response = subscribe(&app, &format!("reader{}%40example.com", i), None).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = subscribe(&app, "reader2%40example.com", None).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+}
+
+#[tokio::test]
+async fn too_many_subscriptions_of_one_email_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        limited(c, 10, 1);
+        c.application.trusted_proxies =
+            TrustedProxies::try_from(vec!["127.0.0.1".to_string()]).unwrap();
+    })
+    .await;
+    let response = subscribe(&app, "ursula_le_guin%40gmail.com", Some("198.51.100.1")).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - from another address, and another spelling
+    let response = subscribe(&app, "Ursula_Le_Guin%40gmail.com", Some("198.51.100.2")).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn forwarded_addresses_are_limited_separately_behind_a_trusted_proxy() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        limited(c, 1, 10);
+        c.application.trusted_proxies =
+            TrustedProxies::try_from(vec!["127.0.0.0/8".to_string()]).unwrap();
+    })
+    .await;
+
+    // Act
+    let first = subscribe(&app, "a%40example.com", Some("198.51.100.1")).await;
+    let second = subscribe(&app, "b%40example.com", Some("198.51.100.2")).await;
+    let third = subscribe(&app, "c%40example.com", Some("198.51.100.1")).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(third.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn forwarded_addresses_are_ignored_from_untrusted_peers() {
+    // Arrange
+    let app = spawn_app_with(|c| limited(c, 1, 10)).await;
+
+    // Act
+    let first = subscribe(&app, "a%40example.com", Some("198.51.100.1")).await;
+    let second = subscribe(&app, "b%40example.com", Some("198.51.100.2")).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn the_mysql_store_limits_too() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        limited(c, 10, 1);
+        c.rate_limit.store = RateLimitStore::Mysql;
+    })
+    .await;
+
+    // Act
+    let first = subscribe(&app, "ursula_le_guin%40gmail.com", None).await;
+    let second = subscribe(&app, "ursula_le_guin%40gmail.com", None).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn confirmations_are_limited_per_address() {
+    // Arrange
+    let app = spawn_app_with(|c| limited(c, 1, 10)).await;
+    let confirm = || reqwest::get(format!("{}/subscriptions/confirm", app.address));
+
+    // Act
+    let first = confirm().await.unwrap();
+    let second = confirm().await.unwrap();
+
+    // Assert - the first one fails for the missing token, not the limit
+    assert_eq!(first.status().as_u16(), 400);
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn too_many_privacy_requests_for_one_email_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| limited(c, 10, 1)).await;
+    let request = || {
+        reqwest::Client::new()
+            .post(format!("{}/privacy/requests", app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("email=ursula_le_guin%40gmail.com&kind=access")
+            .send()
+    };
+    assert_eq!(request().await.unwrap().status().as_u16(), 200);
+
+    // Act
+    let response = request().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn spellings_of_one_mailbox_share_a_bucket() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        limited(c, 10, 1);
+        c.email_policy.canonicalization.strip_plus_tags = true;
+    })
+    .await;
+    let first = subscribe(&app, "ursula_le_guin%2B1%40gmail.com", None).await;
+    assert_eq!(first.status().as_u16(), 200);
+
+    // Act
+    let second = subscribe(&app, "ursula_le_guin%2B2%40gmail.com", None).await;
+
+    // Assert
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn refilled_buckets_are_pruned() {
+    // Arrange
+    let app = spawn_app_with(|_| {}).await;
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    for (key, updated_at_ms) in [("idle", now_ms - 60 * 60 * 1000), ("recent", now_ms)] {
+        rate_limit_buckets::Entity::insert(rate_limit_buckets::ActiveModel {
+            bucket_key: Set(key.to_string()),
+            tokens: Set(0.0),
+            updated_at_ms: Set(updated_at_ms),
+        })
+        .exec(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    let limits = RateLimits {
+        enabled: true,
+        per_ip: BucketLimit {
+            burst: 10,
+            per_minute: 5,
+        },
+        per_email: BucketLimit {
+            burst: 3,
+            per_minute: 1,
+        },
+        store: BucketStore::MySql(app.db_pool.clone()),
+    };
+
+    // Act
+    let pruned = limits.prune().await.unwrap();
+
+    // Assert
+    assert_eq!(pruned, 1);
+    let left = rate_limit_buckets::Entity::find()
+        .count(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(left, 1);
+}
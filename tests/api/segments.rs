@@ -0,0 +1,246 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::Method;
+use sea_orm::EntityTrait;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use z2p_axum::entity::subscriptions;
+
+async fn tag(app: &TestApp, email: &str, tag: &str) -> reqwest::Response {
+    let subscriber_id = app.saved_subscriber(email).await.id;
+    app.admin_request(
+        Method::PUT,
+        &format!("/admin/api/subscribers/{}/tags/{}", subscriber_id, tag),
+    )
+    .send()
+    .await
+    .expect("Failed to execute request.")
+}
+
+async fn create_segment(app: &TestApp, definition: serde_json::Value) -> reqwest::Response {
+    app.admin_request(Method::POST, "/admin/api/segments")
+        .json(&serde_json::json!({ "name": "Rustaceans", "definition": definition }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn dry_run(app: &TestApp, body: serde_json::Value) -> serde_json::Value {
+    app.admin_request(Method::POST, "/admin/api/segments/dry-run")
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Two confirmed subscribers, only the first one tagged `rust`.
+async fn tagged_subscribers(app: &TestApp) {
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    app.create_confirmed_subscriber("butler", "octavia_butler@gmail.com")
+        .await;
+    let response = tag(app, "ursula_le_guin@gmail.com", "rust").await;
+    assert_eq!(response.status().as_u16(), 204);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_tagged() {
+    // Arrange
+    let app = spawn_app().await;
+    tagged_subscribers(&app).await;
+
+    // Act - Tagging twice is a no-op
+    let response = tag(&app, "ursula_le_guin@gmail.com", "rust").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let tags: serde_json::Value = app
+        .admin_request(Method::GET, "/admin/api/tags")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        tags,
+        serde_json::json!([{ "name": "rust", "subscribers": 1 }])
+    );
+}
+
+#[tokio::test]
+async fn invalid_tags_and_unknown_subscribers_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    // Act & Assert
+    let response = tag(&app, "ursula_le_guin@gmail.com", "Not%20A%20Slug").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app
+        .admin_request(Method::PUT, "/admin/api/subscribers/4242/tags/rust")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_dry_run_counts_the_recipients_of_a_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    tagged_subscribers(&app).await;
+
+    // Act
+    let whole_list = dry_run(&app, serde_json::json!({})).await;
+    let drafted = dry_run(&app, serde_json::json!({ "definition": { "tag": "rust" } })).await;
+    let negated = dry_run(
+        &app,
+        serde_json::json!({ "definition": { "not": { "tag": "rust" } } }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(whole_list["recipients"], 2);
+    assert_eq!(drafted["recipients"], 1);
+    assert_eq!(negated["recipients"], 1);
+}
+
+#[tokio::test]
+async fn segments_can_filter_on_locale() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr-CA";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let report = dry_run(
+        &app,
+        serde_json::json!({ "definition": { "all": [
+            { "locale": "fr" },
+            { "status": "pending_confirmation" },
+        ] } }),
+    )
+    .await;
+
+    // Assert - Only confirmed subscribers are ever counted
+    assert_eq!(report["recipients"], 0);
+    let saved = subscriptions::Entity::find()
+        .one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.locale.as_deref(), Some("fr-CA"));
+}
+
+#[tokio::test]
+async fn invalid_locales_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=%3Cfr%3E".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act & Assert
+    let response = create_segment(&app, serde_json::json!({ "tag": "" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = create_segment(&app, serde_json::json!({ "opened_within_days": 0 })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = create_segment(&app, serde_json::json!({ "unknown": 1 })).await;
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn issues_can_target_a_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    tagged_subscribers(&app).await;
+    let response = create_segment(&app, serde_json::json!({ "tag": "rust" })).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let segment: serde_json::Value = response.json().await.unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Rust news",
+            "html_content": "<p>Hello</p>",
+            "segment_id": segment["id"],
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["recipients"], 1);
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+
+    // A segment in use cannot be deleted
+    let response = app
+        .admin_request(
+            Method::DELETE,
+            &format!("/admin/api/segments/{}", segment["id"]),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn issues_for_unknown_segments_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Rust news",
+            "html_content": "<p>Hello</p>",
+            "segment_id": 4242,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,89 @@
+use crate::helpers::{TestApp, spawn_app};
+use reqwest::Method;
+
+async fn export(app: &TestApp, format: &str) -> reqwest::Response {
+    app.admin_request(
+        Method::GET,
+        &format!("/admin/api/subscribers/export?format={}", format),
+    )
+    .send()
+    .await
+    .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    let response = export(&app, "csv").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("id,email,name,status,subscribed_at"));
+    assert!(lines[1].contains(",ursula_le_guin@gmail.com,le guin,confirmed,"));
+}
+
+#[tokio::test]
+async fn every_subscriber_is_exported_as_json_lines_across_chunks() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut csv = "email,name\n".to_string();
+    for i in 0..2_500 {
+        csv.push_str(&format!("reader{}@example.com,reader {}\n", i, i));
+    }
+    app.import_subscribers(&csv)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = export(&app, "jsonl").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(subscribers.len(), 2_500);
+    assert_eq!(subscribers[2_499]["email"], "reader2499@example.com");
+    assert!(subscribers[0].get("preferences_token").is_none());
+}
+
+#[tokio::test]
+async fn unknown_formats_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = export(&app, "xlsx").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn exports_require_admin_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/api/subscribers/export", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
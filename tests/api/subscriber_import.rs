@@ -0,0 +1,139 @@
+use crate::helpers::spawn_app;
+use reqwest::Method;
+
+#[tokio::test]
+async fn valid_rows_are_imported_and_the_others_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let csv = "email,name,status,subscribed_at\n\
+               octavia_butler@gmail.com,butler,confirmed,2020-01-31T10:00:00Z\n\
+               nk_jemisin@example.com,\"Jemisin, N. K.\",unsubscribed,2021-06-01\n\
+               ursula_le_guin@gmail.com,le guin,confirmed,\n\
+               octavia_butler@gmail.com,butler again,,\n\
+               not-an-email,nobody,,\n\
+               gene_wolfe@gmail.com,wolfe,sleeping,\n";
+
+    // Act
+    let response = app.import_subscribers(csv).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let job: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["accepted"], 2);
+    assert_eq!(job["duplicates"], 2);
+    assert_eq!(job["rejected"], 2);
+    let report: serde_json::Value = app
+        .admin_request(
+            Method::GET,
+            &format!("/admin/api/subscribers/import/{}", job["id"]),
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let rows = |kind: &str| -> Vec<i64> {
+        report["report"][kind]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["row"].as_i64().unwrap())
+            .collect()
+    };
+    assert_eq!(rows("duplicates"), [5, 4]);
+    assert_eq!(rows("rejected"), [6, 7]);
+    assert_eq!(report["report"]["rejected"][0]["email"], "not-an-email");
+
+    let subscribers: serde_json::Value = app
+        .admin_request(Method::GET, "/admin/api/subscribers?order=asc")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let imported = &subscribers["subscribers"][1];
+    assert_eq!(imported["email"], "octavia_butler@gmail.com");
+    assert_eq!(imported["status"], "confirmed");
+    assert_eq!(imported["subscribed_at"], "2020-01-31T10:00:00Z");
+    assert_eq!(subscribers["subscribers"][2]["name"], "Jemisin, N. K.");
+    assert_eq!(subscribers["subscribers"][2]["status"], "unsubscribed");
+}
+
+#[tokio::test]
+async fn imported_subscribers_receive_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = app
+        .import_subscribers("email,name\noctavia_butler@gmail.com,butler\n")
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Act
+    let response = app
+        .publish_issue(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+
+    // Assert
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["recipients"], 1);
+}
+
+#[tokio::test]
+async fn large_files_are_imported_in_chunks() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut csv = "email,name\n".to_string();
+    for i in 0..1_200 {
+        csv.push_str(&format!("reader{}@example.com,reader {}\n", i, i));
+    }
+
+    // Act
+    let response = app.import_subscribers(&csv).await;
+
+    // Assert
+    let job: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(job["accepted"], 1_200);
+    assert_eq!(job["rejected"], 0);
+}
+
+#[tokio::test]
+async fn files_without_an_email_column_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for csv in ["name\nbutler\n", ""] {
+        // Act
+        let response = app.import_subscribers(csv).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+        let job: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(job["status"], "failed");
+        assert_eq!(job["report"]["rejected"][0]["row"], 1);
+    }
+}
+
+#[tokio::test]
+async fn importing_into_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_request(Method::POST, "/admin/api/subscribers/import?list=nope")
+        .body("email,name\noctavia_butler@gmail.com,butler\n")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -99,6 +99,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
         .await;
     // Act
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     // Assert
     // Mock asserts on drop
 }
@@ -115,6 +116,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
     // Act
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     // Assert
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(&email_request);
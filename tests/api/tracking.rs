@@ -0,0 +1,209 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": r#"<html><body><p>Read <a href="https://example.com/post?a=1&amp;b=2">the post</a>.</p></body></html>"#,
+    })
+}
+
+/// Publish an issue to the confirmed subscribers and return its id along
+/// with the HTML body of the email that was sent.
+async fn publish_and_deliver(app: &TestApp) -> (i64, String) {
+    publish_and_deliver_issue(app, &issue()).await
+}
+
+async fn publish_and_deliver_issue(app: &TestApp, issue: &serde_json::Value) -> (i64, String) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app.publish_issue(issue).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let published: serde_json::Value = response.json().await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    (
+        published["id"].as_i64().unwrap(),
+        body["HtmlBody"].as_str().unwrap().to_owned(),
+    )
+}
+
+/// The tracking URLs of our own server found in `html`, pointed at the test
+/// port.
+fn tracking_urls(app: &TestApp, html: &str) -> Vec<reqwest::Url> {
+    linkify::LinkFinder::new()
+        .links(html)
+        .filter(|l| *l.kind() == linkify::LinkKind::Url)
+        .filter_map(|l| reqwest::Url::parse(l.as_str()).ok())
+        .filter(|url| url.host_str() == Some("127.0.0.1") && url.path().starts_with("/t/"))
+        .map(|mut url| {
+            url.set_port(Some(app.port)).unwrap();
+            url
+        })
+        .collect()
+}
+
+async fn stats(app: &TestApp, issue_id: i64) -> serde_json::Value {
+    app.admin_request(
+        Method::GET,
+        &format!("/admin/api/issues/{}/stats", issue_id),
+    )
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap()
+    .json()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn published_issues_have_tracked_links_and_an_open_pixel() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    let (_, html) = publish_and_deliver(&app).await;
+
+    // Assert
+    assert!(!html.contains("https://example.com/post"));
+    let urls = tracking_urls(&app, &html);
+    assert_eq!(urls.len(), 2);
+    assert!(urls[0].path().starts_with("/t/c/"));
+    assert!(urls[1].path().starts_with("/t/o/"));
+}
+
+#[tokio::test]
+async fn links_with_non_ascii_characters_redirect_to_their_encoded_url() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let (_, html) = publish_and_deliver_issue(
+        &app,
+        &serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": r#"<p>Meet us at <a href="https://example.com/café">the café</a>.</p>"#,
+        }),
+    )
+    .await;
+    let urls = tracking_urls(&app, &html);
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    // Act
+    let response = client.get(urls[0].clone()).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/caf%C3%A9"
+    );
+}
+
+#[tokio::test]
+async fn opens_and_clicks_are_recorded_in_the_issue_stats() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+    let (issue_id, html) = publish_and_deliver(&app).await;
+    let urls = tracking_urls(&app, &html);
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    // Act - Part 1 - Open the email twice
+    for _ in 0..2 {
+        let response = client.get(urls[1].clone()).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Content-Type"], "image/gif");
+    }
+
+    // Act - Part 2 - Click the link
+    let response = client.get(urls[0].clone()).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/post?a=1&b=2"
+    );
+
+    // Assert
+    let stats = stats(&app, issue_id).await;
+    assert_eq!(stats["recipients"], 1);
+    assert_eq!(stats["opens"], 2);
+    assert_eq!(stats["unique_opens"], 1);
+    assert_eq!(stats["clicks"], 1);
+    assert_eq!(stats["unique_clicks"], 1);
+    assert_eq!(stats["links"][0]["url"], "https://example.com/post?a=1&b=2");
+    assert_eq!(stats["links"][0]["clicks"], 1);
+}
+
+#[tokio::test]
+async fn unknown_click_tokens_are_rejected_with_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for token in ["unknown.1", "no-link-id"] {
+        // Act
+        let response = reqwest::get(format!("{}/t/c/{}", app.address, token))
+            .await
+            .unwrap();
+        // Assert
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn stats_of_unknown_issues_are_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_request(Method::GET, "/admin/api/issues/42/stats")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_are_sent_untouched_when_tracking_is_disabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.tracking.enabled = false).await;
+    app.create_confirmed_subscriber("le guin", "ursula_le_guin@gmail.com")
+        .await;
+
+    // Act
+    let (issue_id, html) = publish_and_deliver(&app).await;
+
+    // Assert
+    assert!(html.contains(r#"href="https://example.com/post?a=1&amp;b=2""#));
+    assert!(tracking_urls(&app, &html).is_empty());
+    let stats = stats(&app, issue_id).await;
+    assert_eq!(stats["opens"], 0);
+    assert_eq!(stats["links"].as_array().unwrap().len(), 0);
+}